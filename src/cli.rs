@@ -0,0 +1,171 @@
+use chrono::DateTime;
+use rusqlite::Connection;
+use std::collections::BTreeSet;
+
+use crate::models::AppConfig;
+use crate::utils::{
+    self,
+    blobs::deduplicate_table,
+    bucket::{drop_bucket_table, is_valid_new_bucket_name, list_bucket_tables, recorded_buckets},
+    bucket_meta::{bucket_creation_dates, bucket_usage},
+};
+
+const ADMIN_USAGE: &str = "Usage: s3insqlite admin [--config <path>] <command>
+
+Commands:
+  create-bucket <name>   Create an empty bucket
+  list-buckets           List the buckets with their object count and size
+  delete-bucket <name>   Delete an empty bucket
+  dedup-bucket <name>    Store the objects of a bucket as blobs shared by identical objects";
+
+/// Objects moved into shared blobs per transaction by `dedup-bucket`
+const DEDUP_BATCH_SIZE: usize = 100;
+
+/// Run an offline administration command on the database of the config file,
+/// without starting the server. `args` follow `admin` on the command line.
+pub fn run_admin(args: &[String]) -> std::io::Result<()> {
+    let (config_path, args) = match args {
+        [flag, path, rest @ ..] if flag == "--config" => (path.as_str(), rest),
+        _ => ("config.toml", args),
+    };
+    let command = match args {
+        [command, bucket] => (command.as_str(), Some(bucket.as_str())),
+        [command] => (command.as_str(), None),
+        _ => return Err(usage_error()),
+    };
+
+    let config = AppConfig::from_file(config_path).map_err(|e| {
+        std::io::Error::other(format!("Failed to read config file {config_path}: {e}"))
+    })?;
+    let mut conn = open_database(&config).map_err(database_error)?;
+
+    match command {
+        ("create-bucket", Some(bucket)) => create_bucket(&conn, &config, bucket),
+        ("list-buckets", None) => list_buckets(&conn, &config),
+        ("delete-bucket", Some(bucket)) => delete_bucket(&mut conn, &config, bucket),
+        ("dedup-bucket", Some(bucket)) => dedup_bucket(&mut conn, &config, bucket),
+        _ => Err(usage_error()),
+    }
+}
+
+/// Open the database and bring it up to date, as the server does on startup
+fn open_database(config: &AppConfig) -> rusqlite::Result<Connection> {
+    let mut conn = Connection::open(&config.database_path)?;
+    conn.execute_batch(
+        "PRAGMA journal_mode = WAL;
+         PRAGMA synchronous = FULL;
+         PRAGMA busy_timeout = 5000;",
+    )?;
+    utils::run_migrations(&mut conn, config.get_table_prefix())?;
+    utils::ensure_bucket_meta_table(&conn)?;
+    utils::ensure_lifecycle_table(&conn)?;
+    utils::ensure_acl_table(&conn)?;
+    utils::multipart::ensure_multipart_tables(&conn)?;
+    utils::blobs::ensure_blobs_table(&conn)?;
+    Ok(conn)
+}
+
+fn create_bucket(conn: &Connection, config: &AppConfig, bucket: &str) -> std::io::Result<()> {
+    let prefix = config.get_table_prefix();
+    let table_name = match utils::sanitize_bucket_name(bucket, prefix) {
+        Some(table_name) if is_valid_new_bucket_name(bucket) => table_name,
+        _ => {
+            return Err(std::io::Error::other(format!(
+                "Invalid bucket name {bucket}: use 3 to 63 lowercase letters, digits and hyphens"
+            )));
+        }
+    };
+    let tables = list_bucket_tables(conn, prefix).map_err(database_error)?;
+    if tables.contains(&table_name) {
+        return Err(std::io::Error::other(format!(
+            "Bucket {bucket} already exists"
+        )));
+    }
+
+    utils::ensure_bucket_table(conn, bucket, prefix).map_err(database_error)?;
+    utils::create_bucket_indexes(conn, &table_name).map_err(database_error)?;
+    println!("Created bucket {bucket}");
+    Ok(())
+}
+
+/// Configured and recorded buckets, one per line: name, creation date, object count,
+/// size in bytes, and whether the bucket is configured
+fn list_buckets(conn: &Connection, config: &AppConfig) -> std::io::Result<()> {
+    let prefix = config.get_table_prefix();
+    let mut buckets: BTreeSet<String> = recorded_buckets(conn, prefix)
+        .map_err(database_error)?
+        .into_iter()
+        .collect();
+    buckets.extend(config.buckets.iter().cloned());
+    let creation_dates = bucket_creation_dates(conn).map_err(database_error)?;
+
+    println!("BUCKET\tCREATED\tOBJECTS\tBYTES\tCONFIGURED");
+    for bucket in buckets {
+        let created = creation_dates
+            .get(&bucket)
+            .and_then(|&seconds| DateTime::from_timestamp(seconds, 0))
+            .map(|date| date.to_rfc3339())
+            .unwrap_or_else(|| "-".to_string());
+        let (objects, bytes) = bucket_usage(conn, &bucket).map_err(database_error)?;
+        let configured = if config.buckets.contains(&bucket) {
+            "yes"
+        } else {
+            "no"
+        };
+        println!("{bucket}\t{created}\t{objects}\t{bytes}\t{configured}");
+    }
+    Ok(())
+}
+
+fn delete_bucket(conn: &mut Connection, config: &AppConfig, bucket: &str) -> std::io::Result<()> {
+    let prefix = config.get_table_prefix();
+    let exists = utils::sanitize_bucket_name(bucket, prefix).is_some_and(|table_name| {
+        list_bucket_tables(conn, prefix).is_ok_and(|tables| tables.contains(&table_name))
+    });
+    if !exists {
+        return Err(std::io::Error::other(format!(
+            "Bucket {bucket} does not exist"
+        )));
+    }
+
+    if !drop_bucket_table(conn, bucket, prefix).map_err(database_error)? {
+        return Err(std::io::Error::other(format!(
+            "Bucket {bucket} is not empty: delete its objects and multipart uploads first"
+        )));
+    }
+    println!("Deleted bucket {bucket}");
+    if config.buckets.iter().any(|b| b == bucket) {
+        eprintln!("Bucket {bucket} is configured: the server creates it again on startup");
+    }
+    Ok(())
+}
+
+/// Move the objects of a bucket stored before deduplication was enabled into shared
+/// blobs. Runs in batches, so it may run while the server is serving the bucket.
+fn dedup_bucket(conn: &mut Connection, config: &AppConfig, bucket: &str) -> std::io::Result<()> {
+    let prefix = config.get_table_prefix();
+    let table_name = utils::sanitize_bucket_name(bucket, prefix)
+        .filter(|table_name| {
+            list_bucket_tables(conn, prefix).is_ok_and(|tables| tables.contains(table_name))
+        })
+        .ok_or_else(|| std::io::Error::other(format!("Bucket {bucket} does not exist")))?;
+
+    // The reference counting triggers of a table from an older version
+    utils::ensure_bucket_table(conn, bucket, prefix).map_err(database_error)?;
+    let moved = deduplicate_table(conn, &table_name, DEDUP_BATCH_SIZE).map_err(database_error)?;
+    println!("Moved {moved} objects of bucket {bucket} into shared blobs");
+    if !config.is_dedup(bucket) {
+        eprintln!(
+            "Bucket {bucket} does not set dedup in [bucket_storage.{bucket}]: new uploads are stored as before"
+        );
+    }
+    Ok(())
+}
+
+fn usage_error() -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidInput, ADMIN_USAGE)
+}
+
+fn database_error(e: rusqlite::Error) -> std::io::Error {
+    std::io::Error::other(format!("Database error: {e}"))
+}
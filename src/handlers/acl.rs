@@ -0,0 +1,231 @@
+use axum::{
+    body::Bytes,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use log::{error, info, warn};
+use std::sync::Arc;
+
+use crate::models::AppState;
+use crate::utils::{
+    acl::{
+        AclError, access_control_policy_xml, canned_acl, canned_acl_from_policy, read_bucket_acl,
+        read_object_acl, store_bucket_acl, store_object_acl,
+    },
+    database_error_response, pool_error_response, retry_on_busy, sanitize_bucket_name,
+    validate_bucket, xml_error_response, xml_response,
+};
+
+/// GetBucketAcl: GET /{bucket}?acl
+pub async fn get_bucket_acl(state: Arc<AppState>, bucket: String) -> Response {
+    let bucket = match validate_bucket(&bucket, &state.buckets) {
+        Ok(b) => b,
+        Err(resp) => return *resp,
+    };
+
+    info!("GetBucketAcl for bucket '{bucket}'");
+    let mut conn = match state.read_conn() {
+        Ok(c) => c,
+        Err(e) => return pool_error_response(&e),
+    };
+
+    let max_retries = state.config.get_busy_retry_max_attempts();
+    match retry_on_busy(max_retries, &mut conn, |conn| {
+        read_bucket_acl(conn, &bucket)
+    })
+    .await
+    {
+        Ok(acl) => policy_response(&state, &acl),
+        Err(e) => {
+            error!("Failed to read ACL of bucket '{bucket}': {e}");
+            database_error_response(&e)
+        }
+    }
+}
+
+/// PutBucketAcl: PUT /{bucket}?acl
+/// Accepts a canned ACL in `x-amz-acl` or an equivalent `AccessControlPolicy` body.
+pub async fn put_bucket_acl(
+    state: Arc<AppState>,
+    bucket: String,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let bucket = match validate_bucket(&bucket, &state.buckets) {
+        Ok(b) => b,
+        Err(resp) => return *resp,
+    };
+    let acl = match requested_acl(&state, &headers, &body) {
+        Ok(acl) => acl,
+        Err(resp) => return *resp,
+    };
+
+    info!("PutBucketAcl '{acl}' for bucket '{bucket}'");
+    let mut conn = match state.write_conn() {
+        Ok(c) => c,
+        Err(e) => return pool_error_response(&e),
+    };
+
+    let max_retries = state.config.get_busy_retry_max_attempts();
+    match retry_on_busy(max_retries, &mut conn, |conn| {
+        store_bucket_acl(conn, &bucket, acl)
+    })
+    .await
+    {
+        Ok(()) => StatusCode::OK.into_response(),
+        Err(e) => {
+            error!("Failed to store ACL of bucket '{bucket}': {e}");
+            database_error_response(&e)
+        }
+    }
+}
+
+/// GetObjectAcl: GET /{bucket}/{key}?acl
+pub async fn get_object_acl(state: Arc<AppState>, bucket: String, key: String) -> Response {
+    let bucket = match validate_bucket(&bucket, &state.buckets) {
+        Ok(b) => b,
+        Err(resp) => return *resp,
+    };
+    let Some(table_name) = sanitize_bucket_name(&bucket, state.config.get_table_prefix()) else {
+        warn!("Invalid bucket name attempted: {bucket}");
+        return xml_error_response(
+            StatusCode::BAD_REQUEST,
+            "InvalidBucketName",
+            &format!("Invalid bucket name attempted: {bucket}"),
+        );
+    };
+
+    info!("GetObjectAcl for '{key}' in bucket '{bucket}'");
+    let mut conn = match state.read_conn() {
+        Ok(c) => c,
+        Err(e) => return pool_error_response(&e),
+    };
+
+    let max_retries = state.config.get_busy_retry_max_attempts();
+    match retry_on_busy(max_retries, &mut conn, |conn| {
+        read_object_acl(conn, &table_name, &key)
+    })
+    .await
+    {
+        Ok(Some(acl)) => policy_response(&state, &acl),
+        Ok(None) => xml_error_response(
+            StatusCode::NOT_FOUND,
+            "NoSuchKey",
+            &format!("The object you requested does not exist: {key}"),
+        ),
+        Err(e) => {
+            error!("Failed to read ACL of '{key}' in bucket '{bucket}': {e}");
+            database_error_response(&e)
+        }
+    }
+}
+
+/// PutObjectAcl: PUT /{bucket}/{key}?acl
+/// Accepts a canned ACL in `x-amz-acl` or an equivalent `AccessControlPolicy` body.
+/// The ACL is reset to `private` whenever the object is overwritten.
+pub async fn put_object_acl(
+    state: Arc<AppState>,
+    bucket: String,
+    key: String,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let bucket = match validate_bucket(&bucket, &state.buckets) {
+        Ok(b) => b,
+        Err(resp) => return *resp,
+    };
+    let Some(table_name) = sanitize_bucket_name(&bucket, state.config.get_table_prefix()) else {
+        warn!("Invalid bucket name attempted: {bucket}");
+        return xml_error_response(
+            StatusCode::BAD_REQUEST,
+            "InvalidBucketName",
+            &format!("Invalid bucket name attempted: {bucket}"),
+        );
+    };
+    let acl = match requested_acl(&state, &headers, &body) {
+        Ok(acl) => acl,
+        Err(resp) => return *resp,
+    };
+
+    info!("PutObjectAcl '{acl}' for '{key}' in bucket '{bucket}'");
+    let mut conn = match state.write_conn() {
+        Ok(c) => c,
+        Err(e) => return pool_error_response(&e),
+    };
+
+    let max_retries = state.config.get_busy_retry_max_attempts();
+    match retry_on_busy(max_retries, &mut conn, |conn| {
+        store_object_acl(conn, &table_name, &key, acl)
+    })
+    .await
+    {
+        Ok(true) => StatusCode::OK.into_response(),
+        Ok(false) => xml_error_response(
+            StatusCode::NOT_FOUND,
+            "NoSuchKey",
+            &format!("The object you requested does not exist: {key}"),
+        ),
+        Err(e) => {
+            error!("Failed to store ACL of '{key}' in bucket '{bucket}': {e}");
+            database_error_response(&e)
+        }
+    }
+}
+
+/// Determine the canned ACL of a PutBucketAcl/PutObjectAcl request, from the
+/// `x-amz-acl` header or else the `AccessControlPolicy` body
+fn requested_acl(
+    state: &AppState,
+    headers: &HeaderMap,
+    body: &[u8],
+) -> Result<&'static str, Box<Response>> {
+    if headers
+        .keys()
+        .any(|name| name.as_str().starts_with("x-amz-grant-"))
+    {
+        return Err(Box::new(xml_error_response(
+            StatusCode::NOT_IMPLEMENTED,
+            "NotImplemented",
+            "Only canned ACLs are supported, x-amz-grant-* headers are not",
+        )));
+    }
+
+    if let Some(acl) = headers.get("x-amz-acl") {
+        let acl = acl.to_str().unwrap_or_default();
+        return canned_acl(acl).ok_or_else(|| {
+            Box::new(xml_error_response(
+                StatusCode::BAD_REQUEST,
+                "InvalidArgument",
+                &format!("Invalid canned ACL: {acl}"),
+            ))
+        });
+    }
+
+    let xml = std::str::from_utf8(body).unwrap_or_default();
+    canned_acl_from_policy(xml, state.config.get_owner_id()).map_err(|e| {
+        Box::new(match e {
+            AclError::Malformed => xml_error_response(
+                StatusCode::BAD_REQUEST,
+                "MalformedACLError",
+                "The XML you provided was not well-formed or did not validate against our published schema",
+            ),
+            AclError::Unsupported => xml_error_response(
+                StatusCode::NOT_IMPLEMENTED,
+                "NotImplemented",
+                "Only ACLs equivalent to a canned ACL are supported",
+            ),
+        })
+    })
+}
+
+/// `AccessControlPolicy` response for a canned ACL owned by the configured owner
+fn policy_response(state: &AppState, acl: &str) -> Response {
+    xml_response(
+        StatusCode::OK,
+        access_control_policy_xml(
+            acl,
+            state.config.get_owner_id(),
+            state.config.get_owner_display_name(),
+        ),
+    )
+}
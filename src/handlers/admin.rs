@@ -0,0 +1,531 @@
+use axum::{
+    extract::{Extension, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use chrono::Utc;
+use log::{error, info, warn};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Lifetime of temporary credentials minted without `duration-seconds`
+const DEFAULT_TEMP_CREDENTIALS_SECONDS: i64 = 3600;
+/// Shortest lifetime of temporary credentials, like the 15 minutes of AWS STS
+const MIN_TEMP_CREDENTIALS_SECONDS: i64 = 900;
+
+use crate::handlers::audit::get_audit_log;
+use crate::handlers::auth::reload_credentials;
+use crate::handlers::browse::{browse_buckets, wants_browser_ui};
+use crate::handlers::bucket::list_buckets;
+use crate::models::{
+    AppState, AuthMode, BucketPermission, BucketTable, BucketTableReport, Principal,
+    TempCredentialsReport,
+};
+use crate::utils::backup::{SnapshotError, take_snapshot};
+use crate::utils::temp_credentials::mint_temp_credentials;
+use crate::utils::{
+    backup_database, blobs::blob_stats, bucket::list_bucket_tables, database_error_response,
+    database_used_bytes, is_busy_error, not_implemented_response, optimize_database,
+    pool_error_response, retry_on_busy, run_blocking, sanitize_bucket_name, wal_frame_count,
+    xml_error_response,
+};
+
+/// Route GET requests on the service root based on query parameters
+pub async fn get_root_dispatch(
+    State(state): State<Arc<AppState>>,
+    principal: Option<Extension<Principal>>,
+    query: Query<HashMap<String, String>>,
+    headers: HeaderMap,
+) -> Response {
+    let principal = principal.map(|Extension(p)| p);
+    if query.is_empty() && wants_browser_ui(&state, &headers) {
+        browse_buckets(&state, principal.as_ref()).await
+    } else if query.contains_key("metrics") {
+        get_metrics(state).await
+    } else if query.contains_key("tables") {
+        get_bucket_tables(state).await
+    } else if query.contains_key("audit") {
+        get_audit_log(state, &query).await
+    } else {
+        list_buckets(State(state), principal, query).await
+    }
+}
+
+/// Route admin operations on the service root based on query parameters
+pub async fn post_root_dispatch(
+    State(state): State<Arc<AppState>>,
+    principal: Option<Extension<Principal>>,
+    query: Query<HashMap<String, String>>,
+) -> Response {
+    if query.contains_key("optimize") {
+        optimize(state).await
+    } else if query.contains_key("backup") {
+        backup(state, &query).await
+    } else if query.contains_key("snapshot") {
+        snapshot(state).await
+    } else if query.contains_key("reload-credentials") {
+        reload_credentials_now(state)
+    } else if query.contains_key("temp-credentials") {
+        let principal = principal.map(|Extension(p)| p);
+        create_temp_credentials(state, principal, &query).await
+    } else {
+        not_implemented_response()
+    }
+}
+
+/// Reload the access keys on demand: POST /?reload-credentials
+fn reload_credentials_now(state: Arc<AppState>) -> Response {
+    match reload_credentials(&state) {
+        Ok(count) => {
+            info!("Reloaded {count} access keys on request");
+            let body = format!(r#"{{"status":"ok","access_keys":{count}}}"#);
+            let mut headers = HeaderMap::new();
+            headers.insert("Content-Type", "application/json".parse().unwrap());
+            headers.insert("Content-Length", body.len().to_string().parse().unwrap());
+
+            (StatusCode::OK, headers, body).into_response()
+        }
+        Err(e) => {
+            error!("Failed to reload credentials, keeping the previous keys: {e}");
+            xml_error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "InternalError",
+                &format!("Failed to reload credentials: {e}"),
+            )
+        }
+    }
+}
+
+/// Mint temporary credentials: POST /?temp-credentials&bucket=<bucket> with optional
+/// `prefix` limiting them to the keys under it, `permission` (`ro`, the default, or
+/// `rw`) and `duration-seconds` (default 3600, at least 900)
+async fn create_temp_credentials(
+    state: Arc<AppState>,
+    principal: Option<Principal>,
+    query: &HashMap<String, String>,
+) -> Response {
+    if state.config.get_auth_mode() == AuthMode::None {
+        return xml_error_response(
+            StatusCode::BAD_REQUEST,
+            "InvalidRequest",
+            "Temporary credentials need authentication to be enabled",
+        );
+    }
+    let Some(bucket) = query.get("bucket").filter(|b| !b.is_empty()) else {
+        return xml_error_response(
+            StatusCode::BAD_REQUEST,
+            "InvalidArgument",
+            "bucket is required",
+        );
+    };
+    if !state.buckets.read().unwrap().contains(bucket) {
+        return xml_error_response(
+            StatusCode::NOT_FOUND,
+            "NoSuchBucket",
+            "The specified bucket does not exist",
+        );
+    }
+    let prefix = query.get("prefix").map(String::as_str).unwrap_or_default();
+    let permission = match query.get("permission").map(String::as_str) {
+        None | Some("ro") => BucketPermission::ReadOnly,
+        Some("rw") => BucketPermission::ReadWrite,
+        Some(_) => {
+            return xml_error_response(
+                StatusCode::BAD_REQUEST,
+                "InvalidArgument",
+                "permission must be ro or rw",
+            );
+        }
+    };
+    let max_duration = state.config.get_max_temp_credentials_duration();
+    let duration = match query.get("duration-seconds").map(|d| d.parse::<i64>()) {
+        None => chrono::Duration::seconds(DEFAULT_TEMP_CREDENTIALS_SECONDS).min(max_duration),
+        Some(Ok(seconds))
+            if seconds >= MIN_TEMP_CREDENTIALS_SECONDS
+                && chrono::Duration::seconds(seconds) <= max_duration =>
+        {
+            chrono::Duration::seconds(seconds)
+        }
+        Some(_) => {
+            return xml_error_response(
+                StatusCode::BAD_REQUEST,
+                "InvalidArgument",
+                &format!(
+                    "duration-seconds must be between {MIN_TEMP_CREDENTIALS_SECONDS} and {}",
+                    max_duration.num_seconds()
+                ),
+            );
+        }
+    };
+
+    let mut conn = match state.write_conn() {
+        Ok(c) => c,
+        Err(e) => return pool_error_response(&e),
+    };
+    let issued_by = principal.as_ref().map(|p| p.access_key_id.as_str());
+    let expires_at = Utc::now() + duration;
+    let max_retries = state.config.get_busy_retry_max_attempts();
+    let credentials = match retry_on_busy(max_retries, &mut conn, |conn| {
+        mint_temp_credentials(conn, bucket, prefix, permission, expires_at, issued_by)
+    })
+    .await
+    {
+        Ok(credentials) => credentials,
+        Err(e) => {
+            error!("Failed to store temporary credentials for bucket '{bucket}': {e}");
+            return database_error_response(&e);
+        }
+    };
+    info!(
+        "Issued temporary credentials {} for '{bucket}/{prefix}' ({permission:?}) until {expires_at}, by {}",
+        credentials.access_key_id,
+        issued_by.unwrap_or("anonymous")
+    );
+
+    let report = TempCredentialsReport {
+        access_key_id: credentials.access_key_id,
+        secret_access_key: credentials.secret.expose().to_string(),
+        session_token: credentials.session_token.expose().to_string(),
+        expiration: credentials
+            .expires_at
+            .to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        bucket: credentials.bucket,
+        prefix: credentials.prefix,
+        permission: match credentials.permission {
+            BucketPermission::ReadOnly => "ro",
+            BucketPermission::ReadWrite => "rw",
+        }
+        .to_string(),
+    };
+    let body = serde_json::to_string(&report).unwrap();
+    let mut headers = HeaderMap::new();
+    headers.insert("Content-Type", "application/json".parse().unwrap());
+    headers.insert("Content-Length", body.len().to_string().parse().unwrap());
+
+    (StatusCode::OK, headers, body).into_response()
+}
+
+/// Run VACUUM and ANALYZE on demand: POST /?optimize
+async fn optimize(state: Arc<AppState>) -> Response {
+    info!("Manual database optimization requested");
+    let conn = match state.write_conn() {
+        Ok(conn) => conn,
+        Err(e) => return pool_error_response(&e),
+    };
+    let start = Instant::now();
+
+    match run_blocking(|| optimize_database(&conn)) {
+        Ok(()) => {
+            let elapsed = start.elapsed();
+            info!("Manual database optimization completed in {elapsed:?}");
+
+            let body = format!(r#"{{"status":"ok","duration_ms":{}}}"#, elapsed.as_millis());
+            let mut headers = HeaderMap::new();
+            headers.insert("Content-Type", "application/json".parse().unwrap());
+            headers.insert("Content-Length", body.len().to_string().parse().unwrap());
+
+            (StatusCode::OK, headers, body).into_response()
+        }
+        Err(e) if is_busy_error(&e) => {
+            warn!("Manual database optimization aborted, database is locked: {e}");
+            xml_error_response(
+                StatusCode::CONFLICT,
+                "OperationAborted",
+                &format!("Database is busy, try again later: {e}"),
+            )
+        }
+        Err(e) => {
+            error!("Manual database optimization failed: {e}");
+            xml_error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "InternalError",
+                &e.to_string(),
+            )
+        }
+    }
+}
+
+/// Copy the database into the backup directory: POST /?backup&path=<file>.
+/// `path` is relative to `backup_dir`, or absolute inside it, and must not exist yet.
+async fn backup(state: Arc<AppState>, query: &HashMap<String, String>) -> Response {
+    let Some(backup_dir) = state.config.get_backup_dir() else {
+        return xml_error_response(
+            StatusCode::BAD_REQUEST,
+            "InvalidRequest",
+            "Backups need backup_dir to be configured",
+        );
+    };
+    let Some(requested) = query.get("path").filter(|p| !p.is_empty()) else {
+        return xml_error_response(
+            StatusCode::BAD_REQUEST,
+            "InvalidArgument",
+            "path is required",
+        );
+    };
+    let target = match resolve_backup_path(Path::new(backup_dir), Path::new(requested)) {
+        Ok(Some(target)) => target,
+        Ok(None) => {
+            warn!("Refused backup to '{requested}', outside of {backup_dir}");
+            return xml_error_response(
+                StatusCode::BAD_REQUEST,
+                "InvalidArgument",
+                "path must name a file inside the backup directory",
+            );
+        }
+        Err(e) => {
+            error!("Failed to open backup directory {backup_dir}: {e}");
+            return xml_error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "InternalError",
+                &format!("Failed to open backup directory: {e}"),
+            );
+        }
+    };
+    if target.exists() {
+        return xml_error_response(
+            StatusCode::CONFLICT,
+            "BackupExists",
+            &format!("Backup file already exists: {requested}"),
+        );
+    }
+
+    let conn = match state.read_conn() {
+        Ok(conn) => conn,
+        Err(e) => return pool_error_response(&e),
+    };
+    info!("Database backup to {} requested", target.display());
+    let start = Instant::now();
+    let result = run_blocking(|| -> rusqlite::Result<u64> {
+        backup_database(&conn, &target)?;
+        Ok(std::fs::metadata(&target).map_or(0, |metadata| metadata.len()))
+    });
+    match result {
+        Ok(size) => {
+            let elapsed = start.elapsed();
+            info!(
+                "Database backup to {} completed in {elapsed:?}, {size} bytes",
+                target.display()
+            );
+
+            let body = format!(
+                r#"{{"status":"ok","path":{},"size_bytes":{},"duration_ms":{}}}"#,
+                serde_json::to_string(&target.to_string_lossy()).unwrap(),
+                size,
+                elapsed.as_millis()
+            );
+            let mut headers = HeaderMap::new();
+            headers.insert("Content-Type", "application/json".parse().unwrap());
+            headers.insert("Content-Length", body.len().to_string().parse().unwrap());
+
+            (StatusCode::OK, headers, body).into_response()
+        }
+        Err(e) => {
+            error!("Database backup to {} failed: {e}", target.display());
+            database_error_response(&e)
+        }
+    }
+}
+
+/// Take an online backup snapshot into the backup directory now: POST /?snapshot
+async fn snapshot(state: Arc<AppState>) -> Response {
+    let Some(backup_dir) = state.config.get_backup_dir() else {
+        return xml_error_response(
+            StatusCode::BAD_REQUEST,
+            "InvalidRequest",
+            "Snapshots need backup_dir to be configured",
+        );
+    };
+    info!("Snapshot requested");
+
+    // The copy pauses between steps, so it runs on a blocking thread
+    let pool = (*state.read_pool).clone();
+    let dir = PathBuf::from(backup_dir);
+    let retention = state.config.get_snapshot_retention();
+    let result = tokio::task::spawn_blocking(move || take_snapshot(&pool, &dir, retention)).await;
+    match result {
+        Ok(Ok(report)) => {
+            let body = format!(
+                r#"{{"status":"ok","path":{},"pages":{},"size_bytes":{},"restarts":{},"duration_ms":{}}}"#,
+                serde_json::to_string(&report.path.to_string_lossy()).unwrap(),
+                report.pages,
+                report.size_bytes,
+                report.restarts,
+                report.duration.as_millis()
+            );
+            let mut headers = HeaderMap::new();
+            headers.insert("Content-Type", "application/json".parse().unwrap());
+            headers.insert("Content-Length", body.len().to_string().parse().unwrap());
+
+            (StatusCode::OK, headers, body).into_response()
+        }
+        Ok(Err(SnapshotError::Running)) => xml_error_response(
+            StatusCode::CONFLICT,
+            "OperationAborted",
+            "A snapshot is already being taken, try again later",
+        ),
+        Ok(Err(SnapshotError::Pool(e))) => pool_error_response(&e),
+        Ok(Err(SnapshotError::Database(e))) => {
+            error!("Snapshot failed: {e}");
+            database_error_response(&e)
+        }
+        Ok(Err(e)) => {
+            error!("Snapshot failed: {e}");
+            xml_error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "InternalError",
+                &format!("Snapshot failed: {e}"),
+            )
+        }
+        Err(e) => {
+            error!("Snapshot task failed: {e}");
+            xml_error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "InternalError",
+                "Snapshot failed",
+            )
+        }
+    }
+}
+
+/// Absolute path of a backup file, None if it is not a file directly or indirectly
+/// inside `backup_dir`. The directory is created if needed; symlinks are resolved, so
+/// a link in the directory cannot point a backup elsewhere.
+fn resolve_backup_path(backup_dir: &Path, requested: &Path) -> std::io::Result<Option<PathBuf>> {
+    std::fs::create_dir_all(backup_dir)?;
+    let backup_dir = std::fs::canonicalize(backup_dir)?;
+    let requested = backup_dir.join(requested);
+    let (Some(parent), Some(file_name)) = (requested.parent(), requested.file_name()) else {
+        return Ok(None);
+    };
+    // A missing parent directory is refused, like any path outside the directory
+    let Ok(parent) = std::fs::canonicalize(parent) else {
+        return Ok(None);
+    };
+    if !parent.starts_with(&backup_dir) {
+        return Ok(None);
+    }
+    let target = parent.join(file_name);
+    if target.is_symlink() || target.is_dir() {
+        return Ok(None);
+    }
+    Ok(Some(target))
+}
+
+/// Operational metrics: GET /?metrics
+async fn get_metrics(state: Arc<AppState>) -> Response {
+    let conn = match state.read_conn() {
+        Ok(c) => c,
+        Err(e) => return pool_error_response(&e),
+    };
+
+    let sizes = run_blocking(|| -> rusqlite::Result<_> {
+        Ok((
+            wal_frame_count(&conn)?,
+            database_used_bytes(&conn)?,
+            blob_stats(&conn)?,
+        ))
+    });
+    let (wal_frames, db_used_bytes, (blobs, blob_bytes)) = match sizes {
+        Ok(sizes) => sizes,
+        Err(e) => {
+            error!("Failed to read database size: {e}");
+            return xml_error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "InternalError",
+                &e.to_string(),
+            );
+        }
+    };
+    let pool_state = state.read_pool.state();
+    let writer_busy = state.write_pool.state().idle_connections == 0;
+    // Zero while the object cache is disabled
+    let (cache_hits, cache_misses, cache_entries, cache_bytes) = state
+        .object_cache
+        .as_ref()
+        .map(|cache| cache.stats())
+        .map_or((0, 0, 0, 0), |stats| {
+            (stats.hits, stats.misses, stats.entries, stats.bytes)
+        });
+
+    let body = format!(
+        r#"{{"wal_frames":{},"db_used_bytes":{},"shared_blobs":{},"shared_blob_bytes":{},"db_pool_connections":{},"db_pool_idle_connections":{},"db_writer_busy":{},"object_cache_hits":{},"object_cache_misses":{},"object_cache_entries":{},"object_cache_bytes":{}}}"#,
+        wal_frames,
+        db_used_bytes,
+        blobs,
+        blob_bytes,
+        pool_state.connections,
+        pool_state.idle_connections,
+        writer_busy,
+        cache_hits,
+        cache_misses,
+        cache_entries,
+        cache_bytes
+    );
+    let mut headers = HeaderMap::new();
+    headers.insert("Content-Type", "application/json".parse().unwrap());
+    headers.insert("Content-Length", body.len().to_string().parse().unwrap());
+
+    (StatusCode::OK, headers, body).into_response()
+}
+
+/// Bucket tables present in the database: GET /?tables
+/// Tables without a configured bucket are flagged as orphans, and configured
+/// buckets without a table are listed as missing.
+async fn get_bucket_tables(state: Arc<AppState>) -> Response {
+    let mut conn = match state.read_conn() {
+        Ok(c) => c,
+        Err(e) => return pool_error_response(&e),
+    };
+
+    let max_retries = state.config.get_busy_retry_max_attempts();
+    let tables = match retry_on_busy(max_retries, &mut conn, |conn| {
+        list_bucket_tables(conn, state.config.get_table_prefix())
+    })
+    .await
+    {
+        Ok(tables) => tables,
+        Err(e) => {
+            error!("Failed to list bucket tables: {e}");
+            return database_error_response(&e);
+        }
+    };
+
+    let mut configured: HashMap<String, String> = state
+        .bucket_names()
+        .into_iter()
+        .filter_map(|bucket| {
+            sanitize_bucket_name(&bucket, state.config.get_table_prefix())
+                .map(|table| (table, bucket))
+        })
+        .collect();
+    let tables: Vec<BucketTable> = tables
+        .into_iter()
+        .map(|table| {
+            let bucket = configured.remove(&table);
+            BucketTable {
+                orphan: bucket.is_none(),
+                table,
+                bucket,
+            }
+        })
+        .collect();
+    let mut missing: Vec<String> = configured.into_values().collect();
+    missing.sort();
+
+    let orphans = tables.iter().filter(|t| t.orphan).count();
+    if orphans > 0 || !missing.is_empty() {
+        warn!(
+            "Bucket tables drift from configuration: {orphans} orphaned tables, {} missing",
+            missing.len()
+        );
+    }
+
+    let body = serde_json::to_string(&BucketTableReport { tables, missing }).unwrap();
+    let mut headers = HeaderMap::new();
+    headers.insert("Content-Type", "application/json".parse().unwrap());
+    headers.insert("Content-Length", body.len().to_string().parse().unwrap());
+
+    (StatusCode::OK, headers, body).into_response()
+}
@@ -0,0 +1,168 @@
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{HeaderMap, Method, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use log::error;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use crate::models::{AppState, AuthFailure, Principal};
+use crate::utils::access_log::operation_name;
+use crate::utils::audit::{AuditQuery, AuditRecord, MAX_AUDIT_QUERY_RECORDS};
+use crate::utils::{percent_decode, run_blocking, xml_error_response};
+
+/// Records returned by an audit log query without `limit`
+const DEFAULT_AUDIT_QUERY_RECORDS: usize = 1000;
+
+/// Record mutating requests (PUT, POST, DELETE) and failed authentications in the
+/// audit log. The record is stored before the response is sent, so a client never
+/// sees the success of a write that is missing from the log.
+pub async fn audit_log_middleware(
+    State(state): State<Arc<AppState>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let Some(audit_log) = state.audit_log.clone() else {
+        return next.run(req).await;
+    };
+
+    let method = req.method().clone();
+    let path = req.uri().path().trim_start_matches('/');
+    let (bucket, key) = match path.split_once('/') {
+        Some((bucket, key)) => (bucket, Some(key).filter(|k| !k.is_empty())),
+        None => (path, None),
+    };
+    let bucket = Some(bucket.to_string()).filter(|b| !b.is_empty());
+    let key = key.map(|k| percent_decode(k).unwrap_or_else(|| k.to_string()));
+    let query: HashMap<String, String> = req
+        .uri()
+        .query()
+        .map(|q| {
+            url::form_urlencoded::parse(q.as_bytes())
+                .into_owned()
+                .collect()
+        })
+        .unwrap_or_default();
+    let operation = if method == Method::PUT && req.headers().contains_key("x-amz-copy-source") {
+        "REST.COPY.OBJECT".to_string()
+    } else {
+        operation_name(method.as_str(), key.is_some(), &query)
+    };
+    let peer = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|info| info.0.ip());
+    let remote_addr = state
+        .ip_filter
+        .client_ip(peer, req.headers())
+        .map(|ip| ip.to_string());
+    let bytes = req
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok());
+    let time = Utc::now();
+
+    let response = next.run(req).await;
+
+    let failure = response.extensions().get::<AuthFailure>();
+    let writes = !matches!(method, Method::GET | Method::HEAD | Method::OPTIONS);
+    if !writes && failure.is_none() {
+        return response;
+    }
+    let access_key = match (failure, response.extensions().get::<Principal>()) {
+        (Some(failure), _) => failure.access_key_id.clone(),
+        (None, Some(principal)) => Some(principal.access_key_id.clone()),
+        (None, None) => None,
+    };
+    let record = AuditRecord {
+        time,
+        access_key: access_key.unwrap_or_else(|| "anonymous".to_string()),
+        remote_addr,
+        operation,
+        bucket,
+        key,
+        status: response.status().as_u16(),
+        bytes: if writes { bytes } else { None },
+    };
+    if let Err(e) = run_blocking(|| audit_log.record(&record)) {
+        error!(
+            "Failed to record {} {:?}/{:?} in the audit log: {e}",
+            record.operation, record.bucket, record.key
+        );
+    }
+    response
+}
+
+/// Query the audit log: GET /?audit with optional `bucket`, `key`, `since` and
+/// `until` (RFC 3339, `until` excluded) and `limit`, oldest records first
+pub async fn get_audit_log(state: Arc<AppState>, query: &HashMap<String, String>) -> Response {
+    let Some(audit_log) = state.audit_log.clone() else {
+        return xml_error_response(
+            StatusCode::BAD_REQUEST,
+            "InvalidRequest",
+            "Audit logging is disabled",
+        );
+    };
+
+    let time = |name: &str| match query.get(name) {
+        Some(value) => DateTime::parse_from_rfc3339(value)
+            .map(|t| Some(t.with_timezone(&Utc)))
+            .map_err(|_| {
+                Box::new(xml_error_response(
+                    StatusCode::BAD_REQUEST,
+                    "InvalidArgument",
+                    &format!("{name} must be an RFC 3339 date"),
+                ))
+            }),
+        None => Ok(None),
+    };
+    let since = match time("since") {
+        Ok(since) => since,
+        Err(response) => return *response,
+    };
+    let until = match time("until") {
+        Ok(until) => until,
+        Err(response) => return *response,
+    };
+    let limit = match query.get("limit").map(|l| l.parse::<usize>()) {
+        Some(Ok(limit)) if limit > 0 => limit.min(MAX_AUDIT_QUERY_RECORDS),
+        Some(_) => {
+            return xml_error_response(
+                StatusCode::BAD_REQUEST,
+                "InvalidArgument",
+                "limit must be a positive integer",
+            );
+        }
+        None => DEFAULT_AUDIT_QUERY_RECORDS,
+    };
+    let audit_query = AuditQuery {
+        bucket: query.get("bucket").cloned(),
+        key: query.get("key").cloned(),
+        since,
+        until,
+        limit,
+    };
+
+    match run_blocking(|| audit_log.query(&audit_query)) {
+        Ok(records) => {
+            let body = serde_json::to_string(&records).unwrap();
+            let mut headers = HeaderMap::new();
+            headers.insert("Content-Type", "application/json".parse().unwrap());
+            headers.insert("Content-Length", body.len().to_string().parse().unwrap());
+            (StatusCode::OK, headers, body).into_response()
+        }
+        Err(e) => {
+            error!("Failed to query the audit log: {e}");
+            xml_error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "InternalError",
+                &e.to_string(),
+            )
+        }
+    }
+}
@@ -0,0 +1,633 @@
+use axum::{
+    extract::{Request, State},
+    http::{HeaderMap, Method, StatusCode, Uri, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use log::{debug, error, info, warn};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use crate::handlers::object::parse_copy_source;
+use crate::models::{AppState, AuthFailure, AuthMode, BucketPermission, Credentials, Principal};
+use crate::utils::{
+    bucket_meta::get_bucket_policy,
+    database_error_response, html_escape, percent_decode,
+    policy::parse_bucket_policy,
+    pool_error_response,
+    request_id::current_request_id,
+    retry_on_busy,
+    sigv4::{
+        canonical_request, parse_authorization, parse_presigned_query, parse_timestamp,
+        request_timestamp, signature_matches, string_to_sign,
+    },
+    temp_credentials::{TempCredentials, lookup_temp_credentials},
+    xml_error_response,
+};
+
+/// Query parameters of object listings, the only bucket requests temporary credentials may make
+const LISTING_PARAMETERS: &[&str] = &[
+    "continuation-token",
+    "delimiter",
+    "encoding-type",
+    "fetch-owner",
+    "list-type",
+    "marker",
+    "max-keys",
+    "prefix",
+    "start-after",
+    "x-id",
+];
+
+/// Subresources that stay private on publicly readable buckets and objects
+const PRIVATE_SUBRESOURCES: &[&str] = &[
+    "acl",
+    "cors",
+    "lifecycle",
+    "logging",
+    "policy",
+    "policyStatus",
+    "uploadId",
+    "uploads",
+];
+
+/// Verify the AWS Signature Version 4 of requests, signed in the `Authorization`
+/// header or in the query string of a presigned URL, against the configured credentials.
+/// Unsigned requests are refused unless they are CORS preflights or public reads.
+/// Only headers are involved: the payload is covered through the hash the client
+/// declares in `x-amz-content-sha256`, so rejections are sent before the body is read.
+/// In `log_only` mode failures are logged the same way but the request proceeds.
+pub async fn auth_middleware(
+    State(state): State<Arc<AppState>>,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    let mode = state.config.get_auth_mode();
+    if mode == AuthMode::None {
+        return next.run(req).await;
+    }
+    let query: HashMap<String, String> = req
+        .uri()
+        .query()
+        .map(|q| {
+            url::form_urlencoded::parse(q.as_bytes())
+                .into_owned()
+                .collect()
+        })
+        .unwrap_or_default();
+
+    match authenticate(&state, req.method(), req.uri(), req.headers(), &query).await {
+        Ok(Some(principal)) => {
+            debug!(
+                "Authenticated {} {} as {}",
+                req.method(),
+                req.uri().path(),
+                state.credentials().display_name(&principal.access_key_id)
+            );
+            req.extensions_mut().insert(principal.clone());
+            let mut response = next.run(req).await;
+            response.extensions_mut().insert(principal);
+            return response;
+        }
+        Ok(None) => {}
+        Err(denial) => {
+            let path = req.uri().path().trim_start_matches('/');
+            let (bucket, key) = path.split_once('/').unwrap_or((path, ""));
+            warn!(
+                "{} {} {}: bucket={bucket:?} key={key:?} access_key={:?} reason={:?}",
+                if mode == AuthMode::LogOnly {
+                    "Would deny"
+                } else {
+                    "Denied"
+                },
+                req.method(),
+                req.uri().path(),
+                denial.access_key_id.as_deref().unwrap_or("-"),
+                denial.reason
+            );
+            let failure = AuthFailure {
+                access_key_id: denial.access_key_id,
+            };
+            let mut response = if mode == AuthMode::Required {
+                denial.response
+            } else {
+                next.run(req).await
+            };
+            response.extensions_mut().insert(failure);
+            return response;
+        }
+    }
+    next.run(req).await
+}
+
+/// Reload the access keys of the config file and its `credentials_file`, returning how
+/// many there are. The current keys stay in use if the new ones cannot be read or if
+/// there would be none left while authentication is enabled.
+pub fn reload_credentials(state: &AppState) -> Result<usize, String> {
+    let credentials = Credentials::load(&state.config)?;
+    if credentials.is_empty() && state.config.get_auth_mode() != AuthMode::None {
+        return Err("no access keys left, every request would be refused".to_string());
+    }
+    let count = credentials.len();
+    state.replace_credentials(credentials);
+    Ok(count)
+}
+
+/// Reload the access keys whenever the modification time of the credentials file changes,
+/// checking every `interval`. Requests in flight finish with the keys they started with.
+pub fn schedule_credentials_reload(state: Arc<AppState>, path: String, interval: Duration) {
+    let modified = |path: &str| -> Option<SystemTime> {
+        std::fs::metadata(path).and_then(|m| m.modified()).ok()
+    };
+    tokio::spawn(async move {
+        let mut last_modified = modified(&path);
+        let mut interval = tokio::time::interval(interval);
+
+        loop {
+            interval.tick().await;
+            let current = modified(&path);
+            if current == last_modified {
+                continue;
+            }
+            last_modified = current;
+            match reload_credentials(&state) {
+                Ok(count) => info!("Reloaded {count} access keys after {path} changed"),
+                Err(e) => error!("Failed to reload credentials, keeping the previous keys: {e}"),
+            }
+        }
+    });
+}
+
+/// Why a request failed authentication, and the S3 error it gets
+struct Denial {
+    access_key_id: Option<String>,
+    reason: String,
+    response: Response,
+}
+
+impl Denial {
+    fn new(access_key_id: Option<&str>, reason: impl Into<String>, response: Response) -> Self {
+        Self {
+            access_key_id: access_key_id.map(str::to_string),
+            reason: reason.into(),
+            response,
+        }
+    }
+}
+
+/// Check the signature and bucket permissions of a request. Returns the principal
+/// of a signed request, None for an anonymous one that is allowed, or the denial.
+async fn authenticate(
+    state: &AppState,
+    method: &Method,
+    uri: &Uri,
+    headers: &HeaderMap,
+    query: &HashMap<String, String>,
+) -> Result<Option<Principal>, Denial> {
+    let (signature, timestamp, payload_hash) = if let Some(authorization) =
+        headers.get(header::AUTHORIZATION)
+    {
+        let signature = match authorization.to_str().map(parse_authorization) {
+            Ok(Ok(signature)) => signature,
+            Ok(Err(reason)) => {
+                let response = xml_error_response(
+                    StatusCode::BAD_REQUEST,
+                    "AuthorizationHeaderMalformed",
+                    &reason,
+                );
+                return Err(Denial::new(None, reason, response));
+            }
+            Err(_) => {
+                let reason = "The authorization header is not valid ASCII";
+                let response = xml_error_response(
+                    StatusCode::BAD_REQUEST,
+                    "AuthorizationHeaderMalformed",
+                    reason,
+                );
+                return Err(Denial::new(None, reason, response));
+            }
+        };
+        let Some(timestamp) = request_timestamp(headers) else {
+            let reason = "AWS authentication requires a valid Date or x-amz-date header";
+            let response = xml_error_response(StatusCode::FORBIDDEN, "AccessDenied", reason);
+            return Err(Denial::new(
+                Some(&signature.access_key_id),
+                reason,
+                response,
+            ));
+        };
+        let max_skew = state.config.get_max_clock_skew();
+        let now = Utc::now();
+        if let Some(signed_at) = parse_timestamp(&timestamp)
+            && (signed_at - now).abs() > max_skew
+        {
+            let response = clock_skew_response(&timestamp, now, max_skew);
+            return Err(Denial::new(
+                Some(&signature.access_key_id),
+                format!(
+                    "Request time {timestamp} is {}s away from the server time",
+                    (signed_at - now).num_seconds()
+                ),
+                response,
+            ));
+        }
+        let payload_hash = headers
+            .get("x-amz-content-sha256")
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("UNSIGNED-PAYLOAD");
+        (signature, timestamp, payload_hash)
+    } else if query.contains_key("X-Amz-Algorithm") {
+        let presigned = match parse_presigned_query(query) {
+            Ok(presigned) => presigned,
+            Err(reason) => {
+                let response = xml_error_response(
+                    StatusCode::BAD_REQUEST,
+                    "AuthorizationQueryParametersError",
+                    &reason,
+                );
+                return Err(Denial::new(None, reason, response));
+            }
+        };
+        let now = Utc::now();
+        if let Some(signed_at) = parse_timestamp(&presigned.timestamp)
+            && signed_at - now > state.config.get_max_clock_skew()
+        {
+            let response = xml_error_response(
+                StatusCode::FORBIDDEN,
+                "AccessDenied",
+                "Request is not valid yet",
+            );
+            return Err(Denial::new(
+                Some(&presigned.signature.access_key_id),
+                format!("Presigned URL dated {} in the future", presigned.timestamp),
+                response,
+            ));
+        }
+        if now > presigned.expires_at {
+            let response =
+                xml_error_response(StatusCode::FORBIDDEN, "AccessDenied", "Request has expired");
+            return Err(Denial::new(
+                Some(&presigned.signature.access_key_id),
+                format!("Presigned URL expired at {}", presigned.expires_at),
+                response,
+            ));
+        }
+        // The payload of presigned requests is never signed
+        (presigned.signature, presigned.timestamp, "UNSIGNED-PAYLOAD")
+    } else {
+        if method == Method::OPTIONS || is_public_read(state, method, uri.path(), query).await {
+            return Ok(None);
+        }
+        let response = xml_error_response(StatusCode::FORBIDDEN, "AccessDenied", "Access Denied");
+        return Err(Denial::new(None, "Anonymous request", response));
+    };
+
+    let access_key_id = Some(signature.access_key_id.as_str());
+    // Requests with a session token are signed with temporary credentials
+    let session_token = headers
+        .get("x-amz-security-token")
+        .and_then(|v| v.to_str().ok())
+        .or(query.get("X-Amz-Security-Token").map(String::as_str));
+    let temp = match session_token {
+        Some(token) => Some(session_credentials(state, &signature.access_key_id, token).await?),
+        None => None,
+    };
+    let credentials = state.credentials();
+    let secret = match &temp {
+        Some(temp) => &temp.secret,
+        None => match credentials.lookup(&signature.access_key_id) {
+            Some(secret) => secret,
+            None => {
+                let response = xml_error_response(
+                    StatusCode::FORBIDDEN,
+                    "InvalidAccessKeyId",
+                    "The AWS Access Key Id you provided does not exist in our records.",
+                );
+                return Err(Denial::new(access_key_id, "Unknown access key", response));
+            }
+        },
+    };
+
+    let canonical_request = canonical_request(
+        method.as_str(),
+        uri.path(),
+        uri.query(),
+        headers,
+        &signature.signed_headers,
+        payload_hash,
+    );
+    let string_to_sign = string_to_sign(&timestamp, &signature.scope, &canonical_request);
+    if !signature_matches(secret.expose(), &signature, &string_to_sign) {
+        let response = signature_mismatch_response(
+            &signature.access_key_id,
+            &string_to_sign,
+            &signature.signature,
+            &canonical_request,
+        );
+        return Err(Denial::new(
+            access_key_id,
+            "Signature does not match",
+            response,
+        ));
+    }
+    if let Some(temp) = &temp {
+        if let Some(reason) = temp_scope_violation(temp, method, uri.path(), headers, query) {
+            let response = xml_error_response(StatusCode::FORBIDDEN, "AccessDenied", &reason);
+            return Err(Denial::new(access_key_id, reason, response));
+        }
+    } else if !is_authorized(
+        state,
+        &signature.access_key_id,
+        method,
+        uri.path(),
+        headers,
+        query,
+    ) {
+        let response = xml_error_response(StatusCode::FORBIDDEN, "AccessDenied", "Access Denied");
+        return Err(Denial::new(
+            access_key_id,
+            "Not permitted by the bucket permissions of the key",
+            response,
+        ));
+    }
+
+    Ok(Some(Principal {
+        access_key_id: signature.access_key_id,
+    }))
+}
+
+/// The temporary credentials of an access key, if the session token is theirs and
+/// they have not expired
+async fn session_credentials(
+    state: &AppState,
+    access_key_id: &str,
+    session_token: &str,
+) -> Result<TempCredentials, Denial> {
+    let mut conn = match state.read_conn() {
+        Ok(conn) => conn,
+        Err(e) => {
+            return Err(Denial::new(
+                Some(access_key_id),
+                format!("No database connection: {e}"),
+                pool_error_response(&e),
+            ));
+        }
+    };
+    let max_retries = state.config.get_busy_retry_max_attempts();
+    let temp = match retry_on_busy(max_retries, &mut conn, |conn| {
+        lookup_temp_credentials(conn, access_key_id)
+    })
+    .await
+    {
+        Ok(Some(temp)) if temp.session_token.expose() == session_token => temp,
+        Ok(_) => {
+            let response = xml_error_response(
+                StatusCode::BAD_REQUEST,
+                "InvalidToken",
+                "The provided token is malformed or otherwise invalid.",
+            );
+            return Err(Denial::new(
+                Some(access_key_id),
+                "Unknown temporary credentials or session token",
+                response,
+            ));
+        }
+        Err(e) => {
+            error!("Failed to look up temporary credentials {access_key_id}: {e}");
+            return Err(Denial::new(
+                Some(access_key_id),
+                format!("Database error: {e}"),
+                database_error_response(&e),
+            ));
+        }
+    };
+    if temp.expires_at <= Utc::now() {
+        let response = xml_error_response(
+            StatusCode::BAD_REQUEST,
+            "ExpiredToken",
+            "The provided token has expired.",
+        );
+        return Err(Denial::new(
+            Some(access_key_id),
+            format!(
+                "Temporary credentials issued by {} expired at {}",
+                temp.issued_by.as_deref().unwrap_or("anonymous"),
+                temp.expires_at
+            ),
+            response,
+        ));
+    }
+    Ok(temp)
+}
+
+/// Why a request falls outside the scope of temporary credentials, None if it does not.
+/// They reach objects under their prefix, copies from there and renames within it
+/// included, and may list keys under it and check that the bucket exists; writing
+/// needs `rw` credentials.
+fn temp_scope_violation(
+    temp: &TempCredentials,
+    method: &Method,
+    path: &str,
+    headers: &HeaderMap,
+    query: &HashMap<String, String>,
+) -> Option<String> {
+    let scope = format!("{}/{}*", temp.bucket, temp.prefix);
+    let writes = !matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS);
+    if writes && temp.permission == BucketPermission::ReadOnly {
+        return Some(format!("Temporary credentials for {scope} are read-only"));
+    }
+
+    let path = path.trim_start_matches('/');
+    let (bucket, key) = match path.split_once('/') {
+        Some((bucket, key)) => (bucket, Some(key).filter(|k| !k.is_empty())),
+        None => (path, None),
+    };
+    let in_scope = match key {
+        Some(key) => percent_decode(key).is_some_and(|key| temp.covers(bucket, &key)),
+        None => {
+            let prefix = query.get("prefix").map(String::as_str).unwrap_or_default();
+            let lists = *method == Method::GET
+                && prefix.starts_with(&temp.prefix)
+                && query.keys().all(|name| {
+                    LISTING_PARAMETERS.contains(&name.as_str()) || name.starts_with("X-Amz-")
+                });
+            bucket == temp.bucket && (*method == Method::HEAD || lists)
+        }
+    };
+    if !in_scope {
+        return Some(format!(
+            "Temporary credentials are limited to {scope}; use a prefix under it"
+        ));
+    }
+
+    if let Some((source_bucket, source_key)) = headers
+        .get("x-amz-copy-source")
+        .and_then(|v| v.to_str().ok())
+        .and_then(parse_copy_source)
+        && !temp.covers(&source_bucket, &source_key)
+    {
+        return Some(format!(
+            "The copy source is outside {scope}, the scope of the temporary credentials"
+        ));
+    }
+
+    // A rename writes its target, in the bucket of the renamed object
+    match headers
+        .get("x-s3insqlite-rename-target")
+        .and_then(|v| v.to_str().ok())
+        .and_then(percent_decode)
+    {
+        Some(target) if !temp.covers(bucket, &target) => Some(format!(
+            "The rename target is outside {scope}, the scope of the temporary credentials"
+        )),
+        _ => None,
+    }
+}
+
+/// Whether the bucket permissions of an access key allow a request: reading a bucket
+/// needs read access to it, writing (PUT, POST, DELETE) read and write access, and a
+/// copy reads its source bucket. At the service root, keys limited to some buckets
+/// may only list buckets, not use the admin operations.
+fn is_authorized(
+    state: &AppState,
+    access_key_id: &str,
+    method: &Method,
+    path: &str,
+    headers: &HeaderMap,
+    query: &HashMap<String, String>,
+) -> bool {
+    let bucket = path
+        .trim_start_matches('/')
+        .split('/')
+        .next()
+        .unwrap_or_default();
+    let credentials = state.credentials();
+    if bucket.is_empty() {
+        return !credentials.is_restricted(access_key_id)
+            || (method == Method::GET
+                && !query.contains_key("metrics")
+                && !query.contains_key("tables")
+                && !query.contains_key("audit"));
+    }
+
+    let writes = !matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS);
+    let allowed = match credentials.permission(access_key_id, bucket) {
+        Some(BucketPermission::ReadWrite) => true,
+        Some(BucketPermission::ReadOnly) => !writes,
+        None => false,
+    };
+    if !allowed {
+        return false;
+    }
+
+    match headers
+        .get("x-amz-copy-source")
+        .and_then(|v| v.to_str().ok())
+        .and_then(parse_copy_source)
+    {
+        Some((source_bucket, _)) => credentials
+            .permission(access_key_id, &source_bucket)
+            .is_some(),
+        None => true,
+    }
+}
+
+/// Whether an unsigned request only reads a bucket listed in `public_read`,
+/// or an object the bucket policy makes public
+async fn is_public_read(
+    state: &AppState,
+    method: &Method,
+    path: &str,
+    query: &HashMap<String, String>,
+) -> bool {
+    if method != Method::GET && method != Method::HEAD {
+        return false;
+    }
+    let path = path.trim_start_matches('/');
+    let (bucket, key) = match path.split_once('/') {
+        Some((bucket, key)) => (bucket, Some(key).filter(|k| !k.is_empty())),
+        None => (path, None),
+    };
+    if bucket.is_empty() {
+        return false;
+    }
+    if PRIVATE_SUBRESOURCES.iter().any(|s| query.contains_key(*s)) {
+        return false;
+    }
+    if state.config.is_public_read(bucket) {
+        return true;
+    }
+
+    let Some(key) = key.and_then(percent_decode) else {
+        return false;
+    };
+    if !state.buckets.read().unwrap().contains(bucket) {
+        return false;
+    }
+    let Ok(mut conn) = state.read_conn() else {
+        return false;
+    };
+    let max_retries = state.config.get_busy_retry_max_attempts();
+    match retry_on_busy(max_retries, &mut conn, |conn| {
+        get_bucket_policy(conn, bucket)
+    })
+    .await
+    {
+        Ok(Some(policy)) => {
+            parse_bucket_policy(&policy, bucket).is_ok_and(|policy| policy.allows_public_read(&key))
+        }
+        Ok(None) => false,
+        Err(e) => {
+            error!("Failed to read policy of bucket '{bucket}': {e}");
+            false
+        }
+    }
+}
+
+/// S3 `RequestTimeTooSkewed` error, with the request and server times so that
+/// clients can correct their clock
+fn clock_skew_response(
+    request_time: &str,
+    server_time: DateTime<Utc>,
+    max_skew: chrono::Duration,
+) -> Response {
+    let body = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<Error><Code>RequestTimeTooSkewed</Code><Message>The difference between the request time and the current time is too large.</Message><RequestTime>{}</RequestTime><ServerTime>{}</ServerTime><MaxAllowedSkewMilliseconds>{}</MaxAllowedSkewMilliseconds><RequestId>{}</RequestId></Error>"#,
+        html_escape(request_time),
+        server_time.format("%Y-%m-%dT%H:%M:%SZ"),
+        max_skew.num_milliseconds(),
+        current_request_id().unwrap_or_default()
+    );
+    let mut headers = HeaderMap::new();
+    headers.insert("Content-Type", "application/xml".parse().unwrap());
+    headers.insert("Content-Length", body.len().to_string().parse().unwrap());
+
+    (StatusCode::FORBIDDEN, headers, body).into_response()
+}
+
+/// S3 `SignatureDoesNotMatch` error, with the string to sign and canonical request
+/// computed by the server so that clients can find where they differ
+fn signature_mismatch_response(
+    access_key_id: &str,
+    string_to_sign: &str,
+    signature: &str,
+    canonical_request: &str,
+) -> Response {
+    let body = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<Error><Code>SignatureDoesNotMatch</Code><Message>The request signature we calculated does not match the signature you provided. Check your key and signing method.</Message><AWSAccessKeyId>{}</AWSAccessKeyId><StringToSign>{}</StringToSign><SignatureProvided>{}</SignatureProvided><CanonicalRequest>{}</CanonicalRequest><RequestId>{}</RequestId></Error>"#,
+        html_escape(access_key_id),
+        html_escape(string_to_sign),
+        html_escape(signature),
+        html_escape(canonical_request),
+        current_request_id().unwrap_or_default()
+    );
+    let mut headers = HeaderMap::new();
+    headers.insert("Content-Type", "application/xml".parse().unwrap());
+    headers.insert("Content-Length", body.len().to_string().parse().unwrap());
+
+    (StatusCode::FORBIDDEN, headers, body).into_response()
+}
@@ -0,0 +1,158 @@
+use axum::{
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use log::{error, info};
+
+use crate::models::{AppState, Principal};
+use crate::utils::{
+    bucket::query_bucket_objects_delimited, database_error_response, html_escape,
+    percent_encode_path, pool_error_response, retry_on_busy,
+};
+
+/// Whether a request should get the HTML browse UI instead of S3 XML.
+/// Only requests whose `Accept` header explicitly ranks `text/html` at least as high
+/// as any XML type qualify, so SDK clients sending `*/*` or nothing are unaffected.
+pub fn wants_browser_ui(state: &AppState, headers: &HeaderMap) -> bool {
+    state.config.is_browser_ui_enabled() && prefers_html(headers)
+}
+
+/// Parse the `Accept` header and compare the quality of `text/html` with XML types
+fn prefers_html(headers: &HeaderMap) -> bool {
+    let Some(accept) = headers.get("Accept").and_then(|v| v.to_str().ok()) else {
+        return false;
+    };
+
+    let mut html_quality = None;
+    let mut xml_quality: f32 = 0.0;
+    for entry in accept.split(',') {
+        let mut parts = entry.split(';').map(|p| p.trim());
+        let media_type = parts.next().unwrap_or_default().to_ascii_lowercase();
+        let quality = parts
+            .find_map(|p| p.strip_prefix("q="))
+            .and_then(|q| q.parse::<f32>().ok())
+            .unwrap_or(1.0);
+        match media_type.as_str() {
+            "text/html" => html_quality = Some(quality),
+            "application/xml" | "text/xml" => xml_quality = xml_quality.max(quality),
+            _ => {}
+        }
+    }
+
+    html_quality.is_some_and(|q| q > 0.0 && q >= xml_quality)
+}
+
+/// Render the bucket list: GET / from a browser
+pub async fn browse_buckets(state: &AppState, principal: Option<&Principal>) -> Response {
+    let buckets = state.visible_bucket_names(principal);
+
+    info!("Browse UI: listing {} buckets", buckets.len());
+
+    let mut body = String::from("<ul>");
+    for bucket in &buckets {
+        body.push_str(&format!(
+            r#"<li><a href="/{}/">{}/</a></li>"#,
+            percent_encode_path(bucket),
+            html_escape(bucket)
+        ));
+    }
+    body.push_str("</ul>");
+
+    html_response(StatusCode::OK, &html_page("Buckets", "", &body))
+}
+
+/// Render the objects and "directories" directly under a prefix, using `/` as delimiter:
+/// GET /{bucket}/ or GET /{bucket}/{prefix}/ from a browser
+pub async fn browse_bucket(state: &AppState, bucket: &str, prefix: &str) -> Response {
+    info!("Browse UI: listing bucket '{bucket}' under '{prefix}'");
+
+    let mut conn = match state.read_conn() {
+        Ok(c) => c,
+        Err(e) => return pool_error_response(&e),
+    };
+
+    let max_retries = state.config.get_busy_retry_max_attempts();
+    let listing = retry_on_busy(max_retries, &mut conn, |conn| {
+        query_bucket_objects_delimited(
+            conn,
+            bucket,
+            state.config.get_table_prefix(),
+            prefix,
+            '/',
+            None,
+            usize::MAX,
+        )
+    })
+    .await;
+    let (objects, common_prefixes) = match listing {
+        Ok(rows) => rows,
+        Err(e) => {
+            error!("Failed to list bucket '{bucket}': {e}");
+            return database_error_response(&e);
+        }
+    };
+
+    // Breadcrumbs: one link per path segment of the prefix
+    let mut breadcrumbs = format!(
+        r#"<a href="/">Buckets</a> / <a href="/{}/">{}</a>"#,
+        percent_encode_path(bucket),
+        html_escape(bucket)
+    );
+    let mut path = String::new();
+    for segment in prefix.split_terminator('/') {
+        path.push_str(segment);
+        path.push('/');
+        breadcrumbs.push_str(&format!(
+            r#" / <a href="/{}/{}">{}</a>"#,
+            percent_encode_path(bucket),
+            percent_encode_path(&path),
+            html_escape(segment)
+        ));
+    }
+
+    let mut body = String::from(
+        "<table><thead><tr><th>Name</th><th>Size</th><th>Last modified</th></tr></thead><tbody>",
+    );
+    for common_prefix in &common_prefixes {
+        body.push_str(&format!(
+            r#"<tr><td><a href="/{}/{}">{}</a></td><td></td><td></td></tr>"#,
+            percent_encode_path(bucket),
+            percent_encode_path(common_prefix),
+            html_escape(&common_prefix[prefix.len()..])
+        ));
+    }
+    for (key, size, last_modified, _, _) in &objects {
+        body.push_str(&format!(
+            r#"<tr><td><a href="/{}/{}">{}</a></td><td>{}</td><td>{}</td></tr>"#,
+            percent_encode_path(bucket),
+            percent_encode_path(key),
+            html_escape(&key[prefix.len()..]),
+            size,
+            last_modified.format("%Y-%m-%d %H:%M:%S UTC")
+        ));
+    }
+    body.push_str("</tbody></table>");
+
+    let title = format!("{bucket}/{prefix}");
+    html_response(StatusCode::OK, &html_page(&title, &breadcrumbs, &body))
+}
+
+/// Wrap a page body in a minimal HTML document
+fn html_page(title: &str, breadcrumbs: &str, body: &str) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html><head><meta charset="utf-8"><title>{title}</title>
+<style>body{{font-family:sans-serif;margin:2em}}td,th{{padding:0.2em 1em;text-align:left}}</style>
+</head><body><h1>{title}</h1><p>{breadcrumbs}</p>{body}</body></html>"#,
+        title = html_escape(title),
+    )
+}
+
+/// Generate HTTP response with an HTML body
+fn html_response(status: StatusCode, body: &str) -> Response {
+    let mut headers = HeaderMap::new();
+    headers.insert("Content-Type", "text/html; charset=utf-8".parse().unwrap());
+    headers.insert("Content-Length", body.len().to_string().parse().unwrap());
+
+    (status, headers, body.to_string()).into_response()
+}
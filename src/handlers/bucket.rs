@@ -1,40 +1,171 @@
 use axum::{
+    body::{Body, Bytes},
     extract::{Path, Query, State},
     http::{HeaderMap, StatusCode},
     response::{IntoResponse, Response},
 };
-use log::{error, info};
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use log::{error, info, warn};
 use std::collections::HashMap;
 use std::sync::Arc;
+use tokio::sync::mpsc;
 
-use crate::models::{AppState, ListBucketResult};
-use crate::utils::{bucket::query_bucket_objects, validate_bucket, xml_error_response};
+use crate::handlers::acl::{get_bucket_acl, put_bucket_acl};
+use crate::handlers::browse::{browse_bucket, wants_browser_ui};
+use crate::handlers::cors::{delete_bucket_cors, get_bucket_cors, put_bucket_cors};
+use crate::handlers::import::import_archive;
+use crate::handlers::logging::{get_bucket_logging, put_bucket_logging};
+use crate::handlers::multipart::list_multipart_uploads;
+use crate::handlers::object::get_object_by_etag;
+use crate::handlers::policy::{
+    delete_bucket_policy, get_bucket_policy, get_bucket_policy_status, put_bucket_policy,
+};
+use crate::models::{AppState, BucketStats, ListBucketResult, Principal, S3Object};
+use crate::utils::{
+    HandlerEncoded, accepts_gzip,
+    bucket::{
+        QueryBucketResult, drop_bucket_table, for_each_bucket_object, is_valid_new_bucket_name,
+        query_bucket_objects, query_bucket_objects_delimited, query_bucket_stats,
+    },
+    bucket_meta::{
+        bucket_creation_dates, get_versioning_status, parse_versioning_status,
+        put_versioning_status,
+    },
+    create_bucket_indexes, database_error_response, ensure_bucket_table,
+    error_format::prefers_json,
+    gzip,
+    lifecycle::{
+        LifecycleError, delete_lifecycle_configuration, get_lifecycle_configuration,
+        parse_expiration_rules, put_lifecycle_configuration,
+    },
+    not_implemented_response, pool_error_response, retry_on_busy, run_blocking,
+    sanitize_bucket_name, validate_bucket, xml_error_response, xml_response,
+};
+
+/// Bucket subresource query keys. A GET carrying any of these is not a listing.
+const BUCKET_SUBRESOURCES: &[&str] = &[
+    "accelerate",
+    "acl",
+    "analytics",
+    "cors",
+    "encryption",
+    "intelligent-tiering",
+    "inventory",
+    "lifecycle",
+    "location",
+    "logging",
+    "metadataTable",
+    "metrics",
+    "notification",
+    "object-lock",
+    "ownershipControls",
+    "policy",
+    "policyStatus",
+    "publicAccessBlock",
+    "replication",
+    "requestPayment",
+    "stats",
+    "tagging",
+    "uploads",
+    "versioning",
+    "versions",
+    "website",
+];
+
+/// Number of pending chunks buffered between the database reader and the client
+const STREAM_CHANNEL_CAPACITY: usize = 4;
+/// Approximate size of each chunk of a streamed listing
+const STREAM_CHUNK_SIZE: usize = 64 * 1024;
 
 /// S3 ListBuckets API: GET /
+/// Supports `prefix`, `bucket-region`, `max-buckets` and `continuation-token`
 pub async fn list_buckets(
     State(state): State<Arc<AppState>>,
+    principal: Option<Principal>,
     query: Query<HashMap<String, String>>,
 ) -> Response {
+    let prefix = query.get("prefix");
+    let region = state.config.get_region();
+    // Every bucket lives in the configured region
+    let region_matches = query.get("bucket-region").is_none_or(|r| r == region);
+    let continuation_token = query.get("continuation-token");
+    let max_buckets = match query.get("max-buckets") {
+        Some(v) => match v.parse::<usize>() {
+            Ok(n) if (1..=10000).contains(&n) => n,
+            _ => {
+                return xml_error_response(
+                    StatusCode::BAD_REQUEST,
+                    "InvalidArgument",
+                    &format!("Invalid max-buckets value: {v}"),
+                );
+            }
+        },
+        None => usize::MAX,
+    };
+
+    // Iterate a sorted snapshot so that pagination is stable
+    let all_buckets = state.visible_bucket_names(principal.as_ref());
+    let mut buckets: Vec<&String> = all_buckets
+        .iter()
+        .filter(|_| region_matches)
+        .filter(|bucket| prefix.is_none_or(|p| bucket.starts_with(p.as_str())))
+        .filter(|bucket| continuation_token.is_none_or(|t| bucket.as_str() > t.as_str()))
+        .collect();
+
+    let is_truncated = buckets.len() > max_buckets;
+    buckets.truncate(max_buckets);
+    let next_token = if is_truncated {
+        buckets.last().map(|b| b.to_string())
+    } else {
+        None
+    };
+
     info!(
-        "ListBuckets called, returning {} buckets",
-        state.buckets.len()
+        "ListBuckets called, returning {} of {} buckets",
+        buckets.len(),
+        all_buckets.len()
     );
 
-    let prefix = query.get("prefix");
+    let mut conn = match state.read_conn() {
+        Ok(c) => c,
+        Err(e) => return pool_error_response(&e),
+    };
+    let max_retries = state.config.get_busy_retry_max_attempts();
+    let creation_dates =
+        match retry_on_busy(max_retries, &mut conn, |conn| bucket_creation_dates(conn)).await {
+            Ok(dates) => dates,
+            Err(e) => {
+                error!("Failed to read bucket creation dates: {e}");
+                return database_error_response(&e);
+            }
+        };
 
     let mut xml = String::from(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
-    xml.push_str("\n<ListAllMyBucketsResult>\n   <Buckets>");
+    xml.push_str(
+        "\n<ListAllMyBucketsResult xmlns=\"http://s3.amazonaws.com/doc/2006-03-01/\">\n   <Buckets>",
+    );
 
-    for bucket in state.buckets.iter() {
-        if let Some(prefix) = prefix
-            && !bucket.starts_with(prefix)
-        {
-            continue; // Skip buckets that don't match the prefix
-        }
-        xml.push_str(&format!("\n<Bucket>\n<Name>{bucket}</Name>\n</Bucket>"));
+    for bucket in buckets {
+        let created = creation_dates
+            .get(bucket.as_str())
+            .and_then(|secs| DateTime::<Utc>::from_timestamp(*secs, 0))
+            .unwrap_or(state.started_at)
+            .to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+        xml.push_str(&format!(
+            "\n<Bucket>\n<Name>{bucket}</Name>\n<CreationDate>{created}</CreationDate>\n<BucketRegion>{region}</BucketRegion>\n</Bucket>"
+        ));
     }
 
     xml.push_str("\n</Buckets>");
+    xml.push_str(&format!(
+        "\n<Owner><ID>{}</ID><DisplayName>{}</DisplayName></Owner>",
+        state.config.get_owner_id(),
+        state.config.get_owner_display_name()
+    ));
+    if let Some(token) = next_token {
+        xml.push_str(&format!("\n<ContinuationToken>{token}</ContinuationToken>"));
+    }
     if let Some(prefix) = prefix {
         xml.push_str(&format!("\n<Prefix>{prefix}</Prefix>"));
     }
@@ -48,6 +179,7 @@ pub async fn list_buckets(
 }
 
 /// S3 Bucket Versioning endpoint
+/// Reports the stored status, `Suspended` for buckets that were never configured
 pub async fn get_bucket_versioning(
     State(state): State<Arc<AppState>>,
     Path(bucket): Path<String>,
@@ -58,11 +190,30 @@ pub async fn get_bucket_versioning(
     };
 
     info!("GetBucketVersioning for bucket '{bucket}'");
+    let mut conn = match state.read_conn() {
+        Ok(c) => c,
+        Err(e) => return pool_error_response(&e),
+    };
 
-    let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+    let max_retries = state.config.get_busy_retry_max_attempts();
+    let status = match retry_on_busy(max_retries, &mut conn, |conn| {
+        get_versioning_status(conn, &bucket)
+    })
+    .await
+    {
+        Ok(status) => status.unwrap_or_else(|| "Suspended".to_string()),
+        Err(e) => {
+            error!("Failed to read versioning status of bucket '{bucket}': {e}");
+            return database_error_response(&e);
+        }
+    };
+
+    let xml = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
         <VersioningConfiguration>
-            <Status>Suspended</Status>
-        </VersioningConfiguration>"#;
+            <Status>{status}</Status>
+        </VersioningConfiguration>"#
+    );
 
     let mut headers = HeaderMap::new();
     headers.insert("Content-Type", "application/xml".parse().unwrap());
@@ -71,18 +222,779 @@ pub async fn get_bucket_versioning(
     (StatusCode::OK, headers, xml).into_response()
 }
 
+/// PutBucketVersioning: PUT /{bucket}?versioning
+/// Only the status is stored: objects are not versioned yet, so writes always
+/// replace the current object and suspending versioning has nothing to delete.
+async fn put_bucket_versioning(state: Arc<AppState>, bucket: String, body: Bytes) -> Response {
+    let bucket = match validate_bucket(&bucket, &state.buckets) {
+        Ok(b) => b,
+        Err(resp) => return *resp,
+    };
+
+    let status = match std::str::from_utf8(&body)
+        .ok()
+        .and_then(parse_versioning_status)
+    {
+        Some(Some(status @ ("Enabled" | "Suspended"))) => status,
+        Some(_) => {
+            return xml_error_response(
+                StatusCode::BAD_REQUEST,
+                "IllegalVersioningConfigurationException",
+                "The versioning configuration specified in the request is invalid",
+            );
+        }
+        None => {
+            return xml_error_response(
+                StatusCode::BAD_REQUEST,
+                "MalformedXML",
+                "The XML you provided was not well-formed or did not validate against our published schema",
+            );
+        }
+    };
+
+    info!("PutBucketVersioning '{status}' for bucket '{bucket}'");
+    let mut conn = match state.write_conn() {
+        Ok(c) => c,
+        Err(e) => return pool_error_response(&e),
+    };
+
+    let max_retries = state.config.get_busy_retry_max_attempts();
+    match retry_on_busy(max_retries, &mut conn, |conn| {
+        put_versioning_status(conn, &bucket, status)
+    })
+    .await
+    {
+        Ok(()) => StatusCode::OK.into_response(),
+        Err(e) => {
+            error!("Failed to store versioning status of bucket '{bucket}': {e}");
+            database_error_response(&e)
+        }
+    }
+}
+
+/// HeadBucket: HEAD /{bucket}
+/// Reports whether the bucket exists, and its region in `x-amz-bucket-region`
+pub async fn head_bucket(
+    State(state): State<Arc<AppState>>,
+    Path(bucket): Path<String>,
+) -> Response {
+    let bucket = match validate_bucket(&bucket, &state.buckets) {
+        Ok(b) => b,
+        Err(resp) => return *resp,
+    };
+
+    info!("HeadBucket for bucket '{bucket}'");
+    let stats = match read_bucket_stats(&state, &bucket).await {
+        Ok(stats) => stats,
+        Err(resp) => return *resp,
+    };
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        "x-amz-bucket-region",
+        state.config.get_region().parse().unwrap(),
+    );
+    headers.insert("x-s3insqlite-object-count", stats.object_count.into());
+    headers.insert("x-s3insqlite-bytes-used", stats.size_bytes.into());
+    (StatusCode::OK, headers).into_response()
+}
+
+/// GetBucketLocation: GET /{bucket}?location
+/// As in S3, `us-east-1` is reported as an empty LocationConstraint.
+async fn get_bucket_location(state: Arc<AppState>, bucket: String) -> Response {
+    let bucket = match validate_bucket(&bucket, &state.buckets) {
+        Ok(b) => b,
+        Err(resp) => return *resp,
+    };
+
+    info!("GetBucketLocation for bucket '{bucket}'");
+
+    let constraint = match state.config.get_region() {
+        "us-east-1" => "",
+        region => region,
+    };
+    xml_response(
+        StatusCode::OK,
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<LocationConstraint xmlns="http://s3.amazonaws.com/doc/2006-03-01/">{constraint}</LocationConstraint>"#
+        ),
+    )
+}
+
 /// Route bucket operations based on query parameters
 pub async fn get_bucket_dispatch(
     State(state): State<Arc<AppState>>,
     Path(bucket): Path<String>,
     query: Query<HashMap<String, String>>,
+    headers: HeaderMap,
+) -> Response {
+    if query.is_empty() && wants_browser_ui(&state, &headers) {
+        let bucket = match validate_bucket(&bucket, &state.buckets) {
+            Ok(b) => b,
+            Err(resp) => return *resp,
+        };
+        return browse_bucket(&state, &bucket, "").await;
+    }
+
+    let subresource = BUCKET_SUBRESOURCES
+        .iter()
+        .find(|name| query.contains_key(**name))
+        .copied();
+
+    match subresource {
+        Some("versioning") => get_bucket_versioning(State(state), Path(bucket)).await,
+        Some("stats") => get_bucket_stats(state, bucket).await,
+        Some("lifecycle") => get_bucket_lifecycle(state, bucket).await,
+        Some("uploads") => list_multipart_uploads(state, bucket, query.0).await,
+        Some("location") => get_bucket_location(state, bucket).await,
+        Some("acl") => get_bucket_acl(state, bucket).await,
+        Some("cors") => get_bucket_cors(state, bucket).await,
+        Some("logging") => get_bucket_logging(state, bucket).await,
+        Some("policy") => get_bucket_policy(state, bucket).await,
+        Some("policyStatus") => get_bucket_policy_status(state, bucket).await,
+        Some(name @ ("accelerate" | "requestPayment" | "ownershipControls")) => {
+            get_bucket_default_configuration(state, bucket, name).await
+        }
+        Some(name) => {
+            info!("Unsupported bucket subresource '{name}' requested on bucket '{bucket}'");
+            not_implemented_response()
+        }
+        None if query.contains_key("etag") => {
+            let etag = query.get("etag").cloned().unwrap_or_default();
+            get_object_by_etag(state, bucket, etag, headers).await
+        }
+        None if query.get("list-type").map(|v| v == "2").unwrap_or(false) => {
+            let json = wants_json_listing(&query, &headers);
+            list_objects_v2(state, bucket, query.0, json, &headers).await
+        }
+        None => {
+            let json = wants_json_listing(&query, &headers);
+            list_objects(state, bucket, query.0, json, &headers).await
+        }
+    }
+}
+
+/// Route PUT requests on a bucket based on query parameters
+pub async fn put_bucket_dispatch(
+    State(state): State<Arc<AppState>>,
+    Path(bucket): Path<String>,
+    query: Query<HashMap<String, String>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    if query.is_empty() {
+        create_bucket(state, bucket).await
+    } else if query.contains_key("lifecycle") {
+        put_bucket_lifecycle(state, bucket, body).await
+    } else if query.contains_key("acl") {
+        put_bucket_acl(state, bucket, headers, body).await
+    } else if query.contains_key("versioning") {
+        put_bucket_versioning(state, bucket, body).await
+    } else if query.contains_key("cors") {
+        put_bucket_cors(state, bucket, body).await
+    } else if query.contains_key("policy") {
+        put_bucket_policy(state, bucket, body).await
+    } else if query.contains_key("logging") {
+        put_bucket_logging(state, bucket, body).await
+    } else {
+        not_implemented_response()
+    }
+}
+
+/// Route POST requests on a bucket based on query parameters
+pub async fn post_bucket_dispatch(
+    State(state): State<Arc<AppState>>,
+    Path(bucket): Path<String>,
+    query: Query<HashMap<String, String>>,
+    body: Bytes,
+) -> Response {
+    if query.contains_key("import") {
+        import_archive(state, bucket, body).await
+    } else {
+        not_implemented_response()
+    }
+}
+
+/// CreateBucket: PUT /{bucket}
+/// Only available when `allow_bucket_creation` is set, and refused once `max_buckets`
+/// buckets exist. The bucket table and indexes are created and the bucket is added to
+/// the shared bucket set.
+async fn create_bucket(state: Arc<AppState>, bucket: String) -> Response {
+    if !state.config.is_bucket_creation_allowed() {
+        warn!("Bucket creation is disabled, refusing to create '{bucket}'");
+        return xml_error_response(
+            StatusCode::FORBIDDEN,
+            "AccessDenied",
+            "Bucket creation is disabled",
+        );
+    }
+    if state.buckets.read().unwrap().contains(&bucket) {
+        return xml_error_response(
+            StatusCode::CONFLICT,
+            "BucketAlreadyOwnedByYou",
+            &format!("Bucket already exists: {bucket}"),
+        );
+    }
+    if let Some(max_buckets) = state.config.get_max_buckets()
+        && state.buckets.read().unwrap().len() >= max_buckets
+    {
+        warn!("Bucket limit of {max_buckets} reached, refusing to create '{bucket}'");
+        return xml_error_response(
+            StatusCode::BAD_REQUEST,
+            "TooManyBuckets",
+            &format!("You have attempted to create more buckets than allowed ({max_buckets})"),
+        );
+    }
+    let table_name = match sanitize_bucket_name(&bucket, state.config.get_table_prefix()) {
+        Some(table_name) if is_valid_new_bucket_name(&bucket) => table_name,
+        _ => {
+            return xml_error_response(
+                StatusCode::BAD_REQUEST,
+                "InvalidBucketName",
+                &format!("The specified bucket is not valid: {bucket}"),
+            );
+        }
+    };
+    // A configured bucket spelled with underscores would share the table
+    let table_taken = state.bucket_names().iter().any(|other| {
+        sanitize_bucket_name(other, state.config.get_table_prefix()).as_ref() == Some(&table_name)
+    });
+    if table_taken {
+        return xml_error_response(
+            StatusCode::CONFLICT,
+            "BucketAlreadyExists",
+            &format!("The requested bucket name is not available: {bucket}"),
+        );
+    }
+
+    let mut conn = match state.write_conn() {
+        Ok(c) => c,
+        Err(e) => return pool_error_response(&e),
+    };
+
+    let max_retries = state.config.get_busy_retry_max_attempts();
+    let result = retry_on_busy(max_retries, &mut conn, |conn| {
+        ensure_bucket_table(conn, &bucket, state.config.get_table_prefix())?;
+        create_bucket_indexes(conn, &table_name)
+    })
+    .await;
+    if let Err(e) = result {
+        error!("Failed to create bucket '{bucket}': {e}");
+        return database_error_response(&e);
+    }
+
+    // Creating the table is idempotent, so a concurrent request for the same name
+    // only loses the race for the bucket set
+    if !state.buckets.write().unwrap().insert(bucket.clone()) {
+        return xml_error_response(
+            StatusCode::CONFLICT,
+            "BucketAlreadyOwnedByYou",
+            &format!("Bucket already exists: {bucket}"),
+        );
+    }
+    info!("Created bucket '{bucket}'");
+
+    let mut headers = HeaderMap::new();
+    headers.insert("Location", format!("/{bucket}").parse().unwrap());
+    (StatusCode::OK, headers).into_response()
+}
+
+/// Route DELETE requests on a bucket based on query parameters
+pub async fn delete_bucket_dispatch(
+    State(state): State<Arc<AppState>>,
+    Path(bucket): Path<String>,
+    query: Query<HashMap<String, String>>,
+) -> Response {
+    if query.is_empty() {
+        delete_bucket(state, bucket).await
+    } else if query.contains_key("lifecycle") {
+        delete_bucket_lifecycle(state, bucket).await
+    } else if query.contains_key("cors") {
+        delete_bucket_cors(state, bucket).await
+    } else if query.contains_key("policy") {
+        delete_bucket_policy(state, bucket).await
+    } else {
+        not_implemented_response()
+    }
+}
+
+/// DeleteBucket: DELETE /{bucket}
+/// Only available when `allow_bucket_creation` is set. The bucket must have no objects
+/// and no pending multipart uploads.
+async fn delete_bucket(state: Arc<AppState>, bucket: String) -> Response {
+    if !state.config.is_bucket_creation_allowed() {
+        warn!("Bucket creation is disabled, refusing to delete '{bucket}'");
+        return xml_error_response(
+            StatusCode::FORBIDDEN,
+            "AccessDenied",
+            "Bucket deletion is disabled",
+        );
+    }
+    // Withdraw the bucket first, so new requests stop validating against it
+    if !state.buckets.write().unwrap().remove(&bucket) {
+        return xml_error_response(
+            StatusCode::NOT_FOUND,
+            "NoSuchBucket",
+            &format!("The specified bucket does not exist: {bucket}"),
+        );
+    }
+    let restore = |state: &AppState| {
+        state.buckets.write().unwrap().insert(bucket.clone());
+    };
+
+    let mut conn = match state.write_conn() {
+        Ok(c) => c,
+        Err(e) => {
+            restore(&state);
+            return pool_error_response(&e);
+        }
+    };
+
+    let max_retries = state.config.get_busy_retry_max_attempts();
+    let result = retry_on_busy(max_retries, &mut conn, |conn| {
+        drop_bucket_table(conn, &bucket, state.config.get_table_prefix())
+    })
+    .await;
+    state.invalidate_cached_bucket(&bucket);
+    match result {
+        Ok(true) => {
+            state.logging_targets.write().unwrap().remove(&bucket);
+            info!("Deleted bucket '{bucket}'");
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Ok(false) => {
+            restore(&state);
+            xml_error_response(
+                StatusCode::CONFLICT,
+                "BucketNotEmpty",
+                "The bucket you tried to delete is not empty",
+            )
+        }
+        Err(e) => {
+            restore(&state);
+            error!("Failed to delete bucket '{bucket}': {e}");
+            database_error_response(&e)
+        }
+    }
+}
+
+/// GetBucketLifecycleConfiguration: GET /{bucket}?lifecycle
+async fn get_bucket_lifecycle(state: Arc<AppState>, bucket: String) -> Response {
+    let bucket = match validate_bucket(&bucket, &state.buckets) {
+        Ok(b) => b,
+        Err(resp) => return *resp,
+    };
+
+    info!("GetBucketLifecycleConfiguration for bucket '{bucket}'");
+    let conn = match state.read_conn() {
+        Ok(c) => c,
+        Err(e) => return pool_error_response(&e),
+    };
+
+    match run_blocking(|| get_lifecycle_configuration(&conn, &bucket)) {
+        Ok(Some(configuration)) => xml_response(StatusCode::OK, configuration),
+        Ok(None) => xml_error_response(
+            StatusCode::NOT_FOUND,
+            "NoSuchLifecycleConfiguration",
+            &format!("The lifecycle configuration does not exist: {bucket}"),
+        ),
+        Err(e) => {
+            error!("Failed to read lifecycle configuration of bucket '{bucket}': {e}");
+            xml_error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "InternalError",
+                &e.to_string(),
+            )
+        }
+    }
+}
+
+/// PutBucketLifecycleConfiguration: PUT /{bucket}?lifecycle
+/// The document is stored as sent; enabled `Expiration` rules with `<Days>` are
+/// enforced by the background lifecycle sweep. Rules other than a prefix with an
+/// expiration after days, e.g. transitions, tag or size filters and dates, get 501.
+async fn put_bucket_lifecycle(state: Arc<AppState>, bucket: String, body: Bytes) -> Response {
+    let bucket = match validate_bucket(&bucket, &state.buckets) {
+        Ok(b) => b,
+        Err(resp) => return *resp,
+    };
+
+    let configuration = match std::str::from_utf8(&body)
+        .map_err(|_| LifecycleError::Malformed)
+        .and_then(|xml| parse_expiration_rules(xml).map(|_| xml))
+    {
+        Ok(xml) => xml,
+        Err(LifecycleError::Unsupported(feature)) => {
+            warn!("Rejected lifecycle configuration of bucket '{bucket}' using {feature}");
+            return xml_error_response(
+                StatusCode::NOT_IMPLEMENTED,
+                "NotImplemented",
+                &format!("{feature} in lifecycle rules are not supported"),
+            );
+        }
+        Err(LifecycleError::Malformed) => {
+            return xml_error_response(
+                StatusCode::BAD_REQUEST,
+                "MalformedXML",
+                "The XML you provided was not well-formed or did not validate against our published schema",
+            );
+        }
+    };
+
+    info!("PutBucketLifecycleConfiguration for bucket '{bucket}'");
+    let conn = match state.write_conn() {
+        Ok(c) => c,
+        Err(e) => return pool_error_response(&e),
+    };
+
+    match run_blocking(|| put_lifecycle_configuration(&conn, &bucket, configuration)) {
+        Ok(()) => StatusCode::OK.into_response(),
+        Err(e) => {
+            error!("Failed to store lifecycle configuration of bucket '{bucket}': {e}");
+            xml_error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "InternalError",
+                &e.to_string(),
+            )
+        }
+    }
+}
+
+/// DeleteBucketLifecycle: DELETE /{bucket}?lifecycle
+async fn delete_bucket_lifecycle(state: Arc<AppState>, bucket: String) -> Response {
+    let bucket = match validate_bucket(&bucket, &state.buckets) {
+        Ok(b) => b,
+        Err(resp) => return *resp,
+    };
+
+    info!("DeleteBucketLifecycle for bucket '{bucket}'");
+    let conn = match state.write_conn() {
+        Ok(c) => c,
+        Err(e) => return pool_error_response(&e),
+    };
+
+    match run_blocking(|| delete_lifecycle_configuration(&conn, &bucket)) {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => {
+            error!("Failed to delete lifecycle configuration of bucket '{bucket}': {e}");
+            xml_error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "InternalError",
+                &e.to_string(),
+            )
+        }
+    }
+}
+
+/// Canonical "disabled" documents for bucket subresources that clients probe
+/// but this server has no configurable state for
+fn default_configuration_xml(subresource: &str) -> Option<&'static str> {
+    match subresource {
+        "accelerate" => {
+            Some(r#"<AccelerateConfiguration xmlns="http://s3.amazonaws.com/doc/2006-03-01/"/>"#)
+        }
+        "requestPayment" => Some(
+            r#"<RequestPaymentConfiguration xmlns="http://s3.amazonaws.com/doc/2006-03-01/"><Payer>BucketOwner</Payer></RequestPaymentConfiguration>"#,
+        ),
+        "ownershipControls" => Some(
+            r#"<OwnershipControls xmlns="http://s3.amazonaws.com/doc/2006-03-01/"><Rule><ObjectOwnership>BucketOwnerEnforced</ObjectOwnership></Rule></OwnershipControls>"#,
+        ),
+        _ => None,
+    }
+}
+
+/// GetBucketAccelerateConfiguration, GetBucketRequestPayment and GetBucketOwnershipControls: GET /{bucket}?<subresource>
+async fn get_bucket_default_configuration(
+    state: Arc<AppState>,
+    bucket: String,
+    subresource: &str,
 ) -> Response {
-    if query.contains_key("versioning") {
-        get_bucket_versioning(State(state), Path(bucket)).await
-    } else if query.get("list-type").map(|v| v == "2").unwrap_or(false) {
-        list_objects_v2(state, bucket, query.0).await
+    let bucket = match validate_bucket(&bucket, &state.buckets) {
+        Ok(b) => b,
+        Err(resp) => return *resp,
+    };
+
+    info!("Get '{subresource}' configuration for bucket '{bucket}'");
+
+    match default_configuration_xml(subresource) {
+        Some(document) => xml_response(
+            StatusCode::OK,
+            format!(r#"<?xml version="1.0" encoding="UTF-8"?>{document}"#),
+        ),
+        None => not_implemented_response(),
+    }
+}
+
+/// Non-standard bucket stats endpoint: GET /{bucket}?stats
+/// Returns the object count and total size as JSON, from counters maintained on every write.
+async fn get_bucket_stats(state: Arc<AppState>, bucket: String) -> Response {
+    let bucket = match validate_bucket(&bucket, &state.buckets) {
+        Ok(b) => b,
+        Err(resp) => return *resp,
+    };
+
+    let stats = match read_bucket_stats(&state, &bucket).await {
+        Ok(stats) => stats,
+        Err(resp) => return *resp,
+    };
+
+    info!(
+        "GetBucketStats for bucket '{bucket}': {} objects, {} bytes",
+        stats.object_count, stats.size_bytes
+    );
+
+    let body = serde_json::to_string(&stats).unwrap();
+    let mut headers = HeaderMap::new();
+    headers.insert("Content-Type", "application/json".parse().unwrap());
+    headers.insert("Content-Length", body.len().to_string().parse().unwrap());
+
+    (StatusCode::OK, headers, body).into_response()
+}
+
+/// Read the maintained object count and total size of a bucket
+async fn read_bucket_stats(state: &AppState, bucket: &str) -> Result<BucketStats, Box<Response>> {
+    let mut conn = match state.read_conn() {
+        Ok(c) => c,
+        Err(e) => return Err(Box::new(pool_error_response(&e))),
+    };
+
+    let max_retries = state.config.get_busy_retry_max_attempts();
+    retry_on_busy(max_retries, &mut conn, |conn| {
+        query_bucket_stats(conn, bucket)
+    })
+    .await
+    .map_err(|e| {
+        error!("Failed to read stats of bucket '{bucket}': {e}");
+        Box::new(database_error_response(&e))
+    })
+}
+
+/// Whether a listing should be streamed instead of built in memory
+fn should_stream(state: &AppState, max_keys: i32) -> bool {
+    state
+        .config
+        .get_list_streaming_threshold()
+        .is_some_and(|threshold| max_keys as usize > threshold)
+}
+
+/// Stream a delimiter-less listing, writing `<Contents>` entries as rows are read
+/// from the database cursor. The response uses chunked transfer encoding.
+/// If the client disconnects, the receiver is dropped and the producer stops,
+/// returning its connection to the pool.
+fn stream_listing(
+    state: Arc<AppState>,
+    mut result: ListBucketResult,
+    start_after: Option<String>,
+    v2: bool,
+) -> Response {
+    let (tx, rx) = mpsc::channel::<Result<Bytes, std::io::Error>>(STREAM_CHANNEL_CAPACITY);
+    let pool = state.read_pool.clone();
+    let table_prefix = state.config.get_table_prefix().to_string();
+
+    tokio::task::spawn_blocking(move || {
+        let conn = match pool.get() {
+            Ok(c) => c,
+            Err(e) => {
+                error!("Database connection error: {}", e);
+                let _ = tx.blocking_send(Err(std::io::Error::other(e.to_string())));
+                return;
+            }
+        };
+
+        let max_keys = result.max_keys as usize;
+        let mut chunk = result.to_xml_stream_head(v2);
+        let mut key_count = 0;
+        let mut last_key = None;
+        let mut is_truncated = false;
+        let outcome = for_each_bucket_object(
+            &conn,
+            &result.name,
+            &table_prefix,
+            &result.prefix,
+            start_after.as_deref(),
+            |(key, size, last_modified, md5_hash, storage_class)| {
+                if key_count == max_keys {
+                    is_truncated = true;
+                    return false;
+                }
+                key_count += 1;
+                last_key = Some(key.clone());
+                chunk.push_str(
+                    &S3Object::new(key, size, last_modified, md5_hash, storage_class).to_xml(),
+                );
+                if chunk.len() < STREAM_CHUNK_SIZE {
+                    return true;
+                }
+                tx.blocking_send(Ok(Bytes::from(std::mem::take(&mut chunk))))
+                    .is_ok()
+            },
+        );
+
+        match outcome {
+            // The visitor also stops early once max-keys entries have been written
+            Ok(finished) if finished || is_truncated => {
+                if is_truncated {
+                    result.is_truncated = true;
+                    result.next_continuation_token = last_key;
+                }
+                chunk.push_str(&result.to_xml_stream_tail(v2, key_count));
+                let _ = tx.blocking_send(Ok(Bytes::from(chunk)));
+                info!(
+                    "Streamed listing: bucket='{}', prefix='{}', contents_count={}",
+                    result.name, result.prefix, key_count
+                );
+            }
+            Ok(_) => {
+                info!(
+                    "Client disconnected during streamed listing of bucket '{}'",
+                    result.name
+                );
+            }
+            Err(e) => {
+                error!("Streamed listing of bucket '{}' failed: {}", result.name, e);
+                let _ = tx.blocking_send(Err(std::io::Error::other(e.to_string())));
+            }
+        }
+    });
+
+    // Fused, since the compression layer polls the body again after it has ended
+    let stream = futures::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|item| (item, rx))
+    })
+    .fuse();
+
+    let mut headers = HeaderMap::new();
+    headers.insert("Content-Type", "application/xml".parse().unwrap());
+
+    (StatusCode::OK, headers, Body::from_stream(stream)).into_response()
+}
+
+/// Delimiter of a listing. S3 delimiters are a single character (usually '/'), so only the
+/// first one is used. Without the parameter `default_delimiter` applies; an explicit
+/// empty `delimiter=` disables grouping.
+fn request_delimiter(state: &AppState, params: &HashMap<String, String>) -> Option<char> {
+    match params.get("delimiter") {
+        Some(delimiter) => delimiter.chars().next(),
+        None => state.config.get_default_delimiter(),
+    }
+}
+
+/// Fetch the rows for a listing, computing common prefixes in SQL when a delimiter is set.
+/// At most `max_keys` entries are returned; the last element of the tuple is the key or
+/// common prefix to continue from when more entries remain.
+/// The query is retried while the database is busy.
+async fn list_rows(
+    state: &AppState,
+    conn: &mut rusqlite::Connection,
+    bucket: &str,
+    prefix: &str,
+    delimiter: Option<char>,
+    start_after: Option<&str>,
+    max_keys: usize,
+) -> Result<(QueryBucketResult, Vec<String>, Option<String>), Box<Response>> {
+    // Read one entry past the limit to learn whether the listing is truncated
+    let limit = max_keys + 1;
+    let max_retries = state.config.get_busy_retry_max_attempts();
+    let table_prefix = state.config.get_table_prefix();
+    let (mut rows, mut common_prefixes) =
+        retry_on_busy(max_retries, conn, |conn| match delimiter {
+            Some(delimiter) => query_bucket_objects_delimited(
+                conn,
+                bucket,
+                table_prefix,
+                prefix,
+                delimiter,
+                start_after,
+                limit,
+            ),
+            None => query_bucket_objects(conn, bucket, table_prefix, prefix, start_after, limit)
+                .map(|rows| (rows, Vec::new())),
+        })
+        .await
+        .map_err(|e| {
+            error!("Failed to list bucket '{bucket}': {e}");
+            Box::new(database_error_response(&e))
+        })?;
+
+    if rows.len() + common_prefixes.len() <= max_keys {
+        return Ok((rows, common_prefixes, None));
+    }
+
+    // Drop the entry that sorts last, whether it is an object or a common prefix
+    let last_row = rows.last().map(|row| row.0.as_str());
+    if last_row > common_prefixes.last().map(|p| p.as_str()) {
+        rows.pop();
     } else {
-        list_objects(state, bucket, query.0).await
+        common_prefixes.pop();
+    }
+    let last_row = rows.last().map(|row| row.0.clone());
+    // With max-keys=0 nothing is returned and the listing continues where it started
+    let next_marker = last_row
+        .max(common_prefixes.last().cloned())
+        .or_else(|| start_after.map(str::to_string))
+        .unwrap_or_default();
+    Ok((rows, common_prefixes, Some(next_marker)))
+}
+
+/// Whether a listing is requested as JSON, with `format=json` or an `Accept` header
+/// preferring JSON to XML
+fn wants_json_listing(params: &HashMap<String, String>, headers: &HeaderMap) -> bool {
+    params.get("format").is_some_and(|f| f == "json") || prefers_json(headers)
+}
+
+/// A listing serialized as JSON instead of S3 XML
+fn json_listing_response(result: &ListBucketResult) -> Response {
+    let body = serde_json::to_string(result).unwrap();
+    let mut headers = HeaderMap::new();
+    headers.insert("Content-Type", "application/json".parse().unwrap());
+    headers.insert("Content-Length", body.len().to_string().parse().unwrap());
+
+    (StatusCode::OK, headers, body).into_response()
+}
+
+/// A listing as S3 XML, gzip compressed when it is larger than `list_gzip_threshold_bytes`
+/// and the request accepts gzip. The compression layer leaves the response alone, so
+/// small listings are sent uncompressed.
+fn xml_listing_response(state: &AppState, request_headers: &HeaderMap, body: String) -> Response {
+    let mut headers = HeaderMap::new();
+    headers.insert("Content-Type", "application/xml".parse().unwrap());
+    headers.insert("Vary", "Accept-Encoding".parse().unwrap());
+
+    let body =
+        if body.len() > state.config.get_list_gzip_threshold() && accepts_gzip(request_headers) {
+            match gzip(body.as_bytes()) {
+                Ok(compressed) => {
+                    headers.insert("Content-Encoding", "gzip".parse().unwrap());
+                    compressed
+                }
+                Err(e) => {
+                    warn!("Failed to compress a listing of {} bytes: {e}", body.len());
+                    body.into_bytes()
+                }
+            }
+        } else {
+            body.into_bytes()
+        };
+    headers.insert("Content-Length", body.len().to_string().parse().unwrap());
+
+    let mut response = (StatusCode::OK, headers, body).into_response();
+    response.extensions_mut().insert(HandlerEncoded);
+    response
+}
+
+/// Parse the `max-keys` query parameter, defaulting to no limit
+fn parse_max_keys(params: &HashMap<String, String>) -> Result<i32, Box<Response>> {
+    match params.get("max-keys") {
+        None => Ok(i32::MAX),
+        Some(v) => match v.parse::<i32>() {
+            Ok(n) if n >= 0 => Ok(n),
+            _ => Err(Box::new(xml_error_response(
+                StatusCode::BAD_REQUEST,
+                "InvalidArgument",
+                &format!("Invalid max-keys value: {v}"),
+            ))),
+        },
     }
 }
 
@@ -90,6 +1002,8 @@ async fn list_objects(
     state: Arc<AppState>,
     bucket: String,
     params: HashMap<String, String>,
+    json: bool,
+    headers: &HeaderMap,
 ) -> Response {
     // Validate bucket
     let bucket = match validate_bucket(&bucket, &state.buckets) {
@@ -99,31 +1013,38 @@ async fn list_objects(
 
     // Extract query parameters for ListObjects v1
     let prefix = params.get("prefix").cloned().unwrap_or_default();
-    let delimiter = params
-        .get("delimiter")
-        .and_then(|d| if d.is_empty() { None } else { d.chars().next() });
-    let _marker = params.get("marker").cloned().unwrap_or_default();
-    let max_keys = params
-        .get("max-keys")
-        .and_then(|v| v.parse::<i32>().ok())
-        .unwrap_or(i32::MAX); // disable limit by default
+    let delimiter = request_delimiter(&state, &params);
+    let marker = params.get("marker").filter(|m| !m.is_empty()).cloned();
+    let max_keys = match parse_max_keys(&params) {
+        Ok(n) => n,
+        Err(resp) => return *resp,
+    };
+
+    if delimiter.is_none() && !json && should_stream(&state, max_keys) {
+        let mut result = ListBucketResult::new(&bucket, &prefix, None);
+        result.set_max_keys(max_keys);
+        result.set_continuation(marker.clone(), None);
+        return stream_listing(state, result, marker, false);
+    }
 
     // Get DB connection
-    let pool = &state.db_pool;
-    let conn = match pool.get() {
+    let mut conn = match state.read_conn() {
         Ok(c) => c,
-        Err(e) => {
-            error!("Database connection error: {}", e);
-            return xml_error_response(
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "InternalError",
-                &format!("Database connection error: {}", e),
-            );
-        }
+        Err(e) => return pool_error_response(&e),
     };
 
     // Use shared query logic
-    let rows_vec = match query_bucket_objects(&conn, &bucket, &prefix) {
+    let (rows_vec, common_prefixes, next_marker) = match list_rows(
+        &state,
+        &mut conn,
+        &bucket,
+        &prefix,
+        delimiter,
+        marker.as_deref(),
+        max_keys as usize,
+    )
+    .await
+    {
         Ok(rows) => rows,
         Err(resp) => return *resp,
     };
@@ -131,18 +1052,18 @@ async fn list_objects(
     // Build ListBucketResult (v1 style)
     let mut result = ListBucketResult::new(&bucket, &prefix, delimiter);
     result.set_max_keys(max_keys);
-    result.is_truncated = false; // disable pagination for now
-    // v1: no encoding_type, no continuation_token, no start_after
+    result.is_truncated = next_marker.is_some();
+    // v1: Marker and NextMarker are carried in the continuation fields
+    result.set_continuation(marker, next_marker);
 
     // Process the collected keys with md5 hashes
     result.process_keys(rows_vec);
+    result.add_common_prefixes(common_prefixes);
 
-    let body = result.to_xml();
-    let mut headers = HeaderMap::new();
-    headers.insert("Content-Type", "application/xml".parse().unwrap());
-    headers.insert("Content-Length", body.len().to_string().parse().unwrap());
-
-    (StatusCode::OK, headers, body).into_response()
+    if json {
+        return json_listing_response(&result);
+    }
+    xml_listing_response(&state, headers, result.to_xml())
 }
 
 /// Implementation for ListObjectsV2 S3 API
@@ -150,6 +1071,8 @@ async fn list_objects_v2(
     state: Arc<AppState>,
     bucket: String,
     params: HashMap<String, String>,
+    json: bool,
+    headers: &HeaderMap,
 ) -> Response {
     // Validate bucket
     let bucket = match validate_bucket(&bucket, &state.buckets) {
@@ -160,34 +1083,46 @@ async fn list_objects_v2(
     // Extract query parameters used by S3 ListObjectsV2
     let prefix = params.get("prefix").cloned().unwrap_or_default();
     let encoding_type = params.get("encoding-type").cloned();
-    let max_keys = params
-        .get("max-keys")
-        .and_then(|v| v.parse::<i32>().ok())
-        .unwrap_or(i32::MAX);
+    let max_keys = match parse_max_keys(&params) {
+        Ok(n) => n,
+        Err(resp) => return *resp,
+    };
     let start_after = params.get("start-after").cloned();
     let continuation_token = params.get("continuation-token").cloned();
 
-    // S3 API expects delimiter to be a single character (usually '/')
-    // Extract just the first character if delimiter is present
-    let delimiter = params
-        .get("delimiter")
-        .and_then(|d| if d.is_empty() { None } else { d.chars().next() });
+    let delimiter = request_delimiter(&state, &params);
 
-    let pool = &state.db_pool;
-    let conn = match pool.get() {
+    // The continuation token is the last key or common prefix of the previous page.
+    // start-after only applies to the first page; a continuation token takes precedence
+    let key_start_after = continuation_token.as_deref().or(start_after.as_deref());
+
+    if delimiter.is_none() && !json && should_stream(&state, max_keys) {
+        let key_start_after = key_start_after.map(|s| s.to_string());
+        let mut result = ListBucketResult::new(&bucket, &prefix, None);
+        result.set_encoding_type(encoding_type);
+        result.set_max_keys(max_keys);
+        result.set_start_after(start_after);
+        result.set_continuation(continuation_token, None);
+        return stream_listing(state, result, key_start_after, true);
+    }
+
+    let mut conn = match state.read_conn() {
         Ok(c) => c,
-        Err(e) => {
-            error!("Database connection error: {}", e);
-            return xml_error_response(
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "InternalError",
-                &format!("Database connection error: {}", e),
-            );
-        }
+        Err(e) => return pool_error_response(&e),
     };
 
     // Use shared query logic
-    let rows_vec = match query_bucket_objects(&conn, &bucket, &prefix) {
+    let (rows_vec, common_prefixes, next_token) = match list_rows(
+        &state,
+        &mut conn,
+        &bucket,
+        &prefix,
+        delimiter,
+        key_start_after,
+        max_keys as usize,
+    )
+    .await
+    {
         Ok(rows) => rows,
         Err(resp) => return *resp,
     };
@@ -199,10 +1134,12 @@ async fn list_objects_v2(
     result.set_encoding_type(encoding_type);
     result.set_max_keys(max_keys);
     result.set_start_after(start_after);
-    result.set_continuation(continuation_token, None); // We don't implement pagination yet
+    result.is_truncated = next_token.is_some();
+    result.set_continuation(continuation_token, next_token);
 
     // Process the collected keys with md5 hashes
     result.process_keys(rows_vec);
+    result.add_common_prefixes(common_prefixes);
 
     info!(
         "ListObjectsV2 result: bucket='{}', prefix='{}', delimiter={:?}, contents_count={}, prefixes_count={}",
@@ -215,10 +1152,8 @@ async fn list_objects_v2(
         result.common_prefixes.len()
     );
 
-    let body = result.to_xml_v2();
-    let mut headers = HeaderMap::new();
-    headers.insert("Content-Type", "application/xml".parse().unwrap());
-    headers.insert("Content-Length", body.len().to_string().parse().unwrap());
-
-    (StatusCode::OK, headers, body).into_response()
+    if json {
+        return json_listing_response(&result);
+    }
+    xml_listing_response(&state, headers, result.to_xml_v2())
 }
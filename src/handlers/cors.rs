@@ -0,0 +1,278 @@
+use axum::{
+    body::Bytes,
+    extract::{Path, Request, State},
+    http::{HeaderMap, Method, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use log::{error, info, warn};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::models::AppState;
+use crate::utils::{
+    bucket_meta::{delete_cors_configuration, get_cors_configuration, put_cors_configuration},
+    cors::{CorsRule, find_cors_rule, parse_cors_rules},
+    database_error_response, pool_error_response, retry_on_busy, validate_bucket,
+    xml_error_response, xml_response,
+};
+
+/// GetBucketCors: GET /{bucket}?cors
+pub async fn get_bucket_cors(state: Arc<AppState>, bucket: String) -> Response {
+    let bucket = match validate_bucket(&bucket, &state.buckets) {
+        Ok(b) => b,
+        Err(resp) => return *resp,
+    };
+
+    info!("GetBucketCors for bucket '{bucket}'");
+    let mut conn = match state.read_conn() {
+        Ok(c) => c,
+        Err(e) => return pool_error_response(&e),
+    };
+
+    let max_retries = state.config.get_busy_retry_max_attempts();
+    match retry_on_busy(max_retries, &mut conn, |conn| {
+        get_cors_configuration(conn, &bucket)
+    })
+    .await
+    {
+        Ok(Some(configuration)) => xml_response(StatusCode::OK, configuration),
+        Ok(None) => xml_error_response(
+            StatusCode::NOT_FOUND,
+            "NoSuchCORSConfiguration",
+            &format!("The CORS configuration does not exist: {bucket}"),
+        ),
+        Err(e) => {
+            error!("Failed to read CORS configuration of bucket '{bucket}': {e}");
+            database_error_response(&e)
+        }
+    }
+}
+
+/// PutBucketCors: PUT /{bucket}?cors
+/// The document is stored as sent once its rules parse.
+pub async fn put_bucket_cors(state: Arc<AppState>, bucket: String, body: Bytes) -> Response {
+    let bucket = match validate_bucket(&bucket, &state.buckets) {
+        Ok(b) => b,
+        Err(resp) => return *resp,
+    };
+
+    let configuration = match std::str::from_utf8(&body) {
+        Ok(xml) if parse_cors_rules(xml).is_some() => xml,
+        _ => {
+            return xml_error_response(
+                StatusCode::BAD_REQUEST,
+                "MalformedXML",
+                "The XML you provided was not well-formed or did not validate against our published schema",
+            );
+        }
+    };
+
+    info!("PutBucketCors for bucket '{bucket}'");
+    let mut conn = match state.write_conn() {
+        Ok(c) => c,
+        Err(e) => return pool_error_response(&e),
+    };
+
+    let max_retries = state.config.get_busy_retry_max_attempts();
+    match retry_on_busy(max_retries, &mut conn, |conn| {
+        put_cors_configuration(conn, &bucket, configuration)
+    })
+    .await
+    {
+        Ok(()) => StatusCode::OK.into_response(),
+        Err(e) => {
+            error!("Failed to store CORS configuration of bucket '{bucket}': {e}");
+            database_error_response(&e)
+        }
+    }
+}
+
+/// DeleteBucketCors: DELETE /{bucket}?cors
+pub async fn delete_bucket_cors(state: Arc<AppState>, bucket: String) -> Response {
+    let bucket = match validate_bucket(&bucket, &state.buckets) {
+        Ok(b) => b,
+        Err(resp) => return *resp,
+    };
+
+    info!("DeleteBucketCors for bucket '{bucket}'");
+    let mut conn = match state.write_conn() {
+        Ok(c) => c,
+        Err(e) => return pool_error_response(&e),
+    };
+
+    let max_retries = state.config.get_busy_retry_max_attempts();
+    match retry_on_busy(max_retries, &mut conn, |conn| {
+        delete_cors_configuration(conn, &bucket)
+    })
+    .await
+    {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => {
+            error!("Failed to delete CORS configuration of bucket '{bucket}': {e}");
+            database_error_response(&e)
+        }
+    }
+}
+
+/// CORS preflight: OPTIONS /{bucket} and /{bucket}/{key}
+/// Evaluates Origin, Access-Control-Request-Method and Access-Control-Request-Headers
+/// against the bucket's rules, answering 403 if none allows the request.
+pub async fn cors_preflight(
+    State(state): State<Arc<AppState>>,
+    Path(params): Path<HashMap<String, String>>,
+    headers: HeaderMap,
+) -> Response {
+    let bucket = params.get("bucket").map(String::as_str).unwrap_or_default();
+    let bucket = match validate_bucket(bucket, &state.buckets) {
+        Ok(b) => b,
+        Err(resp) => return *resp,
+    };
+
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+    let (Some(origin), Some(method)) = (header("Origin"), header("Access-Control-Request-Method"))
+    else {
+        return xml_error_response(
+            StatusCode::BAD_REQUEST,
+            "BadRequest",
+            "Insufficient information. Origin and Access-Control-Request-Method request headers needed.",
+        );
+    };
+    let request_headers: Vec<&str> = header("Access-Control-Request-Headers")
+        .map(|v| {
+            v.split(',')
+                .map(str::trim)
+                .filter(|h| !h.is_empty())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let rules = match load_cors_rules(&state, &bucket).await {
+        Ok(Some(rules)) => rules,
+        Ok(None) => {
+            info!("CORS preflight from '{origin}' on bucket '{bucket}' without CORS configuration");
+            return xml_error_response(
+                StatusCode::FORBIDDEN,
+                "AccessForbidden",
+                "CORSResponse: CORS is not enabled for this bucket.",
+            );
+        }
+        Err(resp) => return *resp,
+    };
+    let Some(rule) = find_cors_rule(&rules, origin, method, &request_headers) else {
+        info!("CORS preflight from '{origin}' for {method} on bucket '{bucket}' not allowed");
+        return xml_error_response(
+            StatusCode::FORBIDDEN,
+            "AccessForbidden",
+            "CORSResponse: This CORS request is not allowed. The Origin, Access-Control-Request-Method or Access-Control-Request-Headers are not allowed by the bucket's CORS configuration.",
+        );
+    };
+
+    let mut response_headers = HeaderMap::new();
+    insert_cors_headers(&mut response_headers, rule, origin);
+    if !request_headers.is_empty()
+        && let Ok(value) = request_headers.join(", ").parse()
+    {
+        response_headers.insert(header::ACCESS_CONTROL_ALLOW_HEADERS, value);
+    }
+    if let Some(max_age) = rule.max_age_seconds {
+        response_headers.insert(header::ACCESS_CONTROL_MAX_AGE, max_age.into());
+    }
+    (StatusCode::OK, response_headers).into_response()
+}
+
+/// Add the `Access-Control-*` headers of the matching CORS rule to the response of a
+/// cross-origin request on a bucket or object, including error responses, so that
+/// browsers let the page read them. Preflight requests are answered by `cors_preflight`.
+pub async fn cors_middleware(
+    State(state): State<Arc<AppState>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let origin = req
+        .headers()
+        .get(header::ORIGIN)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+    let bucket = req
+        .uri()
+        .path()
+        .trim_start_matches('/')
+        .split('/')
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    let method = req.method().clone();
+
+    let mut response = next.run(req).await;
+
+    let Some(origin) = origin else {
+        return response;
+    };
+    if method == Method::OPTIONS || !state.buckets.read().unwrap().contains(&bucket) {
+        return response;
+    }
+    match load_cors_rules(&state, &bucket).await {
+        Ok(Some(rules)) => {
+            if let Some(rule) = find_cors_rule(&rules, &origin, method.as_str(), &[]) {
+                insert_cors_headers(response.headers_mut(), rule, &origin);
+            }
+        }
+        Ok(None) => {}
+        Err(_) => warn!("CORS headers omitted, failed to read the configuration of '{bucket}'"),
+    }
+    response
+}
+
+/// The rules of a bucket's stored CORS configuration, None if it has none
+async fn load_cors_rules(
+    state: &AppState,
+    bucket: &str,
+) -> Result<Option<Vec<CorsRule>>, Box<Response>> {
+    let mut conn = match state.read_conn() {
+        Ok(c) => c,
+        Err(e) => return Err(Box::new(pool_error_response(&e))),
+    };
+
+    let max_retries = state.config.get_busy_retry_max_attempts();
+    match retry_on_busy(max_retries, &mut conn, |conn| {
+        get_cors_configuration(conn, bucket)
+    })
+    .await
+    {
+        Ok(configuration) => Ok(configuration.as_deref().and_then(parse_cors_rules)),
+        Err(e) => {
+            error!("Failed to read CORS configuration of bucket '{bucket}': {e}");
+            Err(Box::new(database_error_response(&e)))
+        }
+    }
+}
+
+/// Headers granting `origin` access under `rule`, common to preflight and actual responses
+fn insert_cors_headers(headers: &mut HeaderMap, rule: &CorsRule, origin: &str) {
+    let allow_origin = rule.allow_origin(origin);
+    if let Ok(value) = allow_origin.parse() {
+        headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, value);
+    }
+    if let Ok(value) = rule.allowed_methods.join(", ").parse() {
+        headers.insert(header::ACCESS_CONTROL_ALLOW_METHODS, value);
+    }
+    // As in S3, credentials are allowed unless every origin is
+    if allow_origin != "*" {
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_CREDENTIALS,
+            "true".parse().unwrap(),
+        );
+    }
+    if !rule.expose_headers.is_empty()
+        && let Ok(value) = rule.expose_headers.join(", ").parse()
+    {
+        headers.insert(header::ACCESS_CONTROL_EXPOSE_HEADERS, value);
+    }
+    headers.insert(
+        header::VARY,
+        "Origin, Access-Control-Request-Headers, Access-Control-Request-Method"
+            .parse()
+            .unwrap(),
+    );
+}
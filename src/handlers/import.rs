@@ -0,0 +1,189 @@
+use axum::{
+    body::Bytes,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use log::{error, info, warn};
+use rusqlite::{Connection, TransactionBehavior};
+use std::sync::Arc;
+
+use crate::handlers::object::{
+    NewObject, WriteOutcome, quota_exceeded_response, write_object_row, write_once_response,
+};
+use crate::models::{AppState, ImportReport, ImportedEntry};
+use crate::utils::{
+    archive::{ArchiveEntry, ArchiveError, read_archive},
+    blobs::blob_hash,
+    bucket_meta::ObjectQuota,
+    checksum::payload_digests,
+    compression::compress,
+    database_error_response,
+    keys::key_violation,
+    pool_error_response, retry_on_busy, sanitize_bucket_name, validate_bucket, xml_error_response,
+};
+
+/// Why an import stopped at one of its entries
+enum ImportStop {
+    /// The entry would overwrite an object of a write-once bucket or exceed its quota
+    Refused(WriteOutcome),
+    Database(rusqlite::Error),
+}
+
+/// Non-standard bulk import: POST /{bucket}?import with a tar or zip archive body.
+/// Every regular file becomes an object keyed by its path in the archive. Entries
+/// that cannot be imported (too large, disallowed key, links) are skipped; the others
+/// are written in a single transaction as they are decompressed, so either all of
+/// them are stored or none. Archives decompressing to more than `max_import_size`
+/// are refused. Returns a JSON report of each entry, in archive order.
+pub async fn import_archive(state: Arc<AppState>, bucket: String, body: Bytes) -> Response {
+    let bucket = match validate_bucket(&bucket, &state.buckets) {
+        Ok(b) => b,
+        Err(resp) => return *resp,
+    };
+    let Some(table_name) = sanitize_bucket_name(&bucket, state.config.get_table_prefix()) else {
+        warn!("Invalid bucket name attempted: {bucket}");
+        return xml_error_response(
+            StatusCode::BAD_REQUEST,
+            "InvalidBucketName",
+            &format!("Invalid bucket name attempted: {bucket}"),
+        );
+    };
+
+    let compression = state.config.get_bucket_compression(&bucket);
+    let min_size = state.config.get_compression_min_size();
+    let dedup = state.config.is_dedup(&bucket);
+    let write_once = state.config.is_write_once(&bucket);
+    let quota = ObjectQuota::for_bucket(&state.config, &bucket);
+    let max_size = state.config.get_max_object_size();
+    let max_total = state.config.get_max_import_size() as u64;
+
+    // Write one entry within the transaction of the import, or report why it is skipped
+    let import_entry =
+        |tx: &Connection, entry: ArchiveEntry| -> Result<ImportedEntry, ImportStop> {
+            let key = entry.path.trim_start_matches("./").trim_start_matches('/');
+            let violation = if key.is_empty() {
+                Some("Empty object key".to_string())
+            } else {
+                key_violation(
+                    key,
+                    state.config.get_disallowed_key_patterns(),
+                    state.config.rejects_key_control_chars(),
+                )
+            };
+            let error = match (entry.data, violation) {
+                (Ok(data), None) => {
+                    let (md5_hash, sha256) = payload_digests(&data);
+                    let compressed = compression.and_then(|compression| {
+                        compress(compression, &data, min_size)
+                            .map(|stored| (stored, (compression, data.len() as u64)))
+                    });
+                    let stored = compressed.as_ref().map_or(&data, |(stored, _)| stored);
+                    let shared_blob = dedup.then(|| match &compressed {
+                        Some((stored, _)) => blob_hash(stored),
+                        None => sha256.clone(),
+                    });
+                    let object = NewObject {
+                        key,
+                        data: stored,
+                        md5_hash: &md5_hash,
+                        storage_class: state.config.get_default_storage_class(),
+                        checksum: None,
+                        content_encoding: None,
+                        content_sha256: Some(&sha256),
+                        expires_at: None,
+                        compression: compressed.as_ref().map(|(_, compression)| *compression),
+                        blob_hash: shared_blob.as_deref(),
+                    };
+                    match write_object_row(
+                        tx,
+                        &table_name,
+                        &object,
+                        &HeaderMap::new(),
+                        write_once,
+                        quota.as_ref(),
+                    ) {
+                        Ok(WriteOutcome::Written) => None,
+                        Ok(outcome) => return Err(ImportStop::Refused(outcome)),
+                        Err(e) => return Err(ImportStop::Database(e)),
+                    }
+                }
+                (Err(reason), _) => Some(reason),
+                (Ok(_), Some(reason)) => Some(reason),
+            };
+            Ok(ImportedEntry {
+                key: key.to_string(),
+                imported: error.is_none(),
+                error,
+            })
+        };
+
+    let mut conn = match state.write_conn() {
+        Ok(c) => c,
+        Err(e) => return pool_error_response(&e),
+    };
+    let max_retries = state.config.get_busy_retry_max_attempts();
+    let result = retry_on_busy(max_retries, &mut conn, |conn| {
+        // Entries are written as they are decompressed, all of them or none
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let mut entries = Vec::new();
+        let read = read_archive(&body, max_size, max_total, |entry| {
+            entries.push(import_entry(&tx, entry)?);
+            Ok(())
+        });
+        match read {
+            Ok(()) => {
+                tx.commit()?;
+                Ok(Ok(entries))
+            }
+            Err(ArchiveError::Visit(ImportStop::Database(e))) => Err(e),
+            Err(ArchiveError::Visit(ImportStop::Refused(outcome))) => {
+                Ok(Err(ArchiveError::Visit(outcome)))
+            }
+            Err(ArchiveError::Malformed(reason)) => Ok(Err(ArchiveError::Malformed(reason))),
+            Err(ArchiveError::TooLarge(reason)) => Ok(Err(ArchiveError::TooLarge(reason))),
+        }
+    })
+    .await;
+    state.invalidate_cached_bucket(&bucket);
+    let report = match result {
+        Ok(Ok(entries)) => entries,
+        Ok(Err(ArchiveError::Malformed(reason))) => {
+            warn!("Rejected import into bucket '{bucket}': {reason}");
+            return xml_error_response(StatusCode::BAD_REQUEST, "MalformedArchive", &reason);
+        }
+        Ok(Err(ArchiveError::TooLarge(reason))) => {
+            warn!("Rejected import into bucket '{bucket}': {reason}");
+            return xml_error_response(StatusCode::BAD_REQUEST, "EntityTooLarge", &reason);
+        }
+        Ok(Err(ArchiveError::Visit(WriteOutcome::QuotaExceeded))) => {
+            warn!("Refused import over the quota of bucket '{bucket}'");
+            return quota_exceeded_response();
+        }
+        Ok(Err(ArchiveError::Visit(_))) => {
+            info!("Refused import overwriting objects of write-once bucket '{bucket}'");
+            return write_once_response();
+        }
+        Err(e) => {
+            error!("Failed to import archive into bucket '{bucket}': {e}");
+            return database_error_response(&e);
+        }
+    };
+
+    let imported = report.iter().filter(|entry| entry.imported).count();
+    info!(
+        "Imported {imported} of {} archive entries into bucket '{bucket}'",
+        report.len()
+    );
+    let report = ImportReport {
+        bucket,
+        imported,
+        failed: report.len() - imported,
+        entries: report,
+    };
+    let body = serde_json::to_string(&report).unwrap();
+    let mut headers = HeaderMap::new();
+    headers.insert("Content-Type", "application/json".parse().unwrap());
+    headers.insert("Content-Length", body.len().to_string().parse().unwrap());
+
+    (StatusCode::OK, headers, body).into_response()
+}
@@ -0,0 +1,50 @@
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{Method, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use log::warn;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use crate::models::AppState;
+use crate::utils::xml_error_response;
+
+/// Refuse requests from client addresses filtered by `allow_ips`, `deny_ips` and
+/// `write_allow_ips` with `403 AccessDenied`, before authentication or any database
+/// work. Requests on the unix socket are filtered by their `X-Forwarded-For` client,
+/// and refused without one.
+pub async fn ip_filter_middleware(
+    State(state): State<Arc<AppState>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    if state.ip_filter.is_empty() {
+        return next.run(req).await;
+    }
+    let peer = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|info| info.0.ip());
+    let peer_name = peer.map_or_else(|| "unix socket".to_string(), |ip| ip.to_string());
+
+    let Some(client) = state.ip_filter.client_ip(peer, req.headers()) else {
+        warn!(
+            "Rejected {} {} on the unix socket: no client address in X-Forwarded-For",
+            req.method(),
+            req.uri().path()
+        );
+        return xml_error_response(StatusCode::FORBIDDEN, "AccessDenied", "Access Denied");
+    };
+    let writes = !matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS);
+    if let Err(reason) = state.ip_filter.check(client, writes) {
+        warn!(
+            "Rejected {} {} from {client} (peer {peer_name}): {reason}",
+            req.method(),
+            req.uri().path()
+        );
+        return xml_error_response(StatusCode::FORBIDDEN, "AccessDenied", "Access Denied");
+    }
+    next.run(req).await
+}
@@ -0,0 +1,281 @@
+use axum::{
+    body::Bytes,
+    extract::{ConnectInfo, Request, State},
+    http::{HeaderMap, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::Utc;
+use log::{debug, error, info, warn};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+use crate::handlers::object::{NewObject, WriteOutcome, put_object_row};
+use crate::models::AppState;
+use crate::utils::{
+    RequestId,
+    access_log::{
+        AccessRecord, LoggingTarget, log_object_key, logging_status_xml, operation_name,
+        parse_logging_status,
+    },
+    bucket_meta::put_logging_target,
+    checksum::payload_digests,
+    database_error_response, pool_error_response, retry_on_busy, sanitize_bucket_name,
+    validate_bucket, xml_error_response, xml_response,
+};
+
+/// Records buffered for delivery before new ones are dropped
+pub const ACCESS_LOG_QUEUE_CAPACITY: usize = 10_000;
+/// Records of one target written as a single log object before the interval elapses
+const MAX_RECORDS_PER_LOG_OBJECT: usize = 1000;
+
+/// GetBucketLogging: GET /{bucket}?logging
+pub async fn get_bucket_logging(state: Arc<AppState>, bucket: String) -> Response {
+    let bucket = match validate_bucket(&bucket, &state.buckets) {
+        Ok(b) => b,
+        Err(resp) => return *resp,
+    };
+
+    info!("GetBucketLogging for bucket '{bucket}'");
+    let target = state.logging_targets.read().unwrap().get(&bucket).cloned();
+    xml_response(StatusCode::OK, logging_status_xml(target.as_ref()))
+}
+
+/// PutBucketLogging: PUT /{bucket}?logging
+/// An empty `BucketLoggingStatus` disables logging. The target bucket must exist.
+pub async fn put_bucket_logging(state: Arc<AppState>, bucket: String, body: Bytes) -> Response {
+    let bucket = match validate_bucket(&bucket, &state.buckets) {
+        Ok(b) => b,
+        Err(resp) => return *resp,
+    };
+
+    let Some(target) = std::str::from_utf8(&body)
+        .ok()
+        .and_then(parse_logging_status)
+    else {
+        return xml_error_response(
+            StatusCode::BAD_REQUEST,
+            "MalformedXML",
+            "The XML you provided was not well-formed or did not validate against our published schema",
+        );
+    };
+    if let Some(target) = &target
+        && !state.buckets.read().unwrap().contains(&target.bucket)
+    {
+        return xml_error_response(
+            StatusCode::BAD_REQUEST,
+            "InvalidTargetBucketForLogging",
+            &format!(
+                "The target bucket for logging does not exist: {}",
+                target.bucket
+            ),
+        );
+    }
+
+    info!("PutBucketLogging for bucket '{bucket}': {target:?}");
+    let mut conn = match state.write_conn() {
+        Ok(c) => c,
+        Err(e) => return pool_error_response(&e),
+    };
+
+    let max_retries = state.config.get_busy_retry_max_attempts();
+    match retry_on_busy(max_retries, &mut conn, |conn| {
+        put_logging_target(conn, &bucket, target.as_ref())
+    })
+    .await
+    {
+        Ok(()) => {
+            let mut targets = state.logging_targets.write().unwrap();
+            match target {
+                Some(target) => targets.insert(bucket, target),
+                None => targets.remove(&bucket),
+            };
+            StatusCode::OK.into_response()
+        }
+        Err(e) => {
+            error!("Failed to store logging configuration of bucket '{bucket}': {e}");
+            database_error_response(&e)
+        }
+    }
+}
+
+/// Queue an access record for requests to buckets with logging enabled.
+/// Records are handed to the delivery task without waiting; when its queue
+/// is full they are dropped rather than delaying the response.
+pub async fn access_log_middleware(
+    State(state): State<Arc<AppState>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let Some(access_log) = state.access_log.clone() else {
+        return next.run(req).await;
+    };
+    let path = req.uri().path().trim_start_matches('/');
+    let (bucket, key) = match path.split_once('/') {
+        Some((bucket, key)) => (bucket, Some(key).filter(|k| !k.is_empty())),
+        None => (path, None),
+    };
+    let Some(target) = state.logging_targets.read().unwrap().get(bucket).cloned() else {
+        return next.run(req).await;
+    };
+
+    let query: HashMap<String, String> = req
+        .uri()
+        .query()
+        .map(|q| {
+            url::form_urlencoded::parse(q.as_bytes())
+                .into_owned()
+                .collect()
+        })
+        .unwrap_or_default();
+    let headers = req.headers();
+    let header_value = |name| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string)
+    };
+    let mut record = AccessRecord {
+        target,
+        bucket: bucket.to_string(),
+        key: key.map(str::to_string),
+        time: Utc::now(),
+        remote_addr: req
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|info| info.0.ip().to_string()),
+        request_id: req
+            .extensions()
+            .get::<RequestId>()
+            .map(|id| id.0.clone())
+            .unwrap_or_default(),
+        operation: operation_name(req.method().as_str(), key.is_some(), &query),
+        request_line: format!("{} {} {:?}", req.method(), req.uri(), req.version()),
+        status: 0,
+        bytes_sent: None,
+        total_time_ms: 0,
+        referer: header_value(header::REFERER),
+        user_agent: header_value(header::USER_AGENT),
+    };
+    let started = Instant::now();
+
+    let response = next.run(req).await;
+
+    record.status = response.status().as_u16();
+    record.bytes_sent = response
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok());
+    record.total_time_ms = started.elapsed().as_millis();
+    if let Err(e) = access_log.try_send(record) {
+        warn!("Access log record dropped: {e}");
+    }
+    response
+}
+
+/// Deliver queued access records as log objects into their target buckets,
+/// every `interval` or as soon as a target has `MAX_RECORDS_PER_LOG_OBJECT` records
+pub fn schedule_access_log_delivery(
+    state: Arc<AppState>,
+    mut records: mpsc::Receiver<AccessRecord>,
+    interval: Duration,
+) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(interval);
+        let mut pending: HashMap<LoggingTarget, Vec<String>> = HashMap::new();
+
+        loop {
+            tokio::select! {
+                record = records.recv() => {
+                    let Some(record) = record else { break };
+                    let line = record.to_log_line(state.config.get_owner_id());
+                    let lines = pending.entry(record.target.clone()).or_default();
+                    lines.push(line);
+                    if lines.len() >= MAX_RECORDS_PER_LOG_OBJECT {
+                        let lines = pending.remove(&record.target).unwrap_or_default();
+                        deliver_log_object(&state, &record.target, lines).await;
+                    }
+                }
+                _ = interval.tick() => {
+                    for (target, lines) in std::mem::take(&mut pending) {
+                        deliver_log_object(&state, &target, lines).await;
+                    }
+                }
+            }
+        }
+    });
+}
+
+/// Store log lines as one object in the target bucket, like a PutObject without preconditions
+async fn deliver_log_object(state: &AppState, target: &LoggingTarget, lines: Vec<String>) {
+    if !state.buckets.read().unwrap().contains(&target.bucket) {
+        warn!(
+            "Dropped {} access log records, target bucket '{}' no longer exists",
+            lines.len(),
+            target.bucket
+        );
+        return;
+    }
+    let Some(table_name) = sanitize_bucket_name(&target.bucket, state.config.get_table_prefix())
+    else {
+        return;
+    };
+
+    let mut data = lines.join("\n");
+    data.push('\n');
+    let (md5_hash, sha256) = payload_digests(data.as_bytes());
+    let key = log_object_key(&target.prefix, Utc::now());
+    let object = NewObject {
+        key: &key,
+        data: data.as_bytes(),
+        md5_hash: &md5_hash,
+        storage_class: state.config.get_default_storage_class(),
+        checksum: None,
+        content_encoding: None,
+        content_sha256: Some(&sha256),
+        expires_at: None,
+        compression: None,
+        blob_hash: None,
+    };
+
+    let mut conn = match state.write_conn() {
+        Ok(conn) => conn,
+        Err(e) => {
+            error!("Access log delivery skipped, no database connection: {e}");
+            return;
+        }
+    };
+    let max_retries = state.config.get_busy_retry_max_attempts();
+    let result = retry_on_busy(max_retries, &mut conn, |conn| {
+        put_object_row(
+            conn,
+            &table_name,
+            &object,
+            &HeaderMap::new(),
+            state.config.is_write_once(&target.bucket),
+            None,
+        )
+    })
+    .await;
+    state.invalidate_cached_object(&target.bucket, &key);
+    match result {
+        Ok(WriteOutcome::Immutable) => warn!(
+            "Dropped {} access log records, '{}/{key}' exists in a write-once bucket",
+            lines.len(),
+            target.bucket
+        ),
+        Ok(_) => debug!(
+            "Delivered {} access log records to '{}/{key}'",
+            lines.len(),
+            target.bucket
+        ),
+        Err(e) => error!(
+            "Failed to deliver access logs to bucket '{}': {e}",
+            target.bucket
+        ),
+    }
+}
@@ -1,6 +1,31 @@
+pub mod acl;
+pub mod admin;
+pub mod audit;
+pub mod auth;
+pub mod browse;
 pub mod bucket;
+pub mod cors;
+pub mod import;
+pub mod ip_filter;
+pub mod logging;
+pub mod multipart;
 pub mod object;
+pub mod policy;
+pub mod rate_limit;
+pub mod write_batch;
 
 // Re-exports for convenience
-pub use bucket::{get_bucket_dispatch, list_buckets};
+pub use admin::{get_root_dispatch, post_root_dispatch};
+pub use audit::audit_log_middleware;
+pub use auth::{auth_middleware, schedule_credentials_reload};
+pub use bucket::{
+    delete_bucket_dispatch, get_bucket_dispatch, head_bucket, post_bucket_dispatch,
+    put_bucket_dispatch,
+};
+pub use cors::{cors_middleware, cors_preflight};
+pub use ip_filter::ip_filter_middleware;
+pub use logging::{ACCESS_LOG_QUEUE_CAPACITY, access_log_middleware, schedule_access_log_delivery};
+pub use multipart::post_object_dispatch;
 pub use object::{delete_object, download_object, head_object, upload_object};
+pub use rate_limit::rate_limit_middleware;
+pub use write_batch::{WRITE_BATCH_QUEUE_CAPACITY, schedule_write_batching};
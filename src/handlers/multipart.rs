@@ -0,0 +1,777 @@
+use axum::{
+    body::Bytes,
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use log::{error, info, warn};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use crate::handlers::object::{
+    check_object_key, check_upload_headers, content_sha256_error_response, copy_preconditions_hold,
+    parse_copy_source, precondition_failed_response, quota_exceeded_response, rename_object,
+    write_once_response,
+};
+use crate::models::AppState;
+use crate::utils::{
+    blobs::blob_hash,
+    bucket_meta::ObjectQuota,
+    checksum::{payload_digests, verify_content_sha256},
+    compression::compress,
+    database_error_response, html_escape,
+    multipart::{
+        AssembledObject, CompleteOutcome, MultipartUpload, PART_NUMBER_RANGE, PartCopy,
+        PartCopyOutcome, abort_upload, assemble_parts, complete_upload, copy_part,
+        generate_upload_id, insert_multipart_upload, list_parts, list_uploads,
+        parse_complete_manifest, put_part,
+    },
+    not_implemented_response, percent_encode_path, pool_error_response, retry_on_busy,
+    run_blocking, sanitize_bucket_name, validate_bucket, xml_error_response, xml_response,
+};
+
+/// Maximum number of parts returned by one ListParts request
+const MAX_PARTS_PER_LISTING: u32 = 1000;
+/// Maximum number of uploads returned by one ListMultipartUploads request
+const MAX_UPLOADS_PER_LISTING: usize = 1000;
+
+/// Route POST requests on an object based on query parameters
+pub async fn post_object_dispatch(
+    State(state): State<Arc<AppState>>,
+    Path((bucket, key)): Path<(String, String)>,
+    query: Query<HashMap<String, String>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    if query.contains_key("uploads") {
+        create_multipart_upload(state, bucket, key, headers).await
+    } else if let Some(upload_id) = query.get("uploadId") {
+        complete_multipart_upload(state, bucket, key, upload_id, body).await
+    } else if query.contains_key("rename") {
+        rename_object(state, bucket, key, headers).await
+    } else {
+        not_implemented_response()
+    }
+}
+
+/// CreateMultipartUpload: POST /{bucket}/{key}?uploads
+/// Records the pending upload with its content type, user metadata and storage class.
+async fn create_multipart_upload(
+    state: Arc<AppState>,
+    bucket: String,
+    key: String,
+    headers: HeaderMap,
+) -> Response {
+    let bucket = match validate_bucket(&bucket, &state.buckets) {
+        Ok(b) => b,
+        Err(resp) => return *resp,
+    };
+    if let Err(resp) = check_object_key(&state, &key) {
+        return *resp;
+    }
+    let storage_class = match check_upload_headers(&state, &headers, &key) {
+        Ok(class) => class,
+        Err(resp) => return *resp,
+    };
+
+    let content_type = headers.get("Content-Type").and_then(|v| v.to_str().ok());
+    let metadata: BTreeMap<&str, &str> = headers
+        .iter()
+        .filter_map(|(name, value)| {
+            let name = name.as_str().strip_prefix("x-amz-meta-")?;
+            Some((name, value.to_str().ok()?))
+        })
+        .collect();
+    let metadata = serde_json::to_string(&metadata).unwrap();
+
+    let upload_id = generate_upload_id(&bucket, &key);
+    info!("CreateMultipartUpload for '{key}' in bucket '{bucket}': {upload_id}");
+
+    let mut conn = match state.write_conn() {
+        Ok(conn) => conn,
+        Err(e) => return pool_error_response(&e),
+    };
+
+    let upload = MultipartUpload {
+        upload_id: &upload_id,
+        bucket: &bucket,
+        key: &key,
+        content_type,
+        metadata: &metadata,
+        storage_class: &storage_class,
+    };
+    let max_retries = state.config.get_busy_retry_max_attempts();
+    let result = retry_on_busy(max_retries, &mut conn, |conn| {
+        insert_multipart_upload(conn, &upload)
+    })
+    .await;
+
+    match result {
+        Ok(()) => xml_response(
+            StatusCode::OK,
+            format!(
+                r#"<?xml version="1.0" encoding="UTF-8"?>
+<InitiateMultipartUploadResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/"><Bucket>{bucket}</Bucket><Key>{}</Key><UploadId>{upload_id}</UploadId></InitiateMultipartUploadResult>"#,
+                html_escape(&key)
+            ),
+        ),
+        Err(e) => {
+            error!("Failed to create multipart upload for '{key}' in bucket '{bucket}': {e}");
+            database_error_response(&e)
+        }
+    }
+}
+
+/// UploadPart: PUT /{bucket}/{key}?partNumber=N&uploadId=X
+/// Parts are kept apart from the bucket table until the upload completes.
+/// With `x-amz-copy-source`, the part is copied from an existing object (UploadPartCopy).
+pub async fn upload_part(
+    state: Arc<AppState>,
+    bucket: String,
+    key: String,
+    params: HashMap<String, String>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let bucket = match validate_bucket(&bucket, &state.buckets) {
+        Ok(b) => b,
+        Err(resp) => return *resp,
+    };
+    let upload_id = params.get("uploadId").cloned().unwrap_or_default();
+    let part_number = match parse_part_number(&params) {
+        Ok(n) => n,
+        Err(resp) => return *resp,
+    };
+
+    if let Some(copy_source) = headers
+        .get("x-amz-copy-source")
+        .and_then(|v| v.to_str().ok())
+    {
+        return upload_part_copy(
+            &state,
+            &upload_id,
+            &bucket,
+            &key,
+            part_number,
+            copy_source,
+            &headers,
+        )
+        .await;
+    }
+
+    info!("UploadPart {part_number} of upload {upload_id} for '{key}' in bucket '{bucket}'");
+    let (md5_hash, sha256) = payload_digests(&body);
+    if let Err(e) = verify_content_sha256(&headers, &sha256) {
+        warn!("Rejected part {part_number} of upload {upload_id}: {e:?}");
+        return content_sha256_error_response(&e);
+    }
+    let mut conn = match state.write_conn() {
+        Ok(conn) => conn,
+        Err(e) => return pool_error_response(&e),
+    };
+
+    let max_retries = state.config.get_busy_retry_max_attempts();
+    let result = retry_on_busy(max_retries, &mut conn, |conn| {
+        put_part(
+            conn,
+            &upload_id,
+            &bucket,
+            &key,
+            part_number,
+            &body,
+            &md5_hash,
+        )
+    })
+    .await;
+
+    match result {
+        Ok(true) => {
+            let mut headers = HeaderMap::new();
+            headers.insert("ETag", format!("\"{md5_hash}\"").parse().unwrap());
+            (StatusCode::OK, headers).into_response()
+        }
+        Ok(false) => no_such_upload_response(&upload_id),
+        Err(e) => {
+            error!("Failed to store part {part_number} of upload {upload_id}: {e}");
+            database_error_response(&e)
+        }
+    }
+}
+
+/// UploadPartCopy: PUT /{bucket}/{key}?partNumber=N&uploadId=X with x-amz-copy-source
+/// Supports `x-amz-copy-source-range` and the x-amz-copy-source-if-* conditions.
+async fn upload_part_copy(
+    state: &AppState,
+    upload_id: &str,
+    bucket: &str,
+    key: &str,
+    part_number: u32,
+    copy_source: &str,
+    headers: &HeaderMap,
+) -> Response {
+    let Some((source_bucket, source_key)) = parse_copy_source(copy_source) else {
+        warn!("Invalid copy source: {copy_source}");
+        return xml_error_response(
+            StatusCode::BAD_REQUEST,
+            "InvalidArgument",
+            &format!("Invalid copy source: {copy_source}"),
+        );
+    };
+    let source_bucket = match validate_bucket(&source_bucket, &state.buckets) {
+        Ok(b) => b,
+        Err(resp) => return *resp,
+    };
+    let Some(source_table) = sanitize_bucket_name(&source_bucket, state.config.get_table_prefix())
+    else {
+        warn!("Invalid bucket name attempted: {source_bucket}");
+        return xml_error_response(
+            StatusCode::BAD_REQUEST,
+            "InvalidBucketName",
+            &format!("Invalid bucket name attempted: {source_bucket}"),
+        );
+    };
+    let range = match headers
+        .get("x-amz-copy-source-range")
+        .map(|v| v.to_str().ok().and_then(parse_copy_source_range))
+    {
+        None => None,
+        Some(Some(range)) => Some(range),
+        Some(None) => {
+            return xml_error_response(
+                StatusCode::BAD_REQUEST,
+                "InvalidArgument",
+                "The x-amz-copy-source-range value must be of the form bytes=first-last",
+            );
+        }
+    };
+
+    info!("UploadPartCopy {part_number} of upload {upload_id} from '{source_bucket}/{source_key}'");
+    let mut conn = match state.write_conn() {
+        Ok(conn) => conn,
+        Err(e) => return pool_error_response(&e),
+    };
+
+    let copy = PartCopy {
+        upload_id,
+        bucket,
+        key,
+        part_number,
+        source_table: &source_table,
+        source_key: &source_key,
+        range,
+    };
+    let max_retries = state.config.get_busy_retry_max_attempts();
+    let result = retry_on_busy(max_retries, &mut conn, |conn| {
+        copy_part(conn, &copy, |md5_hash, last_modified| {
+            copy_preconditions_hold(headers, md5_hash, last_modified)
+        })
+    })
+    .await;
+
+    match result {
+        Ok(PartCopyOutcome::Copied {
+            md5_hash,
+            last_modified,
+        }) => {
+            let last_modified =
+                DateTime::<Utc>::from_timestamp(last_modified, 0).unwrap_or(Utc::now());
+            xml_response(
+                StatusCode::OK,
+                format!(
+                    r#"<?xml version="1.0" encoding="UTF-8"?>
+<CopyPartResult><LastModified>{}</LastModified><ETag>"{}"</ETag></CopyPartResult>"#,
+                    last_modified.to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+                    md5_hash
+                ),
+            )
+        }
+        Ok(PartCopyOutcome::NoSuchUpload) => no_such_upload_response(upload_id),
+        Ok(PartCopyOutcome::SourceMissing) => xml_error_response(
+            StatusCode::NOT_FOUND,
+            "NoSuchKey",
+            &format!("The object you requested does not exist: {source_key}"),
+        ),
+        Ok(PartCopyOutcome::PreconditionFailed) => {
+            info!("Precondition failed for part copy of '{source_bucket}/{source_key}'");
+            precondition_failed_response()
+        }
+        Ok(PartCopyOutcome::InvalidRange(size)) => xml_error_response(
+            StatusCode::BAD_REQUEST,
+            "InvalidArgument",
+            &format!("Range specified is not valid for source object of size: {size}"),
+        ),
+        Err(e) => {
+            error!("Failed to copy part {part_number} of upload {upload_id}: {e}");
+            database_error_response(&e)
+        }
+    }
+}
+
+/// Parse an `x-amz-copy-source-range` value (`bytes=first-last`, both inclusive)
+fn parse_copy_source_range(value: &str) -> Option<(u64, u64)> {
+    let (first, last) = value.strip_prefix("bytes=")?.split_once('-')?;
+    Some((first.parse().ok()?, last.parse().ok()?))
+}
+
+/// CompleteMultipartUpload: POST /{bucket}/{key}?uploadId=X
+/// Assembles the parts listed in the `<CompleteMultipartUpload>` manifest into the object.
+async fn complete_multipart_upload(
+    state: Arc<AppState>,
+    bucket: String,
+    key: String,
+    upload_id: &str,
+    body: Bytes,
+) -> Response {
+    let bucket = match validate_bucket(&bucket, &state.buckets) {
+        Ok(b) => b,
+        Err(resp) => return *resp,
+    };
+    let Some(table_name) = sanitize_bucket_name(&bucket, state.config.get_table_prefix()) else {
+        warn!("Invalid bucket name attempted: {bucket}");
+        return xml_error_response(
+            StatusCode::BAD_REQUEST,
+            "InvalidBucketName",
+            &format!("Invalid bucket name attempted: {bucket}"),
+        );
+    };
+    let Some(manifest) = std::str::from_utf8(&body)
+        .ok()
+        .and_then(parse_complete_manifest)
+    else {
+        return xml_error_response(
+            StatusCode::BAD_REQUEST,
+            "MalformedXML",
+            "The XML you provided was not well-formed or did not validate against our published schema",
+        );
+    };
+
+    info!(
+        "CompleteMultipartUpload {upload_id} for '{key}' in bucket '{bucket}' with {} parts",
+        manifest.len()
+    );
+    let assembled = match assemble_object(&state, &bucket, upload_id, &manifest).await {
+        Ok(assembled) => assembled,
+        Err(resp) => return *resp,
+    };
+    let mut conn = match state.write_conn() {
+        Ok(conn) => conn,
+        Err(e) => return pool_error_response(&e),
+    };
+
+    let quota = ObjectQuota::for_bucket(&state.config, &bucket);
+    let max_retries = state.config.get_busy_retry_max_attempts();
+    let result = retry_on_busy(max_retries, &mut conn, |conn| {
+        complete_upload(
+            conn,
+            upload_id,
+            &bucket,
+            &key,
+            &table_name,
+            &manifest,
+            assembled.as_ref(),
+            state.config.is_write_once(&bucket),
+            quota.as_ref(),
+        )
+    })
+    .await;
+    state.invalidate_cached_object(&bucket, &key);
+
+    match result {
+        Ok(CompleteOutcome::Completed { etag }) => {
+            info!("Completed multipart upload {upload_id} of '{key}' in bucket '{bucket}'");
+            xml_response(
+                StatusCode::OK,
+                format!(
+                    r#"<?xml version="1.0" encoding="UTF-8"?>
+<CompleteMultipartUploadResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/"><Location>/{bucket}/{}</Location><Bucket>{bucket}</Bucket><Key>{}</Key><ETag>"{etag}"</ETag></CompleteMultipartUploadResult>"#,
+                    percent_encode_path(&key),
+                    html_escape(&key)
+                ),
+            )
+        }
+        Ok(CompleteOutcome::NoSuchUpload) => no_such_upload_response(upload_id),
+        Ok(CompleteOutcome::Immutable) => {
+            info!("Refused completion over '{key}' in write-once bucket '{bucket}'");
+            write_once_response()
+        }
+        Ok(CompleteOutcome::QuotaExceeded) => {
+            warn!("Refused completion of '{key}' over the quota of bucket '{bucket}'");
+            quota_exceeded_response()
+        }
+        Ok(CompleteOutcome::InvalidPart(part_number)) => xml_error_response(
+            StatusCode::BAD_REQUEST,
+            "InvalidPart",
+            &format!(
+                "One or more of the specified parts could not be found or its ETag did not match: part {part_number}"
+            ),
+        ),
+        Ok(CompleteOutcome::InvalidPartOrder) => xml_error_response(
+            StatusCode::BAD_REQUEST,
+            "InvalidPartOrder",
+            "The list of parts was not in ascending order. Parts must be ordered by part number.",
+        ),
+        Ok(CompleteOutcome::EntityTooSmall(part_number)) => xml_error_response(
+            StatusCode::BAD_REQUEST,
+            "EntityTooSmall",
+            &format!(
+                "Your proposed upload is smaller than the minimum allowed object size: part {part_number}"
+            ),
+        ),
+        Err(e) => {
+            error!("Failed to complete multipart upload {upload_id}: {e}");
+            database_error_response(&e)
+        }
+    }
+}
+
+/// The body of an upload to a compressed or deduplicated bucket, assembled, compressed
+/// and hashed before the write like uploads, so the writer is not held meanwhile.
+/// None if the object is stored as the concatenated parts: in other buckets, when it
+/// is neither deduplicated nor compresses well, or when it is larger than
+/// `max_object_size`, as it is assembled in memory.
+async fn assemble_object(
+    state: &AppState,
+    bucket: &str,
+    upload_id: &str,
+    manifest: &[(u32, String)],
+) -> Result<Option<AssembledObject>, Box<Response>> {
+    let compression = state.config.get_bucket_compression(bucket);
+    let dedup = state.config.is_dedup(bucket);
+    if compression.is_none() && !dedup {
+        return Ok(None);
+    }
+
+    let mut conn = match state.read_conn() {
+        Ok(conn) => conn,
+        Err(e) => return Err(Box::new(pool_error_response(&e))),
+    };
+    let max_size = state.config.get_max_object_size() as u64;
+    let max_retries = state.config.get_busy_retry_max_attempts();
+    let body = match retry_on_busy(max_retries, &mut conn, |conn| {
+        assemble_parts(conn, upload_id, manifest, max_size)
+    })
+    .await
+    {
+        Ok(Some(body)) => body,
+        // Missing or changed parts are reported by the completion
+        Ok(None) => return Ok(None),
+        Err(e) => {
+            error!("Failed to assemble multipart upload {upload_id}: {e}");
+            return Err(Box::new(database_error_response(&e)));
+        }
+    };
+    drop(conn);
+
+    let min_size = state.config.get_compression_min_size();
+    let compressed = compression.and_then(|compression| {
+        run_blocking(|| compress(compression, &body, min_size))
+            .map(|data| (data, (compression, body.len() as u64)))
+    });
+    let (data, compression) = match compressed {
+        Some((data, compression)) => (data, Some(compression)),
+        None if dedup => (body, None),
+        None => return Ok(None),
+    };
+    // Blobs are keyed by their stored bytes, which are the body unless compressed
+    let blob_hash = dedup.then(|| run_blocking(|| blob_hash(&data)));
+    Ok(Some(AssembledObject {
+        data,
+        compression,
+        blob_hash,
+    }))
+}
+
+/// AbortMultipartUpload: DELETE /{bucket}/{key}?uploadId=X
+/// Exactly one of a racing Complete and Abort succeeds: both remove the upload row
+/// inside an IMMEDIATE transaction, and the loser finds it gone.
+pub async fn abort_multipart_upload(
+    state: Arc<AppState>,
+    bucket: String,
+    key: String,
+    upload_id: &str,
+) -> Response {
+    let bucket = match validate_bucket(&bucket, &state.buckets) {
+        Ok(b) => b,
+        Err(resp) => return *resp,
+    };
+
+    info!("AbortMultipartUpload {upload_id} for '{key}' in bucket '{bucket}'");
+    let mut conn = match state.write_conn() {
+        Ok(conn) => conn,
+        Err(e) => return pool_error_response(&e),
+    };
+
+    let max_retries = state.config.get_busy_retry_max_attempts();
+    let result = retry_on_busy(max_retries, &mut conn, |conn| {
+        abort_upload(conn, upload_id, &bucket, &key)
+    })
+    .await;
+
+    match result {
+        Ok(true) => StatusCode::NO_CONTENT.into_response(),
+        Ok(false) => no_such_upload_response(upload_id),
+        Err(e) => {
+            error!("Failed to abort multipart upload {upload_id}: {e}");
+            database_error_response(&e)
+        }
+    }
+}
+
+/// ListParts: GET /{bucket}/{key}?uploadId=X
+/// Supports `max-parts` (at most 1000) and `part-number-marker` pagination.
+pub async fn list_multipart_parts(
+    state: Arc<AppState>,
+    bucket: String,
+    key: String,
+    params: HashMap<String, String>,
+) -> Response {
+    let bucket = match validate_bucket(&bucket, &state.buckets) {
+        Ok(b) => b,
+        Err(resp) => return *resp,
+    };
+    let upload_id = params.get("uploadId").cloned().unwrap_or_default();
+    let max_parts = match params.get("max-parts").map(|v| v.parse::<u32>()) {
+        None => MAX_PARTS_PER_LISTING,
+        Some(Ok(n)) => n.min(MAX_PARTS_PER_LISTING),
+        Some(Err(_)) => {
+            return xml_error_response(
+                StatusCode::BAD_REQUEST,
+                "InvalidArgument",
+                "max-parts must be a non-negative integer",
+            );
+        }
+    };
+    let marker = match params.get("part-number-marker").map(|v| v.parse::<u32>()) {
+        None => 0,
+        Some(Ok(n)) => n,
+        Some(Err(_)) => {
+            return xml_error_response(
+                StatusCode::BAD_REQUEST,
+                "InvalidArgument",
+                "part-number-marker must be a non-negative integer",
+            );
+        }
+    };
+
+    info!("ListParts of upload {upload_id} for '{key}' in bucket '{bucket}'");
+    let mut conn = match state.read_conn() {
+        Ok(conn) => conn,
+        Err(e) => return pool_error_response(&e),
+    };
+
+    let max_retries = state.config.get_busy_retry_max_attempts();
+    let result = retry_on_busy(max_retries, &mut conn, |conn| {
+        list_parts(conn, &upload_id, &bucket, &key, marker, max_parts)
+    })
+    .await;
+
+    let (storage_class, parts, is_truncated) = match result {
+        Ok(Some(listing)) => listing,
+        Ok(None) => return no_such_upload_response(&upload_id),
+        Err(e) => {
+            error!("Failed to list parts of upload {upload_id}: {e}");
+            return database_error_response(&e);
+        }
+    };
+
+    let owner = format!(
+        "<ID>{}</ID><DisplayName>{}</DisplayName>",
+        state.config.get_owner_id(),
+        state.config.get_owner_display_name()
+    );
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<ListPartsResult xmlns=\"http://s3.amazonaws.com/doc/2006-03-01/\">",
+    );
+    xml.push_str(&format!("<Bucket>{bucket}</Bucket>"));
+    xml.push_str(&format!("<Key>{}</Key>", html_escape(&key)));
+    xml.push_str(&format!("<UploadId>{upload_id}</UploadId>"));
+    xml.push_str(&format!("<Initiator>{owner}</Initiator>"));
+    xml.push_str(&format!("<Owner>{owner}</Owner>"));
+    xml.push_str(&format!("<StorageClass>{storage_class}</StorageClass>"));
+    xml.push_str(&format!("<PartNumberMarker>{marker}</PartNumberMarker>"));
+    if let Some(last) = parts.last() {
+        xml.push_str(&format!(
+            "<NextPartNumberMarker>{}</NextPartNumberMarker>",
+            last.part_number
+        ));
+    }
+    xml.push_str(&format!("<MaxParts>{max_parts}</MaxParts>"));
+    xml.push_str(&format!("<IsTruncated>{is_truncated}</IsTruncated>"));
+    for part in &parts {
+        let last_modified =
+            DateTime::<Utc>::from_timestamp(part.last_modified, 0).unwrap_or(Utc::now());
+        xml.push_str(&format!(
+            "<Part><PartNumber>{}</PartNumber><LastModified>{}</LastModified><ETag>\"{}\"</ETag><Size>{}</Size></Part>",
+            part.part_number,
+            last_modified.to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+            part.md5_hash,
+            part.size
+        ));
+    }
+    xml.push_str("</ListPartsResult>");
+
+    xml_response(StatusCode::OK, xml)
+}
+
+/// ListMultipartUploads: GET /{bucket}?uploads
+/// Supports `prefix`, `delimiter`, `key-marker`, `upload-id-marker` and `max-uploads`
+/// (at most 1000).
+pub async fn list_multipart_uploads(
+    state: Arc<AppState>,
+    bucket: String,
+    params: HashMap<String, String>,
+) -> Response {
+    let bucket = match validate_bucket(&bucket, &state.buckets) {
+        Ok(b) => b,
+        Err(resp) => return *resp,
+    };
+    let prefix = params.get("prefix").cloned().unwrap_or_default();
+    let delimiter = params
+        .get("delimiter")
+        .and_then(|d| if d.is_empty() { None } else { d.chars().next() });
+    let key_marker = params.get("key-marker").filter(|m| !m.is_empty()).cloned();
+    // The upload id marker is ignored without a key marker
+    let upload_id_marker = params
+        .get("upload-id-marker")
+        .filter(|m| !m.is_empty() && key_marker.is_some())
+        .cloned();
+    let max_uploads = match params.get("max-uploads").map(|v| v.parse::<usize>()) {
+        None => MAX_UPLOADS_PER_LISTING,
+        Some(Ok(n)) => n.min(MAX_UPLOADS_PER_LISTING),
+        Some(Err(_)) => {
+            return xml_error_response(
+                StatusCode::BAD_REQUEST,
+                "InvalidArgument",
+                "max-uploads must be a non-negative integer",
+            );
+        }
+    };
+
+    let mut conn = match state.read_conn() {
+        Ok(conn) => conn,
+        Err(e) => return pool_error_response(&e),
+    };
+
+    let max_retries = state.config.get_busy_retry_max_attempts();
+    let result = retry_on_busy(max_retries, &mut conn, |conn| {
+        list_uploads(
+            conn,
+            &bucket,
+            &prefix,
+            delimiter,
+            key_marker.as_deref(),
+            upload_id_marker.as_deref(),
+            max_uploads,
+        )
+    })
+    .await;
+    let listing = match result {
+        Ok(listing) => listing,
+        Err(e) => {
+            error!("Failed to list multipart uploads of bucket '{bucket}': {e}");
+            return database_error_response(&e);
+        }
+    };
+    info!(
+        "ListMultipartUploads for bucket '{bucket}': {} uploads, {} common prefixes",
+        listing.uploads.len(),
+        listing.common_prefixes.len()
+    );
+
+    // Continue after the entry that sorts last, whether an upload or a common prefix
+    let last_upload = listing.uploads.last();
+    let (next_key_marker, next_upload_id_marker) =
+        match (last_upload, listing.common_prefixes.last()) {
+            (Some(upload), Some(prefix)) if prefix.as_str() > upload.key.as_str() => {
+                (Some(prefix.as_str()), None)
+            }
+            (Some(upload), _) => (Some(upload.key.as_str()), Some(upload.upload_id.as_str())),
+            (None, prefix) => (prefix.map(|p| p.as_str()), None),
+        };
+
+    let owner = format!(
+        "<ID>{}</ID><DisplayName>{}</DisplayName>",
+        state.config.get_owner_id(),
+        state.config.get_owner_display_name()
+    );
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<ListMultipartUploadsResult xmlns=\"http://s3.amazonaws.com/doc/2006-03-01/\">",
+    );
+    xml.push_str(&format!("<Bucket>{bucket}</Bucket>"));
+    xml.push_str(&format!(
+        "<KeyMarker>{}</KeyMarker>",
+        html_escape(key_marker.as_deref().unwrap_or_default())
+    ));
+    xml.push_str(&format!(
+        "<UploadIdMarker>{}</UploadIdMarker>",
+        html_escape(upload_id_marker.as_deref().unwrap_or_default())
+    ));
+    if listing.is_truncated {
+        xml.push_str(&format!(
+            "<NextKeyMarker>{}</NextKeyMarker>",
+            html_escape(next_key_marker.unwrap_or_default())
+        ));
+        xml.push_str(&format!(
+            "<NextUploadIdMarker>{}</NextUploadIdMarker>",
+            next_upload_id_marker.unwrap_or_default()
+        ));
+    }
+    if let Some(delimiter) = delimiter {
+        xml.push_str(&format!(
+            "<Delimiter>{}</Delimiter>",
+            html_escape(&delimiter.to_string())
+        ));
+    }
+    xml.push_str(&format!("<Prefix>{}</Prefix>", html_escape(&prefix)));
+    xml.push_str(&format!("<MaxUploads>{max_uploads}</MaxUploads>"));
+    xml.push_str(&format!(
+        "<IsTruncated>{}</IsTruncated>",
+        listing.is_truncated
+    ));
+    for upload in &listing.uploads {
+        let initiated = DateTime::<Utc>::from_timestamp(upload.initiated, 0).unwrap_or(Utc::now());
+        xml.push_str(&format!(
+            "<Upload><Key>{}</Key><UploadId>{}</UploadId><Initiator>{owner}</Initiator><Owner>{owner}</Owner><StorageClass>{}</StorageClass><Initiated>{}</Initiated></Upload>",
+            html_escape(&upload.key),
+            upload.upload_id,
+            upload.storage_class,
+            initiated.to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
+        ));
+    }
+    for prefix in &listing.common_prefixes {
+        xml.push_str(&format!(
+            "<CommonPrefixes><Prefix>{}</Prefix></CommonPrefixes>",
+            html_escape(prefix)
+        ));
+    }
+    xml.push_str("</ListMultipartUploadsResult>");
+
+    xml_response(StatusCode::OK, xml)
+}
+
+/// Parse and validate the `partNumber` query parameter
+fn parse_part_number(params: &HashMap<String, String>) -> Result<u32, Box<Response>> {
+    params
+        .get("partNumber")
+        .and_then(|v| v.parse::<u32>().ok())
+        .filter(|n| PART_NUMBER_RANGE.contains(n))
+        .ok_or_else(|| {
+            Box::new(xml_error_response(
+                StatusCode::BAD_REQUEST,
+                "InvalidArgument",
+                "Part number must be an integer between 1 and 10000, inclusive",
+            ))
+        })
+}
+
+/// S3 404 NoSuchUpload response
+fn no_such_upload_response(upload_id: &str) -> Response {
+    xml_error_response(
+        StatusCode::NOT_FOUND,
+        "NoSuchUpload",
+        &format!(
+            "The specified upload does not exist. The upload ID may be invalid, or the upload may have been aborted or completed: {upload_id}"
+        ),
+    )
+}
@@ -1,78 +1,304 @@
 use axum::{
     body::Bytes,
-    extract::{Path, State},
+    extract::{Path, Query, State},
     http::{HeaderMap, StatusCode},
     response::{IntoResponse, Response},
 };
 use chrono::{DateTime, Utc};
+use flate2::read::MultiGzDecoder;
 use log::{error, info, warn};
-use rusqlite::params;
+use rusqlite::{Connection, MAIN_DB, OptionalExtension, TransactionBehavior, params};
+use std::collections::HashMap;
+use std::io::Read;
 use std::sync::Arc;
 
-use crate::models::AppState;
-use crate::utils::{sanitize_bucket_name, validate_bucket, xml_error_response};
+use crate::handlers::acl::{get_object_acl, put_object_acl};
+use crate::handlers::browse::{browse_bucket, wants_browser_ui};
+use crate::handlers::multipart::{abort_multipart_upload, list_multipart_parts, upload_part};
+use crate::handlers::write_batch::{PendingWrite, queue_write};
+use crate::models::{AppState, Compression};
+use crate::utils::{
+    blobs::{blob_hash, delete_unreferenced_blob, locate_data, store_blob},
+    bucket::{MISSING_MD5_DATA, OBJECT_SIZE, USAGE_SIZE, stored_or_computed_md5},
+    bucket_meta::{ObjectQuota, object_usage_size},
+    checksum::{
+        ChecksumError, ContentSha256Error, ObjectChecksum, payload_digests, request_checksum,
+        verify_content_sha256,
+    },
+    compression::{compress, decompress},
+    database_error_response, etag_matches,
+    expiry::{not_expired, requested_expiry},
+    keys::key_violation,
+    object_cache::CachedObject,
+    percent_decode, percent_encode_path, pool_error_response,
+    range::{ByteRange, resolve_range},
+    retry_on_busy, run_blocking, sanitize_bucket_name, validate_bucket, xml_error_response,
+    xml_response,
+};
+
+/// Outcome of a conditional write
+pub enum WriteOutcome {
+    Written,
+    PreconditionFailed,
+    /// The object exists in a write-once bucket and was kept
+    Immutable,
+    /// The write would take the bucket over its maximum number of objects or bytes
+    QuotaExceeded,
+}
+
+/// An object row written by `put_object_row`
+pub struct NewObject<'a> {
+    pub key: &'a str,
+    /// Data as stored, compressed if `compression` is set
+    pub data: &'a [u8],
+    pub md5_hash: &'a str,
+    pub storage_class: &'a str,
+    pub checksum: Option<&'a ObjectChecksum>,
+    pub content_encoding: Option<&'a str>,
+    /// Hex SHA-256 of the data
+    pub content_sha256: Option<&'a str>,
+    /// Seconds since the epoch after which the object is gone, None if it never expires
+    pub expires_at: Option<i64>,
+    /// Compression of the data and the size of the object before it, None if stored as sent
+    pub compression: Option<(Compression, u64)>,
+    /// Hex SHA-256 of the data if it is stored as a blob shared by identical objects
+    pub blob_hash: Option<&'a str>,
+}
+
+impl NewObject<'_> {
+    /// Bytes the object counts towards the usage of its bucket, see `USAGE_SIZE`
+    fn usage_size(&self) -> u64 {
+        match (self.blob_hash, self.compression) {
+            (Some(_), Some((_, original_size))) => original_size,
+            _ => self.data.len() as u64,
+        }
+    }
+}
+
+/// Outcome of a conditional server-side copy
+enum CopyOutcome {
+    Copied {
+        md5_hash: String,
+        last_modified: i64,
+    },
+    SourceMissing,
+    PreconditionFailed,
+    /// The target exists in a write-once bucket
+    Immutable,
+    /// The copy would take the bucket over its maximum number of objects or bytes
+    QuotaExceeded,
+}
+
+/// Outcome of a server-side rename
+enum RenameOutcome {
+    Renamed,
+    SourceMissing,
+    TargetExists,
+}
 
 /// Upload an object to a bucket
 /// PUT /{bucket}/{key}
 pub async fn upload_object(
     State(state): State<Arc<AppState>>,
     Path((bucket, key)): Path<(String, String)>,
+    query: Query<HashMap<String, String>>,
+    headers: HeaderMap,
     body: Bytes,
 ) -> Response {
+    if query.contains_key("uploadId") {
+        return upload_part(state, bucket, key, query.0, headers, body).await;
+    }
+    if query.contains_key("acl") {
+        return put_object_acl(state, bucket, key, headers, body).await;
+    }
+
     let bucket = match validate_bucket(&bucket, &state.buckets) {
         Ok(b) => b,
         Err(resp) => return *resp,
     };
 
-    info!("Uploading object '{key}' to bucket '{bucket}'");
-    let pool = &state.db_pool;
-    let conn = match pool.get() {
-        Ok(conn) => conn,
-        Err(e) => {
-            error!("Failed to get database connection: {e}");
+    if let Err(resp) = check_object_key(&state, &key) {
+        return *resp;
+    }
+    let storage_class = match check_upload_headers(&state, &headers, &key) {
+        Ok(class) => class,
+        Err(resp) => return *resp,
+    };
+    let dry_run = is_dry_run(&query, &headers);
+    let expires_at = match requested_expiry(&query, &headers, Utc::now()) {
+        Ok(expires_at) => expires_at,
+        Err(reason) => {
+            warn!("Rejected upload of '{key}' to bucket '{bucket}': {reason}");
+            return xml_error_response(StatusCode::BAD_REQUEST, "InvalidArgument", &reason);
+        }
+    };
+
+    if let Some(copy_source) = headers
+        .get("x-amz-copy-source")
+        .and_then(|v| v.to_str().ok())
+    {
+        if dry_run {
             return xml_error_response(
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "InternalError",
-                &format!("Database connection error: {e}"),
+                StatusCode::NOT_IMPLEMENTED,
+                "NotImplemented",
+                "Dry runs are not supported for copies",
             );
         }
-    };
+        return copy_object(
+            &state,
+            &bucket,
+            &key,
+            copy_source,
+            &storage_class,
+            expires_at,
+            &headers,
+        )
+        .await;
+    }
 
-    match sanitize_bucket_name(&bucket) {
+    info!("Uploading object '{key}' to bucket '{bucket}'");
+    match sanitize_bucket_name(&bucket, state.config.get_table_prefix()) {
         Some(table_name) => {
-            // Calculate MD5 hash of the data
-            let md5_hash = hex::encode(md5::compute(&body[..]).0);
+            // Validate or compute the additional checksum requested by the client
+            let checksum = match request_checksum(&headers, &body) {
+                Ok(checksum) => checksum,
+                Err(e) => {
+                    warn!("Rejected upload of '{key}' to bucket '{bucket}': {e:?}");
+                    return checksum_error_response(&e);
+                }
+            };
 
-            let sql = format!(
-                "INSERT INTO {table_name} (key, data, md5) VALUES (?1, ?2, ?3)
-                 ON CONFLICT(key) DO UPDATE SET data=excluded.data, md5=excluded.md5",
-            );
+            let (md5_hash, sha256) = payload_digests(&body);
+            if let Err(e) = verify_content_sha256(&headers, &sha256) {
+                warn!("Rejected upload of '{key}' to bucket '{bucket}': {e:?}");
+                return content_sha256_error_response(&e);
+            }
+            let content_encoding = stored_content_encoding(&headers);
+
+            // Compressed before the write, so the writer is not held while compressing
+            let compression = state.config.get_bucket_compression(&bucket);
+            let compressed = compression.and_then(|compression| {
+                let min_size = state.config.get_compression_min_size();
+                run_blocking(|| compress(compression, &body, min_size))
+                    .map(|data| (Bytes::from(data), (compression, body.len() as u64)))
+            });
+            let (stored, compression) = match compressed {
+                Some((data, compression)) => (data, Some(compression)),
+                None => (body.clone(), None),
+            };
+            // Blobs are keyed by their stored bytes, which are the body unless compressed
+            let shared_blob = state.config.is_dedup(&bucket).then(|| match compression {
+                Some(_) => run_blocking(|| blob_hash(&stored)),
+                None => sha256.clone(),
+            });
+
+            // An empty body is bound as a zero-length blob, not NULL, so empty objects
+            // and "directory marker" keys ending in `/` have a length of 0
+            let object = NewObject {
+                key: &key,
+                data: &stored,
+                md5_hash: &md5_hash,
+                storage_class: &storage_class,
+                checksum: checksum.as_ref(),
+                content_encoding: content_encoding.as_deref(),
+                content_sha256: Some(&sha256),
+                expires_at,
+                compression,
+                blob_hash: shared_blob.as_deref(),
+            };
+            let write_once = state.config.is_write_once(&bucket);
+            let quota = ObjectQuota::for_bucket(&state.config, &bucket);
+            // Checked after compression, as quotas count the stored bytes
+            if dry_run {
+                let mut conn = match state.read_conn() {
+                    Ok(conn) => conn,
+                    Err(e) => return pool_error_response(&e),
+                };
+                return dry_run_upload(
+                    &state,
+                    &mut conn,
+                    &table_name,
+                    &object,
+                    &headers,
+                    write_once,
+                    quota.as_ref(),
+                )
+                .await;
+            }
+            // With write batching the upload is committed by the batching task
+            let batched = match state.write_batch {
+                Some(_) => {
+                    let write = PendingWrite {
+                        bucket: bucket.clone(),
+                        table_name: table_name.clone(),
+                        key: key.clone(),
+                        data: stored.clone(),
+                        md5_hash: md5_hash.clone(),
+                        storage_class: storage_class.clone(),
+                        checksum: checksum.clone(),
+                        content_encoding: content_encoding.clone(),
+                        content_sha256: Some(sha256.clone()),
+                        expires_at,
+                        compression,
+                        blob_hash: shared_blob.clone(),
+                        headers: headers.clone(),
+                        write_once,
+                        max_objects: quota.as_ref().and_then(|q| q.max_objects),
+                        max_bytes: quota.as_ref().and_then(|q| q.max_bytes),
+                    };
+                    queue_write(&state, write).await
+                }
+                None => None,
+            };
+            let result = match batched {
+                Some(result) => result,
+                None => {
+                    let mut conn = match state.write_conn() {
+                        Ok(conn) => conn,
+                        Err(e) => return pool_error_response(&e),
+                    };
+                    let max_retries = state.config.get_busy_retry_max_attempts();
+                    retry_on_busy(max_retries, &mut conn, |conn| {
+                        put_object_row(
+                            conn,
+                            &table_name,
+                            &object,
+                            &headers,
+                            write_once,
+                            quota.as_ref(),
+                        )
+                    })
+                    .await
+                }
+            };
+            state.invalidate_cached_object(&bucket, &key);
 
-            match conn.prepare(&sql) {
-                Ok(mut stmt) => {
-                    match stmt.execute(params![key, &body[..], md5_hash]) {
-                        Ok(_) => {
-                            info!("Uploaded object '{key}' to bucket '{bucket}'");
-                            // S3: 200 OK, no body required
-                            StatusCode::OK.into_response()
-                        }
-                        Err(e) => {
-                            error!("Failed to upload object '{key}' to bucket '{bucket}': {e}");
-                            xml_error_response(
-                                StatusCode::INTERNAL_SERVER_ERROR,
-                                "InternalError",
-                                &e.to_string(),
-                            )
-                        }
+            match result {
+                Ok(WriteOutcome::Written) => {
+                    info!("Uploaded object '{key}' to bucket '{bucket}'");
+                    // S3: 200 OK, no body required
+                    let mut headers = HeaderMap::new();
+                    headers.insert("ETag", format!("\"{md5_hash}\"").parse().unwrap());
+                    if let Some(checksum) = &checksum {
+                        checksum.insert_header(&mut headers);
                     }
+                    (StatusCode::OK, headers).into_response()
+                }
+                Ok(WriteOutcome::PreconditionFailed) => {
+                    info!("Precondition failed for upload of '{key}' to bucket '{bucket}'");
+                    precondition_failed_response()
+                }
+                Ok(WriteOutcome::Immutable) => {
+                    info!("Refused overwrite of '{key}' in write-once bucket '{bucket}'");
+                    write_once_response()
+                }
+                Ok(WriteOutcome::QuotaExceeded) => {
+                    warn!("Refused '{key}' over the quota of bucket '{bucket}'");
+                    quota_exceeded_response()
                 }
                 Err(e) => {
-                    error!("Failed to prepare statement: {e}");
-                    xml_error_response(
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                        "InternalError",
-                        &e.to_string(),
-                    )
+                    error!("Failed to upload object '{key}' to bucket '{bucket}': {e}");
+                    database_error_response(&e)
                 }
             }
         }
@@ -87,12 +313,264 @@ pub async fn upload_object(
     }
 }
 
+/// Whether an upload asks to be validated only, via `x-amz-dry-run: true` or `?dry-run`
+fn is_dry_run(query: &HashMap<String, String>, headers: &HeaderMap) -> bool {
+    query.contains_key("dry-run")
+        || headers
+            .get("x-amz-dry-run")
+            .is_some_and(|v| v.as_bytes().eq_ignore_ascii_case(b"true"))
+}
+
+/// Answer a dry-run upload whose headers and body already passed validation:
+/// evaluate the conditional headers against the current object, the quota of the
+/// bucket and refuse overwrites in a `write_once` bucket, as `write_object_row` does,
+/// and report the would-be ETag, without writing anything
+async fn dry_run_upload(
+    state: &AppState,
+    conn: &mut Connection,
+    table_name: &str,
+    object: &NewObject<'_>,
+    headers: &HeaderMap,
+    write_once: bool,
+    quota: Option<&ObjectQuota<'_>>,
+) -> Response {
+    let key = object.key;
+    let max_retries = state.config.get_busy_retry_max_attempts();
+    let (current_md5, fits_quota) = match retry_on_busy(max_retries, conn, |conn| {
+        let current_md5 = query_current_md5(conn, table_name, key)?;
+        Ok((
+            current_md5,
+            object_fits_quota(conn, table_name, object, quota)?,
+        ))
+    })
+    .await
+    {
+        Ok(checked) => checked,
+        Err(e) => {
+            error!("Failed to check dry-run upload of '{key}': {e}");
+            return database_error_response(&e);
+        }
+    };
+    if !write_preconditions_hold(headers, current_md5.as_deref()) {
+        info!("Precondition failed for dry-run upload of '{key}'");
+        return precondition_failed_response();
+    }
+    if !fits_quota {
+        warn!("Refused dry-run upload of '{key}' over the quota of its bucket");
+        return quota_exceeded_response();
+    }
+    if write_once && current_md5.is_some() {
+        info!("Refused dry-run overwrite of '{key}' in a write-once bucket");
+        return write_once_response();
+    }
+
+    info!("Dry-run upload of '{key}' would be accepted");
+    let mut headers = HeaderMap::new();
+    headers.insert("ETag", format!("\"{}\"", object.md5_hash).parse().unwrap());
+    headers.insert("x-amz-dry-run", "true".parse().unwrap());
+    (StatusCode::OK, headers).into_response()
+}
+
+/// Copy an object server-side
+/// PUT /{bucket}/{key} with x-amz-copy-source
+async fn copy_object(
+    state: &AppState,
+    bucket: &str,
+    key: &str,
+    copy_source: &str,
+    storage_class: &str,
+    expires_at: Option<i64>,
+    headers: &HeaderMap,
+) -> Response {
+    let Some((source_bucket, source_key)) = parse_copy_source(copy_source) else {
+        warn!("Invalid copy source: {copy_source}");
+        return xml_error_response(
+            StatusCode::BAD_REQUEST,
+            "InvalidArgument",
+            &format!("Invalid copy source: {copy_source}"),
+        );
+    };
+    let source_bucket = match validate_bucket(&source_bucket, &state.buckets) {
+        Ok(b) => b,
+        Err(resp) => return *resp,
+    };
+    let (Some(source_table), Some(table_name)) = (
+        sanitize_bucket_name(&source_bucket, state.config.get_table_prefix()),
+        sanitize_bucket_name(bucket, state.config.get_table_prefix()),
+    ) else {
+        warn!("Invalid bucket name attempted: {source_bucket} -> {bucket}");
+        return xml_error_response(
+            StatusCode::BAD_REQUEST,
+            "InvalidBucketName",
+            &format!("Invalid bucket name attempted: {source_bucket} -> {bucket}"),
+        );
+    };
+
+    info!("Copying object '{source_bucket}/{source_key}' to '{bucket}/{key}'");
+    let mut conn = match state.write_conn() {
+        Ok(conn) => conn,
+        Err(e) => return pool_error_response(&e),
+    };
+
+    let quota = ObjectQuota::for_bucket(&state.config, bucket);
+    let max_retries = state.config.get_busy_retry_max_attempts();
+    let result = retry_on_busy(max_retries, &mut conn, |conn| {
+        copy_object_row(
+            conn,
+            &source_table,
+            &source_key,
+            &table_name,
+            key,
+            storage_class,
+            expires_at,
+            headers,
+            state.config.is_write_once(bucket),
+            quota.as_ref(),
+        )
+    })
+    .await;
+    state.invalidate_cached_object(bucket, key);
+
+    match result {
+        Ok(CopyOutcome::Copied {
+            md5_hash,
+            last_modified,
+        }) => {
+            info!("Copied object '{source_bucket}/{source_key}' to '{bucket}/{key}'");
+            let last_modified =
+                DateTime::<Utc>::from_timestamp(last_modified, 0).unwrap_or(Utc::now());
+            xml_response(
+                StatusCode::OK,
+                format!(
+                    r#"<?xml version="1.0" encoding="UTF-8"?>
+<CopyObjectResult><LastModified>{}</LastModified><ETag>"{}"</ETag></CopyObjectResult>"#,
+                    last_modified.to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+                    md5_hash
+                ),
+            )
+        }
+        Ok(CopyOutcome::SourceMissing) => xml_error_response(
+            StatusCode::NOT_FOUND,
+            "NoSuchKey",
+            &format!("The object you requested does not exist: {source_key}"),
+        ),
+        Ok(CopyOutcome::PreconditionFailed) => {
+            info!("Precondition failed for copy of '{source_bucket}/{source_key}'");
+            precondition_failed_response()
+        }
+        Ok(CopyOutcome::Immutable) => {
+            info!("Refused copy over '{key}' in write-once bucket '{bucket}'");
+            write_once_response()
+        }
+        Ok(CopyOutcome::QuotaExceeded) => {
+            warn!("Refused copy to '{key}' over the quota of bucket '{bucket}'");
+            quota_exceeded_response()
+        }
+        Err(e) => {
+            error!("Failed to copy object '{source_bucket}/{source_key}' to '{bucket}/{key}': {e}");
+            database_error_response(&e)
+        }
+    }
+}
+
+/// Rename an object within its bucket (non-standard)
+/// POST /{bucket}/{key}?rename with the URL-encoded new key in x-s3insqlite-rename-target.
+/// Only the key changes: data, ETag and last modified time are preserved. An existing
+/// target is replaced only with `x-s3insqlite-rename-overwrite: true`.
+pub async fn rename_object(
+    state: Arc<AppState>,
+    bucket: String,
+    key: String,
+    headers: HeaderMap,
+) -> Response {
+    let bucket = match validate_bucket(&bucket, &state.buckets) {
+        Ok(b) => b,
+        Err(resp) => return *resp,
+    };
+    // A rename deletes the object at its old key
+    if state.config.is_write_once(&bucket) {
+        info!("Refused rename of '{key}' in write-once bucket '{bucket}'");
+        return write_once_response();
+    }
+    let Some(target) = headers
+        .get("x-s3insqlite-rename-target")
+        .and_then(|v| v.to_str().ok())
+        .and_then(percent_decode)
+        .filter(|t| !t.is_empty())
+    else {
+        return xml_error_response(
+            StatusCode::BAD_REQUEST,
+            "InvalidArgument",
+            "Missing or invalid x-s3insqlite-rename-target header",
+        );
+    };
+    if let Err(resp) = check_object_key(&state, &target) {
+        return *resp;
+    }
+    let overwrite = headers
+        .get("x-s3insqlite-rename-overwrite")
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.eq_ignore_ascii_case("true"));
+    let Some(table_name) = sanitize_bucket_name(&bucket, state.config.get_table_prefix()) else {
+        warn!("Invalid bucket name attempted: {bucket}");
+        return xml_error_response(
+            StatusCode::BAD_REQUEST,
+            "InvalidBucketName",
+            &format!("Invalid bucket name attempted: {bucket}"),
+        );
+    };
+
+    info!("Renaming object '{key}' to '{target}' in bucket '{bucket}'");
+    let mut conn = match state.write_conn() {
+        Ok(conn) => conn,
+        Err(e) => return pool_error_response(&e),
+    };
+
+    let max_retries = state.config.get_busy_retry_max_attempts();
+    let result = retry_on_busy(max_retries, &mut conn, |conn| {
+        rename_object_row(conn, &table_name, &key, &target, overwrite)
+    })
+    .await;
+    state.invalidate_cached_object(&bucket, &key);
+    state.invalidate_cached_object(&bucket, &target);
+
+    match result {
+        Ok(RenameOutcome::Renamed) => {
+            info!("Renamed object '{key}' to '{target}' in bucket '{bucket}'");
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Ok(RenameOutcome::SourceMissing) => xml_error_response(
+            StatusCode::NOT_FOUND,
+            "NoSuchKey",
+            &format!("The object you requested does not exist: {key}"),
+        ),
+        Ok(RenameOutcome::TargetExists) => xml_error_response(
+            StatusCode::CONFLICT,
+            "KeyAlreadyExists",
+            &format!("The target key already exists: {target}"),
+        ),
+        Err(e) => {
+            error!("Failed to rename object '{key}' to '{target}' in bucket '{bucket}': {e}");
+            database_error_response(&e)
+        }
+    }
+}
+
 /// Download an object from a bucket
 /// GET /{bucket}/{key}
 pub async fn download_object(
     State(state): State<Arc<AppState>>,
     Path((bucket, key)): Path<(String, String)>,
+    query: Query<HashMap<String, String>>,
+    headers: HeaderMap,
 ) -> Response {
+    if query.contains_key("uploadId") {
+        return list_multipart_parts(state, bucket, key, query.0).await;
+    }
+    if query.contains_key("acl") {
+        return get_object_acl(state, bucket, key).await;
+    }
+
     info!("Downloading object '{key}' from bucket '{bucket}'");
 
     let bucket = match validate_bucket(&bucket, &state.buckets) {
@@ -100,43 +578,67 @@ pub async fn download_object(
         Err(resp) => return *resp,
     };
 
-    let pool = &state.db_pool;
-    let conn = match pool.get() {
+    // A browser following a "directory" link of the browse UI
+    if key.ends_with('/') && wants_browser_ui(&state, &headers) {
+        return browse_bucket(&state, &bucket, &key).await;
+    }
+
+    let range = headers.get("Range").and_then(|v| v.to_str().ok());
+    let decode_limit = (state.config.decodes_content_encoding() && accepts_identity_only(&headers))
+        .then(|| state.config.get_max_object_size());
+    // Objects decoded for identity-only clients bypass the cache
+    let cache = state
+        .object_cache
+        .as_ref()
+        .filter(|_| decode_limit.is_none());
+    if let Some(cache) = cache
+        && let Some(object) = cache.get(&bucket, &key, Utc::now().timestamp())
+    {
+        let object = ObjectRead::from_cached(&object, range);
+        return object_read_response(&headers, &bucket, &key, range, object);
+    }
+    let generation = cache.map(|cache| cache.generation());
+
+    let mut conn = match state.read_conn() {
         Ok(conn) => conn,
-        Err(e) => {
-            error!("Failed to get database connection: {e}");
-            return xml_error_response(
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "InternalError",
-                &format!("Database connection error: {e}"),
-            );
-        }
+        Err(e) => return pool_error_response(&e),
     };
 
-    match sanitize_bucket_name(&bucket) {
+    match sanitize_bucket_name(&bucket, state.config.get_table_prefix()) {
         Some(table_name) => {
-            let sql = format!("SELECT data FROM {table_name} WHERE key = ?1");
-            match conn.query_row(&sql, params![key], |row| row.get::<_, Vec<u8>>(0)) {
-                Ok(data) => {
-                    info!("Downloaded object '{key}' from bucket '{bucket}'");
-                    let mut headers = HeaderMap::new();
-                    headers.insert("Content-Type", "application/octet-stream".parse().unwrap());
-                    headers.insert("Content-Length", data.len().to_string().parse().unwrap());
-
-                    (StatusCode::OK, headers, data).into_response()
+            let max_retries = state.config.get_busy_retry_max_attempts();
+            let result = retry_on_busy(max_retries, &mut conn, |conn| {
+                read_object_range(conn, &table_name, &key, range, decode_limit)
+            })
+            .await;
+            match result {
+                Ok(Some(object)) => {
+                    // Whole objects read for a GET without Range fill the cache
+                    if let (Some(cache), Some(generation)) = (cache, generation)
+                        && range.is_none()
+                        && cache.admits(object.data.len())
+                    {
+                        let cached = CachedObject {
+                            data: object.data.clone(),
+                            md5_hash: object.md5_hash.clone(),
+                            last_modified: object.last_modified,
+                            storage_class: object.storage_class.clone(),
+                            checksum: object.checksum.clone(),
+                            content_encoding: object.content_encoding.clone(),
+                            expires_at: object.expires_at,
+                        };
+                        cache.insert(&bucket, &key, cached, generation);
+                    }
+                    object_read_response(&headers, &bucket, &key, range, object)
                 }
-                Err(rusqlite::Error::QueryReturnedNoRows) => xml_error_response(
+                Ok(None) => xml_error_response(
                     StatusCode::NOT_FOUND,
                     "NoSuchKey",
                     &format!("The object you requested does not exist: {key}"),
                 ),
                 Err(e) => {
                     error!("Failed to download object '{key}' from bucket '{bucket}': {e}");
-                    xml_error_response(
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                        "InternalError",
-                        &e.to_string(),
-                    )
+                    database_error_response(&e)
                 }
             }
         }
@@ -151,47 +653,129 @@ pub async fn download_object(
     }
 }
 
+/// Response to a GET of an object: 304 or 412 if a precondition says so, 416 for an
+/// unsatisfiable range, else the object or its requested range
+fn object_read_response(
+    request_headers: &HeaderMap,
+    bucket: &str,
+    key: &str,
+    range: Option<&str>,
+    object: ObjectRead,
+) -> Response {
+    if let Some(status) =
+        read_precondition_status(request_headers, &object.md5_hash, object.last_modified)
+    {
+        info!("Precondition of GET '{key}' in bucket '{bucket}' gave {status}");
+        return read_precondition_response(status, &object.md5_hash, object.last_modified);
+    }
+    if object.range == ByteRange::Unsatisfiable {
+        info!("Unsatisfiable range {range:?} for '{key}' in bucket '{bucket}'");
+        let mut response = xml_error_response(
+            StatusCode::RANGE_NOT_SATISFIABLE,
+            "InvalidRange",
+            "The requested range is not satisfiable",
+        );
+        if let Some(content_range) = object.range.content_range(object.size) {
+            response
+                .headers_mut()
+                .insert("Content-Range", content_range.parse().unwrap());
+        }
+        return response;
+    }
+
+    info!("Downloaded object '{key}' from bucket '{bucket}'");
+    let mut headers = validator_headers(&object.md5_hash, object.last_modified);
+    headers.insert("Content-Type", "application/octet-stream".parse().unwrap());
+    headers.insert(
+        "Content-Length",
+        object.data.len().to_string().parse().unwrap(),
+    );
+    headers.insert("Accept-Ranges", "bytes".parse().unwrap());
+    headers.insert("x-amz-storage-class", object.storage_class.parse().unwrap());
+    if let Some(encoding) = object.content_encoding.and_then(|e| e.parse().ok()) {
+        headers.insert("Content-Encoding", encoding);
+    }
+    match object.range.content_range(object.size) {
+        // The checksum covers the whole object, not the range sent
+        Some(content_range) => {
+            headers.insert("Content-Range", content_range.parse().unwrap());
+            (StatusCode::PARTIAL_CONTENT, headers, object.data).into_response()
+        }
+        None => {
+            if let Some(checksum) = object.checksum {
+                checksum.insert_header(&mut headers);
+            }
+            (StatusCode::OK, headers, object.data).into_response()
+        }
+    }
+}
+
 /// Delete an object from a bucket
 /// DELETE /{bucket}/{key}
 pub async fn delete_object(
     State(state): State<Arc<AppState>>,
     Path((bucket, key)): Path<(String, String)>,
+    query: Query<HashMap<String, String>>,
+    headers: HeaderMap,
 ) -> Response {
+    if let Some(upload_id) = query.get("uploadId") {
+        return abort_multipart_upload(state, bucket, key, upload_id).await;
+    }
+
     info!("Deleting object '{key}' from bucket '{bucket}'");
 
     let bucket = match validate_bucket(&bucket, &state.buckets) {
         Ok(b) => b,
         Err(resp) => return *resp,
     };
+    if state.config.is_write_once(&bucket) {
+        info!("Refused delete of '{key}' in write-once bucket '{bucket}'");
+        return write_once_response();
+    }
+    // Only one version of an object is stored, the `null` version S3 assigns
+    // to objects written while versioning is not enabled
+    let version_id = query.get("versionId");
+    if let Some(version_id) = version_id
+        && version_id != "null"
+    {
+        info!("Delete of version '{version_id}' of '{key}' in bucket '{bucket}' refused");
+        return xml_error_response(
+            StatusCode::NOT_FOUND,
+            "NoSuchVersion",
+            "The specified version does not exist.",
+        );
+    }
 
-    let pool = &state.db_pool;
-    let conn = match pool.get() {
+    let mut conn = match state.write_conn() {
         Ok(conn) => conn,
-        Err(e) => {
-            error!("Failed to get database connection: {e}");
-            return xml_error_response(
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "InternalError",
-                &format!("Database connection error: {e}"),
-            );
-        }
+        Err(e) => return pool_error_response(&e),
     };
 
-    match sanitize_bucket_name(&bucket) {
+    match sanitize_bucket_name(&bucket, state.config.get_table_prefix()) {
         Some(table_name) => {
-            let sql = format!("DELETE FROM {table_name} WHERE key = ?1");
-            match conn.execute(&sql, params![key]) {
-                Ok(_) => {
+            let max_retries = state.config.get_busy_retry_max_attempts();
+            let result = retry_on_busy(max_retries, &mut conn, |conn| {
+                delete_object_row(conn, &table_name, &key, &headers)
+            })
+            .await;
+            state.invalidate_cached_object(&bucket, &key);
+
+            match result {
+                Ok(true) => {
                     info!("Deleted object '{key}' from bucket '{bucket}'");
-                    StatusCode::NO_CONTENT.into_response()
+                    let mut response_headers = HeaderMap::new();
+                    if version_id.is_some() {
+                        response_headers.insert("x-amz-version-id", "null".parse().unwrap());
+                    }
+                    (StatusCode::NO_CONTENT, response_headers).into_response()
+                }
+                Ok(false) => {
+                    info!("Precondition failed for delete of '{key}' from bucket '{bucket}'");
+                    precondition_failed_response()
                 }
                 Err(e) => {
                     error!("Failed to delete object '{key}' from bucket '{bucket}': {e}");
-                    xml_error_response(
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                        "InternalError",
-                        &e.to_string(),
-                    )
+                    database_error_response(&e)
                 }
             }
         }
@@ -211,6 +795,7 @@ pub async fn delete_object(
 pub async fn head_object(
     State(state): State<Arc<AppState>>,
     Path((bucket, key)): Path<(String, String)>,
+    headers: HeaderMap,
 ) -> Response {
     let bucket = match validate_bucket(&bucket, &state.buckets) {
         Ok(b) => b,
@@ -218,43 +803,63 @@ pub async fn head_object(
     };
 
     info!("HEAD object '{key}' from bucket '{bucket}'");
-    let pool = &state.db_pool;
-    let conn = match pool.get() {
+    if let Some(cache) = &state.object_cache
+        && let Some(object) = cache.get(&bucket, &key, Utc::now().timestamp())
+    {
+        return object_head_response(
+            &headers,
+            object.data.len() as i64,
+            object.last_modified,
+            &object.md5_hash,
+            &object.storage_class,
+            object.checksum.clone(),
+            object.content_encoding.clone(),
+        );
+    }
+    let mut conn = match state.read_conn() {
         Ok(conn) => conn,
-        Err(e) => {
-            error!("Failed to get database connection: {e}");
-            return xml_error_response(
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "InternalError",
-                &format!("Database connection error: {e}"),
-            );
-        }
+        Err(e) => return pool_error_response(&e),
     };
 
-    match sanitize_bucket_name(&bucket) {
+    match sanitize_bucket_name(&bucket, state.config.get_table_prefix()) {
         Some(table_name) => {
-            let sql =
-                format!("SELECT LENGTH(data), last_modified, md5 FROM {table_name} WHERE key = ?1");
-            match conn.query_row(&sql, params![key], |row| {
-                let size: i64 = row.get(0)?;
-                let last_modified: i64 = row.get(1)?;
-                let md5_hash: String = row.get(2)?;
-                Ok((size, last_modified, md5_hash))
-            }) {
-                Ok((size, last_modified, md5_hash)) => {
-                    // Convert seconds timestamp to DateTime
-                    let last_modified_datetime =
-                        DateTime::<Utc>::from_timestamp(last_modified, 0).unwrap_or(Utc::now());
-
-                    let mut headers = HeaderMap::new();
-                    headers.insert("Content-Length", size.to_string().parse().unwrap());
-                    headers.insert(
-                        "Last-Modified",
-                        last_modified_datetime.to_rfc2822().parse().unwrap(),
-                    );
-                    headers.insert("ETag", format!("\"{}\"", md5_hash).parse().unwrap());
-
-                    (StatusCode::OK, headers).into_response()
+            let sql = format!(
+                "SELECT {OBJECT_SIZE}, last_modified, md5, storage_class, {MISSING_MD5_DATA},
+                 checksum_algorithm, checksum, content_encoding FROM {table_name}
+                 WHERE key = ?1 AND {}",
+                not_expired(2)
+            );
+            let max_retries = state.config.get_busy_retry_max_attempts();
+            let result = retry_on_busy(max_retries, &mut conn, |conn| {
+                conn.query_row(&sql, params![key, Utc::now().timestamp()], |row| {
+                    let size: i64 = row.get(0)?;
+                    let last_modified: i64 = row.get(1)?;
+                    let md5_hash = stored_or_computed_md5(row.get(2)?, row.get(4)?);
+                    let storage_class: String = row.get(3)?;
+                    let checksum = ObjectChecksum::from_columns(row.get(5)?, row.get(6)?);
+                    let content_encoding: Option<String> = row.get(7)?;
+                    Ok((
+                        size,
+                        last_modified,
+                        md5_hash,
+                        storage_class,
+                        checksum,
+                        content_encoding,
+                    ))
+                })
+            })
+            .await;
+            match result {
+                Ok((size, last_modified, md5_hash, storage_class, checksum, content_encoding)) => {
+                    object_head_response(
+                        &headers,
+                        size,
+                        last_modified,
+                        &md5_hash,
+                        &storage_class,
+                        checksum,
+                        content_encoding,
+                    )
                 }
                 Err(rusqlite::Error::QueryReturnedNoRows) => xml_error_response(
                     StatusCode::NOT_FOUND,
@@ -263,11 +868,7 @@ pub async fn head_object(
                 ),
                 Err(e) => {
                     error!("Failed to head object '{key}' from bucket '{bucket}': {e}");
-                    xml_error_response(
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                        "InternalError",
-                        &e.to_string(),
-                    )
+                    database_error_response(&e)
                 }
             }
         }
@@ -281,3 +882,835 @@ pub async fn head_object(
         }
     }
 }
+
+/// Response to a HEAD of an object: 304 or 412 if a precondition says so, else its headers
+fn object_head_response(
+    request_headers: &HeaderMap,
+    size: i64,
+    last_modified: i64,
+    md5_hash: &str,
+    storage_class: &str,
+    checksum: Option<ObjectChecksum>,
+    content_encoding: Option<String>,
+) -> Response {
+    if let Some(status) = read_precondition_status(request_headers, md5_hash, last_modified) {
+        return read_precondition_response(status, md5_hash, last_modified);
+    }
+
+    let mut headers = validator_headers(md5_hash, last_modified);
+    headers.insert("Content-Length", size.to_string().parse().unwrap());
+    headers.insert("Accept-Ranges", "bytes".parse().unwrap());
+    headers.insert("x-amz-storage-class", storage_class.parse().unwrap());
+    if let Some(checksum) = checksum {
+        checksum.insert_header(&mut headers);
+    }
+    if let Some(encoding) = content_encoding.and_then(|e| e.parse().ok()) {
+        headers.insert("Content-Encoding", encoding);
+    }
+
+    (StatusCode::OK, headers).into_response()
+}
+
+/// Insert or replace an object row inside an IMMEDIATE transaction,
+/// so the write lock is taken before the preconditions are evaluated.
+/// In a `write_once` bucket an existing object is never replaced.
+pub fn put_object_row(
+    conn: &mut Connection,
+    table_name: &str,
+    object: &NewObject,
+    headers: &HeaderMap,
+    write_once: bool,
+    quota: Option<&ObjectQuota>,
+) -> rusqlite::Result<WriteOutcome> {
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    let outcome = write_object_row(&tx, table_name, object, headers, write_once, quota)?;
+    if matches!(outcome, WriteOutcome::Written) {
+        tx.commit()?;
+    }
+    Ok(outcome)
+}
+
+/// Evaluate the preconditions and the quota of an upload and write its row, within
+/// a transaction (or savepoint) of the caller that holds the write lock
+pub fn write_object_row(
+    tx: &Connection,
+    table_name: &str,
+    object: &NewObject,
+    headers: &HeaderMap,
+    write_once: bool,
+    quota: Option<&ObjectQuota>,
+) -> rusqlite::Result<WriteOutcome> {
+    // Evaluate If-Match / If-None-Match against the current row
+    let current_md5 = query_current_md5(tx, table_name, object.key)?;
+    if !write_preconditions_hold(headers, current_md5.as_deref()) {
+        return Ok(WriteOutcome::PreconditionFailed);
+    }
+
+    if !object_fits_quota(tx, table_name, object, quota)? {
+        return Ok(WriteOutcome::QuotaExceeded);
+    }
+    if !upsert_object_row(tx, table_name, object, write_once)? {
+        return Ok(WriteOutcome::Immutable);
+    }
+    Ok(WriteOutcome::Written)
+}
+
+/// Whether an object row may be written within the quota of its bucket, if it has one
+fn object_fits_quota(
+    tx: &Connection,
+    table_name: &str,
+    object: &NewObject,
+    quota: Option<&ObjectQuota>,
+) -> rusqlite::Result<bool> {
+    match quota {
+        Some(quota) => {
+            let existing_size = object_usage_size(tx, table_name, object.key)?;
+            quota.allows(tx, object.usage_size(), existing_size)
+        }
+        None => Ok(true),
+    }
+}
+
+/// What to do when a written key exists: replace the object, resetting its ACL,
+/// or in a write-once bucket keep it
+fn on_conflict_clause(write_once: bool) -> &'static str {
+    if write_once {
+        "ON CONFLICT(key) DO NOTHING"
+    } else {
+        "ON CONFLICT(key) DO UPDATE SET data=excluded.data, md5=excluded.md5,
+         storage_class=excluded.storage_class, checksum_algorithm=excluded.checksum_algorithm,
+         checksum=excluded.checksum, acl=NULL, content_encoding=excluded.content_encoding,
+         content_sha256=excluded.content_sha256, expires_at=excluded.expires_at,
+         compression=excluded.compression, original_size=excluded.original_size,
+         blob_hash=excluded.blob_hash"
+    }
+}
+
+/// Insert or replace an object row. Returns false if the object exists in
+/// a `write_once` bucket and was kept.
+/// The data of a deduplicated object is stored as a shared blob unless an identical one
+/// exists; its row keeps no data but its size, as `original_size`.
+fn upsert_object_row(
+    conn: &Connection,
+    table_name: &str,
+    object: &NewObject,
+    write_once: bool,
+) -> rusqlite::Result<bool> {
+    let (data, original_size) = match object.blob_hash {
+        Some(hash) => {
+            store_blob(conn, hash, object.data)?;
+            let original_size = object
+                .compression
+                .map_or(object.data.len() as u64, |(_, size)| size);
+            (&[][..], Some(original_size))
+        }
+        None => (object.data, object.compression.map(|(_, size)| size)),
+    };
+    let sql = format!(
+        "INSERT INTO {table_name}
+         (key, data, md5, storage_class, checksum_algorithm, checksum, content_encoding,
+          content_sha256, expires_at, compression, original_size, blob_hash)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12) {}",
+        on_conflict_clause(write_once)
+    );
+    let written = conn.execute(
+        &sql,
+        params![
+            object.key,
+            data,
+            object.md5_hash,
+            object.storage_class,
+            object.checksum.map(|c| c.algorithm.name()),
+            object.checksum.map(|c| c.value.as_str()),
+            object.content_encoding,
+            object.content_sha256,
+            object.expires_at,
+            object
+                .compression
+                .map(|(compression, _)| compression.name()),
+            original_size.map(|size| size as i64),
+            object.blob_hash,
+        ],
+    )?;
+    if written == 0
+        && let Some(hash) = object.blob_hash
+    {
+        delete_unreferenced_blob(conn, hash)?;
+    }
+    Ok(written > 0)
+}
+
+/// Delete an object row inside an IMMEDIATE transaction after evaluating If-Match.
+/// Returns false, deleting nothing, if the precondition fails.
+fn delete_object_row(
+    conn: &mut Connection,
+    table_name: &str,
+    key: &str,
+    headers: &HeaderMap,
+) -> rusqlite::Result<bool> {
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+
+    // Evaluate If-Match against the current row
+    let current_md5 = query_current_md5(&tx, table_name, key)?;
+    if !write_preconditions_hold(headers, current_md5.as_deref()) {
+        return Ok(false);
+    }
+
+    let sql = format!("DELETE FROM {table_name} WHERE key = ?1");
+    tx.execute(&sql, params![key])?;
+    tx.commit()?;
+    Ok(true)
+}
+
+/// Change the key of an object row inside an IMMEDIATE transaction.
+/// With `overwrite`, an existing row at the target key is deleted first.
+fn rename_object_row(
+    conn: &mut Connection,
+    table_name: &str,
+    key: &str,
+    target: &str,
+    overwrite: bool,
+) -> rusqlite::Result<RenameOutcome> {
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+
+    let now = Utc::now().timestamp();
+    let sql = format!(
+        "SELECT 1 FROM {table_name} WHERE key = ?1 AND {}",
+        not_expired(2)
+    );
+    if tx
+        .query_row(&sql, params![key, now], |_| Ok(()))
+        .optional()?
+        .is_none()
+    {
+        return Ok(RenameOutcome::SourceMissing);
+    }
+    if key == target {
+        return Ok(RenameOutcome::Renamed);
+    }
+    if tx
+        .query_row(&sql, params![target, now], |_| Ok(()))
+        .optional()?
+        .is_some()
+        && !overwrite
+    {
+        return Ok(RenameOutcome::TargetExists);
+    }
+    // Drop the overwritten target, or an expired row still holding its key
+    let sql = format!("DELETE FROM {table_name} WHERE key = ?1");
+    tx.execute(&sql, params![target])?;
+
+    let sql = format!("UPDATE {table_name} SET key = ?2 WHERE key = ?1");
+    tx.execute(&sql, params![key, target])?;
+    tx.commit()?;
+    Ok(RenameOutcome::Renamed)
+}
+
+/// Refuse object keys matching the configured denylist.
+/// Returns an S3 formatted `InvalidArgument` error response on violation.
+pub fn check_object_key(state: &AppState, key: &str) -> Result<(), Box<Response>> {
+    match key_violation(
+        key,
+        state.config.get_disallowed_key_patterns(),
+        state.config.rejects_key_control_chars(),
+    ) {
+        Some(reason) => {
+            warn!("Rejected object key {key:?}: {reason}");
+            Err(Box::new(xml_error_response(
+                StatusCode::BAD_REQUEST,
+                "InvalidArgument",
+                &reason,
+            )))
+        }
+        None => Ok(()),
+    }
+}
+
+/// Validate the user metadata size and storage class headers of an upload.
+/// Returns the storage class to record, or an S3 formatted error response.
+pub fn check_upload_headers(
+    state: &AppState,
+    headers: &HeaderMap,
+    key: &str,
+) -> Result<String, Box<Response>> {
+    let metadata_size = user_metadata_size(headers);
+    let max_metadata_size = state.config.get_max_user_metadata_size();
+    if metadata_size > max_metadata_size {
+        warn!("Rejected upload of '{key}': user metadata is {metadata_size} bytes");
+        return Err(Box::new(xml_error_response(
+            StatusCode::BAD_REQUEST,
+            "MetadataTooLarge",
+            &format!(
+                "Your metadata headers exceed the maximum allowed metadata size of {max_metadata_size} bytes"
+            ),
+        )));
+    }
+
+    match headers
+        .get("x-amz-storage-class")
+        .and_then(|v| v.to_str().ok())
+    {
+        Some(class) if state.config.is_storage_class_allowed(class) => Ok(class.to_string()),
+        Some(class) => {
+            warn!("Rejected upload of '{key}': invalid storage class '{class}'");
+            Err(Box::new(xml_error_response(
+                StatusCode::BAD_REQUEST,
+                "InvalidStorageClass",
+                &format!("The storage class you specified is not valid: {class}"),
+            )))
+        }
+        None => Ok(state.config.get_default_storage_class().to_string()),
+    }
+}
+
+/// Total size of user-defined metadata, measured like S3 as the sum of the
+/// UTF-8 lengths of each metadata key (without the `x-amz-meta-` prefix) and value
+fn user_metadata_size(headers: &HeaderMap) -> usize {
+    headers
+        .iter()
+        .filter_map(|(name, value)| {
+            name.as_str()
+                .strip_prefix("x-amz-meta-")
+                .map(|key| key.len() + value.as_bytes().len())
+        })
+        .sum()
+}
+
+/// Copy a row between bucket tables inside an IMMEDIATE transaction,
+/// evaluating the x-amz-copy-source-if-* conditions against the source
+/// and the quota of the target bucket first
+#[allow(clippy::too_many_arguments)]
+fn copy_object_row(
+    conn: &mut Connection,
+    source_table: &str,
+    source_key: &str,
+    table_name: &str,
+    key: &str,
+    storage_class: &str,
+    expires_at: Option<i64>,
+    headers: &HeaderMap,
+    write_once: bool,
+    quota: Option<&ObjectQuota>,
+) -> rusqlite::Result<CopyOutcome> {
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+
+    let sql = format!(
+        "SELECT md5, last_modified, {MISSING_MD5_DATA}, {USAGE_SIZE} FROM {source_table}
+         WHERE key = ?1 AND {}",
+        not_expired(2)
+    );
+    let source = tx
+        .query_row(&sql, params![source_key, Utc::now().timestamp()], |row| {
+            let md5_hash = stored_or_computed_md5(row.get(0)?, row.get(2)?);
+            Ok((
+                md5_hash,
+                row.get::<_, i64>(1)?,
+                row.get::<_, i64>(3)? as u64,
+            ))
+        })
+        .optional()?;
+    let Some((md5_hash, source_modified, source_size)) = source else {
+        return Ok(CopyOutcome::SourceMissing);
+    };
+    if !copy_preconditions_hold(headers, &md5_hash, source_modified) {
+        return Ok(CopyOutcome::PreconditionFailed);
+    }
+    // The copy shares the stored form of the source, so it counts the same bytes
+    if let Some(quota) = quota
+        && !quota.allows(&tx, source_size, object_usage_size(&tx, table_name, key)?)?
+    {
+        return Ok(CopyOutcome::QuotaExceeded);
+    }
+
+    let sql = format!(
+        "INSERT INTO {table_name}
+         (key, data, md5, storage_class, checksum_algorithm, checksum, content_encoding,
+          content_sha256, expires_at, compression, original_size, blob_hash)
+         SELECT ?1, data, COALESCE(md5, ?4), ?2, checksum_algorithm, checksum, content_encoding,
+         content_sha256, ?5, compression, original_size, blob_hash
+         FROM {source_table} WHERE key = ?3 {}",
+        on_conflict_clause(write_once)
+    );
+    let written = tx.execute(
+        &sql,
+        params![key, storage_class, source_key, md5_hash, expires_at],
+    )?;
+    if written == 0 {
+        return Ok(CopyOutcome::Immutable);
+    }
+
+    let sql = format!("SELECT last_modified FROM {table_name} WHERE key = ?1");
+    let last_modified: i64 = tx.query_row(&sql, params![key], |row| row.get(0))?;
+    tx.commit()?;
+
+    Ok(CopyOutcome::Copied {
+        md5_hash,
+        last_modified,
+    })
+}
+
+/// Parse an `x-amz-copy-source` value (`[/]bucket/key[?versionId=...]`, URL-encoded)
+pub fn parse_copy_source(value: &str) -> Option<(String, String)> {
+    let path = value.split('?').next()?;
+    let decoded = percent_decode(path)?;
+    let (bucket, key) = decoded.trim_start_matches('/').split_once('/')?;
+    if bucket.is_empty() || key.is_empty() {
+        return None;
+    }
+    Some((bucket.to_string(), key.to_string()))
+}
+
+/// Evaluate the x-amz-copy-source-if-* conditions against the source object.
+/// As in S3, a matching if-match overrides a failing if-unmodified-since,
+/// and a failing if-none-match fails regardless of if-modified-since.
+pub fn copy_preconditions_hold(headers: &HeaderMap, md5_hash: &str, last_modified: i64) -> bool {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+    let modified_after = |name: &str| {
+        header(name)
+            .and_then(|v| DateTime::parse_from_rfc2822(v).ok())
+            .map(|since| last_modified > since.timestamp())
+    };
+
+    let if_match = header("x-amz-copy-source-if-match").map(|v| etag_matches(v, md5_hash));
+    let if_none_match =
+        header("x-amz-copy-source-if-none-match").map(|v| !etag_matches(v, md5_hash));
+    let if_modified_since = modified_after("x-amz-copy-source-if-modified-since");
+    let if_unmodified_since =
+        modified_after("x-amz-copy-source-if-unmodified-since").map(|modified| !modified);
+
+    if if_match == Some(false) || if_none_match == Some(false) {
+        return false;
+    }
+    if if_match.is_none() && if_unmodified_since == Some(false) {
+        return false;
+    }
+    if if_none_match.is_none() && if_modified_since == Some(false) {
+        return false;
+    }
+    true
+}
+
+/// Non-standard GET /{bucket}?etag={md5}: download the most recently modified object
+/// whose ETag is `etag`, quoted or not, naming its key, percent-encoded, in `x-amz-object-key`.
+/// Range and conditional headers apply as in `download_object`.
+pub async fn get_object_by_etag(
+    state: Arc<AppState>,
+    bucket: String,
+    etag: String,
+    headers: HeaderMap,
+) -> Response {
+    let bucket = match validate_bucket(&bucket, &state.buckets) {
+        Ok(b) => b,
+        Err(resp) => return *resp,
+    };
+    let Some(table_name) = sanitize_bucket_name(&bucket, state.config.get_table_prefix()) else {
+        warn!("Invalid bucket name attempted: {bucket}");
+        return xml_error_response(
+            StatusCode::BAD_REQUEST,
+            "InvalidBucketName",
+            &format!("Invalid bucket name attempted: {bucket}"),
+        );
+    };
+    let etag = etag.trim().trim_matches('"').to_ascii_lowercase();
+
+    info!("GetObject by ETag {etag} from bucket '{bucket}'");
+    let mut conn = match state.read_conn() {
+        Ok(conn) => conn,
+        Err(e) => return pool_error_response(&e),
+    };
+    let max_retries = state.config.get_busy_retry_max_attempts();
+    let result = retry_on_busy(max_retries, &mut conn, |conn| {
+        query_key_by_md5(conn, &table_name, &etag)
+    })
+    .await;
+    // Return the connection before download_object takes its own
+    drop(conn);
+
+    let key = match result {
+        Ok(Some(key)) => key,
+        Ok(None) => {
+            return xml_error_response(
+                StatusCode::NOT_FOUND,
+                "NoSuchKey",
+                &format!("No object with ETag {etag} in bucket {bucket}"),
+            );
+        }
+        Err(e) => {
+            error!("Failed to look up ETag {etag} in bucket '{bucket}': {e}");
+            return database_error_response(&e);
+        }
+    };
+
+    let mut response = download_object(
+        State(state),
+        Path((bucket, key.clone())),
+        Query(HashMap::new()),
+        headers,
+    )
+    .await;
+    if let Ok(value) = percent_encode_path(&key).parse() {
+        response.headers_mut().insert("x-amz-object-key", value);
+    }
+    response
+}
+
+/// Key of the most recently modified unexpired object with the given md5
+fn query_key_by_md5(
+    conn: &Connection,
+    table_name: &str,
+    md5_hash: &str,
+) -> rusqlite::Result<Option<String>> {
+    let sql = format!(
+        "SELECT key FROM {table_name} WHERE md5 = ?1 AND {}
+         ORDER BY last_modified DESC, key DESC LIMIT 1",
+        not_expired(2)
+    );
+    conn.query_row(&sql, params![md5_hash, Utc::now().timestamp()], |row| {
+        row.get(0)
+    })
+    .optional()
+}
+
+/// Read the md5 of an object, or None if the key does not exist or has expired
+fn query_current_md5(
+    conn: &Connection,
+    table_name: &str,
+    key: &str,
+) -> rusqlite::Result<Option<String>> {
+    let sql = format!(
+        "SELECT md5, {MISSING_MD5_DATA} FROM {table_name} WHERE key = ?1 AND {}",
+        not_expired(2)
+    );
+    conn.query_row(&sql, params![key, Utc::now().timestamp()], |row| {
+        Ok(stored_or_computed_md5(row.get(0)?, row.get(1)?))
+    })
+    .optional()
+}
+
+/// Evaluate the If-Match / If-None-Match write preconditions.
+/// `current_md5` is the md5 of the existing object, or None if it does not exist.
+fn write_preconditions_hold(headers: &HeaderMap, current_md5: Option<&str>) -> bool {
+    if let Some(if_match) = headers.get("If-Match").and_then(|v| v.to_str().ok()) {
+        match current_md5 {
+            Some(md5_hash) if etag_matches(if_match, md5_hash) => {}
+            _ => return false,
+        }
+    }
+    if let Some(if_none_match) = headers.get("If-None-Match").and_then(|v| v.to_str().ok())
+        && let Some(md5_hash) = current_md5
+        && etag_matches(if_none_match, md5_hash)
+    {
+        return false;
+    }
+    true
+}
+
+/// Evaluate the If-Match / If-None-Match / If-Modified-Since / If-Unmodified-Since
+/// conditions of a GET or HEAD against an existing object.
+/// Returns the status to answer with instead of the object, if any: 412 when If-Match
+/// or If-Unmodified-Since fails, 304 when If-None-Match or If-Modified-Since fails.
+/// As in S3, a matching If-Match overrides a failing If-Unmodified-Since, and a failing
+/// If-None-Match gives 304 regardless of If-Modified-Since.
+fn read_precondition_status(
+    headers: &HeaderMap,
+    md5_hash: &str,
+    last_modified: i64,
+) -> Option<StatusCode> {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+    let modified_after = |name: &str| {
+        header(name)
+            .and_then(|v| DateTime::parse_from_rfc2822(v).ok())
+            .map(|since| last_modified > since.timestamp())
+    };
+
+    let if_match = header("If-Match").map(|v| etag_matches(v, md5_hash));
+    let if_none_match = header("If-None-Match").map(|v| !etag_matches(v, md5_hash));
+    let if_modified_since = modified_after("If-Modified-Since");
+    let if_unmodified_since = modified_after("If-Unmodified-Since").map(|modified| !modified);
+
+    if if_match == Some(false) || (if_match.is_none() && if_unmodified_since == Some(false)) {
+        return Some(StatusCode::PRECONDITION_FAILED);
+    }
+    if if_none_match == Some(false) || (if_none_match.is_none() && if_modified_since == Some(false))
+    {
+        return Some(StatusCode::NOT_MODIFIED);
+    }
+    None
+}
+
+/// An object read by GET, with the bytes of the requested range
+struct ObjectRead {
+    data: Bytes,
+    size: u64,
+    range: ByteRange,
+    storage_class: String,
+    md5_hash: String,
+    last_modified: i64,
+    checksum: Option<ObjectChecksum>,
+    content_encoding: Option<String>,
+    expires_at: Option<i64>,
+}
+
+impl ObjectRead {
+    /// The requested range of a cached object, sliced without copying
+    fn from_cached(object: &CachedObject, range: Option<&str>) -> Self {
+        let size = object.data.len() as u64;
+        let range = range.map_or(ByteRange::Full, |value| resolve_range(value, size));
+        let start = range.start() as usize;
+        Self {
+            data: object.data.slice(start..start + range.len(size) as usize),
+            size,
+            range,
+            storage_class: object.storage_class.clone(),
+            md5_hash: object.md5_hash.clone(),
+            last_modified: object.last_modified,
+            checksum: object.checksum.clone(),
+            content_encoding: object.content_encoding.clone(),
+            expires_at: object.expires_at,
+        }
+    }
+}
+
+/// Read an object, or only the bytes selected by a `Range` header value.
+/// The size comes from `length(data)`, of the shared blob for deduplicated objects, and
+/// only the selected bytes are read from the blob,
+/// both within one read transaction. Returns None if the object does not exist or has expired.
+/// With `decode_limit`, a gzip encoded object is decompressed if it decodes to at most
+/// that many bytes; the range then selects decompressed bytes.
+fn read_object_range(
+    conn: &mut Connection,
+    table_name: &str,
+    key: &str,
+    range: Option<&str>,
+    decode_limit: Option<usize>,
+) -> rusqlite::Result<Option<ObjectRead>> {
+    let tx = conn.transaction()?;
+    let sql = format!(
+        "SELECT rowid, length(data), storage_class, md5, last_modified, checksum_algorithm,
+         checksum, {MISSING_MD5_DATA}, content_encoding, expires_at, compression, original_size,
+         blob_hash FROM {table_name} WHERE key = ?1 AND {}",
+        not_expired(2)
+    );
+    let row = tx
+        .query_row(&sql, params![key, Utc::now().timestamp()], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, i64>(1)? as u64,
+                row.get::<_, String>(2)?,
+                stored_or_computed_md5(row.get(3)?, row.get(7)?),
+                row.get::<_, i64>(4)?,
+                ObjectChecksum::from_columns(row.get(5)?, row.get(6)?),
+                row.get::<_, Option<String>>(8)?,
+                row.get::<_, Option<i64>>(9)?,
+                Compression::from_columns(row.get(10)?, row.get(11)?)?,
+                row.get::<_, Option<String>>(12)?,
+            ))
+        })
+        .optional()?;
+    let Some((
+        rowid,
+        size,
+        storage_class,
+        md5_hash,
+        last_modified,
+        checksum,
+        content_encoding,
+        expires_at,
+        compression,
+        blob_hash,
+    )) = row
+    else {
+        return Ok(None);
+    };
+    // A deduplicated object is read from the shared blob it references
+    let (data_table, rowid, size) =
+        locate_data(&tx, table_name, rowid, size, blob_hash.as_deref())?;
+    let decode_limit = decode_limit.filter(|_| {
+        content_encoding
+            .as_deref()
+            .is_some_and(|e| e.eq_ignore_ascii_case("gzip"))
+    });
+
+    // Compressed objects are decompressed whole before the range is cut out of them
+    if compression.is_some() || decode_limit.is_some() {
+        let mut stored = vec![0u8; size as usize];
+        if !stored.is_empty() {
+            let blob = tx.blob_open(MAIN_DB, data_table, "data", rowid, true)?;
+            blob.read_at_exact(&mut stored, 0)?;
+        }
+        tx.commit()?;
+
+        let data = match compression {
+            Some((compression, original_size)) => decompress(compression, &stored, original_size)?,
+            None => stored,
+        };
+        // Objects that fail to decode are sent as stored
+        let decoded = decode_limit.and_then(|max_size| gunzip(&data, max_size));
+        let (data, content_encoding, checksum) = match decoded {
+            Some(decoded) => (decoded, None, None),
+            None => (data, content_encoding, checksum),
+        };
+        let size = data.len() as u64;
+        let range = range.map_or(ByteRange::Full, |value| resolve_range(value, size));
+        let start = range.start() as usize;
+        let data = Bytes::from(data).slice(start..start + range.len(size) as usize);
+        return Ok(Some(ObjectRead {
+            data,
+            size,
+            range,
+            storage_class,
+            md5_hash,
+            last_modified,
+            checksum,
+            content_encoding,
+            expires_at,
+        }));
+    }
+
+    let range = range.map_or(ByteRange::Full, |value| resolve_range(value, size));
+    let mut data = vec![0u8; range.len(size) as usize];
+    if !data.is_empty() {
+        let blob = tx.blob_open(MAIN_DB, data_table, "data", rowid, true)?;
+        blob.read_at_exact(&mut data, range.start() as usize)?;
+    }
+    tx.commit()?;
+
+    Ok(Some(ObjectRead {
+        data: Bytes::from(data),
+        size,
+        range,
+        storage_class,
+        md5_hash,
+        last_modified,
+        checksum,
+        content_encoding,
+        expires_at,
+    }))
+}
+
+/// The `Content-Encoding` of an upload as stored with the object. `aws-chunked` only
+/// describes the transfer of the request, as in S3 it is not recorded.
+fn stored_content_encoding(headers: &HeaderMap) -> Option<String> {
+    let value = headers.get("Content-Encoding")?.to_str().ok()?;
+    let codings: Vec<&str> = value
+        .split(',')
+        .map(str::trim)
+        .filter(|c| !c.is_empty() && !c.eq_ignore_ascii_case("aws-chunked"))
+        .collect();
+    (!codings.is_empty()).then(|| codings.join(","))
+}
+
+/// Decompress gzip data of at most `max_size` decompressed bytes
+fn gunzip(data: &[u8], max_size: usize) -> Option<Vec<u8>> {
+    let mut decoded = Vec::new();
+    MultiGzDecoder::new(data)
+        .take(max_size as u64 + 1)
+        .read_to_end(&mut decoded)
+        .ok()?;
+    (decoded.len() <= max_size).then_some(decoded)
+}
+
+/// Whether the client asks for `Accept-Encoding: identity` only, refusing other codings
+fn accepts_identity_only(headers: &HeaderMap) -> bool {
+    let Some(value) = headers.get("Accept-Encoding").and_then(|v| v.to_str().ok()) else {
+        return false;
+    };
+    value.split(',').all(|coding| {
+        let name = coding.split(';').next().unwrap_or_default().trim();
+        name.eq_ignore_ascii_case("identity")
+    })
+}
+
+/// Headers identifying the version of an object: ETag and Last-Modified
+fn validator_headers(md5_hash: &str, last_modified: i64) -> HeaderMap {
+    let last_modified = DateTime::<Utc>::from_timestamp(last_modified, 0).unwrap_or(Utc::now());
+    let mut headers = HeaderMap::new();
+    headers.insert("ETag", format!("\"{md5_hash}\"").parse().unwrap());
+    headers.insert("Last-Modified", last_modified.to_rfc2822().parse().unwrap());
+    headers
+}
+
+/// Answer a GET or HEAD whose preconditions failed
+fn read_precondition_response(status: StatusCode, md5_hash: &str, last_modified: i64) -> Response {
+    if status == StatusCode::NOT_MODIFIED {
+        (status, validator_headers(md5_hash, last_modified)).into_response()
+    } else {
+        precondition_failed_response()
+    }
+}
+
+/// 403 response to overwriting or deleting an object in a write-once bucket
+pub fn write_once_response() -> Response {
+    xml_error_response(
+        StatusCode::FORBIDDEN,
+        "AccessDenied",
+        "Objects in this write-once bucket cannot be overwritten or deleted",
+    )
+}
+
+/// S3 412 Precondition Failed response
+pub fn precondition_failed_response() -> Response {
+    xml_error_response(
+        StatusCode::PRECONDITION_FAILED,
+        "PreconditionFailed",
+        "At least one of the pre-conditions you specified did not hold",
+    )
+}
+
+/// 403 QuotaExceeded response for a write over `max_objects_per_bucket` or `max_bytes_per_bucket`
+pub fn quota_exceeded_response() -> Response {
+    xml_error_response(
+        StatusCode::FORBIDDEN,
+        "QuotaExceeded",
+        "The bucket would exceed its maximum number of objects or bytes.",
+    )
+}
+
+/// S3 formatted response for a rejected x-amz-content-sha256 header
+pub fn content_sha256_error_response(error: &ContentSha256Error) -> Response {
+    match error {
+        ContentSha256Error::Malformed => xml_error_response(
+            StatusCode::BAD_REQUEST,
+            "InvalidArgument",
+            "x-amz-content-sha256 must be UNSIGNED-PAYLOAD, STREAMING-UNSIGNED-PAYLOAD-TRAILER, \
+             STREAMING-AWS4-HMAC-SHA256-PAYLOAD, or a valid sha256 value.",
+        ),
+        ContentSha256Error::Mismatch => xml_error_response(
+            StatusCode::BAD_REQUEST,
+            "XAmzContentSHA256Mismatch",
+            "The provided 'x-amz-content-sha256' header does not match what was computed.",
+        ),
+    }
+}
+
+/// S3 formatted response for rejected x-amz-checksum-* headers
+pub fn checksum_error_response(error: &ChecksumError) -> Response {
+    match error {
+        ChecksumError::UnknownAlgorithm(name) => xml_error_response(
+            StatusCode::BAD_REQUEST,
+            "InvalidRequest",
+            &format!("Checksum algorithm provided is unsupported: {name}"),
+        ),
+        ChecksumError::MultipleChecksums => xml_error_response(
+            StatusCode::BAD_REQUEST,
+            "InvalidRequest",
+            "Expecting a single x-amz-checksum- header. Multiple checksum types are not allowed.",
+        ),
+        ChecksumError::AlgorithmMismatch(algorithm) => xml_error_response(
+            StatusCode::BAD_REQUEST,
+            "InvalidRequest",
+            &format!(
+                "Value for x-amz-checksum-algorithm does not match the {} header",
+                algorithm.header_name()
+            ),
+        ),
+        ChecksumError::Mismatch(algorithm) => xml_error_response(
+            StatusCode::BAD_REQUEST,
+            "BadDigest",
+            &format!(
+                "The {} you specified did not match the calculated checksum.",
+                algorithm.header_name()
+            ),
+        ),
+    }
+}
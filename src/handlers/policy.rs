@@ -0,0 +1,140 @@
+use axum::{
+    body::Bytes,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use log::{error, info, warn};
+use std::sync::Arc;
+
+use crate::models::AppState;
+use crate::utils::{
+    bucket_meta, database_error_response, policy::parse_bucket_policy, pool_error_response,
+    retry_on_busy, validate_bucket, xml_error_response, xml_response,
+};
+
+/// GetBucketPolicy: GET /{bucket}?policy
+pub async fn get_bucket_policy(state: Arc<AppState>, bucket: String) -> Response {
+    let bucket = match validate_bucket(&bucket, &state.buckets) {
+        Ok(b) => b,
+        Err(resp) => return *resp,
+    };
+
+    info!("GetBucketPolicy for bucket '{bucket}'");
+    match read_policy(&state, &bucket).await {
+        Ok(Some(policy)) => {
+            let mut headers = HeaderMap::new();
+            headers.insert("Content-Type", "application/json".parse().unwrap());
+            headers.insert("Content-Length", policy.len().to_string().parse().unwrap());
+            (StatusCode::OK, headers, policy).into_response()
+        }
+        Ok(None) => xml_error_response(
+            StatusCode::NOT_FOUND,
+            "NoSuchBucketPolicy",
+            &format!("The bucket policy does not exist: {bucket}"),
+        ),
+        Err(resp) => *resp,
+    }
+}
+
+/// PutBucketPolicy: PUT /{bucket}?policy
+/// Only policies granting anonymous `s3:GetObject` are accepted, anything else is
+/// refused so that it is never stored without being enforced.
+pub async fn put_bucket_policy(state: Arc<AppState>, bucket: String, body: Bytes) -> Response {
+    let bucket = match validate_bucket(&bucket, &state.buckets) {
+        Ok(b) => b,
+        Err(resp) => return *resp,
+    };
+
+    let policy = std::str::from_utf8(&body).unwrap_or_default();
+    if let Err(reason) = parse_bucket_policy(policy, &bucket) {
+        warn!("Refused policy for bucket '{bucket}': {reason}");
+        return xml_error_response(StatusCode::BAD_REQUEST, "MalformedPolicy", &reason);
+    }
+
+    info!("PutBucketPolicy for bucket '{bucket}'");
+    let mut conn = match state.write_conn() {
+        Ok(c) => c,
+        Err(e) => return pool_error_response(&e),
+    };
+
+    let max_retries = state.config.get_busy_retry_max_attempts();
+    match retry_on_busy(max_retries, &mut conn, |conn| {
+        bucket_meta::put_bucket_policy(conn, &bucket, policy)
+    })
+    .await
+    {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => {
+            error!("Failed to store policy of bucket '{bucket}': {e}");
+            database_error_response(&e)
+        }
+    }
+}
+
+/// DeleteBucketPolicy: DELETE /{bucket}?policy
+pub async fn delete_bucket_policy(state: Arc<AppState>, bucket: String) -> Response {
+    let bucket = match validate_bucket(&bucket, &state.buckets) {
+        Ok(b) => b,
+        Err(resp) => return *resp,
+    };
+
+    info!("DeleteBucketPolicy for bucket '{bucket}'");
+    let mut conn = match state.write_conn() {
+        Ok(c) => c,
+        Err(e) => return pool_error_response(&e),
+    };
+
+    let max_retries = state.config.get_busy_retry_max_attempts();
+    match retry_on_busy(max_retries, &mut conn, |conn| {
+        bucket_meta::delete_bucket_policy(conn, &bucket)
+    })
+    .await
+    {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => {
+            error!("Failed to delete policy of bucket '{bucket}': {e}");
+            database_error_response(&e)
+        }
+    }
+}
+
+/// GetBucketPolicyStatus: GET /{bucket}?policyStatus
+/// The bucket is public when its policy grants anonymous read on any object.
+pub async fn get_bucket_policy_status(state: Arc<AppState>, bucket: String) -> Response {
+    let bucket = match validate_bucket(&bucket, &state.buckets) {
+        Ok(b) => b,
+        Err(resp) => return *resp,
+    };
+
+    info!("GetBucketPolicyStatus for bucket '{bucket}'");
+    let is_public = match read_policy(&state, &bucket).await {
+        Ok(policy) => policy
+            .and_then(|policy| parse_bucket_policy(&policy, &bucket).ok())
+            .is_some_and(|policy| policy.is_public()),
+        Err(resp) => return *resp,
+    };
+    xml_response(
+        StatusCode::OK,
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?><PolicyStatus xmlns="http://s3.amazonaws.com/doc/2006-03-01/"><IsPublic>{is_public}</IsPublic></PolicyStatus>"#
+        ),
+    )
+}
+
+/// The stored policy document of a bucket, None if it has none
+async fn read_policy(state: &AppState, bucket: &str) -> Result<Option<String>, Box<Response>> {
+    let mut conn = match state.read_conn() {
+        Ok(c) => c,
+        Err(e) => return Err(Box::new(pool_error_response(&e))),
+    };
+
+    let max_retries = state.config.get_busy_retry_max_attempts();
+    retry_on_busy(max_retries, &mut conn, |conn| {
+        bucket_meta::get_bucket_policy(conn, bucket)
+    })
+    .await
+    .map_err(|e| {
+        error!("Failed to read policy of bucket '{bucket}': {e}");
+        Box::new(database_error_response(&e))
+    })
+}
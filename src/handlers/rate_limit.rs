@@ -0,0 +1,81 @@
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{StatusCode, header},
+    middleware::Next,
+    response::Response,
+};
+use log::warn;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use crate::models::{AppState, Principal};
+use crate::utils::xml_error_response;
+
+/// Limit the rate of requests and bytes of each client, the access key of signed
+/// requests or else the client address. Over the limit, requests get
+/// `503 SlowDown` with `Retry-After`, on which S3 clients back off, before their
+/// body is read.
+pub async fn rate_limit_middleware(
+    State(state): State<Arc<AppState>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    if !state.rate_limiter.is_enabled() {
+        return next.run(req).await;
+    }
+    let client = match req.extensions().get::<Principal>() {
+        Some(principal) => format!("key:{}", principal.access_key_id),
+        None => {
+            let peer = req
+                .extensions()
+                .get::<ConnectInfo<SocketAddr>>()
+                .map(|info| info.0.ip());
+            match state.ip_filter.client_ip(peer, req.headers()) {
+                Some(ip) => format!("ip:{ip}"),
+                None => "local".to_string(),
+            }
+        }
+    };
+    let bucket = req
+        .uri()
+        .path()
+        .trim_start_matches('/')
+        .split('/')
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    let content_length = |headers: &axum::http::HeaderMap| {
+        headers
+            .get(header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(0)
+    };
+
+    if let Err(wait) = state
+        .rate_limiter
+        .acquire(&client, &bucket, content_length(req.headers()))
+    {
+        warn!(
+            "Rate limited {} {} from {client}, retry in {wait:?}",
+            req.method(),
+            req.uri().path()
+        );
+        let mut response = xml_error_response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "SlowDown",
+            "Please reduce your request rate.",
+        );
+        let retry_after = wait.as_secs_f64().ceil().max(1.0) as u64;
+        response
+            .headers_mut()
+            .insert(header::RETRY_AFTER, retry_after.into());
+        return response;
+    }
+
+    let response = next.run(req).await;
+    state
+        .rate_limiter
+        .charge_bytes(&client, &bucket, content_length(response.headers()));
+    response
+}
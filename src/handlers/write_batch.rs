@@ -0,0 +1,202 @@
+use axum::{body::Bytes, http::HeaderMap};
+use log::{error, info, warn};
+use rusqlite::{Connection, TransactionBehavior};
+use std::sync::Arc;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::Instant;
+
+use crate::handlers::object::{NewObject, WriteOutcome, put_object_row, write_object_row};
+use crate::models::{AppState, Compression};
+use crate::utils::bucket_meta::ObjectQuota;
+use crate::utils::checksum::ObjectChecksum;
+use crate::utils::{retry_on_busy, run_blocking};
+
+/// Uploads waiting for the write batching task; senders wait once it is full
+pub const WRITE_BATCH_QUEUE_CAPACITY: usize = 1024;
+
+/// An upload to commit in a batch, owning what `put_object_row` borrows
+pub struct PendingWrite {
+    pub bucket: String,
+    pub table_name: String,
+    pub key: String,
+    pub data: Bytes,
+    pub md5_hash: String,
+    pub storage_class: String,
+    pub checksum: Option<ObjectChecksum>,
+    pub content_encoding: Option<String>,
+    pub content_sha256: Option<String>,
+    pub expires_at: Option<i64>,
+    pub compression: Option<(Compression, u64)>,
+    pub blob_hash: Option<String>,
+    /// Request headers, for the If-Match / If-None-Match preconditions
+    pub headers: HeaderMap,
+    pub write_once: bool,
+    pub max_objects: Option<u64>,
+    pub max_bytes: Option<u64>,
+}
+
+impl PendingWrite {
+    fn object(&self) -> NewObject<'_> {
+        NewObject {
+            key: &self.key,
+            data: &self.data,
+            md5_hash: &self.md5_hash,
+            storage_class: &self.storage_class,
+            checksum: self.checksum.as_ref(),
+            content_encoding: self.content_encoding.as_deref(),
+            content_sha256: self.content_sha256.as_deref(),
+            expires_at: self.expires_at,
+            compression: self.compression,
+            blob_hash: self.blob_hash.as_deref(),
+        }
+    }
+
+    fn quota(&self) -> Option<ObjectQuota<'_>> {
+        (self.max_objects.is_some() || self.max_bytes.is_some()).then_some(ObjectQuota {
+            bucket: &self.bucket,
+            max_objects: self.max_objects,
+            max_bytes: self.max_bytes,
+        })
+    }
+
+    /// Write the object in its own transaction
+    fn put(&self, conn: &mut Connection) -> rusqlite::Result<WriteOutcome> {
+        let quota = self.quota();
+        put_object_row(
+            conn,
+            &self.table_name,
+            &self.object(),
+            &self.headers,
+            self.write_once,
+            quota.as_ref(),
+        )
+    }
+}
+
+/// A queued upload and where to send its outcome once its batch is committed
+pub struct QueuedWrite {
+    write: PendingWrite,
+    outcome: oneshot::Sender<rusqlite::Result<WriteOutcome>>,
+}
+
+/// Queue an upload for the write batching task and wait until its batch is committed.
+/// Returns None if batching is disabled or its task is gone, in which case nothing
+/// was written and the caller writes the object itself.
+pub async fn queue_write(
+    state: &AppState,
+    write: PendingWrite,
+) -> Option<rusqlite::Result<WriteOutcome>> {
+    let queue = state.write_batch.as_ref()?;
+    let (outcome, receiver) = oneshot::channel();
+    queue.send(QueuedWrite { write, outcome }).await.ok()?;
+    receiver.await.ok()
+}
+
+/// Commit queued uploads in shared transactions in a background task. A batch is
+/// committed once it holds `write_batch_max_objects` objects or `write_batch_max_bytes`
+/// bytes, or `write_batch_max_delay_ms` after its first upload was received. Uploads
+/// queued meanwhile make up the next batch.
+pub fn schedule_write_batching(state: Arc<AppState>, mut queue: mpsc::Receiver<QueuedWrite>) {
+    let max_objects = state.config.get_write_batch_max_objects();
+    let max_bytes = state.config.get_write_batch_max_bytes();
+    let max_delay = state.config.get_write_batch_max_delay();
+    info!(
+        "Write batching enabled: up to {max_objects} objects, {max_bytes} bytes or {max_delay:?}"
+    );
+
+    tokio::spawn(async move {
+        while let Some(first) = queue.recv().await {
+            let deadline = Instant::now() + max_delay;
+            let mut bytes = first.write.data.len();
+            let mut batch = vec![first];
+            while batch.len() < max_objects && bytes < max_bytes {
+                match tokio::time::timeout_at(deadline, queue.recv()).await {
+                    Ok(Some(queued)) => {
+                        bytes += queued.write.data.len();
+                        batch.push(queued);
+                    }
+                    Ok(None) | Err(_) => break,
+                }
+            }
+            commit_batch(&state, batch).await;
+        }
+    });
+}
+
+/// Write a batch in one IMMEDIATE transaction and report each outcome once it is
+/// committed. Every object is written in a savepoint: an object whose write fails is
+/// rolled back alone and written again in its own transaction after the commit, and
+/// if the batch as a whole fails every object is.
+async fn commit_batch(state: &AppState, batch: Vec<QueuedWrite>) {
+    // Without a connection the outcome senders are dropped, and the handlers write
+    // the objects themselves, getting the pool error
+    let mut conn = match state.write_conn() {
+        Ok(conn) => conn,
+        Err(e) => {
+            error!(
+                "Write batch of {} objects skipped, no database connection: {e}",
+                batch.len()
+            );
+            return;
+        }
+    };
+
+    let outcomes = match run_blocking(|| write_batch(&mut conn, &batch)) {
+        Ok(outcomes) => outcomes,
+        Err(e) => {
+            warn!(
+                "Write batch of {} objects failed, writing them one by one: {e}",
+                batch.len()
+            );
+            batch.iter().map(|_| None).collect()
+        }
+    };
+
+    let max_retries = state.config.get_busy_retry_max_attempts();
+    for (queued, outcome) in batch.into_iter().zip(outcomes) {
+        let result = match outcome {
+            Some(outcome) => Ok(outcome),
+            None => retry_on_busy(max_retries, &mut conn, |conn| queued.write.put(conn)).await,
+        };
+        // The handler may be gone if its client disconnected
+        let _ = queued.outcome.send(result);
+    }
+}
+
+/// Write the objects of a batch, returning their outcomes, None for those to write
+/// again on their own
+fn write_batch(
+    conn: &mut Connection,
+    batch: &[QueuedWrite],
+) -> rusqlite::Result<Vec<Option<WriteOutcome>>> {
+    let mut tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    let mut outcomes = Vec::with_capacity(batch.len());
+    for queued in batch {
+        let write = &queued.write;
+        let quota = write.quota();
+        let savepoint = tx.savepoint()?;
+        match write_object_row(
+            &savepoint,
+            &write.table_name,
+            &write.object(),
+            &write.headers,
+            write.write_once,
+            quota.as_ref(),
+        ) {
+            Ok(outcome) => {
+                savepoint.commit()?;
+                outcomes.push(Some(outcome));
+            }
+            // Dropping the savepoint rolls the object back
+            Err(e) => {
+                warn!(
+                    "Failed to write '{}' in a batch, writing it on its own: {e}",
+                    write.key
+                );
+                outcomes.push(None);
+            }
+        }
+    }
+    tx.commit()?;
+    Ok(outcomes)
+}
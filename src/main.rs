@@ -1,28 +1,164 @@
 use axum::{
     Router,
-    routing::{delete, get, head, put},
+    extract::{DefaultBodyLimit, Request, State},
+    http::{Extensions, HeaderMap, StatusCode, Version, header},
+    middleware::{self, Next},
+    response::Response,
+    routing::{delete, get, head, options, post, put},
 };
 use log::{error, info, warn};
 use std::env;
 use std::sync::Arc;
-use std::{collections::HashSet, net::ToSocketAddrs};
+use std::{
+    collections::HashSet,
+    net::{SocketAddr, ToSocketAddrs},
+};
 use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+use tower_http::compression::{
+    CompressionLayer,
+    predicate::{DefaultPredicate, Predicate},
+};
 use tower_http::trace::TraceLayer;
 
+mod cli;
 mod handlers;
 mod models;
 mod utils;
 
-use models::{AppConfig, AppState};
+use models::{AppConfig, AppState, AuthMode, Credentials};
+use utils::ip_filter::IpFilter;
+use utils::rate_limit::RateLimiter;
+
+/// Assign a request id, expose it to handlers and error responses, and add the
+/// `x-amz-request-id` and RFC 1123 `Date` headers to every response.
+/// Error responses are JSON if the `Accept` header prefers it.
+async fn request_id_middleware(mut req: Request, next: Next) -> Response {
+    let request_id = utils::next_request_id();
+    req.extensions_mut()
+        .insert(utils::RequestId(request_id.clone()));
+    let json_errors = utils::prefers_json(req.headers());
+
+    let mut response = utils::with_request_id(
+        request_id.clone(),
+        utils::with_error_format(json_errors, next.run(req)),
+    )
+    .await;
+
+    let headers = response.headers_mut();
+    if let Ok(value) = request_id.parse() {
+        headers.insert("x-amz-request-id", value);
+    }
+    let date = chrono::Utc::now()
+        .format("%a, %d %b %Y %H:%M:%S GMT")
+        .to_string();
+    if let Ok(value) = date.parse() {
+        headers.insert("Date", value);
+    }
+    response
+}
+
+/// Reject requests that cannot succeed before their body is read.
+/// Hyper answers `Expect: 100-continue` with `100 Continue` only once the handler
+/// first polls the body, so a response returned here is sent instead of the
+/// interim `100 Continue` and the client never transmits the body.
+/// Authorization checks belong here for the same reason.
+async fn upload_guard(State(state): State<Arc<AppState>>, req: Request, next: Next) -> Response {
+    let headers = req.headers();
+
+    if let Some(expect) = headers.get(header::EXPECT)
+        && !expect.as_bytes().eq_ignore_ascii_case(b"100-continue")
+    {
+        warn!("Rejected request with unsupported expectation: {expect:?}");
+        return utils::xml_error_response(
+            StatusCode::EXPECTATION_FAILED,
+            "ExpectationFailed",
+            "Only the 100-continue expectation is supported",
+        );
+    }
+
+    // Every bucket belongs to the configured owner
+    let owner_id = state.config.get_owner_id();
+    for name in [
+        "x-amz-expected-bucket-owner",
+        "x-amz-source-expected-bucket-owner",
+    ] {
+        if let Some(expected) = headers.get(name)
+            && expected.as_bytes() != owner_id.as_bytes()
+        {
+            warn!("Rejected request with {name} {expected:?}, buckets are owned by {owner_id:?}");
+            return utils::xml_error_response(
+                StatusCode::FORBIDDEN,
+                "AccessDenied",
+                "Access Denied",
+            );
+        }
+    }
+
+    let max_object_size = state.config.get_max_object_size() as u64;
+    if let Some(length) = headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok())
+        && length > max_object_size
+    {
+        warn!("Rejected {length} byte request body before reading it");
+        return utils::xml_error_response(
+            StatusCode::BAD_REQUEST,
+            "EntityTooLarge",
+            &format!(
+                "Your proposed upload exceeds the maximum allowed object size of {max_object_size} bytes"
+            ),
+        );
+    }
+
+    next.run(req).await
+}
+
+/// Compression predicate excluding object bodies. They carry an ETag describing the
+/// stored bytes, so they are sent as stored, with their Content-Length.
+fn is_not_object_body(_: StatusCode, _: Version, headers: &HeaderMap, _: &Extensions) -> bool {
+    !headers.contains_key(header::ETAG)
+}
+
+/// Compression predicate excluding responses whose coding the handler chose, such as
+/// listings, which are only compressed above `list_gzip_threshold_bytes`
+fn is_not_handler_encoded(_: StatusCode, _: Version, _: &HeaderMap, ext: &Extensions) -> bool {
+    ext.get::<utils::HandlerEncoded>().is_none()
+}
+
+/// S3 formatted 405 for a routed path used with a method it does not support.
+/// Axum adds the `Allow` header listing the methods of the route to 405 responses.
+async fn method_not_allowed(req: Request) -> Response {
+    warn!("Method {} not allowed for {}", req.method(), req.uri());
+    utils::xml_error_response(
+        StatusCode::METHOD_NOT_ALLOWED,
+        "MethodNotAllowed",
+        "The specified method is not allowed against this resource.",
+    )
+}
 
 #[tokio::main]
 async fn main() -> std::io::Result<()> {
+    // Offline administration: s3insqlite admin [--config <path>] <command> [<bucket>]
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.first().is_some_and(|arg| arg == "admin") {
+        if let Err(e) = cli::run_admin(&args[1..]) {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
     // Parse command line argument for config file path
     let config_path = env::args().nth(1).unwrap_or("config.toml".to_string());
 
     // Read config file
-    let config = AppConfig::from_file(&config_path)
-        .unwrap_or_else(|_| panic!("Failed to read config file {config_path}"));
+    let config = Arc::new(
+        AppConfig::from_file(&config_path)
+            .unwrap_or_else(|_| panic!("Failed to read config file {config_path}")),
+    );
 
     // Setup logging
     if let Err(e) = utils::initialize_logger(&config.log_path, &config.log_level) {
@@ -32,24 +168,90 @@ async fn main() -> std::io::Result<()> {
 
     info!("Starting S3inSQLite server...");
 
-    // Setup optimized connection pool
-    let pool = utils::create_connection_pool(
+    // Resolve the secrets of the configured access keys
+    let credentials = match Credentials::load(&config) {
+        Ok(credentials) if credentials.is_empty() => {
+            info!("No credentials configured, request signatures are not checked");
+            credentials
+        }
+        Ok(credentials) => {
+            info!("Loaded {} access keys", credentials.len());
+            credentials
+        }
+        Err(e) => {
+            error!("Invalid credentials in config file {config_path} or its credentials_file: {e}");
+            return Err(std::io::Error::other(format!("Invalid credentials: {e}")));
+        }
+    };
+    match config.get_auth_mode() {
+        AuthMode::None if !credentials.is_empty() => {
+            warn!("auth_mode is none, request signatures are not checked")
+        }
+        AuthMode::Required | AuthMode::LogOnly if credentials.is_empty() => {
+            error!(
+                "auth_mode {:?} requires [[credentials]]",
+                config.get_auth_mode()
+            );
+            return Err(std::io::Error::other(
+                "auth_mode requires at least one access key",
+            ));
+        }
+        AuthMode::LogOnly => {
+            warn!("auth_mode is log_only, requests failing authentication are allowed")
+        }
+        _ => {}
+    }
+
+    if !utils::bucket::is_valid_table_prefix(config.get_table_prefix()) {
+        error!(
+            "Invalid table_prefix {:?} in config file {config_path}: it must start with a letter \
+             and contain only letters, digits and underscores",
+            config.get_table_prefix()
+        );
+        return Err(std::io::Error::other("Invalid table_prefix"));
+    }
+
+    let ip_filter = match IpFilter::from_config(&config) {
+        Ok(ip_filter) => ip_filter,
+        Err(e) => {
+            error!("Invalid address ranges in config file {config_path}: {e}");
+            return Err(std::io::Error::other(format!(
+                "Invalid address ranges: {e}"
+            )));
+        }
+    };
+
+    let rate_limiter = match RateLimiter::from_config(&config) {
+        Ok(rate_limiter) => rate_limiter,
+        Err(e) => {
+            error!("Invalid rate limits in config file {config_path}: {e}");
+            return Err(std::io::Error::other(format!("Invalid rate limits: {e}")));
+        }
+    };
+
+    // The single writer connection first: it creates the database in WAL mode
+    let pool = utils::create_write_pool(
         &config.database_path,
-        config.get_db_pool_max_size(),
-        config.get_db_pool_min_idle(),
         config.get_db_pool_timeout_seconds().as_secs(),
+        config.get_db_pool_max_lifetime(),
     )
-    .expect("Failed to create database connection pool");
+    .expect("Failed to create database writer connection");
 
     // Ensure all buckets from config exist in the database
     let mut buckets_set = HashSet::new();
-    {
-        let conn = pool.get().unwrap();
+    let logging_targets = {
+        let mut conn = pool.get().unwrap();
+        // Bring tables of older versions up to date before anything uses them
+        match utils::run_migrations(&mut conn, config.get_table_prefix()) {
+            Ok(version) => info!("Database schema at version {version}"),
+            Err(e) => panic!("Failed to migrate the database schema: {e}"),
+        }
         for bucket in &config.buckets {
-            match utils::ensure_bucket_table(&conn, bucket) {
+            match utils::ensure_bucket_table(&conn, bucket, config.get_table_prefix()) {
                 Ok(_) => {
                     // Create indexes for better performance
-                    if let Some(table_name) = utils::sanitize_bucket_name(bucket)
+                    if let Some(table_name) =
+                        utils::sanitize_bucket_name(bucket, config.get_table_prefix())
                         && let Err(e) = utils::create_bucket_indexes(&conn, &table_name)
                     {
                         warn!("Failed to create indexes for bucket {}: {}", bucket, e);
@@ -62,32 +264,196 @@ async fn main() -> std::io::Result<()> {
                 }
             }
         }
-    }
+        utils::ensure_lifecycle_table(&conn).expect("Failed to create lifecycle table");
+        utils::ensure_acl_table(&conn).expect("Failed to create bucket ACL table");
+        utils::ensure_bucket_meta_table(&conn).expect("Failed to create bucket metadata table");
+        utils::blobs::ensure_blobs_table(&conn).expect("Failed to create shared blob table");
+        // Buckets created at runtime or offline with `s3insqlite admin create-bucket`
+        for bucket in utils::bucket::recorded_buckets(&conn, config.get_table_prefix())
+            .expect("Failed to read recorded buckets")
+        {
+            if buckets_set.insert(bucket.clone()) {
+                info!("Loaded bucket: {bucket}");
+            }
+        }
+        utils::temp_credentials::ensure_temp_credentials_table(&conn)
+            .expect("Failed to create temporary credentials table");
+        utils::bucket_meta::logging_targets(&conn).expect("Failed to read bucket logging targets")
+    };
 
     // Schedule periodic database optimization
-    utils::schedule_optimization(pool.clone());
+    match config.get_optimization_interval() {
+        Some(interval) => utils::schedule_optimization(pool.clone(), interval),
+        None => info!("Scheduled database optimization disabled"),
+    }
+
+    // Schedule periodic WAL checkpoints
+    match config.get_wal_checkpoint_interval() {
+        Some(interval) => utils::schedule_wal_checkpoint(
+            pool.clone(),
+            interval,
+            config.get_wal_checkpoint_threshold_pages(),
+        ),
+        None => info!("Scheduled WAL checkpoints disabled"),
+    }
+
+    // Schedule periodic deletion of expired temporary credentials
+    match config.get_temp_credentials_cleanup_interval() {
+        Some(interval) => {
+            utils::temp_credentials::schedule_temp_credentials_cleanup(pool.clone(), interval)
+        }
+        None => info!("Scheduled deletion of expired temporary credentials disabled"),
+    }
+
+    // Queue access records for delivery to the logging target buckets
+    let (access_log, access_records) = match config.get_log_delivery_interval() {
+        Some(interval) => {
+            let (tx, rx) = mpsc::channel(handlers::ACCESS_LOG_QUEUE_CAPACITY);
+            (Some(tx), Some((rx, interval)))
+        }
+        None => {
+            info!("Server access logging disabled");
+            (None, None)
+        }
+    };
+
+    // Open the audit log, in the main database unless audit_log_path names another file
+    let audit_log = match config.get_audit_log_path() {
+        Some(path) => match utils::audit::AuditLog::open(path) {
+            Ok(audit_log) => {
+                info!("Audit logging to {path}");
+                Some(audit_log)
+            }
+            Err(e) => {
+                error!("Failed to open audit log {path}: {e}");
+                return Err(std::io::Error::other(format!(
+                    "Failed to open audit log: {e}"
+                )));
+            }
+        },
+        None => None,
+    };
+
+    // Queue uploads for the write batching task
+    let (write_batch, queued_writes) = if config.is_write_batching_enabled() {
+        let (tx, rx) = mpsc::channel(handlers::WRITE_BATCH_QUEUE_CAPACITY);
+        (Some(tx), Some(rx))
+    } else {
+        (None, None)
+    };
+
+    // Read-only connections, opened once the tables exist
+    let read_pool = utils::create_read_pool(
+        &config.database_path,
+        config.get_db_pool_max_size(),
+        config.get_db_pool_min_idle(),
+        config.get_db_pool_timeout_seconds().as_secs(),
+        config.get_db_pool_idle_timeout(),
+        config.get_db_pool_max_lifetime(),
+    )
+    .expect("Failed to create database connection pool");
 
     // Create shared application state
-    let state = Arc::new(AppState::new(pool, buckets_set));
+    let state = Arc::new(AppState::new(
+        read_pool,
+        pool,
+        buckets_set,
+        config.clone(),
+        credentials,
+        ip_filter,
+        rate_limiter,
+        logging_targets,
+        access_log,
+        audit_log,
+        write_batch,
+    ));
+    if let Some((records, interval)) = access_records {
+        handlers::schedule_access_log_delivery(state.clone(), records, interval);
+    }
+    if let Some(queued_writes) = queued_writes {
+        handlers::schedule_write_batching(state.clone(), queued_writes);
+    }
+
+    // Reload the access keys when the credentials file changes
+    if let Some(path) = config.get_credentials_file() {
+        match config.get_credentials_reload_interval() {
+            Some(interval) => {
+                handlers::schedule_credentials_reload(state.clone(), path.to_string(), interval)
+            }
+            None => info!("Credentials file {path} is only reloaded on POST /?reload-credentials"),
+        }
+    }
+
+    // Schedule periodic deletion of expired objects
+    match config.get_expiry_reap_interval() {
+        Some(interval) => utils::schedule_expiry_reaper(
+            (*state.write_pool).clone(),
+            state.buckets.clone(),
+            config.get_table_prefix().to_string(),
+            interval,
+        ),
+        None => info!("Scheduled deletion of expired objects disabled"),
+    }
+
+    // Schedule periodic online backups, read from the read-only pool
+    match (config.get_snapshot_interval(), config.get_backup_dir()) {
+        (Some(interval), Some(dir)) => utils::backup::schedule_snapshots(
+            (*state.read_pool).clone(),
+            dir.into(),
+            interval,
+            config.get_snapshot_retention(),
+        ),
+        (Some(_), None) => warn!("Scheduled snapshots disabled, backup_dir is not set"),
+        (None, _) => info!("Scheduled snapshots disabled"),
+    }
+
+    // Schedule periodic lifecycle expiration sweeps
+    match config.get_lifecycle_sweep_interval() {
+        Some(interval) => utils::schedule_lifecycle_sweep(
+            (*state.write_pool).clone(),
+            state.object_cache.clone(),
+            config.get_table_prefix().to_string(),
+            interval,
+        ),
+        None => info!("Scheduled lifecycle sweeps disabled"),
+    }
 
     let max_object_size = config.get_max_object_size();
     let max_workers = config.get_max_workers();
+    let log_request_headers = config.logs_request_headers();
     info!(
-        "Server configuration: bind={}:{}, workers={}, max_object_size={}",
-        config.bind_address, config.port, max_workers, max_object_size
+        "Server configuration: workers={}, max_object_size={}",
+        max_workers, max_object_size
     );
 
     // Build our application with the routes
     let app = Router::new()
         // S3 ListBuckets API: GET /
-        .route("/", get(handlers::list_buckets))
+        .route("/", get(handlers::get_root_dispatch))
+        // Admin operations: POST /?optimize
+        .route("/", post(handlers::post_root_dispatch))
         // Path-style endpoints: /{bucket}/{key:.*} and /{bucket}
         .route("/{bucket}", get(handlers::get_bucket_dispatch))
         .route("/{bucket}/", get(handlers::get_bucket_dispatch))
+        .route("/{bucket}", head(handlers::head_bucket))
+        .route("/{bucket}/", head(handlers::head_bucket))
+        .route("/{bucket}", put(handlers::put_bucket_dispatch))
+        .route("/{bucket}/", put(handlers::put_bucket_dispatch))
+        .route("/{bucket}", delete(handlers::delete_bucket_dispatch))
+        .route("/{bucket}/", delete(handlers::delete_bucket_dispatch))
+        .route("/{bucket}", post(handlers::post_bucket_dispatch))
+        .route("/{bucket}/", post(handlers::post_bucket_dispatch))
         .route("/{bucket}/{*key}", put(handlers::upload_object))
         .route("/{bucket}/{*key}", get(handlers::download_object))
         .route("/{bucket}/{*key}", delete(handlers::delete_object))
         .route("/{bucket}/{*key}", head(handlers::head_object))
+        .route("/{bucket}/{*key}", post(handlers::post_object_dispatch))
+        // CORS preflight requests on buckets and objects
+        .route("/{bucket}", options(handlers::cors_preflight))
+        .route("/{bucket}/", options(handlers::cors_preflight))
+        .route("/{bucket}/{*key}", options(handlers::cors_preflight))
+        // Known paths with an unsupported method; must follow every route
+        .method_not_allowed_fallback(method_not_allowed)
         // Catch-all route for debugging unmatched requests
         .fallback(|req: axum::http::Request<axum::body::Body>| async move {
             use axum::{http::StatusCode, response::IntoResponse};
@@ -96,39 +462,182 @@ async fn main() -> std::io::Result<()> {
             error!("Fallback route hit for method: {} URI: {}", method, uri);
             (StatusCode::NOT_IMPLEMENTED, "").into_response()
         })
+        .layer(middleware::from_fn_with_state(state.clone(), upload_guard))
+        // Inside auth_middleware, to limit signed requests by access key
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            handlers::rate_limit_middleware,
+        ))
+        // Outside upload_guard, so unauthenticated uploads are refused before anything else
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            handlers::auth_middleware,
+        ))
+        // Outside auth_middleware, so failed authentications are recorded too
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            handlers::audit_log_middleware,
+        ))
+        // Outside upload_guard, so its rejections carry CORS headers too
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            handlers::cors_middleware,
+        ))
+        // Outside auth_middleware, so filtered clients never cause a policy lookup
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            handlers::ip_filter_middleware,
+        ))
+        // Outermost of the stateful layers, so rejected requests are logged too
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            handlers::access_log_middleware,
+        ))
         .with_state(state)
+        // Allow request bodies (objects and multipart parts) up to max_object_size
+        .layer(DefaultBodyLimit::max(max_object_size))
+        // Compress listings and error documents for clients sending Accept-Encoding;
+        // compressed responses drop Content-Length and use chunked transfer encoding
+        .layer(
+            CompressionLayer::new().compress_when(
+                DefaultPredicate::new()
+                    .and(is_not_object_body)
+                    .and(is_not_handler_encoded),
+            ),
+        )
         .layer(
             TraceLayer::new_for_http()
-                .on_request(|req: &axum::http::Request<_>, _span: &tracing::Span| {
-                    tracing::debug!(
-                        "Incoming request: {} {}, headers: {:?}",
-                        req.method(),
-                        req.uri(),
-                        req.headers()
-                    );
+                .make_span_with(|req: &axum::http::Request<_>| {
+                    let request_id = req
+                        .extensions()
+                        .get::<utils::RequestId>()
+                        .map(|id| id.0.as_str())
+                        .unwrap_or_default();
+                    tracing::debug_span!(
+                        "request",
+                        request_id,
+                        method = %req.method(),
+                        uri = %req.uri(),
+                    )
+                })
+                .on_request(move |req: &axum::http::Request<_>, _span: &tracing::Span| {
+                    if log_request_headers {
+                        tracing::debug!(
+                            "Incoming request: {} {}, headers: {}",
+                            req.method(),
+                            req.uri().path(),
+                            utils::redacted_headers(req.headers())
+                        );
+                    } else {
+                        tracing::debug!("Incoming request: {} {}", req.method(), req.uri().path());
+                    }
                 })
                 .on_response(
                     |response: &axum::http::Response<_>,
-                     _latency: std::time::Duration,
+                     latency: std::time::Duration,
                      _span: &tracing::Span| {
-                        tracing::debug!("Response: {:?}", response);
+                        tracing::debug!("Response: {} in {:?}", response.status(), latency);
                     },
                 ),
-        );
+        )
+        // Outermost, so the request id is known to the trace span and every handler
+        .layer(middleware::from_fn(request_id_middleware));
 
-    // Create socket address
-    let addr = (config.bind_address.as_str(), config.port)
-        .to_socket_addrs()
-        .expect("Invalid socket address")
-        .next()
-        .unwrap();
+    #[cfg(unix)]
+    if let Some(path) = config.get_unix_socket_path() {
+        use std::os::unix::fs::FileTypeExt;
 
-    info!(
-        "Server started successfully! Listening on {}:{}",
-        config.bind_address, config.port
-    );
+        // Remove a socket left behind by an unclean shutdown, but never other files
+        if std::fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_socket()) {
+            warn!("Removing stale unix socket {path}");
+            std::fs::remove_file(path)?;
+        }
+        let listener = tokio::net::UnixListener::bind(path)?;
+        info!("Server started successfully! Listening on unix socket {path}");
+
+        let result = axum::serve(listener, app)
+            .with_graceful_shutdown(shutdown_signal())
+            .await;
+        if let Err(e) = std::fs::remove_file(path) {
+            warn!("Failed to remove unix socket {path}: {e}");
+        }
+        return result;
+    }
+
+    let binds = config.get_bind_addresses();
+    if binds.is_empty() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "No listen address configured: set port or add [[bind]] entries",
+        ));
+    }
 
-    // Start the server
-    let listener = TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await
+    // Bind every address before serving, so a bad entry fails startup
+    let mut listeners = Vec::with_capacity(binds.len());
+    for bind in &binds {
+        let addr = (bind.address.as_str(), bind.port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("Invalid socket address: {}:{}", bind.address, bind.port),
+                )
+            })?;
+        listeners.push((addr, TcpListener::bind(addr).await?));
+    }
+
+    // Serve each listener in its own task sharing the same router and state
+    let mut servers = JoinSet::new();
+    for (addr, listener) in listeners {
+        info!("Server started successfully! Listening on {addr}");
+        let app = app.clone();
+        servers.spawn(async move {
+            // Client addresses are recorded in server access logs
+            axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .with_graceful_shutdown(shutdown_signal())
+            .await
+            .inspect_err(|e| error!("Listener on {addr} failed: {e}"))
+        });
+    }
+
+    // Exit as soon as any listener fails; dropping the set aborts the others
+    while let Some(result) = servers.join_next().await {
+        result.map_err(std::io::Error::other)??;
+    }
+    Ok(())
+}
+
+/// Resolve on Ctrl-C or SIGTERM, letting in-flight requests finish
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("Failed to listen for Ctrl-C: {e}");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                error!("Failed to listen for SIGTERM: {e}");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+    info!("Shutdown signal received, finishing in-flight requests");
 }
@@ -1,19 +1,165 @@
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::path::Path;
 
+use crate::models::credentials::CredentialEntry;
+
+/// Storage classes accepted when `storage_classes` is not configured
+const DEFAULT_STORAGE_CLASSES: &[&str] = &[
+    "STANDARD",
+    "REDUCED_REDUNDANCY",
+    "STANDARD_IA",
+    "ONEZONE_IA",
+    "INTELLIGENT_TIERING",
+    "GLACIER",
+    "GLACIER_IR",
+    "DEEP_ARCHIVE",
+];
+
 #[derive(Debug, Deserialize)]
 pub struct AppConfig {
     pub database_path: String,
-    pub buckets: Vec<String>, // List of allowed buckets
-    pub port: u16,
-    pub bind_address: String,
+    pub buckets: Vec<String>,       // List of allowed buckets
+    port: Option<u16>,              // Single listen port, kept for compatibility
+    bind_address: Option<String>,   // Address for `port`, default 127.0.0.1
+    bind: Option<Vec<BindAddress>>, // Additional listen addresses
     pub log_path: String,
-    pub log_level: String,                // Add log_level field
-    max_workers: Option<usize>,           // Optional for backward compatibility
-    max_object_size: Option<usize>,       // Maximum object size in bytes, default to 1 MB
-    db_pool_max_size: Option<u32>,        // Maximum number of connections in pool
-    db_pool_min_idle: Option<u32>,        // Minimum idle connections to maintain
-    db_pool_timeout_seconds: Option<u64>, // Connection acquisition timeout
+    pub log_level: String,                         // Add log_level field
+    max_workers: Option<usize>,                    // Optional for backward compatibility
+    max_object_size: Option<usize>,                // Maximum object size in bytes, default to 1 MB
+    max_import_size: Option<usize>,                // Decompressed bytes of an imported archive
+    db_pool_max_size: Option<u32>,                 // Maximum number of connections in pool
+    db_pool_min_idle: Option<u32>,                 // Minimum idle connections to maintain
+    db_pool_timeout_seconds: Option<u64>,          // Connection acquisition timeout
+    db_pool_idle_timeout_seconds: Option<u64>,     // Close connections idle this long, 0 disables
+    db_pool_max_lifetime_seconds: Option<u64>,     // Recycle connections this old, 0 disables
+    optimization_interval_hours: Option<u64>,      // VACUUM/ANALYZE interval, 0 disables
+    wal_checkpoint_interval_seconds: Option<u64>,  // WAL checkpoint interval, 0 disables
+    wal_checkpoint_threshold_pages: Option<u64>,   // Minimum WAL frames before checkpointing
+    max_user_metadata_size: Option<usize>,         // Maximum total size of x-amz-meta-* headers
+    default_storage_class: Option<String>,         // Used when x-amz-storage-class is absent
+    storage_classes: Option<Vec<String>>,          // Accepted x-amz-storage-class values
+    list_streaming_threshold: Option<usize>,       // Stream listings above this max-keys
+    lifecycle_sweep_interval_seconds: Option<u64>, // Lifecycle expiration sweep interval, 0 disables
+    expiry_reap_interval_seconds: Option<u64>,     // Expired object deletion interval, 0 disables
+    enable_browser_ui: Option<bool>,               // Serve HTML listings to browsers
+    owner_id: Option<String>,                      // Id reported as bucket and object owner
+    owner_display_name: Option<String>,            // Display name reported with owner_id
+    busy_retry_max_attempts: Option<u32>,          // Retries of a busy database operation
+    unix_socket_path: Option<String>,              // Serve on this unix socket instead of TCP
+    allow_bucket_creation: Option<bool>,           // Allow CreateBucket via PUT /{bucket}
+    max_buckets: Option<usize>,                    // Refuse CreateBucket at this many buckets
+    disallowed_key_patterns: Option<Vec<String>>,  // Glob patterns of refused object keys
+    reject_key_control_chars: Option<bool>,        // Refuse keys with NUL/control characters
+    region: Option<String>,                        // Region reported for every bucket
+    log_delivery_interval_seconds: Option<u64>,    // Access log delivery interval, 0 disables
+    log_request_headers: Option<bool>,             // Debug log request headers, redacted
+    credentials: Option<Vec<CredentialEntry>>,     // Access keys verifying signed requests
+    auth_mode: Option<AuthMode>,                   // none, required or log_only
+    max_clock_skew_seconds: Option<u64>,           // Accepted age of signed requests
+    allow_ips: Option<Vec<String>>,                // CIDRs allowed to connect, all if unset
+    deny_ips: Option<Vec<String>>,                 // CIDRs refused, over allow_ips
+    write_allow_ips: Option<Vec<String>>,          // CIDRs allowed to write, all if unset
+    trusted_proxies: Option<Vec<String>>,          // Peers whose X-Forwarded-For is trusted
+    public_read: Option<Vec<String>>,              // Buckets readable without credentials
+    write_once: Option<Vec<String>>,               // Buckets whose objects are never replaced
+    decode_content_encoding: Option<bool>,         // Gunzip objects for identity-only clients
+    table_prefix: Option<String>,                  // Prefix of bucket table names
+    max_objects_per_bucket: Option<u64>,           // Refuse new keys in buckets this full
+    max_bytes_per_bucket: Option<u64>,             // Refuse uploads taking buckets over this size
+    audit_log: Option<bool>,                       // Record writes and failed authentications
+    audit_log_path: Option<String>,                // Database of the audit log
+    max_temp_credentials_seconds: Option<u64>,     // Longest lifetime of temporary credentials
+    temp_credentials_cleanup_interval_seconds: Option<u64>, // Deletion of expired ones
+    credentials_file: Option<String>,              // More access keys, reloaded when changed
+    credentials_reload_interval_seconds: Option<u64>, // Checks of credentials_file for changes
+    default_delimiter: Option<String>,             // Listing delimiter when the request has none
+    list_gzip_threshold_bytes: Option<usize>,      // Gzip listing bodies larger than this
+    write_batching: Option<bool>,                  // Commit uploads in shared transactions
+    write_batch_max_objects: Option<usize>,        // Commit a batch once it holds this many
+    write_batch_max_bytes: Option<usize>,          // or this many bytes
+    write_batch_max_delay_ms: Option<u64>,         // or this long after its first upload
+    object_cache_max_bytes: Option<usize>,         // Memory of the object cache, 0 disables it
+    object_cache_max_object_size: Option<usize>,   // Largest object held in the cache
+    backup_dir: Option<String>,                    // Directory of POST /?backup copies
+    snapshot_interval_minutes: Option<u64>,        // Online backups into backup_dir, 0 disables
+    snapshot_retention: Option<usize>,             // Snapshots kept, 0 keeps them all
+    compression_min_size_bytes: Option<usize>,     // Objects smaller than this are stored as is
+    rate_limit: Option<RateLimit>,                 // Per-client limits of every bucket
+    bucket_rate_limits: Option<HashMap<String, RateLimit>>, // Per-client limits by bucket
+    bucket_quotas: Option<HashMap<String, BucketQuota>>, // Quotas overriding the global ones
+    bucket_storage: Option<HashMap<String, BucketStorage>>, // How objects of buckets are stored
+}
+
+/// How requests are authenticated, the `auth_mode` config value
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthMode {
+    /// Every request is allowed and signatures are ignored
+    None,
+    /// Requests must be signed by a configured key, or be public reads
+    Required,
+    /// Signatures are verified and failures logged, but every request is allowed
+    LogOnly,
+}
+
+/// Token bucket limits of the requests and bytes of one client, a `[rate_limit]` or
+/// `[bucket_rate_limits.<bucket>]` table. Bursts default to one second's worth.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RateLimit {
+    pub requests_per_second: Option<f64>,
+    pub burst: Option<f64>,
+    pub bytes_per_second: Option<f64>,
+    pub bytes_burst: Option<f64>,
+}
+
+impl RateLimit {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.requests_per_second.is_none() && self.bytes_per_second.is_none() {
+            return Err("set requests_per_second or bytes_per_second".to_string());
+        }
+        for (name, value) in [
+            ("requests_per_second", self.requests_per_second),
+            ("burst", self.burst),
+            ("bytes_per_second", self.bytes_per_second),
+            ("bytes_burst", self.bytes_burst),
+        ] {
+            if value.is_some_and(|v| !(v > 0.0 && v.is_finite())) {
+                return Err(format!("{name} must be a positive number"));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Quotas of one bucket, a `[bucket_quotas.<bucket>]` table. Values left out fall back
+/// to `max_objects_per_bucket` and `max_bytes_per_bucket`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BucketQuota {
+    pub max_objects: Option<u64>,
+    pub max_bytes: Option<u64>,
+}
+
+/// Storage settings of one bucket, a `[bucket_storage.<bucket>]` table
+#[derive(Debug, Clone, Deserialize)]
+pub struct BucketStorage {
+    pub compression: Option<Compression>,
+    /// Store each distinct object body once, shared by the objects holding it
+    pub dedup: Option<bool>,
+}
+
+/// Compression of stored object bodies, the `compression` value of a bucket
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Compression {
+    Zstd,
+}
+
+/// An address and port to listen on, one `[[bind]]` table in the config file
+#[derive(Debug, Clone, Deserialize)]
+pub struct BindAddress {
+    pub address: String,
+    pub port: u16,
 }
 
 impl AppConfig {
@@ -24,6 +170,30 @@ impl AppConfig {
         settings.try_deserialize()
     }
 
+    pub fn get_table_prefix(&self) -> &str {
+        self.table_prefix.as_deref().unwrap_or("bucket_")
+    }
+
+    fn bucket_quota(&self, bucket: &str) -> Option<&BucketQuota> {
+        self.bucket_quotas
+            .as_ref()
+            .and_then(|quotas| quotas.get(bucket))
+    }
+
+    /// Most objects a bucket may hold, unlimited by default
+    pub fn get_max_objects_per_bucket(&self, bucket: &str) -> Option<u64> {
+        self.bucket_quota(bucket)
+            .and_then(|quota| quota.max_objects)
+            .or(self.max_objects_per_bucket)
+    }
+
+    /// Most bytes of object data a bucket may hold, unlimited by default
+    pub fn get_max_bytes_per_bucket(&self, bucket: &str) -> Option<u64> {
+        self.bucket_quota(bucket)
+            .and_then(|quota| quota.max_bytes)
+            .or(self.max_bytes_per_bucket)
+    }
+
     pub fn get_max_workers(&self) -> usize {
         self.max_workers.unwrap_or_else(num_cpus::get) // Default to number of CPU cores
     }
@@ -32,6 +202,11 @@ impl AppConfig {
         self.max_object_size.unwrap_or(1024 * 1024 * 1024) // Default to 1 GB
     }
 
+    pub fn get_max_import_size(&self) -> usize {
+        self.max_import_size
+            .unwrap_or_else(|| self.get_max_object_size().saturating_mul(4)) // Default to 4x max_object_size
+    }
+
     pub fn get_db_pool_max_size(&self) -> u32 {
         self.db_pool_max_size.unwrap_or(8) // Default to 8 connections
     }
@@ -43,4 +218,331 @@ impl AppConfig {
     pub fn get_db_pool_timeout_seconds(&self) -> std::time::Duration {
         std::time::Duration::from_secs(self.db_pool_timeout_seconds.unwrap_or(30))
     }
+
+    /// How long a connection may sit idle in the pool before it is closed, None if forever
+    pub fn get_db_pool_idle_timeout(&self) -> Option<std::time::Duration> {
+        match self.db_pool_idle_timeout_seconds.unwrap_or(300) {
+            0 => None,
+            seconds => Some(std::time::Duration::from_secs(seconds)),
+        }
+    }
+
+    /// Age at which a connection is closed and replaced, None if connections live until closed
+    pub fn get_db_pool_max_lifetime(&self) -> Option<std::time::Duration> {
+        match self.db_pool_max_lifetime_seconds.unwrap_or(0) {
+            0 => None,
+            seconds => Some(std::time::Duration::from_secs(seconds)),
+        }
+    }
+
+    /// Interval between scheduled database optimizations, None if disabled
+    pub fn get_optimization_interval(&self) -> Option<std::time::Duration> {
+        match self.optimization_interval_hours.unwrap_or(24) {
+            0 => None,
+            hours => Some(std::time::Duration::from_secs(hours * 3600)),
+        }
+    }
+
+    /// Interval between WAL checkpoint attempts, None if disabled
+    pub fn get_wal_checkpoint_interval(&self) -> Option<std::time::Duration> {
+        match self.wal_checkpoint_interval_seconds.unwrap_or(300) {
+            0 => None,
+            seconds => Some(std::time::Duration::from_secs(seconds)),
+        }
+    }
+
+    pub fn get_wal_checkpoint_threshold_pages(&self) -> u64 {
+        self.wal_checkpoint_threshold_pages.unwrap_or(1000) // Default to SQLite's autocheckpoint size
+    }
+
+    pub fn get_max_user_metadata_size(&self) -> usize {
+        self.max_user_metadata_size.unwrap_or(2048) // Default to the S3 limit of 2 KB
+    }
+
+    pub fn get_default_storage_class(&self) -> &str {
+        self.default_storage_class.as_deref().unwrap_or("STANDARD")
+    }
+
+    pub fn is_storage_class_allowed(&self, storage_class: &str) -> bool {
+        match &self.storage_classes {
+            Some(classes) => classes.iter().any(|c| c == storage_class),
+            None => DEFAULT_STORAGE_CLASSES.contains(&storage_class),
+        }
+    }
+
+    /// Delimiter of listings requested without one, e.g. "/" for directory-style browsing
+    pub fn get_default_delimiter(&self) -> Option<char> {
+        self.default_delimiter
+            .as_deref()
+            .and_then(|d| d.chars().next())
+    }
+
+    /// Listings whose max-keys exceeds this are streamed, None if streaming is disabled
+    pub fn get_list_streaming_threshold(&self) -> Option<usize> {
+        self.list_streaming_threshold
+    }
+
+    pub fn is_write_batching_enabled(&self) -> bool {
+        self.write_batching.unwrap_or(false)
+    }
+
+    pub fn get_write_batch_max_objects(&self) -> usize {
+        self.write_batch_max_objects.unwrap_or(64).max(1) // Default to 64 objects
+    }
+
+    pub fn get_write_batch_max_bytes(&self) -> usize {
+        self.write_batch_max_bytes.unwrap_or(16 * 1024 * 1024) // Default to 16 MB
+    }
+
+    pub fn get_write_batch_max_delay(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.write_batch_max_delay_ms.unwrap_or(5)) // Default to 5 ms
+    }
+
+    /// Interval between online backup snapshots into `backup_dir`, None if disabled
+    pub fn get_snapshot_interval(&self) -> Option<std::time::Duration> {
+        match self.snapshot_interval_minutes.unwrap_or(0) {
+            0 => None,
+            minutes => Some(std::time::Duration::from_secs(minutes * 60)),
+        }
+    }
+
+    pub fn get_snapshot_retention(&self) -> usize {
+        self.snapshot_retention.unwrap_or(7) // Default to 7 snapshots
+    }
+
+    /// Compression of the objects uploaded to a bucket, None if they are stored as sent
+    pub fn get_bucket_compression(&self, bucket: &str) -> Option<Compression> {
+        self.bucket_storage
+            .as_ref()
+            .and_then(|storage| storage.get(bucket))
+            .and_then(|storage| storage.compression)
+    }
+
+    /// Whether the objects uploaded to a bucket share the storage of identical bodies
+    pub fn is_dedup(&self, bucket: &str) -> bool {
+        self.bucket_storage
+            .as_ref()
+            .and_then(|storage| storage.get(bucket))
+            .and_then(|storage| storage.dedup)
+            .unwrap_or(false) // Default to storing every object body in its own row
+    }
+
+    pub fn get_compression_min_size(&self) -> usize {
+        self.compression_min_size_bytes.unwrap_or(1024) // Default to 1 KB
+    }
+
+    /// Directory that `POST /?backup` writes into, None if backups are disabled
+    pub fn get_backup_dir(&self) -> Option<&str> {
+        self.backup_dir.as_deref().filter(|dir| !dir.is_empty())
+    }
+
+    /// Memory in bytes of the in-process object cache, None if disabled
+    pub fn get_object_cache_max_bytes(&self) -> Option<usize> {
+        self.object_cache_max_bytes.filter(|&bytes| bytes > 0)
+    }
+
+    pub fn get_object_cache_max_object_size(&self) -> usize {
+        self.object_cache_max_object_size.unwrap_or(1024 * 1024) // Default to 1 MB
+    }
+
+    /// Size in bytes above which listing XML is gzip compressed for clients accepting it
+    pub fn get_list_gzip_threshold(&self) -> usize {
+        self.list_gzip_threshold_bytes.unwrap_or(64 * 1024) // Default to 64 KB
+    }
+
+    /// Interval between lifecycle expiration sweeps, None if disabled
+    pub fn get_lifecycle_sweep_interval(&self) -> Option<std::time::Duration> {
+        match self.lifecycle_sweep_interval_seconds.unwrap_or(3600) {
+            0 => None,
+            seconds => Some(std::time::Duration::from_secs(seconds)),
+        }
+    }
+
+    /// Interval between deletions of objects past their expiry, None if disabled
+    pub fn get_expiry_reap_interval(&self) -> Option<std::time::Duration> {
+        match self.expiry_reap_interval_seconds.unwrap_or(300) {
+            0 => None,
+            seconds => Some(std::time::Duration::from_secs(seconds)),
+        }
+    }
+
+    /// Database file of the audit log, the main database unless `audit_log_path` is set.
+    /// None if audit logging is disabled.
+    pub fn get_audit_log_path(&self) -> Option<&str> {
+        if !self.audit_log.unwrap_or(false) {
+            return None;
+        }
+        Some(
+            self.audit_log_path
+                .as_deref()
+                .unwrap_or(self.database_path.as_str()),
+        )
+    }
+
+    /// Longest lifetime clients may request for temporary credentials (default 12 hours)
+    pub fn get_max_temp_credentials_duration(&self) -> chrono::Duration {
+        chrono::Duration::seconds(self.max_temp_credentials_seconds.unwrap_or(43200) as i64)
+    }
+
+    pub fn get_temp_credentials_cleanup_interval(&self) -> Option<std::time::Duration> {
+        match self
+            .temp_credentials_cleanup_interval_seconds
+            .unwrap_or(3600)
+        {
+            0 => None,
+            seconds => Some(std::time::Duration::from_secs(seconds)),
+        }
+    }
+
+    pub fn get_log_delivery_interval(&self) -> Option<std::time::Duration> {
+        match self.log_delivery_interval_seconds.unwrap_or(300) {
+            0 => None,
+            seconds => Some(std::time::Duration::from_secs(seconds)),
+        }
+    }
+
+    /// The `[[credentials]]` entries, empty if signatures are not checked
+    pub fn get_credentials(&self) -> &[CredentialEntry] {
+        self.credentials.as_deref().unwrap_or_default()
+    }
+
+    /// Path of the file of access keys added to the `[[credentials]]` entries, if any
+    pub fn get_credentials_file(&self) -> Option<&str> {
+        self.credentials_file.as_deref()
+    }
+
+    pub fn get_credentials_reload_interval(&self) -> Option<std::time::Duration> {
+        match self.credentials_reload_interval_seconds.unwrap_or(10) {
+            0 => None,
+            seconds => Some(std::time::Duration::from_secs(seconds)),
+        }
+    }
+
+    /// The configured `auth_mode`: `required` by default when credentials are configured,
+    /// inline or in a `credentials_file`, else `none`
+    pub fn get_auth_mode(&self) -> AuthMode {
+        match self.auth_mode {
+            Some(mode) => mode,
+            None if self.get_credentials().is_empty() && self.credentials_file.is_none() => {
+                AuthMode::None
+            }
+            None => AuthMode::Required,
+        }
+    }
+
+    pub fn get_allow_ips(&self) -> &[String] {
+        self.allow_ips.as_deref().unwrap_or_default()
+    }
+
+    pub fn get_deny_ips(&self) -> &[String] {
+        self.deny_ips.as_deref().unwrap_or_default()
+    }
+
+    pub fn get_write_allow_ips(&self) -> &[String] {
+        self.write_allow_ips.as_deref().unwrap_or_default()
+    }
+
+    pub fn get_trusted_proxies(&self) -> &[String] {
+        self.trusted_proxies.as_deref().unwrap_or_default()
+    }
+
+    pub fn get_rate_limit(&self) -> Option<&RateLimit> {
+        self.rate_limit.as_ref()
+    }
+
+    /// The `[bucket_rate_limits.<bucket>]` tables, by bucket
+    pub fn get_bucket_rate_limits(&self) -> impl Iterator<Item = (&String, &RateLimit)> {
+        self.bucket_rate_limits.iter().flatten()
+    }
+
+    /// Largest difference between the time a request was signed and the server time
+    pub fn get_max_clock_skew(&self) -> chrono::Duration {
+        chrono::Duration::seconds(self.max_clock_skew_seconds.unwrap_or(900) as i64) // 15 minutes
+    }
+
+    /// Whether unsigned GET and HEAD requests may read the bucket
+    pub fn is_public_read(&self, bucket: &str) -> bool {
+        self.public_read
+            .as_ref()
+            .is_some_and(|buckets| buckets.iter().any(|b| b == bucket))
+    }
+
+    /// Whether objects of the bucket, once written, can no longer be overwritten or deleted
+    pub fn is_write_once(&self, bucket: &str) -> bool {
+        self.write_once
+            .as_ref()
+            .is_some_and(|buckets| buckets.iter().any(|b| b == bucket))
+    }
+
+    pub fn decodes_content_encoding(&self) -> bool {
+        self.decode_content_encoding.unwrap_or(false) // Passthrough by default
+    }
+
+    pub fn logs_request_headers(&self) -> bool {
+        self.log_request_headers.unwrap_or(false) // Disabled by default
+    }
+
+    pub fn is_browser_ui_enabled(&self) -> bool {
+        self.enable_browser_ui.unwrap_or(false) // Disabled by default
+    }
+
+    pub fn get_busy_retry_max_attempts(&self) -> u32 {
+        self.busy_retry_max_attempts.unwrap_or(5) // Default to 5 retries, about 1.5 s in total
+    }
+
+    pub fn get_owner_id(&self) -> &str {
+        self.owner_id.as_deref().unwrap_or("s3insqlite")
+    }
+
+    pub fn get_owner_display_name(&self) -> &str {
+        self.owner_display_name
+            .as_deref()
+            .unwrap_or_else(|| self.get_owner_id())
+    }
+
+    /// Path of the unix domain socket to serve on instead of TCP, if any
+    pub fn get_unix_socket_path(&self) -> Option<&str> {
+        self.unix_socket_path.as_deref()
+    }
+
+    pub fn is_bucket_creation_allowed(&self) -> bool {
+        self.allow_bucket_creation.unwrap_or(false)
+    }
+
+    /// Maximum number of buckets, configured and created, None if unlimited
+    pub fn get_max_buckets(&self) -> Option<usize> {
+        match self.max_buckets.unwrap_or(1000) {
+            0 => None, // 0 disables the limit
+            n => Some(n),
+        }
+    }
+
+    /// All TCP addresses to listen on: `bind_address`/`port` if `port` is set,
+    /// followed by the `[[bind]]` entries
+    pub fn get_bind_addresses(&self) -> Vec<BindAddress> {
+        let single = self.port.map(|port| BindAddress {
+            address: self
+                .bind_address
+                .clone()
+                .unwrap_or_else(|| "127.0.0.1".to_string()),
+            port,
+        });
+        single
+            .into_iter()
+            .chain(self.bind.iter().flatten().cloned())
+            .collect()
+    }
+
+    /// Glob patterns of object keys refused on upload
+    pub fn get_disallowed_key_patterns(&self) -> &[String] {
+        self.disallowed_key_patterns.as_deref().unwrap_or_default()
+    }
+
+    pub fn rejects_key_control_chars(&self) -> bool {
+        self.reject_key_control_chars.unwrap_or(false)
+    }
+
+    pub fn get_region(&self) -> &str {
+        self.region.as_deref().unwrap_or("us-east-1")
+    }
 }
@@ -0,0 +1,209 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+
+use crate::models::AppConfig;
+
+/// A secret access key, hidden from debug output and logs
+#[derive(Clone, Deserialize)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for Secret {
+    fn from(secret: String) -> Self {
+        Self(secret)
+    }
+}
+
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("<redacted>")
+    }
+}
+
+/// Access of a key to a bucket, `"ro"` or `"rw"` in the `buckets` table of its credentials
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum BucketPermission {
+    #[serde(rename = "ro")]
+    ReadOnly,
+    #[serde(rename = "rw")]
+    ReadWrite,
+}
+
+/// One `[[credentials]]` table of the config file. The secret is given inline
+/// (`secret_access_key`), read from a file (`secret_access_key_file`) or taken
+/// from an environment variable (`secret_access_key_env`): exactly one of them.
+/// With a `buckets` table the key may only access the buckets listed there.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CredentialEntry {
+    pub access_key_id: String,
+    secret_access_key: Option<Secret>,
+    secret_access_key_file: Option<String>,
+    secret_access_key_env: Option<String>,
+    pub display_name: Option<String>,
+    pub buckets: Option<HashMap<String, BucketPermission>>,
+}
+
+impl CredentialEntry {
+    /// Read the secret from its source
+    fn resolve_secret(&self) -> Result<Secret, String> {
+        let id = &self.access_key_id;
+        let secret = match (
+            &self.secret_access_key,
+            &self.secret_access_key_file,
+            &self.secret_access_key_env,
+        ) {
+            (Some(secret), None, None) => secret.0.clone(),
+            (None, Some(path), None) => std::fs::read_to_string(path)
+                .map_err(|e| format!("access key {id}: cannot read secret file {path}: {e}"))?
+                .trim_end_matches(['\r', '\n'])
+                .to_string(),
+            (None, None, Some(name)) => std::env::var(name).map_err(|e| {
+                format!("access key {id}: cannot read secret from environment variable {name}: {e}")
+            })?,
+            (None, None, None) => {
+                return Err(format!(
+                    "access key {id}: set one of secret_access_key, secret_access_key_file \
+                     or secret_access_key_env"
+                ));
+            }
+            _ => {
+                return Err(format!(
+                    "access key {id}: secret_access_key, secret_access_key_file and \
+                     secret_access_key_env are exclusive"
+                ));
+            }
+        };
+        if secret.is_empty() {
+            return Err(format!("access key {id}: the secret access key is empty"));
+        }
+        Ok(Secret(secret))
+    }
+}
+
+/// A `credentials_file`: `[[credentials]]` tables in TOML, or a JSON object with a
+/// `credentials` array of the same entries when the file name ends in `.json`
+#[derive(Debug, Deserialize)]
+struct CredentialsFile {
+    #[serde(default)]
+    credentials: Vec<CredentialEntry>,
+}
+
+/// Read the entries of a credentials file
+pub fn read_credentials_file(path: &str) -> Result<Vec<CredentialEntry>, String> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| format!("cannot read credentials file {path}: {e}"))?;
+    let file: CredentialsFile = if path.ends_with(".json") {
+        serde_json::from_str(&contents).map_err(|e| format!("{path}: {e}"))?
+    } else {
+        toml::from_str(&contents).map_err(|e| format!("{path}: {e}"))?
+    };
+    Ok(file.credentials)
+}
+
+/// An access key allowed to sign requests
+#[derive(Debug, Clone)]
+pub struct AccessKey {
+    pub display_name: Option<String>,
+    pub secret: Secret,
+    /// Buckets the key may access, None if it has read and write access to all of them
+    pub buckets: Option<HashMap<String, BucketPermission>>,
+}
+
+/// The access key a request was signed with, added to the request extensions
+/// once its signature is verified
+#[derive(Debug, Clone)]
+pub struct Principal {
+    pub access_key_id: String,
+}
+
+/// The access key, if any, of a request that failed authentication, added to the
+/// response extensions for the audit log
+#[derive(Debug, Clone)]
+pub struct AuthFailure {
+    pub access_key_id: Option<String>,
+}
+
+/// The configured access keys, by access key id
+#[derive(Debug, Clone, Default)]
+pub struct Credentials {
+    keys: HashMap<String, AccessKey>,
+}
+
+impl Credentials {
+    /// Resolve the secrets of the `[[credentials]]` entries. Fails on entries
+    /// without a usable access key id or secret, and on duplicate access key ids.
+    pub fn from_entries(entries: &[CredentialEntry]) -> Result<Self, String> {
+        let mut keys = HashMap::with_capacity(entries.len());
+        for entry in entries {
+            let id = &entry.access_key_id;
+            // The access key id is the first part of the slash separated credential scope
+            if id.is_empty() || id.contains('/') || id.chars().any(char::is_whitespace) {
+                return Err(format!(
+                    "invalid access key id {id:?}: it must be non-empty, without '/' or whitespace"
+                ));
+            }
+            let key = AccessKey {
+                display_name: entry.display_name.clone(),
+                secret: entry.resolve_secret()?,
+                buckets: entry.buckets.clone(),
+            };
+            if keys.insert(id.clone(), key).is_some() {
+                return Err(format!("duplicate access key id {id}"));
+            }
+        }
+        Ok(Self { keys })
+    }
+
+    /// The `[[credentials]]` entries of the config together with those of its
+    /// `credentials_file`; an access key id may only appear once across both
+    pub fn load(config: &AppConfig) -> Result<Self, String> {
+        let mut entries = config.get_credentials().to_vec();
+        if let Some(path) = config.get_credentials_file() {
+            entries.extend(read_credentials_file(path)?);
+        }
+        Self::from_entries(&entries)
+    }
+
+    /// Secret of an access key, None if the key is unknown
+    pub fn lookup(&self, access_key_id: &str) -> Option<&Secret> {
+        self.keys.get(access_key_id).map(|key| &key.secret)
+    }
+
+    /// Access of a key to a bucket, None if the key is unknown or may not access it
+    pub fn permission(&self, access_key_id: &str, bucket: &str) -> Option<BucketPermission> {
+        let key = self.keys.get(access_key_id)?;
+        match &key.buckets {
+            Some(buckets) => buckets.get(bucket).copied(),
+            None => Some(BucketPermission::ReadWrite),
+        }
+    }
+
+    /// Whether a key is limited to the buckets of its `buckets` table
+    pub fn is_restricted(&self, access_key_id: &str) -> bool {
+        self.keys
+            .get(access_key_id)
+            .is_some_and(|key| key.buckets.is_some())
+    }
+
+    /// Display name of an access key, or the access key id if it has none
+    pub fn display_name<'a>(&'a self, access_key_id: &'a str) -> &'a str {
+        self.keys
+            .get(access_key_id)
+            .and_then(|key| key.display_name.as_deref())
+            .unwrap_or(access_key_id)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+}
@@ -1,8 +1,13 @@
 pub mod config;
+pub mod credentials;
 pub mod s3;
 pub mod state;
 
 // Re-exports for convenience
-pub use config::AppConfig;
-pub use s3::ListBucketResult;
+pub use config::{AppConfig, AuthMode, Compression, RateLimit};
+pub use credentials::{AuthFailure, BucketPermission, Credentials, Principal, Secret};
+pub use s3::{
+    BucketStats, BucketTable, BucketTableReport, ImportReport, ImportedEntry, ListBucketResult,
+    S3Object, TempCredentialsReport,
+};
 pub use state::AppState;
@@ -2,6 +2,8 @@ use chrono::{DateTime, Utc};
 use serde::Serialize;
 use std::collections::HashSet;
 
+use crate::utils::bucket::ObjectRow;
+
 #[derive(Debug, Serialize)]
 pub struct ListBucketResult {
     /// Bucket name
@@ -32,6 +34,102 @@ pub struct CommonPrefix {
     pub prefix: String,
 }
 
+/// Object count and total size of a bucket
+#[derive(Debug, Clone, Serialize)]
+pub struct BucketStats {
+    pub bucket: String,
+    pub object_count: u64,
+    pub size_bytes: u64,
+}
+
+/// Outcome of an archive import: how many entries became objects, and each entry's result
+#[derive(Debug, Serialize)]
+pub struct ImportReport {
+    pub bucket: String,
+    pub imported: usize,
+    pub failed: usize,
+    pub entries: Vec<ImportedEntry>,
+}
+
+/// Result of importing one archive entry, with the reason if it was not imported
+#[derive(Debug, Serialize)]
+pub struct ImportedEntry {
+    pub key: String,
+    pub imported: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// A bucket table found in the database
+#[derive(Debug, Serialize)]
+pub struct BucketTable {
+    pub table: String,
+    /// Configured bucket stored in this table, None for an orphaned table
+    pub bucket: Option<String>,
+    pub orphan: bool,
+}
+
+/// Bucket tables present in the database, reconciled against the configured buckets
+#[derive(Debug, Serialize)]
+pub struct BucketTableReport {
+    pub tables: Vec<BucketTable>,
+    /// Configured buckets without a table
+    pub missing: Vec<String>,
+}
+
+/// Temporary credentials returned by POST /?temp-credentials
+#[derive(Debug, Serialize)]
+pub struct TempCredentialsReport {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub session_token: String,
+    /// Expiry time, RFC 3339
+    pub expiration: String,
+    pub bucket: String,
+    pub prefix: String,
+    /// `"ro"` or `"rw"`
+    pub permission: String,
+}
+
+impl S3Object {
+    pub fn new(
+        key: String,
+        size: usize,
+        last_modified: DateTime<Utc>,
+        md5_hash: Option<String>,
+        storage_class: String,
+    ) -> Self {
+        Self {
+            key,
+            size,
+            last_modified,
+            etag: md5_hash
+                .map(|h| format!("\"{}\"", h))
+                .unwrap_or_else(|| "\"00000000000000000000000000000000\"".to_string()),
+            storage_class,
+        }
+    }
+
+    /// `<Contents>` element of a ListObjects response
+    pub fn to_xml(&self) -> String {
+        let mut xml = String::from("<Contents>");
+        xml.push_str(&format!("<Key>{}</Key>", self.key));
+        xml.push_str(&format!(
+            "<LastModified>{}</LastModified>",
+            self.last_modified
+                .to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
+        ));
+        xml.push_str(&format!("<ETag>{}</ETag>", self.etag));
+        xml.push_str(&format!("<Size>{}</Size>", self.size));
+        xml.push_str(&format!(
+            "<StorageClass>{}</StorageClass>",
+            self.storage_class
+        ));
+        xml.push_str("</Contents>");
+        xml
+    }
+}
+
 impl ListBucketResult {
     pub fn new(bucket: &str, prefix: &str, delimiter: Option<char>) -> Self {
         Self {
@@ -86,21 +184,7 @@ impl ListBucketResult {
 
         // Add contents
         for object in &self.contents {
-            xml.push_str("<Contents>");
-            xml.push_str(&format!("<Key>{}</Key>", object.key));
-            xml.push_str(&format!(
-                "<LastModified>{}</LastModified>",
-                object
-                    .last_modified
-                    .to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
-            ));
-            xml.push_str(&format!("<ETag>{}</ETag>", object.etag));
-            xml.push_str(&format!("<Size>{}</Size>", object.size));
-            xml.push_str(&format!(
-                "<StorageClass>{}</StorageClass>",
-                object.storage_class
-            ));
-            xml.push_str("</Contents>");
+            xml.push_str(&object.to_xml());
         }
 
         // Add common prefixes
@@ -140,21 +224,7 @@ impl ListBucketResult {
 
         // Add contents
         for object in &self.contents {
-            xml.push_str("<Contents>");
-            xml.push_str(&format!("<Key>{}</Key>", object.key));
-            xml.push_str(&format!(
-                "<LastModified>{}</LastModified>",
-                object
-                    .last_modified
-                    .to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
-            ));
-            xml.push_str(&format!("<ETag>{}</ETag>", object.etag));
-            xml.push_str(&format!("<Size>{}</Size>", object.size));
-            xml.push_str(&format!(
-                "<StorageClass>{}</StorageClass>",
-                object.storage_class
-            ));
-            xml.push_str("</Contents>");
+            xml.push_str(&object.to_xml());
         }
 
         // Add common prefixes
@@ -168,6 +238,69 @@ impl ListBucketResult {
         xml
     }
 
+    /// Opening of a streamed listing document, written before any `<Contents>`.
+    /// Fields only known once all rows are read are left for `to_xml_stream_tail`.
+    pub fn to_xml_stream_head(&self, v2: bool) -> String {
+        let mut xml = String::from(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<ListBucketResult xmlns=\"http://s3.amazonaws.com/doc/2006-03-01/\">",
+        );
+
+        xml.push_str(&format!("<Name>{}</Name>", self.name));
+        xml.push_str(&format!("<Prefix>{}</Prefix>", self.prefix));
+
+        if let Some(delimiter) = self.delimiter {
+            xml.push_str(&format!("<Delimiter>{}</Delimiter>", delimiter));
+        }
+
+        xml.push_str(&format!("<MaxKeys>{}</MaxKeys>", self.max_keys));
+
+        if v2 {
+            if let Some(ref encoding_type) = self.encoding_type {
+                xml.push_str(&format!("<EncodingType>{}</EncodingType>", encoding_type));
+            }
+            if let Some(ref token) = self.continuation_token {
+                xml.push_str(&format!("<ContinuationToken>{}</ContinuationToken>", token));
+            }
+            if let Some(ref start_after) = self.start_after {
+                xml.push_str(&format!("<StartAfter>{}</StartAfter>", start_after));
+            }
+        } else if let Some(ref marker) = self.continuation_token {
+            xml.push_str(&format!("<Marker>{}</Marker>", marker));
+        }
+
+        xml
+    }
+
+    /// Closing of a streamed listing document, written after the last `<Contents>`
+    pub fn to_xml_stream_tail(&self, v2: bool, key_count: usize) -> String {
+        let mut xml = String::new();
+
+        if v2 {
+            xml.push_str(&format!("<KeyCount>{}</KeyCount>", key_count));
+        }
+        xml.push_str(&format!("<IsTruncated>{}</IsTruncated>", self.is_truncated));
+
+        if let Some(ref token) = self.next_continuation_token {
+            if v2 {
+                xml.push_str(&format!(
+                    "<NextContinuationToken>{}</NextContinuationToken>",
+                    token
+                ));
+            } else {
+                xml.push_str(&format!("<NextMarker>{}</NextMarker>", token));
+            }
+        }
+
+        for prefix in &self.common_prefixes {
+            xml.push_str("<CommonPrefixes>");
+            xml.push_str(&format!("<Prefix>{}</Prefix>", prefix.prefix));
+            xml.push_str("</CommonPrefixes>");
+        }
+
+        xml.push_str("</ListBucketResult>");
+        xml
+    }
+
     // Set encoding type (url or none)
     pub fn set_encoding_type(&mut self, encoding_type: Option<String>) {
         self.encoding_type = encoding_type;
@@ -195,25 +328,30 @@ impl ListBucketResult {
         size: usize,
         last_modified: DateTime<Utc>,
         md5_hash: Option<String>,
+        storage_class: String,
     ) {
-        self.contents.push(S3Object {
+        self.contents.push(S3Object::new(
             key,
             size,
             last_modified,
-            etag: md5_hash
-                .map(|h| format!("\"{}\"", h))
-                .unwrap_or_else(|| "\"00000000000000000000000000000000\"".to_string()),
-            storage_class: "STANDARD".to_string(),
-        });
+            md5_hash,
+            storage_class,
+        ));
+    }
+
+    // Add common prefixes computed by the database
+    pub fn add_common_prefixes(&mut self, prefixes: Vec<String>) {
+        self.common_prefixes
+            .extend(prefixes.into_iter().map(|prefix| CommonPrefix { prefix }));
     }
 
     // Process keys with MD5 hashes
-    pub fn process_keys(&mut self, keys: Vec<(String, usize, DateTime<Utc>, Option<String>)>) {
+    pub fn process_keys(&mut self, keys: Vec<ObjectRow>) {
         if self.delimiter.is_none() {
             // No delimiter, add all keys to contents
-            for (key, size, last_modified, md5_hash) in keys {
+            for (key, size, last_modified, md5_hash, storage_class) in keys {
                 if key.starts_with(&self.prefix) {
-                    self.add_content(key, size, last_modified, md5_hash);
+                    self.add_content(key, size, last_modified, md5_hash, storage_class);
                 }
             }
             return;
@@ -223,7 +361,7 @@ impl ListBucketResult {
         let delimiter = self.delimiter.unwrap();
         let mut prefixes = HashSet::new();
 
-        for (key, size, last_modified, md5_hash) in keys {
+        for (key, size, last_modified, md5_hash, storage_class) in keys {
             // Check if key contains delimiter after prefix
             if let Some(suffix) = key.strip_prefix(&self.prefix) {
                 if let Some(pos) = suffix.find(delimiter) {
@@ -232,7 +370,7 @@ impl ListBucketResult {
                     prefixes.insert(common_prefix);
                 } else {
                     // No delimiter found, add to contents
-                    self.add_content(key, size, last_modified, md5_hash);
+                    self.add_content(key, size, last_modified, md5_hash, storage_class);
                 }
             } else {
                 continue; // Key does not start with prefix
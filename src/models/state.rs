@@ -1,20 +1,130 @@
-use r2d2::Pool;
+use chrono::{DateTime, Utc};
+use r2d2::{Pool, PooledConnection};
 use r2d2_sqlite::SqliteConnectionManager;
-use std::collections::HashSet;
-use std::sync::Arc;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+use tokio::sync::mpsc;
+
+use crate::handlers::write_batch::QueuedWrite;
+use crate::models::{AppConfig, Credentials, Principal};
+use crate::utils::access_log::{AccessRecord, LoggingTarget};
+use crate::utils::audit::AuditLog;
+use crate::utils::ip_filter::IpFilter;
+use crate::utils::object_cache::ObjectCache;
+use crate::utils::rate_limit::RateLimiter;
+use crate::utils::run_blocking;
 
 /// Application state shared across all request handlers
 #[derive(Clone)]
 pub struct AppState {
-    pub db_pool: Arc<Pool<SqliteConnectionManager>>,
-    pub buckets: Arc<HashSet<String>>, // The expected buckets
+    pub read_pool: Arc<Pool<SqliteConnectionManager>>, // Read-only connections for GET, HEAD and listings
+    pub write_pool: Arc<Pool<SqliteConnectionManager>>, // The single writer connection
+    pub buckets: Arc<RwLock<HashSet<String>>>,         // Configured and created buckets
+    pub config: Arc<AppConfig>,
+    pub credentials: Arc<RwLock<Arc<Credentials>>>, // Access keys verifying signed requests, empty if not checked
+    pub ip_filter: IpFilter,                        // Client addresses refused, empty if none
+    pub rate_limiter: Arc<RateLimiter>, // Token buckets of each client, if limits are set
+    pub started_at: DateTime<Utc>,      // Creation date reported for buckets without a recorded one
+    pub logging_targets: Arc<RwLock<HashMap<String, LoggingTarget>>>, // Buckets with access logging
+    pub access_log: Option<mpsc::Sender<AccessRecord>>, // Queue of the log delivery task, if running
+    pub audit_log: Option<Arc<AuditLog>>, // Record of writes and failed authentications, if enabled
+    pub write_batch: Option<mpsc::Sender<QueuedWrite>>, // Queue of the write batching task, if enabled
+    pub object_cache: Option<Arc<ObjectCache>>,         // Recently read small objects, if enabled
 }
 
 impl AppState {
-    pub fn new(db_pool: Pool<SqliteConnectionManager>, buckets: HashSet<String>) -> Self {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        read_pool: Pool<SqliteConnectionManager>,
+        write_pool: Pool<SqliteConnectionManager>,
+        buckets: HashSet<String>,
+        config: Arc<AppConfig>,
+        credentials: Credentials,
+        ip_filter: IpFilter,
+        rate_limiter: RateLimiter,
+        logging_targets: HashMap<String, LoggingTarget>,
+        access_log: Option<mpsc::Sender<AccessRecord>>,
+        audit_log: Option<AuditLog>,
+        write_batch: Option<mpsc::Sender<QueuedWrite>>,
+    ) -> Self {
+        let object_cache = config.get_object_cache_max_bytes().map(|max_bytes| {
+            Arc::new(ObjectCache::new(
+                max_bytes,
+                config.get_object_cache_max_object_size(),
+            ))
+        });
         Self {
-            db_pool: Arc::new(db_pool),
-            buckets: Arc::new(buckets),
+            read_pool: Arc::new(read_pool),
+            write_pool: Arc::new(write_pool),
+            buckets: Arc::new(RwLock::new(buckets)),
+            config,
+            credentials: Arc::new(RwLock::new(Arc::new(credentials))),
+            ip_filter,
+            rate_limiter: Arc::new(rate_limiter),
+            started_at: Utc::now(),
+            logging_targets: Arc::new(RwLock::new(logging_targets)),
+            access_log,
+            audit_log: audit_log.map(Arc::new),
+            write_batch,
+            object_cache,
+        }
+    }
+
+    /// Drop a cached object, once a write changing it is committed
+    pub fn invalidate_cached_object(&self, bucket: &str, key: &str) {
+        if let Some(cache) = &self.object_cache {
+            cache.invalidate(bucket, key);
+        }
+    }
+
+    /// Drop the cached objects of a bucket
+    pub fn invalidate_cached_bucket(&self, bucket: &str) {
+        if let Some(cache) = &self.object_cache {
+            cache.invalidate_bucket(bucket);
+        }
+    }
+
+    /// The current access keys. A reload replaces them as a whole, so a request keeps
+    /// seeing one consistent set through this snapshot.
+    pub fn credentials(&self) -> Arc<Credentials> {
+        self.credentials.read().unwrap().clone()
+    }
+
+    /// Swap in reloaded access keys
+    pub fn replace_credentials(&self, credentials: Credentials) {
+        *self.credentials.write().unwrap() = Arc::new(credentials);
+    }
+
+    /// A read-only database connection, waiting for one off the async executor
+    pub fn read_conn(&self) -> Result<PooledConnection<SqliteConnectionManager>, r2d2::Error> {
+        run_blocking(|| self.read_pool.get())
+    }
+
+    /// The writer connection, for requests that write and for maintenance. Writers queue
+    /// for it off the async executor, one at a time.
+    pub fn write_conn(&self) -> Result<PooledConnection<SqliteConnectionManager>, r2d2::Error> {
+        run_blocking(|| self.write_pool.get())
+    }
+
+    /// Snapshot of the bucket names, sorted
+    pub fn bucket_names(&self) -> Vec<String> {
+        let mut buckets: Vec<String> = self.buckets.read().unwrap().iter().cloned().collect();
+        buckets.sort();
+        buckets
+    }
+
+    /// Snapshot of the bucket names a principal may access, sorted.
+    /// Unauthenticated requests, allowed when no credentials are configured, see every bucket.
+    pub fn visible_bucket_names(&self, principal: Option<&Principal>) -> Vec<String> {
+        let mut buckets = self.bucket_names();
+        if let Some(principal) = principal {
+            let credentials = self.credentials();
+            buckets.retain(|bucket| {
+                credentials
+                    .permission(&principal.access_key_id, bucket)
+                    .is_some()
+            });
         }
+        buckets
     }
 }
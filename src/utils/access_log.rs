@@ -0,0 +1,129 @@
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+
+use crate::utils::xml::element_text;
+use crate::utils::{html_escape, next_request_id};
+
+/// Query parameters named as the resource of an operation in access log records
+const LOGGED_SUBRESOURCES: &[&str] = &[
+    "acl",
+    "cors",
+    "lifecycle",
+    "location",
+    "logging",
+    "policy",
+    "policyStatus",
+    "tagging",
+    "uploadId",
+    "uploads",
+    "versioning",
+];
+
+/// Where the access logs of a bucket are delivered
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LoggingTarget {
+    pub bucket: String,
+    pub prefix: String,
+}
+
+/// One request to a bucket with logging enabled, awaiting delivery
+#[derive(Debug, Clone)]
+pub struct AccessRecord {
+    pub target: LoggingTarget,
+    pub bucket: String,
+    pub key: Option<String>,
+    pub time: DateTime<Utc>,
+    pub remote_addr: Option<String>,
+    pub request_id: String,
+    pub operation: String,
+    pub request_line: String,
+    pub status: u16,
+    pub bytes_sent: Option<u64>,
+    pub total_time_ms: u128,
+    pub referer: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl AccessRecord {
+    /// Render the record as a line of the S3 server access log format.
+    /// Fields this server has no equivalent for (requester, version id,
+    /// signature and TLS details) are `-`.
+    pub fn to_log_line(&self, owner_id: &str) -> String {
+        let quoted = |value: &Option<String>| match value {
+            Some(v) => format!("\"{}\"", v.replace('"', "\\\"")),
+            None => "\"-\"".to_string(),
+        };
+        let or_dash = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+        format!(
+            "{owner_id} {bucket} [{time}] {remote} - {request_id} {operation} {key} \"{request_line}\" {status} - {bytes} - {total} {total} {referer} {user_agent} - - - - - - - - -",
+            bucket = self.bucket,
+            time = self.time.format("%d/%b/%Y:%H:%M:%S %z"),
+            remote = or_dash(self.remote_addr.clone()),
+            request_id = self.request_id,
+            operation = self.operation,
+            key = or_dash(self.key.clone()),
+            request_line = self.request_line.replace('"', "\\\""),
+            status = self.status,
+            bytes = or_dash(self.bytes_sent.map(|b| b.to_string())),
+            total = self.total_time_ms,
+            referer = quoted(&self.referer),
+            user_agent = quoted(&self.user_agent),
+        )
+    }
+}
+
+/// Name of a request in access logs, e.g. `REST.GET.OBJECT` or `REST.PUT.ACL`
+pub fn operation_name(method: &str, has_key: bool, query: &HashMap<String, String>) -> String {
+    let resource = match LOGGED_SUBRESOURCES.iter().find(|s| query.contains_key(**s)) {
+        Some(&"uploadId") if method == "PUT" => "PART".to_string(),
+        Some(&"uploadId") => "UPLOAD".to_string(),
+        Some(subresource) => subresource.to_ascii_uppercase(),
+        None if has_key => "OBJECT".to_string(),
+        None => "BUCKET".to_string(),
+    };
+    format!("REST.{method}.{resource}")
+}
+
+/// Key of a delivered log object: the target prefix, the delivery time and a unique string
+pub fn log_object_key(prefix: &str, time: DateTime<Utc>) -> String {
+    format!(
+        "{prefix}{}-{}",
+        time.format("%Y-%m-%d-%H-%M-%S"),
+        next_request_id()
+    )
+}
+
+/// Parse a `BucketLoggingStatus` document.
+/// Returns None if the document is malformed, `Some(None)` if it disables logging.
+pub fn parse_logging_status(xml: &str) -> Option<Option<LoggingTarget>> {
+    if !xml.contains("<BucketLoggingStatus") {
+        return None;
+    }
+    if !xml.contains("<LoggingEnabled>") {
+        return Some(None);
+    }
+    let bucket = element_text(xml, "TargetBucket").filter(|b| !b.is_empty())?;
+    Some(Some(LoggingTarget {
+        bucket: bucket.to_string(),
+        prefix: element_text(xml, "TargetPrefix")
+            .unwrap_or_default()
+            .to_string(),
+    }))
+}
+
+/// Render a `BucketLoggingStatus` document, empty if logging is disabled
+pub fn logging_status_xml(target: Option<&LoggingTarget>) -> String {
+    let enabled = target
+        .map(|t| {
+            format!(
+                "<LoggingEnabled><TargetBucket>{}</TargetBucket><TargetPrefix>{}</TargetPrefix></LoggingEnabled>",
+                html_escape(&t.bucket),
+                html_escape(&t.prefix)
+            )
+        })
+        .unwrap_or_default();
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<BucketLoggingStatus xmlns="http://s3.amazonaws.com/doc/2006-03-01/">{enabled}</BucketLoggingStatus>"#
+    )
+}
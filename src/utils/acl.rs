@@ -0,0 +1,181 @@
+use rusqlite::{Connection, OptionalExtension, params};
+
+use crate::utils::xml::element_text;
+
+/// Table holding the canned ACL of each bucket. Object ACLs live in the `acl`
+/// column of the bucket table, NULL meaning `private`.
+const BUCKET_ACL_TABLE: &str = "_bucket_acl";
+
+const ALL_USERS: &str = "http://acs.amazonaws.com/groups/global/AllUsers";
+const AUTHENTICATED_USERS: &str = "http://acs.amazonaws.com/groups/global/AuthenticatedUsers";
+const LOG_DELIVERY: &str = "http://acs.amazonaws.com/groups/s3/LogDelivery";
+
+/// Canned ACLs with the group grants they add to the owner's FULL_CONTROL.
+/// Grants to the bucket owner or EC2 are implied by ownership here.
+const CANNED_ACLS: &[(&str, &[(&str, &str)])] = &[
+    ("private", &[]),
+    ("public-read", &[(ALL_USERS, "READ")]),
+    (
+        "public-read-write",
+        &[(ALL_USERS, "READ"), (ALL_USERS, "WRITE")],
+    ),
+    ("authenticated-read", &[(AUTHENTICATED_USERS, "READ")]),
+    ("aws-exec-read", &[]),
+    ("bucket-owner-read", &[]),
+    ("bucket-owner-full-control", &[]),
+    (
+        "log-delivery-write",
+        &[(LOG_DELIVERY, "WRITE"), (LOG_DELIVERY, "READ_ACP")],
+    ),
+];
+
+/// Reasons an `AccessControlPolicy` document is refused
+#[derive(Debug, PartialEq)]
+pub enum AclError {
+    /// Not a well-formed `AccessControlPolicy`
+    Malformed,
+    /// Well-formed, but not equivalent to any canned ACL
+    Unsupported,
+}
+
+/// The canned ACL called `name`, None if there is no such canned ACL
+pub fn canned_acl(name: &str) -> Option<&'static str> {
+    CANNED_ACLS
+        .iter()
+        .map(|(canned, _)| *canned)
+        .find(|canned| *canned == name)
+}
+
+/// Render a canned ACL as the `AccessControlPolicy` document of GetBucketAcl/GetObjectAcl
+pub fn access_control_policy_xml(acl: &str, owner_id: &str, owner_display_name: &str) -> String {
+    let group_grants = CANNED_ACLS
+        .iter()
+        .find(|(canned, _)| *canned == acl)
+        .map(|(_, grants)| *grants)
+        .unwrap_or_default();
+
+    let mut grants = format!(
+        r#"<Grant><Grantee xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" xsi:type="CanonicalUser"><ID>{owner_id}</ID><DisplayName>{owner_display_name}</DisplayName></Grantee><Permission>FULL_CONTROL</Permission></Grant>"#
+    );
+    for (uri, permission) in group_grants {
+        grants.push_str(&format!(
+            r#"<Grant><Grantee xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" xsi:type="Group"><URI>{uri}</URI></Grantee><Permission>{permission}</Permission></Grant>"#
+        ));
+    }
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<AccessControlPolicy xmlns="http://s3.amazonaws.com/doc/2006-03-01/"><Owner><ID>{owner_id}</ID><DisplayName>{owner_display_name}</DisplayName></Owner><AccessControlList>{grants}</AccessControlList></AccessControlPolicy>"#
+    )
+}
+
+/// Find the canned ACL equivalent to the grants of an `AccessControlPolicy` document.
+/// FULL_CONTROL for the owner is implied; grants to any other canonical user are unsupported.
+pub fn canned_acl_from_policy(xml: &str, owner_id: &str) -> Result<&'static str, AclError> {
+    if !xml.contains("<AccessControlPolicy") || !xml.contains("</AccessControlPolicy>") {
+        return Err(AclError::Malformed);
+    }
+
+    let mut group_grants = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find("<Grant>") {
+        let end = rest[start..].find("</Grant>").ok_or(AclError::Malformed)? + start;
+        let grant = &rest[start..end];
+        rest = &rest[end + "</Grant>".len()..];
+
+        let permission = element_text(grant, "Permission").ok_or(AclError::Malformed)?;
+        match (element_text(grant, "URI"), element_text(grant, "ID")) {
+            (Some(uri), _) => group_grants.push((uri, permission)),
+            (None, Some(id)) if id == owner_id && permission == "FULL_CONTROL" => {}
+            (None, Some(_)) => return Err(AclError::Unsupported),
+            (None, None) => return Err(AclError::Malformed),
+        }
+    }
+    group_grants.sort_unstable();
+    group_grants.dedup();
+
+    CANNED_ACLS
+        .iter()
+        .find(|(_, grants)| {
+            let mut grants = grants.to_vec();
+            grants.sort_unstable();
+            grants == group_grants
+        })
+        .map(|(canned, _)| *canned)
+        .ok_or(AclError::Unsupported)
+}
+
+/// Ensures the bucket ACL table exists
+pub fn ensure_acl_table(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute(
+        &format!(
+            "CREATE TABLE IF NOT EXISTS {BUCKET_ACL_TABLE} (
+                bucket TEXT NOT NULL PRIMARY KEY,
+                acl TEXT NOT NULL
+            )"
+        ),
+        [],
+    )?;
+    Ok(())
+}
+
+/// Read the canned ACL of a bucket, `private` unless one was stored
+pub fn read_bucket_acl(conn: &Connection, bucket: &str) -> rusqlite::Result<String> {
+    let acl = conn
+        .query_row(
+            &format!("SELECT acl FROM {BUCKET_ACL_TABLE} WHERE bucket = ?1"),
+            params![bucket],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(acl.unwrap_or_else(|| "private".to_string()))
+}
+
+/// Store or replace the canned ACL of a bucket
+pub fn store_bucket_acl(conn: &Connection, bucket: &str, acl: &str) -> rusqlite::Result<()> {
+    conn.execute(
+        &format!(
+            "INSERT INTO {BUCKET_ACL_TABLE} (bucket, acl) VALUES (?1, ?2)
+             ON CONFLICT(bucket) DO UPDATE SET acl=excluded.acl"
+        ),
+        params![bucket, acl],
+    )?;
+    Ok(())
+}
+
+/// Remove the stored ACL of a bucket
+pub fn delete_bucket_acl(conn: &Connection, bucket: &str) -> rusqlite::Result<()> {
+    conn.execute(
+        &format!("DELETE FROM {BUCKET_ACL_TABLE} WHERE bucket = ?1"),
+        params![bucket],
+    )?;
+    Ok(())
+}
+
+/// Read the canned ACL of an object, None if the object does not exist
+pub fn read_object_acl(
+    conn: &Connection,
+    table_name: &str,
+    key: &str,
+) -> rusqlite::Result<Option<String>> {
+    conn.query_row(
+        &format!("SELECT COALESCE(acl, 'private') FROM {table_name} WHERE key = ?1"),
+        params![key],
+        |row| row.get(0),
+    )
+    .optional()
+}
+
+/// Store the canned ACL of an object. Returns false if the object does not exist.
+pub fn store_object_acl(
+    conn: &Connection,
+    table_name: &str,
+    key: &str,
+    acl: &str,
+) -> rusqlite::Result<bool> {
+    let updated = conn.execute(
+        &format!("UPDATE {table_name} SET acl = ?2 WHERE key = ?1"),
+        params![key, acl],
+    )?;
+    Ok(updated > 0)
+}
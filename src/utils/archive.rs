@@ -0,0 +1,125 @@
+use std::io::{Cursor, Read};
+
+/// One file of an uploaded archive: its path and either its contents or the
+/// reason it cannot be imported
+pub struct ArchiveEntry {
+    pub path: String,
+    pub data: Result<Vec<u8>, String>,
+}
+
+/// Why reading an archive stopped before its last entry
+pub enum ArchiveError<E> {
+    /// The archive itself cannot be read
+    Malformed(String),
+    /// The files of the archive decompress to more than the import budget
+    TooLarge(String),
+    /// The visitor of an entry failed
+    Visit(E),
+}
+
+/// Read the files of a tar or zip archive, told apart by the zip signature, and hand
+/// each to `visit` as it is decompressed, so only one entry is held in memory.
+/// Directories are skipped; links and other special entries, and files larger
+/// than `max_size` once decompressed, are passed on with an error.
+/// Stops with an error if the archive cannot be read, or once its files decompress
+/// to more than `max_total` bytes altogether.
+pub fn read_archive<E>(
+    archive: &[u8],
+    max_size: usize,
+    max_total: u64,
+    visit: impl FnMut(ArchiveEntry) -> Result<(), E>,
+) -> Result<(), ArchiveError<E>> {
+    if archive.starts_with(b"PK\x03\x04") || archive.starts_with(b"PK\x05\x06") {
+        read_zip(archive, max_size, max_total, visit)
+    } else {
+        read_tar(archive, max_size, max_total, visit)
+    }
+}
+
+/// Read at most `max_size` bytes, failing if there are more, and take them from the
+/// decompressed bytes `left` to the archive. Returns None once that budget is exceeded.
+fn read_limited(
+    reader: impl Read,
+    max_size: usize,
+    left: &mut u64,
+) -> Option<Result<Vec<u8>, String>> {
+    let mut data = Vec::new();
+    let read = reader
+        .take((max_size as u64).min(*left) + 1)
+        .read_to_end(&mut data);
+    *left = left.checked_sub(data.len() as u64)?;
+    if let Err(e) = read {
+        return Some(Err(format!("Failed to read entry: {e}")));
+    }
+    if data.len() > max_size {
+        return Some(Err(format!(
+            "Entry exceeds the maximum allowed object size of {max_size} bytes"
+        )));
+    }
+    Some(Ok(data))
+}
+
+fn too_large<E>(max_total: u64) -> ArchiveError<E> {
+    ArchiveError::TooLarge(format!(
+        "The archive decompresses to more than the maximum allowed import size of {max_total} bytes"
+    ))
+}
+
+fn read_tar<E>(
+    archive: &[u8],
+    max_size: usize,
+    max_total: u64,
+    mut visit: impl FnMut(ArchiveEntry) -> Result<(), E>,
+) -> Result<(), ArchiveError<E>> {
+    let mut tar = tar::Archive::new(Cursor::new(archive));
+    let mut left = max_total;
+    for entry in tar
+        .entries()
+        .map_err(|e| ArchiveError::Malformed(format!("Not a tar or zip archive: {e}")))?
+    {
+        let entry =
+            entry.map_err(|e| ArchiveError::Malformed(format!("Malformed tar archive: {e}")))?;
+        let path = entry
+            .path()
+            .map_err(|e| ArchiveError::Malformed(format!("Malformed tar entry path: {e}")))?
+            .to_string_lossy()
+            .into_owned();
+        let entry_type = entry.header().entry_type();
+        let data = if entry_type.is_dir() {
+            continue;
+        } else if entry_type.is_file() || entry_type.is_contiguous() {
+            read_limited(entry, max_size, &mut left).ok_or_else(|| too_large(max_total))?
+        } else {
+            Err(format!("Unsupported tar entry type {entry_type:?}"))
+        };
+        visit(ArchiveEntry { path, data }).map_err(ArchiveError::Visit)?;
+    }
+    Ok(())
+}
+
+fn read_zip<E>(
+    archive: &[u8],
+    max_size: usize,
+    max_total: u64,
+    mut visit: impl FnMut(ArchiveEntry) -> Result<(), E>,
+) -> Result<(), ArchiveError<E>> {
+    let mut zip = zip::ZipArchive::new(Cursor::new(archive))
+        .map_err(|e| ArchiveError::Malformed(format!("Malformed zip archive: {e}")))?;
+    let mut left = max_total;
+    for index in 0..zip.len() {
+        let file = zip
+            .by_index(index)
+            .map_err(|e| ArchiveError::Malformed(format!("Malformed zip archive: {e}")))?;
+        if file.is_dir() {
+            continue;
+        }
+        let path = file.name().to_string();
+        let data = if file.is_symlink() {
+            Err("Unsupported zip entry type Symlink".to_string())
+        } else {
+            read_limited(file, max_size, &mut left).ok_or_else(|| too_large(max_total))?
+        };
+        visit(ArchiveEntry { path, data }).map_err(ArchiveError::Visit)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,148 @@
+use chrono::{DateTime, Utc};
+use rusqlite::{Connection, params};
+use serde::Serialize;
+use std::sync::Mutex;
+
+/// Table of the audit log, in the main database or the file of `audit_log_path`
+const AUDIT_LOG_TABLE: &str = "_audit_log";
+
+/// Most records returned by one query of the audit log
+pub const MAX_AUDIT_QUERY_RECORDS: usize = 10_000;
+
+/// A mutating request or a failed authentication
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditRecord {
+    #[serde(serialize_with = "serialize_time")]
+    pub time: DateTime<Utc>,
+    /// Access key the request was signed with, "anonymous" if unsigned
+    pub access_key: String,
+    pub remote_addr: Option<String>,
+    pub operation: String,
+    pub bucket: Option<String>,
+    pub key: Option<String>,
+    pub status: u16,
+    /// Size of the request body, None if unknown
+    pub bytes: Option<u64>,
+}
+
+fn serialize_time<S: serde::Serializer>(time: &DateTime<Utc>, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_str(&time.to_rfc3339_opts(chrono::SecondsFormat::Millis, true))
+}
+
+/// Filters of an audit log query, all optional
+#[derive(Debug, Default)]
+pub struct AuditQuery {
+    pub bucket: Option<String>,
+    pub key: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub limit: usize,
+}
+
+/// Append-only audit log on a connection of its own, so that recording never
+/// waits for the connection pool
+pub struct AuditLog {
+    conn: Mutex<Connection>,
+}
+
+impl AuditLog {
+    /// Open the database at `path`, creating the audit table if needed
+    pub fn open(path: &str) -> rusqlite::Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             PRAGMA synchronous = FULL;
+             PRAGMA busy_timeout = 5000;",
+        )?;
+        ensure_audit_table(&conn)?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    pub fn record(&self, record: &AuditRecord) -> rusqlite::Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            &format!(
+                "INSERT INTO {AUDIT_LOG_TABLE}
+                 (time, access_key, remote_addr, operation, bucket, key, status, bytes)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)"
+            ),
+            params![
+                record.time.timestamp_millis(),
+                record.access_key,
+                record.remote_addr,
+                record.operation,
+                record.bucket,
+                record.key,
+                record.status,
+                record.bytes.map(|b| b as i64),
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Records matching a query, oldest first
+    pub fn query(&self, query: &AuditQuery) -> rusqlite::Result<Vec<AuditRecord>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare_cached(&format!(
+            "SELECT time, access_key, remote_addr, operation, bucket, key, status, bytes
+             FROM {AUDIT_LOG_TABLE}
+             WHERE (?1 IS NULL OR bucket = ?1)
+               AND (?2 IS NULL OR key = ?2)
+               AND (?3 IS NULL OR time >= ?3)
+               AND (?4 IS NULL OR time < ?4)
+             ORDER BY id
+             LIMIT ?5"
+        ))?;
+        let rows = stmt.query_map(
+            params![
+                query.bucket,
+                query.key,
+                query.since.map(|t| t.timestamp_millis()),
+                query.until.map(|t| t.timestamp_millis()),
+                query.limit as i64,
+            ],
+            |row| {
+                Ok(AuditRecord {
+                    time: DateTime::from_timestamp_millis(row.get(0)?).unwrap_or_default(),
+                    access_key: row.get(1)?,
+                    remote_addr: row.get(2)?,
+                    operation: row.get(3)?,
+                    bucket: row.get(4)?,
+                    key: row.get(5)?,
+                    status: row.get(6)?,
+                    bytes: row.get::<_, Option<i64>>(7)?.map(|b| b as u64),
+                })
+            },
+        )?;
+        rows.collect()
+    }
+}
+
+/// Ensures the audit table exists. Triggers refuse updates and deletes, so rows
+/// can only be removed by dropping the table.
+pub fn ensure_audit_table(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(&format!(
+        "CREATE TABLE IF NOT EXISTS {AUDIT_LOG_TABLE} (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            time INTEGER NOT NULL,
+            access_key TEXT NOT NULL,
+            remote_addr TEXT,
+            operation TEXT NOT NULL,
+            bucket TEXT,
+            key TEXT,
+            status INTEGER NOT NULL,
+            bytes INTEGER
+        );
+        CREATE INDEX IF NOT EXISTS idx_{AUDIT_LOG_TABLE}_bucket_key
+            ON {AUDIT_LOG_TABLE} (bucket, key, time);
+        CREATE INDEX IF NOT EXISTS idx_{AUDIT_LOG_TABLE}_time ON {AUDIT_LOG_TABLE} (time);
+        CREATE TRIGGER IF NOT EXISTS {AUDIT_LOG_TABLE}_no_update
+            BEFORE UPDATE ON {AUDIT_LOG_TABLE}
+            BEGIN SELECT RAISE(ABORT, 'the audit log is append-only'); END;
+        CREATE TRIGGER IF NOT EXISTS {AUDIT_LOG_TABLE}_no_delete
+            BEFORE DELETE ON {AUDIT_LOG_TABLE}
+            BEGIN SELECT RAISE(ABORT, 'the audit log is append-only'); END;"
+    ))
+}
@@ -0,0 +1,230 @@
+use chrono::Utc;
+use log::{error, info, warn};
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::Connection;
+use rusqlite::backup::{Backup, StepResult};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// Pages copied per step of a snapshot; the source is only read-locked during a step
+const PAGES_PER_STEP: i32 = 256;
+/// Pause between steps, leaving the disk and CPU to request handlers
+const STEP_PAUSE: Duration = Duration::from_millis(5);
+/// Restarts caused by writes to the database before the rest is copied in one step.
+/// In WAL mode that step reads a snapshot of the database and blocks no writer.
+const MAX_RESTARTS: u32 = 3;
+/// Interval between progress messages of a running snapshot
+const PROGRESS_LOG_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Set while a snapshot is taken, so periodic and requested snapshots never overlap
+static SNAPSHOT_RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// A completed snapshot
+pub struct SnapshotReport {
+    pub path: PathBuf,
+    pub pages: i32,
+    pub size_bytes: u64,
+    /// Times the copy started over because the database was written meanwhile
+    pub restarts: u32,
+    pub duration: Duration,
+}
+
+/// Reasons a snapshot was not taken
+#[derive(Debug)]
+pub enum SnapshotError {
+    /// Another snapshot is being taken
+    Running,
+    Pool(r2d2::Error),
+    Database(rusqlite::Error),
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SnapshotError::Running => write!(f, "a snapshot is already being taken"),
+            SnapshotError::Pool(e) => write!(f, "no database connection: {e}"),
+            SnapshotError::Database(e) => write!(f, "{e}"),
+            SnapshotError::Io(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for SnapshotError {
+    fn from(e: rusqlite::Error) -> Self {
+        SnapshotError::Database(e)
+    }
+}
+
+impl From<std::io::Error> for SnapshotError {
+    fn from(e: std::io::Error) -> Self {
+        SnapshotError::Io(e)
+    }
+}
+
+/// Clears `SNAPSHOT_RUNNING` when the snapshot ends, even by a panic
+struct RunningGuard;
+
+impl Drop for RunningGuard {
+    fn drop(&mut self) {
+        SNAPSHOT_RUNNING.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Copy the live database page by page with the SQLite online backup API into a new
+/// `snapshot-<time>.sqlite` file of `dir`, then delete the oldest snapshots beyond
+/// `retention` (0 keeps them all). Blocking: run it on a blocking thread.
+/// The copy is written to a `.partial` file first, so a snapshot file is always complete.
+pub fn take_snapshot(
+    pool: &Pool<SqliteConnectionManager>,
+    dir: &Path,
+    retention: usize,
+) -> Result<SnapshotReport, SnapshotError> {
+    if SNAPSHOT_RUNNING.swap(true, Ordering::SeqCst) {
+        return Err(SnapshotError::Running);
+    }
+    let _guard = RunningGuard;
+
+    std::fs::create_dir_all(dir)?;
+    let name = format!(
+        "snapshot-{}.sqlite",
+        Utc::now().format("%Y%m%dT%H%M%S%.3fZ")
+    );
+    let path = dir.join(&name);
+    let partial = dir.join(format!("{name}.partial"));
+
+    let start = Instant::now();
+    let source = pool.get().map_err(SnapshotError::Pool)?;
+    info!("Snapshot of the database to {} started", path.display());
+    let copied = Connection::open(&partial)
+        .map_err(SnapshotError::from)
+        .and_then(|mut target| copy_pages(&source, &mut target));
+    drop(source);
+    let (pages, restarts) = match copied {
+        Ok(copied) => copied,
+        Err(e) => {
+            let _ = std::fs::remove_file(&partial);
+            return Err(e);
+        }
+    };
+    std::fs::rename(&partial, &path)?;
+
+    let size_bytes = std::fs::metadata(&path).map_or(0, |metadata| metadata.len());
+    let duration = start.elapsed();
+    info!(
+        "Snapshot of the database to {} completed in {duration:?}: {pages} pages, {size_bytes} bytes, {restarts} restarts",
+        path.display()
+    );
+    if retention > 0 {
+        prune_snapshots(dir, retention);
+    }
+
+    Ok(SnapshotReport {
+        path,
+        pages,
+        size_bytes,
+        restarts,
+        duration,
+    })
+}
+
+/// Copy every page of `source` into `target`, pausing between steps.
+/// Returns the number of pages and of restarts.
+fn copy_pages(source: &Connection, target: &mut Connection) -> Result<(i32, u32), SnapshotError> {
+    let backup = Backup::new(source, target)?;
+    let mut restarts = 0;
+    let mut last_remaining = i32::MAX;
+    let mut last_log = Instant::now();
+
+    loop {
+        let pages = if restarts < MAX_RESTARTS {
+            PAGES_PER_STEP
+        } else {
+            -1
+        };
+        if backup.step(pages)? == StepResult::Done {
+            return Ok((backup.progress().pagecount, restarts));
+        }
+
+        // A write to the database makes the next step start over from the first page
+        let progress = backup.progress();
+        if progress.remaining > last_remaining {
+            restarts += 1;
+            warn!(
+                "Snapshot restarted after a write to the database ({restarts} of {MAX_RESTARTS} before copying the rest at once)"
+            );
+        }
+        last_remaining = progress.remaining;
+        if last_log.elapsed() >= PROGRESS_LOG_INTERVAL {
+            info!(
+                "Snapshot progress: {} of {} pages",
+                progress.pagecount - progress.remaining,
+                progress.pagecount
+            );
+            last_log = Instant::now();
+        }
+        std::thread::sleep(STEP_PAUSE);
+    }
+}
+
+/// Delete the oldest snapshots of `dir` beyond the newest `retention`
+fn prune_snapshots(dir: &Path, retention: usize) {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            error!("Failed to list snapshots in {}: {e}", dir.display());
+            return;
+        }
+    };
+    // Names embed the time of the snapshot, so they sort oldest first
+    let mut snapshots: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with("snapshot-") && name.ends_with(".sqlite"))
+        })
+        .collect();
+    snapshots.sort();
+    let excess = snapshots.len().saturating_sub(retention);
+    for path in &snapshots[..excess] {
+        match std::fs::remove_file(path) {
+            Ok(()) => info!("Deleted old snapshot {}", path.display()),
+            Err(e) => error!("Failed to delete old snapshot {}: {e}", path.display()),
+        }
+    }
+}
+
+/// Take periodic snapshots into `dir` in a background task
+pub fn schedule_snapshots(
+    pool: Pool<SqliteConnectionManager>,
+    dir: PathBuf,
+    interval: Duration,
+    retention: usize,
+) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(interval);
+        // The first tick completes at once: the first snapshot is taken one interval
+        // after startup
+        interval.tick().await;
+
+        loop {
+            interval.tick().await;
+            let pool = pool.clone();
+            let dir = dir.clone();
+            let result =
+                tokio::task::spawn_blocking(move || take_snapshot(&pool, &dir, retention)).await;
+            match result {
+                Ok(Ok(_)) => {}
+                Ok(Err(SnapshotError::Running)) => {
+                    info!("Scheduled snapshot skipped, another snapshot is being taken")
+                }
+                Ok(Err(e)) => error!("Scheduled snapshot failed: {e}"),
+                Err(e) => error!("Scheduled snapshot task failed: {e}"),
+            }
+        }
+    });
+}
@@ -0,0 +1,162 @@
+use rusqlite::{Connection, OptionalExtension, TransactionBehavior, params};
+use sha2::{Digest, Sha256};
+
+/// Table holding the bodies of deduplicated objects once per distinct content,
+/// with the number of object rows referencing each of them
+pub const BLOBS_TABLE: &str = "_blobs";
+
+/// Create the shared blob table if it does not exist
+pub fn ensure_blobs_table(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute(
+        &format!(
+            "CREATE TABLE IF NOT EXISTS {BLOBS_TABLE} (
+                hash TEXT NOT NULL PRIMARY KEY,
+                data BLOB NOT NULL,
+                refcount INTEGER NOT NULL DEFAULT 0
+            )"
+        ),
+        [],
+    )?;
+    Ok(())
+}
+
+/// Number and total size of the shared blobs
+pub fn blob_stats(conn: &Connection) -> rusqlite::Result<(u64, u64)> {
+    conn.query_row(
+        &format!("SELECT COUNT(*), COALESCE(SUM(length(data)), 0) FROM {BLOBS_TABLE}"),
+        [],
+        |row| Ok((row.get::<_, i64>(0)? as u64, row.get::<_, i64>(1)? as u64)),
+    )
+}
+
+/// Maintain the reference counts of the blobs of a bucket table's objects with triggers,
+/// deleting a blob with its last reference. Uploads, copies, overwrites, deletes, expiry
+/// and lifecycle rules then keep the counts in the statement changing the rows.
+/// The count of the new blob is raised before that of the old one is lowered, so an
+/// overwrite with the same body keeps it.
+pub fn ensure_blob_refcounts(conn: &Connection, table_name: &str) -> rusqlite::Result<()> {
+    conn.execute_batch(&format!(
+        "CREATE TRIGGER IF NOT EXISTS {table_name}_blob_insert AFTER INSERT ON {table_name}
+         WHEN NEW.blob_hash IS NOT NULL
+         BEGIN UPDATE {BLOBS_TABLE} SET refcount = refcount + 1 WHERE hash = NEW.blob_hash; END;
+         CREATE TRIGGER IF NOT EXISTS {table_name}_blob_delete AFTER DELETE ON {table_name}
+         WHEN OLD.blob_hash IS NOT NULL
+         BEGIN UPDATE {BLOBS_TABLE} SET refcount = refcount - 1 WHERE hash = OLD.blob_hash;
+             DELETE FROM {BLOBS_TABLE} WHERE hash = OLD.blob_hash AND refcount <= 0; END;
+         CREATE TRIGGER IF NOT EXISTS {table_name}_blob_update
+         AFTER UPDATE OF blob_hash ON {table_name}
+         BEGIN UPDATE {BLOBS_TABLE} SET refcount = refcount + 1 WHERE hash = NEW.blob_hash;
+             UPDATE {BLOBS_TABLE} SET refcount = refcount - 1 WHERE hash = OLD.blob_hash;
+             DELETE FROM {BLOBS_TABLE} WHERE hash = OLD.blob_hash AND refcount <= 0; END;"
+    ))
+}
+
+/// Key of a blob: the hex SHA-256 of its stored bytes
+pub fn blob_hash(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+/// Store a body under its hash unless a blob with the same content exists. A new blob
+/// is unreferenced until a row referencing it is written, see `delete_unreferenced_blob`.
+pub fn store_blob(conn: &Connection, hash: &str, data: &[u8]) -> rusqlite::Result<()> {
+    conn.execute(
+        &format!(
+            "INSERT INTO {BLOBS_TABLE} (hash, data) VALUES (?1, ?2) ON CONFLICT(hash) DO NOTHING"
+        ),
+        params![hash, data],
+    )?;
+    Ok(())
+}
+
+/// Delete a blob stored for a write that did not happen, e.g. to an existing object
+/// of a write-once bucket
+pub fn delete_unreferenced_blob(conn: &Connection, hash: &str) -> rusqlite::Result<()> {
+    conn.execute(
+        &format!("DELETE FROM {BLOBS_TABLE} WHERE hash = ?1 AND refcount <= 0"),
+        params![hash],
+    )?;
+    Ok(())
+}
+
+/// Where the body of an object row is stored, for `blob_open`: the table, rowid and
+/// length of its own `data`, or of the shared blob it references
+pub fn locate_data<'a>(
+    conn: &Connection,
+    table_name: &'a str,
+    rowid: i64,
+    length: u64,
+    blob_hash: Option<&str>,
+) -> rusqlite::Result<(&'a str, i64, u64)> {
+    let Some(hash) = blob_hash else {
+        return Ok((table_name, rowid, length));
+    };
+    let (rowid, length) = conn.query_row(
+        &format!("SELECT rowid, length(data) FROM {BLOBS_TABLE} WHERE hash = ?1"),
+        params![hash],
+        |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)? as u64)),
+    )?;
+    Ok((BLOBS_TABLE, rowid, length))
+}
+
+/// Move the bodies of a bucket table's objects into shared blobs, `batch_size` objects
+/// per IMMEDIATE transaction so writers are not held up for long. Objects keep their
+/// last modification time. Returns the number of objects moved.
+pub fn deduplicate_table(
+    conn: &mut Connection,
+    table_name: &str,
+    batch_size: usize,
+) -> rusqlite::Result<u64> {
+    let mut moved = 0;
+    loop {
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let rowids: Vec<i64> = {
+            let mut stmt = tx.prepare(&format!(
+                "SELECT rowid FROM {table_name} WHERE blob_hash IS NULL LIMIT ?1"
+            ))?;
+            stmt.query_map(params![batch_size as i64], |row| row.get(0))?
+                .collect::<rusqlite::Result<_>>()?
+        };
+        if rowids.is_empty() {
+            return Ok(moved);
+        }
+
+        for rowid in &rowids {
+            // One body at a time, however large the batch
+            let row = tx
+                .query_row(
+                    &format!("SELECT data, last_modified, md5 FROM {table_name} WHERE rowid = ?1"),
+                    params![rowid],
+                    |row| {
+                        Ok((
+                            row.get::<_, Vec<u8>>(0)?,
+                            row.get::<_, i64>(1)?,
+                            row.get::<_, Option<String>>(2)?,
+                        ))
+                    },
+                )
+                .optional()?;
+            let Some((data, last_modified, md5_hash)) = row else {
+                continue;
+            };
+            // The ETag of objects from before md5 was stored can no longer come from the data
+            let md5_hash = md5_hash.unwrap_or_else(|| hex::encode(md5::compute(&data).0));
+            let hash = blob_hash(&data);
+            store_blob(&tx, &hash, &data)?;
+            tx.execute(
+                &format!(
+                    "UPDATE {table_name} SET blob_hash = ?1, md5 = ?2,
+                     original_size = COALESCE(original_size, length(data)), data = X''
+                     WHERE rowid = ?3"
+                ),
+                params![hash, md5_hash, rowid],
+            )?;
+            // The new (empty) data bumped last_modified, the object did not change
+            tx.execute(
+                &format!("UPDATE {table_name} SET last_modified = ?1 WHERE rowid = ?2"),
+                params![last_modified, rowid],
+            )?;
+        }
+        tx.commit()?;
+        moved += rowids.len() as u64;
+    }
+}
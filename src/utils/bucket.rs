@@ -1,13 +1,39 @@
 use axum::{
-    http::{HeaderMap, StatusCode},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
     response::{IntoResponse, Response},
 };
-use rusqlite::Connection;
+use log::{error, warn};
+use rusqlite::{Connection, TransactionBehavior, params};
+use std::collections::HashSet;
 use std::fmt::Write;
+use std::sync::RwLock;
 
-/// Sanitize bucket name to be a valid SQLite table name.
+use crate::models::BucketStats;
+use crate::utils::acl::delete_bucket_acl;
+use crate::utils::blobs::{ensure_blob_refcounts, ensure_blobs_table};
+use crate::utils::bucket_meta::{
+    bucket_creation_dates, bucket_usage, delete_bucket_meta, ensure_bucket_meta_table,
+    ensure_usage_counters, record_bucket_creation,
+};
+use crate::utils::db::is_busy_error;
+use crate::utils::error_format::json_errors;
+use crate::utils::html_escape;
+use crate::utils::lifecycle::delete_lifecycle_configuration;
+use crate::utils::multipart::ensure_multipart_tables;
+use crate::utils::request_id::current_request_id;
+
+/// Whether a `table_prefix` is a safe start of a table name: an ASCII letter followed
+/// by letters, digits and underscores
+pub fn is_valid_table_prefix(prefix: &str) -> bool {
+    prefix.starts_with(|c: char| c.is_ascii_alphabetic())
+        && prefix
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Sanitize bucket name to be a valid SQLite table name, prefixed with `table_prefix`.
 /// Returns Some(table_name) if valid, None if invalid.
-pub fn sanitize_bucket_name(bucket: &str) -> Option<String> {
+pub fn sanitize_bucket_name(bucket: &str, table_prefix: &str) -> Option<String> {
     // Only allow alphanumeric, underscore, and dash
     if bucket.is_empty()
         || !bucket
@@ -18,125 +44,357 @@ pub fn sanitize_bucket_name(bucket: &str) -> Option<String> {
     }
     // replace dash with underscore
     let table_name = bucket.replace('-', "_");
-    Some(format!("bucket_{table_name}"))
+    Some(format!("{table_prefix}{table_name}"))
+}
+
+/// Whether a name is acceptable for a bucket created at runtime: 3 to 63 lowercase
+/// letters, digits and hyphens, starting and ending with a letter or digit.
+/// Underscores are refused so that the table name maps back to a single bucket.
+pub fn is_valid_new_bucket_name(bucket: &str) -> bool {
+    (3..=63).contains(&bucket.len())
+        && bucket
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        && !bucket.starts_with('-')
+        && !bucket.ends_with('-')
 }
 
 /// Extract and validate bucket name against allowed buckets.
 /// Returns Ok(bucket) if valid and allowed, otherwise returns an S3 formatted error response.
 pub fn validate_bucket(
     bucket: &str,
-    allowed_buckets: &std::collections::HashSet<String>,
+    allowed_buckets: &RwLock<HashSet<String>>,
 ) -> Result<String, Box<Response>> {
-    if allowed_buckets.contains(bucket) {
+    if allowed_buckets.read().unwrap().contains(bucket) {
         Ok(bucket.to_string())
     } else {
-        let body = format!(
-            r#"<?xml version="1.0" encoding="UTF-8"?>
-            <Error>
-            <Code>AccessDenied</Code>
-            <Message>Bucket access denied: {bucket}</Message>
-            </Error>"#
-        );
-
-        let mut headers = HeaderMap::new();
-        headers.insert("Content-Type", "application/xml".parse().unwrap());
-        headers.insert("Content-Length", body.len().to_string().parse().unwrap());
+        Err(Box::new(xml_error_response(
+            StatusCode::FORBIDDEN,
+            "AccessDenied",
+            &format!("Bucket access denied: {bucket}"),
+        )))
+    }
+}
 
-        Err(Box::new(
-            (StatusCode::FORBIDDEN, headers, body).into_response(),
-        ))
+/// Smallest string greater than every string starting with `prefix`,
+/// or None if there is no such bound (empty prefix or all chars are char::MAX).
+/// UTF-8 byte order matches code point order, so this agrees with SQLite's BINARY collation.
+pub fn prefix_successor(prefix: &str) -> Option<String> {
+    let mut chars: Vec<char> = prefix.chars().collect();
+    while let Some(last) = chars.pop() {
+        let next = (last as u32 + 1..=char::MAX as u32).find_map(char::from_u32);
+        if let Some(next) = next {
+            chars.push(next);
+            return Some(chars.into_iter().collect());
+        }
     }
+    None
 }
 
-/// Query objects in a bucket with a prefix, returns Vec<(key, size, last_modified, md5)>
-type QueryBucketResult = Vec<(String, usize, chrono::DateTime<chrono::Utc>, Option<String>)>;
+/// SQL expression of an object's size: its size before compression or deduplication,
+/// or that of its data if it is stored as sent
+pub const OBJECT_SIZE: &str = "COALESCE(original_size, length(data))";
 
+/// SQL expression of the bytes an object counts towards the usage of its bucket: its
+/// stored data, or its size if its body is a shared blob, which belongs to no bucket
+pub const USAGE_SIZE: &str = "CASE WHEN blob_hash IS NULL THEN length(data) ELSE original_size END";
+
+/// SQL expression reading an object's data only when its md5 is missing
+pub const MISSING_MD5_DATA: &str = "CASE WHEN md5 IS NULL THEN data END";
+
+/// The md5 of an object: the stored value, or one computed from the data selected with
+/// `MISSING_MD5_DATA` for rows written without it (direct SQL, external tooling, or
+/// tables predating the md5 column). The computed value is not written back, since
+/// any update of the row would also bump its last_modified.
+pub fn stored_or_computed_md5(md5_hash: Option<String>, data: Option<Vec<u8>>) -> String {
+    md5_hash.unwrap_or_else(|| hex::encode(md5::compute(data.unwrap_or_default()).0))
+}
+
+/// A listed object: (key, size, last_modified, md5, storage_class)
+pub type ObjectRow = (
+    String,
+    usize,
+    chrono::DateTime<chrono::Utc>,
+    Option<String>,
+    String,
+);
+
+/// Query objects in a bucket with a prefix, returns Vec<(key, size, last_modified, md5, storage_class)>
+pub type QueryBucketResult = Vec<ObjectRow>;
+
+/// Number of rows fetched per step of the delimiter skip-scan
+const SKIP_SCAN_BATCH: usize = 1000;
+
+fn map_object_row(row: &rusqlite::Row) -> rusqlite::Result<ObjectRow> {
+    let key: String = row.get(0)?;
+    let size: isize = row.get(1)?;
+    let last_modified_secs: i64 = row.get(2)?;
+    let md5_hash = Some(stored_or_computed_md5(row.get(3)?, row.get(5)?));
+    let storage_class: String = row.get(4)?;
+
+    let last_modified = chrono::DateTime::<chrono::Utc>::from_timestamp(last_modified_secs, 0)
+        .unwrap_or(chrono::Utc::now());
+
+    Ok((
+        key,
+        size.try_into().expect("unexpected negative object size"),
+        last_modified,
+        md5_hash,
+        storage_class,
+    ))
+}
+
+/// Only keys strictly greater than `start_after` are returned when it is set,
+/// and at most `limit` rows are returned, in key order.
 pub fn query_bucket_objects(
     conn: &rusqlite::Connection,
     bucket: &str,
+    table_prefix: &str,
     prefix: &str,
-) -> Result<QueryBucketResult, Box<Response>> {
-    let table_name = match sanitize_bucket_name(bucket) {
-        Some(t) => t,
-        None => {
-            return Err(Box::new(xml_error_response(
-                StatusCode::BAD_REQUEST,
-                "InvalidBucketName",
-                &format!("Invalid bucket name: {}", bucket),
-            )));
+    start_after: Option<&str>,
+    limit: usize,
+) -> rusqlite::Result<QueryBucketResult> {
+    let table_name = sanitize_bucket_name(bucket, table_prefix).ok_or_else(|| {
+        rusqlite::Error::InvalidParameterName(format!("Invalid bucket name: {bucket}"))
+    })?;
+
+    // Use a range predicate instead of LIKE so that '%' and '_' in the prefix
+    // are matched literally, and the primary key index can be used.
+    let upper_bound = prefix_successor(prefix);
+    let mut stmt = conn.prepare(&format!(
+        "SELECT key, {OBJECT_SIZE}, last_modified, md5, storage_class, {MISSING_MD5_DATA}
+         FROM {table_name}
+         WHERE key >= ?1 AND (?2 IS NULL OR key < ?2) AND (?3 IS NULL OR key > ?3)
+         ORDER BY key LIMIT ?4",
+    ))?;
+
+    let limit = i64::try_from(limit).unwrap_or(i64::MAX);
+    let sql_params = rusqlite::params![prefix, upper_bound, start_after, limit];
+    stmt.query_map(sql_params, map_object_row)?.collect()
+}
+
+/// Visit the objects in a bucket with a prefix in key order, reading rows from the
+/// cursor one at a time. The visitor returns false to stop early.
+/// Returns Ok(true) if every row was visited.
+pub fn for_each_bucket_object(
+    conn: &rusqlite::Connection,
+    bucket: &str,
+    table_prefix: &str,
+    prefix: &str,
+    start_after: Option<&str>,
+    mut visit: impl FnMut(ObjectRow) -> bool,
+) -> rusqlite::Result<bool> {
+    let table_name = sanitize_bucket_name(bucket, table_prefix).ok_or_else(|| {
+        rusqlite::Error::InvalidParameterName(format!("Invalid bucket name: {bucket}"))
+    })?;
+
+    let upper_bound = prefix_successor(prefix);
+    let mut stmt = conn.prepare(&format!(
+        "SELECT key, {OBJECT_SIZE}, last_modified, md5, storage_class, {MISSING_MD5_DATA}
+         FROM {table_name}
+         WHERE key >= ?1 AND (?2 IS NULL OR key < ?2) AND (?3 IS NULL OR key > ?3)
+         ORDER BY key",
+    ))?;
+    let mut rows = stmt.query(rusqlite::params![prefix, upper_bound, start_after])?;
+
+    while let Some(row) = rows.next()? {
+        if !visit(map_object_row(row)?) {
+            return Ok(false);
         }
-    };
+    }
+    Ok(true)
+}
+
+/// Query objects in a bucket with a prefix and delimiter.
+/// Returns the objects directly under the prefix and the sorted common prefixes.
+///
+/// Instead of reading every key below the prefix, this walks the key index in order
+/// and, whenever a key contains the delimiter, records its common prefix and seeks
+/// past all keys sharing it. The cost is proportional to the number of returned
+/// entries rather than the number of keys in the bucket.
+///
+/// At most `limit` entries (objects and common prefixes together) are returned.
+/// A `start_after` that is itself a common prefix skips every key sharing it, so the
+/// last entry of a page can be used to continue the listing.
+pub fn query_bucket_objects_delimited(
+    conn: &rusqlite::Connection,
+    bucket: &str,
+    table_prefix: &str,
+    prefix: &str,
+    delimiter: char,
+    start_after: Option<&str>,
+    limit: usize,
+) -> rusqlite::Result<(QueryBucketResult, Vec<String>)> {
+    let table_name = sanitize_bucket_name(bucket, table_prefix).ok_or_else(|| {
+        rusqlite::Error::InvalidParameterName(format!("Invalid bucket name: {bucket}"))
+    })?;
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT key, {OBJECT_SIZE}, last_modified, md5, storage_class, {MISSING_MD5_DATA}
+         FROM {table_name}
+         WHERE key >= ?1 AND (?2 IS NULL OR key < ?2) AND (?3 IS NULL OR key > ?3)
+         ORDER BY key LIMIT ?4",
+    ))?;
 
-    let mut stmt = match conn.prepare(&format!(
-        "SELECT key, length(data), last_modified, md5 FROM {table_name} WHERE key LIKE ?1",
-    )) {
-        Ok(stmt) => stmt,
-        Err(e) => {
-            return Err(Box::new(xml_error_response(
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "InternalError",
-                &format!("SQL preparation error: {}", e),
-            )));
+    let upper_bound = prefix_successor(prefix);
+    let mut lower_inclusive = prefix.to_string();
+    let mut lower_exclusive = start_after.map(|s| s.to_string());
+    let mut contents = Vec::new();
+    let mut common_prefixes = Vec::new();
+
+    if let Some(start_after) = start_after
+        && start_after.len() > prefix.len()
+        && start_after.starts_with(prefix)
+        && start_after[prefix.len()..].find(delimiter)
+            == Some(start_after.len() - prefix.len() - delimiter.len_utf8())
+    {
+        match prefix_successor(start_after) {
+            Some(next) => lower_inclusive = next,
+            None => return Ok((contents, common_prefixes)),
         }
-    };
+    }
 
-    let sql_params = rusqlite::params![format!("{prefix}%")];
-
-    let mut rows_vec = Vec::new();
-    let rows = stmt.query_map(sql_params, |row| {
-        let key: String = row.get(0)?;
-        let size: isize = row.get(1)?;
-        let last_modified_secs: i64 = row.get(2)?;
-        let md5_hash: Option<String> = row.get(3).ok();
-
-        let last_modified = chrono::DateTime::<chrono::Utc>::from_timestamp(last_modified_secs, 0)
-            .unwrap_or(chrono::Utc::now());
-
-        Ok((
-            key,
-            size.try_into().expect("unexpected negative length(data)"),
-            last_modified,
-            md5_hash,
-        ))
-    });
-
-    match rows {
-        Ok(rows) => {
-            for row in rows {
-                match row {
-                    Ok(data) => rows_vec.push(data),
-                    Err(_) => continue,
+    'scan: loop {
+        if contents.len() + common_prefixes.len() >= limit {
+            break;
+        }
+
+        let rows = stmt.query_map(
+            rusqlite::params![
+                lower_inclusive,
+                upper_bound,
+                lower_exclusive,
+                SKIP_SCAN_BATCH as i64
+            ],
+            map_object_row,
+        )?;
+
+        let mut fetched = 0;
+        for row in rows {
+            let row = row?;
+            fetched += 1;
+            if contents.len() + common_prefixes.len() >= limit {
+                break 'scan;
+            }
+
+            if let Some(pos) = row.0[prefix.len()..].find(delimiter) {
+                // Record the common prefix and seek past every key sharing it
+                let common_prefix = row.0[..prefix.len() + pos + delimiter.len_utf8()].to_string();
+                match prefix_successor(&common_prefix) {
+                    Some(next) => lower_inclusive = next,
+                    None => {
+                        common_prefixes.push(common_prefix);
+                        break 'scan;
+                    }
                 }
+                common_prefixes.push(common_prefix);
+                continue 'scan;
             }
-            Ok(rows_vec)
+
+            lower_exclusive = Some(row.0.clone());
+            contents.push(row);
+        }
+
+        if fetched < SKIP_SCAN_BATCH {
+            break;
         }
-        Err(e) => Err(Box::new(xml_error_response(
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "InternalError",
-            &format!("SQL query error: {}", e),
-        ))),
     }
+
+    Ok((contents, common_prefixes))
 }
 
-/// Ensures the bucket table exists in the database
-pub fn ensure_bucket_table(conn: &Connection, bucket: &str) -> rusqlite::Result<()> {
-    if let Some(table_name) = sanitize_bucket_name(bucket) {
+/// Names of all bucket tables present in the database, those starting with
+/// `table_prefix`, sorted
+pub fn list_bucket_tables(conn: &Connection, table_prefix: &str) -> rusqlite::Result<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT name FROM sqlite_master
+         WHERE type = 'table' AND substr(name, 1, ?1) = ?2 ORDER BY name",
+    )?;
+    stmt.query_map(params![table_prefix.len() as i64, table_prefix], |row| {
+        row.get(0)
+    })?
+    .collect()
+}
+
+/// Buckets with a recorded creation date whose table exists: the buckets created at
+/// runtime or with `s3insqlite admin create-bucket`, and those configured at some point
+pub fn recorded_buckets(conn: &Connection, table_prefix: &str) -> rusqlite::Result<Vec<String>> {
+    let tables: HashSet<String> = list_bucket_tables(conn, table_prefix)?
+        .into_iter()
+        .collect();
+    let mut buckets: Vec<String> = bucket_creation_dates(conn)?
+        .into_keys()
+        .filter(|bucket| {
+            sanitize_bucket_name(bucket, table_prefix).is_some_and(|table| tables.contains(&table))
+        })
+        .collect();
+    buckets.sort();
+    Ok(buckets)
+}
+
+/// Objects and total stored bytes of a bucket, from the counters maintained by triggers
+pub fn query_bucket_stats(conn: &Connection, bucket: &str) -> rusqlite::Result<BucketStats> {
+    let (object_count, size_bytes) = bucket_usage(conn, bucket)?;
+    Ok(BucketStats {
+        bucket: bucket.to_string(),
+        object_count,
+        size_bytes,
+    })
+}
+
+/// Ensures the bucket table exists in the database, its creation time is recorded
+/// and its usage counters are maintained
+pub fn ensure_bucket_table(
+    conn: &Connection,
+    bucket: &str,
+    table_prefix: &str,
+) -> rusqlite::Result<()> {
+    if let Some(table_name) = sanitize_bucket_name(bucket, table_prefix) {
         let sql = format!(
             "CREATE TABLE IF NOT EXISTS {table_name} (
                 key TEXT NOT NULL PRIMARY KEY,
                 data BLOB NOT NULL,
                 last_modified INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
-                md5 TEXT(32) NOT NULL
+                md5 TEXT(32) NOT NULL,
+                storage_class TEXT NOT NULL DEFAULT 'STANDARD',
+                checksum_algorithm TEXT,
+                checksum TEXT,
+                acl TEXT,
+                content_encoding TEXT,
+                content_sha256 TEXT,
+                expires_at INTEGER,
+                compression TEXT,
+                original_size INTEGER,
+                blob_hash TEXT
             )",
         );
+        // Tables created by older versions are brought up to date by the migrations
+        // run at startup (utils::db::run_migrations)
         conn.execute(&sql, [])?;
 
+        // Only objects with an expiry are indexed, for the reaper
+        conn.execute(
+            &format!(
+                "CREATE INDEX IF NOT EXISTS idx_{table_name}_expires_at
+                 ON {table_name} (expires_at) WHERE expires_at IS NOT NULL"
+            ),
+            [],
+        )?;
+
+        // Only a new object body bumps last_modified, not e.g. an ACL change
         let sql = format!(
-            "CREATE TRIGGER IF NOT EXISTS update_{table_name}_timestamp
-             AFTER UPDATE ON {table_name}
+            "CREATE TRIGGER IF NOT EXISTS update_{table_name}_data_timestamp
+             AFTER UPDATE OF data ON {table_name}
              BEGIN UPDATE {table_name} SET last_modified = strftime('%s', 'now') WHERE key = NEW.key; END;",
         );
-        conn.execute(&sql, [])?;
+        conn.execute_batch(&sql)?;
+
+        ensure_multipart_tables(conn)?;
+        ensure_bucket_meta_table(conn)?;
+        record_bucket_creation(conn, bucket)?;
+        ensure_usage_counters(conn, bucket, &table_name)?;
+        ensure_blobs_table(conn)?;
+        ensure_blob_refcounts(conn, &table_name)?;
         Ok(())
     } else {
         Err(rusqlite::Error::InvalidParameterName(format!(
@@ -145,6 +403,63 @@ pub fn ensure_bucket_table(conn: &Connection, bucket: &str) -> rusqlite::Result<
     }
 }
 
+/// Drop the table of an empty bucket, with its indexes, triggers, settings and lifecycle configuration.
+/// Emptiness is checked inside an IMMEDIATE transaction, so no write can slip in between.
+/// Returns false, leaving everything in place, if objects or pending multipart uploads remain.
+pub fn drop_bucket_table(
+    conn: &mut Connection,
+    bucket: &str,
+    table_prefix: &str,
+) -> rusqlite::Result<bool> {
+    let table_name = sanitize_bucket_name(bucket, table_prefix).ok_or_else(|| {
+        rusqlite::Error::InvalidParameterName(format!("Invalid bucket name: {bucket}"))
+    })?;
+
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    let has_objects: bool = tx.query_row(
+        &format!("SELECT EXISTS (SELECT 1 FROM {table_name})"),
+        [],
+        |row| row.get(0),
+    )?;
+    let has_uploads: bool = tx.query_row(
+        "SELECT EXISTS (SELECT 1 FROM multipart_uploads WHERE bucket = ?1)",
+        [bucket],
+        |row| row.get(0),
+    )?;
+    if has_objects || has_uploads {
+        return Ok(false);
+    }
+
+    // Indexes and triggers are dropped with the table
+    tx.execute(&format!("DROP TABLE {table_name}"), [])?;
+    delete_lifecycle_configuration(&tx, bucket)?;
+    delete_bucket_acl(&tx, bucket)?;
+    delete_bucket_meta(&tx, bucket)?;
+    tx.commit()?;
+    Ok(true)
+}
+
+/// Add a column to a table if it does not exist yet
+pub fn ensure_column(
+    conn: &Connection,
+    table_name: &str,
+    column: &str,
+    definition: &str,
+) -> rusqlite::Result<()> {
+    let exists: bool = conn.query_row(
+        &format!("SELECT COUNT(*) > 0 FROM pragma_table_info('{table_name}') WHERE name = ?1"),
+        [column],
+        |row| row.get(0),
+    )?;
+    if !exists {
+        conn.execute(
+            &format!("ALTER TABLE {table_name} ADD COLUMN {column} {definition}"),
+            [],
+        )?;
+    }
+    Ok(())
+}
+
 /// Generate S3 XML error response
 pub fn generate_xml_error(code: &str, message: &str) -> String {
     let mut xml = String::new();
@@ -154,19 +469,103 @@ pub fn generate_xml_error(code: &str, message: &str) -> String {
         <Error>
             <Code>{}</Code>
             <Message>{}</Message>
+            <RequestId>{}</RequestId>
         </Error>"#,
-        code, message
+        code,
+        html_escape(message),
+        current_request_id().unwrap_or_default()
     )
     .expect("Error formatting XML");
     xml
 }
 
-/// Generate HTTP response with XML error
-pub fn xml_error_response(status: StatusCode, code: &str, message: &str) -> Response {
-    let body = generate_xml_error(code, message);
+/// Generate HTTP response with an XML body
+pub fn xml_response(status: StatusCode, body: String) -> Response {
     let mut headers = HeaderMap::new();
     headers.insert("Content-Type", "application/xml".parse().unwrap());
     headers.insert("Content-Length", body.len().to_string().parse().unwrap());
 
     (status, headers, body).into_response()
 }
+
+/// Generate a JSON error body with the fields of the S3 XML error
+pub fn generate_json_error(code: &str, message: &str) -> String {
+    serde_json::json!({
+        "Code": code,
+        "Message": message,
+        "RequestId": current_request_id().unwrap_or_default(),
+    })
+    .to_string()
+}
+
+/// Generate HTTP response with an S3 error: XML, or JSON if the `Accept` header of
+/// the request prefers it (see `utils::error_format`)
+pub fn xml_error_response(status: StatusCode, code: &str, message: &str) -> Response {
+    let (body, content_type) = if json_errors() {
+        (generate_json_error(code, message), "application/json")
+    } else {
+        (generate_xml_error(code, message), "application/xml")
+    };
+    let mut headers = HeaderMap::new();
+    headers.insert("Content-Type", content_type.parse().unwrap());
+    headers.insert("Content-Length", body.len().to_string().parse().unwrap());
+
+    (status, headers, body).into_response()
+}
+
+/// S3 error response for a failed database operation: 503 SlowDown if the database
+/// was still busy after retrying, so that clients back off, otherwise 500 InternalError
+pub fn database_error_response(e: &rusqlite::Error) -> Response {
+    if is_busy_error(e) {
+        xml_error_response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "SlowDown",
+            "Please reduce your request rate.",
+        )
+    } else {
+        xml_error_response(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "InternalError",
+            &e.to_string(),
+        )
+    }
+}
+
+/// Message of an r2d2 error caused only by every pooled connection being in use,
+/// without a failure to open a new one
+const POOL_TIMEOUT_MESSAGE: &str = "timed out waiting for connection";
+
+/// S3 error response when no database connection could be taken from the pool:
+/// 503 SlowDown with `Retry-After` if every connection stayed busy until the pool
+/// timeout, so that clients back off, otherwise 500 InternalError
+pub fn pool_error_response(e: &r2d2::Error) -> Response {
+    let message = e.to_string();
+    if message == POOL_TIMEOUT_MESSAGE {
+        warn!("Database connection pool exhausted, asking the client to slow down");
+        let mut response = xml_error_response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "SlowDown",
+            "Please reduce your request rate.",
+        );
+        response
+            .headers_mut()
+            .insert(header::RETRY_AFTER, HeaderValue::from_static("1"));
+        response
+    } else {
+        error!("Database connection error: {message}");
+        xml_error_response(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "InternalError",
+            &format!("Database connection error: {message}"),
+        )
+    }
+}
+
+/// S3 501 NotImplemented response for operations this server does not support
+pub fn not_implemented_response() -> Response {
+    xml_error_response(
+        StatusCode::NOT_IMPLEMENTED,
+        "NotImplemented",
+        "A header or query you provided implies functionality that is not implemented",
+    )
+}
@@ -0,0 +1,337 @@
+use rusqlite::{Connection, OptionalExtension, params};
+use std::collections::HashMap;
+
+use crate::models::AppConfig;
+use crate::utils::access_log::LoggingTarget;
+use crate::utils::bucket::{USAGE_SIZE, ensure_column};
+use crate::utils::xml::element_text;
+
+/// Table holding per-bucket settings that have no table of their own.
+/// The leading underscore keeps it out of the `bucket_*` namespace.
+const BUCKET_META_TABLE: &str = "_bucket_meta";
+
+/// Ensures the bucket metadata table exists
+pub fn ensure_bucket_meta_table(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute(
+        &format!(
+            "CREATE TABLE IF NOT EXISTS {BUCKET_META_TABLE} (
+                bucket TEXT NOT NULL PRIMARY KEY,
+                versioning TEXT,
+                created_at INTEGER,
+                cors TEXT,
+                policy TEXT,
+                logging_target TEXT,
+                logging_prefix TEXT,
+                object_count INTEGER,
+                bytes_used INTEGER
+            )"
+        ),
+        [],
+    )?;
+    ensure_column(conn, BUCKET_META_TABLE, "created_at", "INTEGER")?;
+    ensure_column(conn, BUCKET_META_TABLE, "cors", "TEXT")?;
+    ensure_column(conn, BUCKET_META_TABLE, "policy", "TEXT")?;
+    ensure_column(conn, BUCKET_META_TABLE, "logging_target", "TEXT")?;
+    ensure_column(conn, BUCKET_META_TABLE, "logging_prefix", "TEXT")?;
+    ensure_column(conn, BUCKET_META_TABLE, "object_count", "INTEGER")?;
+    ensure_column(conn, BUCKET_META_TABLE, "bytes_used", "INTEGER")?;
+    Ok(())
+}
+
+/// Record now as the creation time of a bucket, unless one is already recorded.
+/// Buckets created by older versions get the time they were first seen.
+pub fn record_bucket_creation(conn: &Connection, bucket: &str) -> rusqlite::Result<()> {
+    conn.execute(
+        &format!(
+            "INSERT INTO {BUCKET_META_TABLE} (bucket, created_at) VALUES (?1, strftime('%s', 'now'))
+             ON CONFLICT(bucket) DO UPDATE SET created_at = COALESCE(created_at, excluded.created_at)"
+        ),
+        params![bucket],
+    )?;
+    Ok(())
+}
+
+/// Maintain the object count and total size of a bucket with triggers on its table,
+/// so that reading them never scans the table. The bucket must have a row, see
+/// `record_bucket_creation`. Counters of buckets from older versions are computed once:
+/// the triggers are created first and leave NULL counters NULL, so no write is missed.
+/// Objects whose body is a shared blob count with their size, see `USAGE_SIZE`.
+pub fn ensure_usage_counters(
+    conn: &Connection,
+    bucket: &str,
+    table_name: &str,
+) -> rusqlite::Result<()> {
+    let bucket_literal = bucket.replace('\'', "''");
+    conn.execute_batch(&format!(
+        "CREATE TRIGGER IF NOT EXISTS {table_name}_usage_insert AFTER INSERT ON {table_name}
+         BEGIN UPDATE {BUCKET_META_TABLE} SET object_count = object_count + 1,
+             bytes_used = bytes_used + length(NEW.data) WHERE bucket = '{bucket_literal}'; END;
+         CREATE TRIGGER IF NOT EXISTS {table_name}_usage_delete AFTER DELETE ON {table_name}
+         BEGIN UPDATE {BUCKET_META_TABLE} SET object_count = object_count - 1,
+             bytes_used = bytes_used - length(OLD.data) WHERE bucket = '{bucket_literal}'; END;
+         CREATE TRIGGER IF NOT EXISTS {table_name}_usage_update AFTER UPDATE OF data ON {table_name}
+         BEGIN UPDATE {BUCKET_META_TABLE} SET bytes_used = bytes_used + length(NEW.data) - length(OLD.data)
+             WHERE bucket = '{bucket_literal}'; END;
+         CREATE TRIGGER IF NOT EXISTS {table_name}_usage_blob_insert AFTER INSERT ON {table_name}
+         WHEN NEW.blob_hash IS NOT NULL
+         BEGIN UPDATE {BUCKET_META_TABLE} SET bytes_used = bytes_used + NEW.original_size
+             WHERE bucket = '{bucket_literal}'; END;
+         CREATE TRIGGER IF NOT EXISTS {table_name}_usage_blob_delete AFTER DELETE ON {table_name}
+         WHEN OLD.blob_hash IS NOT NULL
+         BEGIN UPDATE {BUCKET_META_TABLE} SET bytes_used = bytes_used - OLD.original_size
+             WHERE bucket = '{bucket_literal}'; END;
+         CREATE TRIGGER IF NOT EXISTS {table_name}_usage_blob_update
+         AFTER UPDATE OF blob_hash ON {table_name}
+         BEGIN UPDATE {BUCKET_META_TABLE} SET bytes_used = bytes_used
+             + CASE WHEN NEW.blob_hash IS NULL THEN 0 ELSE NEW.original_size END
+             - CASE WHEN OLD.blob_hash IS NULL THEN 0 ELSE OLD.original_size END
+             WHERE bucket = '{bucket_literal}'; END;"
+    ))?;
+    conn.execute(
+        &format!(
+            "UPDATE {BUCKET_META_TABLE} SET
+                 object_count = (SELECT COUNT(*) FROM {table_name}),
+                 bytes_used = (SELECT COALESCE(SUM({USAGE_SIZE}), 0) FROM {table_name})
+             WHERE bucket = ?1 AND (object_count IS NULL OR bytes_used IS NULL)"
+        ),
+        params![bucket],
+    )?;
+    Ok(())
+}
+
+/// Object count and total size in bytes of a bucket, from its maintained counters
+pub fn bucket_usage(conn: &Connection, bucket: &str) -> rusqlite::Result<(u64, u64)> {
+    conn.query_row(
+        &format!(
+            "SELECT COALESCE(object_count, 0), COALESCE(bytes_used, 0)
+             FROM {BUCKET_META_TABLE} WHERE bucket = ?1"
+        ),
+        params![bucket],
+        |row| Ok((row.get::<_, i64>(0)? as u64, row.get::<_, i64>(1)? as u64)),
+    )
+    .optional()
+    .map(Option::unwrap_or_default)
+}
+
+/// Most objects and bytes a bucket may hold, checked before every write of an object row
+pub struct ObjectQuota<'a> {
+    pub bucket: &'a str,
+    pub max_objects: Option<u64>,
+    pub max_bytes: Option<u64>,
+}
+
+impl<'a> ObjectQuota<'a> {
+    /// The configured quota of a bucket, None if it is unlimited
+    pub fn for_bucket(config: &AppConfig, bucket: &'a str) -> Option<Self> {
+        let quota = ObjectQuota {
+            bucket,
+            max_objects: config.get_max_objects_per_bucket(bucket),
+            max_bytes: config.get_max_bytes_per_bucket(bucket),
+        };
+        (quota.max_objects.is_some() || quota.max_bytes.is_some()).then_some(quota)
+    }
+
+    /// Whether writing an object counting `new_size` bytes fits the quota, replacing
+    /// an object counting `existing_size` bytes if the key exists, see `object_usage_size`.
+    /// Must run in the transaction of the write, after the write lock is taken.
+    /// Overwrites, even of expired objects not yet deleted, never add to the object count
+    /// and only add their growth to the size. Usage is maintained by triggers, so reading
+    /// it costs a single row lookup.
+    pub fn allows(
+        &self,
+        conn: &Connection,
+        new_size: u64,
+        existing_size: Option<u64>,
+    ) -> rusqlite::Result<bool> {
+        let (objects, bytes) = bucket_usage(conn, self.bucket)?;
+        if existing_size.is_none() && self.max_objects.is_some_and(|max| objects >= max) {
+            return Ok(false);
+        }
+        // A bucket over its size, e.g. after the quota was lowered, may still shrink
+        let old_size = existing_size.unwrap_or(0);
+        Ok(new_size <= old_size
+            || self
+                .max_bytes
+                .is_none_or(|max| bytes.saturating_sub(old_size) + new_size <= max))
+    }
+}
+
+/// Bytes an object counts towards the usage of its bucket, None if the key does not exist
+pub fn object_usage_size(
+    conn: &Connection,
+    table_name: &str,
+    key: &str,
+) -> rusqlite::Result<Option<u64>> {
+    conn.query_row(
+        &format!("SELECT {USAGE_SIZE} FROM {table_name} WHERE key = ?1"),
+        params![key],
+        |row| row.get::<_, i64>(0),
+    )
+    .optional()
+    .map(|size| size.map(|size| size as u64))
+}
+
+/// Creation time of every bucket that has one recorded, in seconds since the epoch
+pub fn bucket_creation_dates(conn: &Connection) -> rusqlite::Result<HashMap<String, i64>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT bucket, created_at FROM {BUCKET_META_TABLE} WHERE created_at IS NOT NULL"
+    ))?;
+    stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect()
+}
+
+/// Remove every stored setting of a bucket
+pub fn delete_bucket_meta(conn: &Connection, bucket: &str) -> rusqlite::Result<()> {
+    conn.execute(
+        &format!("DELETE FROM {BUCKET_META_TABLE} WHERE bucket = ?1"),
+        params![bucket],
+    )?;
+    Ok(())
+}
+
+/// Read the stored versioning status (`Enabled` or `Suspended`) of a bucket
+pub fn get_versioning_status(conn: &Connection, bucket: &str) -> rusqlite::Result<Option<String>> {
+    let status = conn
+        .query_row(
+            &format!("SELECT versioning FROM {BUCKET_META_TABLE} WHERE bucket = ?1"),
+            params![bucket],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(status.flatten())
+}
+
+/// Store the versioning status of a bucket
+pub fn put_versioning_status(
+    conn: &Connection,
+    bucket: &str,
+    status: &str,
+) -> rusqlite::Result<()> {
+    conn.execute(
+        &format!(
+            "INSERT INTO {BUCKET_META_TABLE} (bucket, versioning) VALUES (?1, ?2)
+             ON CONFLICT(bucket) DO UPDATE SET versioning=excluded.versioning"
+        ),
+        params![bucket, status],
+    )?;
+    Ok(())
+}
+
+/// Read the stored CORS configuration document of a bucket
+pub fn get_cors_configuration(conn: &Connection, bucket: &str) -> rusqlite::Result<Option<String>> {
+    let configuration = conn
+        .query_row(
+            &format!("SELECT cors FROM {BUCKET_META_TABLE} WHERE bucket = ?1"),
+            params![bucket],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(configuration.flatten())
+}
+
+/// Store or replace the CORS configuration document of a bucket
+pub fn put_cors_configuration(
+    conn: &Connection,
+    bucket: &str,
+    configuration: &str,
+) -> rusqlite::Result<()> {
+    conn.execute(
+        &format!(
+            "INSERT INTO {BUCKET_META_TABLE} (bucket, cors) VALUES (?1, ?2)
+             ON CONFLICT(bucket) DO UPDATE SET cors=excluded.cors"
+        ),
+        params![bucket, configuration],
+    )?;
+    Ok(())
+}
+
+/// Remove the CORS configuration of a bucket
+pub fn delete_cors_configuration(conn: &Connection, bucket: &str) -> rusqlite::Result<()> {
+    conn.execute(
+        &format!("UPDATE {BUCKET_META_TABLE} SET cors = NULL WHERE bucket = ?1"),
+        params![bucket],
+    )?;
+    Ok(())
+}
+
+/// Read the stored policy document of a bucket
+pub fn get_bucket_policy(conn: &Connection, bucket: &str) -> rusqlite::Result<Option<String>> {
+    let policy = conn
+        .query_row(
+            &format!("SELECT policy FROM {BUCKET_META_TABLE} WHERE bucket = ?1"),
+            params![bucket],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(policy.flatten())
+}
+
+/// Store or replace the policy document of a bucket
+pub fn put_bucket_policy(conn: &Connection, bucket: &str, policy: &str) -> rusqlite::Result<()> {
+    conn.execute(
+        &format!(
+            "INSERT INTO {BUCKET_META_TABLE} (bucket, policy) VALUES (?1, ?2)
+             ON CONFLICT(bucket) DO UPDATE SET policy=excluded.policy"
+        ),
+        params![bucket, policy],
+    )?;
+    Ok(())
+}
+
+/// Remove the policy of a bucket
+pub fn delete_bucket_policy(conn: &Connection, bucket: &str) -> rusqlite::Result<()> {
+    conn.execute(
+        &format!("UPDATE {BUCKET_META_TABLE} SET policy = NULL WHERE bucket = ?1"),
+        params![bucket],
+    )?;
+    Ok(())
+}
+
+/// Store the access logging target of a bucket, None disabling logging
+pub fn put_logging_target(
+    conn: &Connection,
+    bucket: &str,
+    target: Option<&LoggingTarget>,
+) -> rusqlite::Result<()> {
+    conn.execute(
+        &format!(
+            "INSERT INTO {BUCKET_META_TABLE} (bucket, logging_target, logging_prefix) VALUES (?1, ?2, ?3)
+             ON CONFLICT(bucket) DO UPDATE SET logging_target=excluded.logging_target,
+             logging_prefix=excluded.logging_prefix"
+        ),
+        params![
+            bucket,
+            target.map(|t| t.bucket.as_str()),
+            target.map(|t| t.prefix.as_str())
+        ],
+    )?;
+    Ok(())
+}
+
+/// Access logging target of every bucket with logging enabled
+pub fn logging_targets(conn: &Connection) -> rusqlite::Result<HashMap<String, LoggingTarget>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT bucket, logging_target, COALESCE(logging_prefix, '') FROM {BUCKET_META_TABLE}
+         WHERE logging_target IS NOT NULL"
+    ))?;
+    stmt.query_map([], |row| {
+        Ok((
+            row.get(0)?,
+            LoggingTarget {
+                bucket: row.get(1)?,
+                prefix: row.get(2)?,
+            },
+        ))
+    })?
+    .collect()
+}
+
+/// Extract the `<Status>` of a `VersioningConfiguration` document.
+/// Returns None if the document is not a `VersioningConfiguration`, and
+/// `Some(None)` if it has no `<Status>`.
+pub fn parse_versioning_status(xml: &str) -> Option<Option<&str>> {
+    if !xml.contains("<VersioningConfiguration") {
+        return None;
+    }
+    Some(element_text(xml, "Status"))
+}
@@ -0,0 +1,181 @@
+use axum::http::HeaderMap;
+use base64::{Engine, prelude::BASE64_STANDARD};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+/// Additional checksum algorithms of the `x-amz-checksum-*` headers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumAlgorithm {
+    Crc32,
+    Crc32c,
+    Sha1,
+    Sha256,
+}
+
+impl ChecksumAlgorithm {
+    pub const ALL: [ChecksumAlgorithm; 4] = [
+        ChecksumAlgorithm::Crc32,
+        ChecksumAlgorithm::Crc32c,
+        ChecksumAlgorithm::Sha1,
+        ChecksumAlgorithm::Sha256,
+    ];
+
+    /// Parse an `x-amz-checksum-algorithm` value, case-insensitively
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|algorithm| algorithm.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Name as stored in the bucket table and used by `x-amz-checksum-algorithm`
+    pub fn name(self) -> &'static str {
+        match self {
+            ChecksumAlgorithm::Crc32 => "CRC32",
+            ChecksumAlgorithm::Crc32c => "CRC32C",
+            ChecksumAlgorithm::Sha1 => "SHA1",
+            ChecksumAlgorithm::Sha256 => "SHA256",
+        }
+    }
+
+    /// Header carrying a checksum of this algorithm
+    pub fn header_name(self) -> &'static str {
+        match self {
+            ChecksumAlgorithm::Crc32 => "x-amz-checksum-crc32",
+            ChecksumAlgorithm::Crc32c => "x-amz-checksum-crc32c",
+            ChecksumAlgorithm::Sha1 => "x-amz-checksum-sha1",
+            ChecksumAlgorithm::Sha256 => "x-amz-checksum-sha256",
+        }
+    }
+
+    /// Base64 encoded checksum of `data`, CRCs in big-endian byte order as S3 does
+    pub fn compute(self, data: &[u8]) -> String {
+        let digest = match self {
+            ChecksumAlgorithm::Crc32 => crc32fast::hash(data).to_be_bytes().to_vec(),
+            ChecksumAlgorithm::Crc32c => crc32c::crc32c(data).to_be_bytes().to_vec(),
+            ChecksumAlgorithm::Sha1 => Sha1::digest(data).to_vec(),
+            ChecksumAlgorithm::Sha256 => Sha256::digest(data).to_vec(),
+        };
+        BASE64_STANDARD.encode(digest)
+    }
+}
+
+/// Checksum recorded with an object
+#[derive(Debug, Clone)]
+pub struct ObjectChecksum {
+    pub algorithm: ChecksumAlgorithm,
+    pub value: String,
+}
+
+impl ObjectChecksum {
+    /// Rebuild from the `checksum_algorithm` and `checksum` columns, None if either is unset
+    pub fn from_columns(algorithm: Option<String>, value: Option<String>) -> Option<Self> {
+        Some(ObjectChecksum {
+            algorithm: ChecksumAlgorithm::from_name(&algorithm?)?,
+            value: value?,
+        })
+    }
+
+    /// Add the matching `x-amz-checksum-*` header to a response
+    pub fn insert_header(&self, headers: &mut HeaderMap) {
+        if let Ok(value) = self.value.parse() {
+            headers.insert(self.algorithm.header_name(), value);
+        }
+    }
+}
+
+/// Reasons an upload's checksum headers are rejected
+#[derive(Debug)]
+pub enum ChecksumError {
+    /// `x-amz-checksum-algorithm` names an unsupported algorithm
+    UnknownAlgorithm(String),
+    /// More than one `x-amz-checksum-*` value was sent
+    MultipleChecksums,
+    /// `x-amz-checksum-algorithm` disagrees with the checksum header that was sent
+    AlgorithmMismatch(ChecksumAlgorithm),
+    /// The supplied checksum does not match the data
+    Mismatch(ChecksumAlgorithm),
+}
+
+/// Hex encoded MD5 and SHA-256 digests of an upload: its ETag and payload hash
+pub fn payload_digests(data: &[u8]) -> (String, String) {
+    (
+        hex::encode(md5::compute(data).0),
+        hex::encode(Sha256::digest(data)),
+    )
+}
+
+/// Reasons an `x-amz-content-sha256` header is rejected
+#[derive(Debug)]
+pub enum ContentSha256Error {
+    /// Neither a sentinel value nor a hex SHA-256
+    Malformed,
+    /// The hash does not match the payload
+    Mismatch,
+}
+
+/// Check the `x-amz-content-sha256` header of an upload against the hex SHA-256 of its
+/// payload. A missing header, `UNSIGNED-PAYLOAD` and the `STREAMING-*` values of
+/// chunk-signed uploads are accepted without verification.
+pub fn verify_content_sha256(headers: &HeaderMap, sha256: &str) -> Result<(), ContentSha256Error> {
+    let Some(value) = headers.get("x-amz-content-sha256") else {
+        return Ok(());
+    };
+    let value = value.to_str().map_err(|_| ContentSha256Error::Malformed)?;
+    if value == "UNSIGNED-PAYLOAD" || value.starts_with("STREAMING-") {
+        return Ok(());
+    }
+    if value.len() != 64 || !value.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(ContentSha256Error::Malformed);
+    }
+    if !value.eq_ignore_ascii_case(sha256) {
+        return Err(ContentSha256Error::Mismatch);
+    }
+    Ok(())
+}
+
+/// Determine the checksum to record for an upload.
+/// A supplied `x-amz-checksum-*` value is validated against the data; a bare
+/// `x-amz-checksum-algorithm` asks the server to compute one. Returns None if neither is sent.
+pub fn request_checksum(
+    headers: &HeaderMap,
+    data: &[u8],
+) -> Result<Option<ObjectChecksum>, ChecksumError> {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+
+    let requested = match header("x-amz-checksum-algorithm") {
+        Some(name) => Some(
+            ChecksumAlgorithm::from_name(name)
+                .ok_or_else(|| ChecksumError::UnknownAlgorithm(name.to_string()))?,
+        ),
+        None => None,
+    };
+
+    let mut supplied = ChecksumAlgorithm::ALL
+        .into_iter()
+        .filter_map(|algorithm| Some((algorithm, header(algorithm.header_name())?)));
+    let supplied_checksum = supplied.next();
+    if supplied.next().is_some() {
+        return Err(ChecksumError::MultipleChecksums);
+    }
+
+    match (requested, supplied_checksum) {
+        (_, Some((algorithm, value))) => {
+            if requested.is_some_and(|r| r != algorithm) {
+                return Err(ChecksumError::AlgorithmMismatch(algorithm));
+            }
+            let computed = algorithm.compute(data);
+            if computed != value.trim() {
+                return Err(ChecksumError::Mismatch(algorithm));
+            }
+            Ok(Some(ObjectChecksum {
+                algorithm,
+                value: computed,
+            }))
+        }
+        (Some(algorithm), None) => Ok(Some(ObjectChecksum {
+            algorithm,
+            value: algorithm.compute(data),
+        })),
+        (None, None) => Ok(None),
+    }
+}
@@ -0,0 +1,84 @@
+use rusqlite::types::Type;
+
+use crate::models::Compression;
+
+/// zstd level of stored objects, favouring speed since uploads wait for it
+const ZSTD_LEVEL: i32 = 3;
+
+/// Compressed objects are only stored compressed if they shrink to this fraction of
+/// their size, so incompressible data is not decompressed on every read for nothing
+const MAX_COMPRESSED_RATIO: f64 = 0.9;
+
+impl Compression {
+    /// Value of the `compression` column of objects stored this way
+    pub fn name(self) -> &'static str {
+        match self {
+            Compression::Zstd => "zstd",
+        }
+    }
+
+    /// Rebuild from the `compression` and `original_size` columns: how the data of an
+    /// object is compressed and its size before, None for objects stored as sent
+    pub fn from_columns(
+        compression: Option<String>,
+        original_size: Option<i64>,
+    ) -> rusqlite::Result<Option<(Self, u64)>> {
+        let compression = match compression.as_deref() {
+            None => return Ok(None),
+            Some("zstd") => Compression::Zstd,
+            Some(other) => {
+                return Err(rusqlite::Error::FromSqlConversionFailure(
+                    0,
+                    Type::Text,
+                    format!("unknown object compression {other:?}").into(),
+                ));
+            }
+        };
+        match original_size.and_then(|size| u64::try_from(size).ok()) {
+            Some(size) => Ok(Some((compression, size))),
+            None => Err(rusqlite::Error::FromSqlConversionFailure(
+                0,
+                Type::Null,
+                "compressed object without its original size".into(),
+            )),
+        }
+    }
+}
+
+/// Compress an object body for storage. None if it is smaller than `min_size` or does
+/// not compress well, in which case it is stored as sent.
+pub fn compress(compression: Compression, data: &[u8], min_size: usize) -> Option<Vec<u8>> {
+    if data.len() < min_size {
+        return None;
+    }
+    let compressed = match compression {
+        Compression::Zstd => zstd::bulk::compress(data, ZSTD_LEVEL).ok()?,
+    };
+    ((compressed.len() as f64) <= data.len() as f64 * MAX_COMPRESSED_RATIO).then_some(compressed)
+}
+
+/// Decompress a stored object body of `original_size` bytes. Corrupt bodies, or bodies
+/// decompressing to another size, are reported as conversion failures of the row.
+pub fn decompress(
+    compression: Compression,
+    stored: &[u8],
+    original_size: u64,
+) -> rusqlite::Result<Vec<u8>> {
+    let original_size = original_size as usize;
+    let decompressed = match compression {
+        Compression::Zstd => zstd::bulk::decompress(stored, original_size),
+    }
+    .map_err(|e| rusqlite::Error::FromSqlConversionFailure(0, Type::Blob, Box::new(e)))?;
+    if decompressed.len() != original_size {
+        return Err(rusqlite::Error::FromSqlConversionFailure(
+            0,
+            Type::Blob,
+            format!(
+                "object decompressed to {} bytes instead of {original_size}",
+                decompressed.len()
+            )
+            .into(),
+        ));
+    }
+    Ok(decompressed)
+}
@@ -0,0 +1,96 @@
+use crate::utils::keys::glob_match;
+use crate::utils::xml::{element_text, element_texts};
+
+/// Methods a `<CORSRule>` may allow
+const CORS_METHODS: &[&str] = &["GET", "PUT", "POST", "DELETE", "HEAD"];
+
+/// One `<CORSRule>` of a bucket CORS configuration
+#[derive(Debug, Clone, PartialEq)]
+pub struct CorsRule {
+    pub allowed_origins: Vec<String>,
+    pub allowed_methods: Vec<String>,
+    pub allowed_headers: Vec<String>,
+    pub expose_headers: Vec<String>,
+    pub max_age_seconds: Option<u32>,
+}
+
+impl CorsRule {
+    /// Whether this rule allows a request from `origin` with `method`, sending `headers`.
+    /// Origins and header names may contain one `*` wildcard; header names match case-insensitively.
+    pub fn allows(&self, origin: &str, method: &str, headers: &[&str]) -> bool {
+        self.allowed_origins.iter().any(|p| glob_match(p, origin))
+            && self.allowed_methods.iter().any(|m| m == method)
+            && headers.iter().all(|header| {
+                let header = header.to_ascii_lowercase();
+                self.allowed_headers
+                    .iter()
+                    .any(|p| glob_match(&p.to_ascii_lowercase(), &header))
+            })
+    }
+
+    /// Value of `Access-Control-Allow-Origin`: `*` if the rule allows every origin
+    pub fn allow_origin<'a>(&self, origin: &'a str) -> &'a str {
+        if self.allowed_origins.iter().any(|o| o == "*") {
+            "*"
+        } else {
+            origin
+        }
+    }
+}
+
+/// Parse the rules of a `CORSConfiguration` document.
+/// Returns None if the document is malformed: no rules, a rule without origins or
+/// methods, an unknown method, or an origin with more than one wildcard.
+pub fn parse_cors_rules(xml: &str) -> Option<Vec<CorsRule>> {
+    if !xml.contains("<CORSConfiguration") || !xml.contains("</CORSConfiguration>") {
+        return None;
+    }
+
+    let mut rules = Vec::new();
+    for rule in xml.split("<CORSRule>").skip(1) {
+        let rule = &rule[..rule.find("</CORSRule>")?];
+        let texts = |tag: &str| -> Vec<String> {
+            element_texts(rule, tag)
+                .into_iter()
+                .map(str::to_string)
+                .collect()
+        };
+        let rule = CorsRule {
+            allowed_origins: texts("AllowedOrigin"),
+            allowed_methods: texts("AllowedMethod"),
+            allowed_headers: texts("AllowedHeader"),
+            expose_headers: texts("ExposeHeader"),
+            max_age_seconds: match element_text(rule, "MaxAgeSeconds") {
+                Some(seconds) => Some(seconds.parse().ok()?),
+                None => None,
+            },
+        };
+        if rule.allowed_origins.is_empty()
+            || rule.allowed_methods.is_empty()
+            || rule
+                .allowed_methods
+                .iter()
+                .any(|m| !CORS_METHODS.contains(&m.as_str()))
+            || rule
+                .allowed_origins
+                .iter()
+                .any(|o| o.matches('*').count() > 1)
+        {
+            return None;
+        }
+        rules.push(rule);
+    }
+    (!rules.is_empty()).then_some(rules)
+}
+
+/// The first rule allowing a request, as S3 evaluates them in order
+pub fn find_cors_rule<'a>(
+    rules: &'a [CorsRule],
+    origin: &str,
+    method: &str,
+    headers: &[&str],
+) -> Option<&'a CorsRule> {
+    rules
+        .iter()
+        .find(|rule| rule.allows(origin, method, headers))
+}
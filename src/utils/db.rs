@@ -1,15 +1,21 @@
-use log::error;
+use log::{error, info, warn};
 use r2d2::Pool;
 use r2d2_sqlite::SqliteConnectionManager;
-use rusqlite::Connection;
+use rusqlite::{Connection, OpenFlags, OptionalExtension, TransactionBehavior, params};
 use std::time::Duration;
 
-/// Create and configure an optimized SQLite connection pool
-pub fn create_connection_pool(
+use crate::utils::bucket::ensure_column;
+
+/// Create the pool of the single writer connection. SQLite in WAL mode lets one
+/// connection write at a time, so writers wait for this connection in the pool
+/// instead of contending for the database lock and retrying on SQLITE_BUSY.
+/// The connection is kept open, which also keeps the WAL files in place for the
+/// read-only connections. Create it before the read pool: it creates the database
+/// file and enables WAL mode.
+pub fn create_write_pool(
     db_path: &str,
-    max_size: u32,
-    min_idle: u32,
     timeout_seconds: u64,
+    max_lifetime: Option<Duration>,
 ) -> Result<Pool<SqliteConnectionManager>, r2d2::Error> {
     // Create a manager that enables WAL mode and other optimizations
     let manager = SqliteConnectionManager::file(db_path).with_init(|conn| {
@@ -22,16 +28,195 @@ pub fn create_connection_pool(
         )
     });
 
-    // Configure the connection pool
+    r2d2::Pool::builder()
+        .max_size(1)
+        .min_idle(Some(1))
+        .max_lifetime(max_lifetime)
+        .idle_timeout(None)
+        .connection_timeout(Duration::from_secs(timeout_seconds))
+        .build(manager)
+}
+
+/// Create the pool of read-only connections serving GET, HEAD and listings.
+/// WAL readers do not block the writer nor each other.
+pub fn create_read_pool(
+    db_path: &str,
+    max_size: u32,
+    min_idle: u32,
+    timeout_seconds: u64,
+    idle_timeout: Option<Duration>,
+    max_lifetime: Option<Duration>,
+) -> Result<Pool<SqliteConnectionManager>, r2d2::Error> {
+    let manager = SqliteConnectionManager::file(db_path)
+        .with_flags(
+            OpenFlags::SQLITE_OPEN_READ_ONLY
+                | OpenFlags::SQLITE_OPEN_URI
+                | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )
+        .with_init(|conn| {
+            conn.execute_batch(
+                "PRAGMA cache_size = 1000;
+                 PRAGMA foreign_keys = OFF;
+                 PRAGMA busy_timeout = 5000;",
+            )
+        });
+
     r2d2::Pool::builder()
         .max_size(max_size)
         .min_idle(Some(min_idle))
-        .max_lifetime(None) // Connections last until closed
-        .idle_timeout(Some(Duration::from_secs(300))) // 5 minutes idle timeout
+        .max_lifetime(max_lifetime)
+        .idle_timeout(idle_timeout)
         .connection_timeout(Duration::from_secs(timeout_seconds))
         .build(manager)
 }
 
+/// Table holding the schema version the database has been migrated to
+const SCHEMA_VERSION_TABLE: &str = "_schema_version";
+
+/// A step bringing every bucket table to the next schema version. Steps must be
+/// idempotent: databases from before the version table may already have some of them.
+struct Migration {
+    version: u32,
+    description: &'static str,
+    apply: fn(&Connection, &str) -> rusqlite::Result<()>,
+}
+
+/// Migrations of the bucket tables, in order. Append new ones with the next version;
+/// `ensure_bucket_table` creates new tables with the latest schema.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "add md5",
+        apply: |conn, table| ensure_column(conn, table, "md5", "TEXT(32)"),
+    },
+    Migration {
+        version: 2,
+        description: "add storage_class",
+        apply: |conn, table| {
+            ensure_column(
+                conn,
+                table,
+                "storage_class",
+                "TEXT NOT NULL DEFAULT 'STANDARD'",
+            )
+        },
+    },
+    Migration {
+        version: 3,
+        description: "add checksum_algorithm and checksum",
+        apply: |conn, table| {
+            ensure_column(conn, table, "checksum_algorithm", "TEXT")?;
+            ensure_column(conn, table, "checksum", "TEXT")
+        },
+    },
+    Migration {
+        version: 4,
+        description: "add acl",
+        apply: |conn, table| ensure_column(conn, table, "acl", "TEXT"),
+    },
+    Migration {
+        version: 5,
+        description: "add content_encoding",
+        apply: |conn, table| ensure_column(conn, table, "content_encoding", "TEXT"),
+    },
+    Migration {
+        version: 6,
+        description: "add content_sha256",
+        apply: |conn, table| ensure_column(conn, table, "content_sha256", "TEXT"),
+    },
+    Migration {
+        version: 7,
+        description: "add expires_at",
+        apply: |conn, table| ensure_column(conn, table, "expires_at", "INTEGER"),
+    },
+    Migration {
+        version: 8,
+        description: "bump last_modified on data updates only",
+        apply: |conn, table| {
+            conn.execute_batch(&format!(
+                "DROP TRIGGER IF EXISTS update_{table}_timestamp;
+                 CREATE TRIGGER IF NOT EXISTS update_{table}_data_timestamp
+                 AFTER UPDATE OF data ON {table}
+                 BEGIN UPDATE {table} SET last_modified = strftime('%s', 'now') WHERE key = NEW.key; END;"
+            ))
+        },
+    },
+    Migration {
+        version: 9,
+        description: "add compression and original_size",
+        apply: |conn, table| {
+            ensure_column(conn, table, "compression", "TEXT")?;
+            ensure_column(conn, table, "original_size", "INTEGER")
+        },
+    },
+    Migration {
+        version: 10,
+        description: "add blob_hash",
+        apply: |conn, table| ensure_column(conn, table, "blob_hash", "TEXT"),
+    },
+];
+
+/// Schema version of the database, 0 before any migration ran
+fn schema_version(conn: &Connection) -> rusqlite::Result<u32> {
+    conn.execute(
+        &format!("CREATE TABLE IF NOT EXISTS {SCHEMA_VERSION_TABLE} (version INTEGER NOT NULL)"),
+        [],
+    )?;
+    let version: Option<u32> = conn
+        .query_row(
+            &format!("SELECT MAX(version) FROM {SCHEMA_VERSION_TABLE}"),
+            [],
+            |row| row.get(0),
+        )
+        .optional()?
+        .flatten();
+    Ok(version.unwrap_or(0))
+}
+
+/// Bring every bucket table to the latest schema version, applying the migrations
+/// newer than the recorded version in order. Each migration runs in its own
+/// IMMEDIATE transaction together with the update of the recorded version.
+/// Returns the schema version of the database.
+pub fn run_migrations(conn: &mut Connection, table_prefix: &str) -> rusqlite::Result<u32> {
+    let recorded = schema_version(conn)?;
+    let mut version = recorded;
+    for migration in MIGRATIONS.iter().filter(|m| m.version > recorded) {
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let tables = object_tables(&tx, table_prefix)?;
+        for table in &tables {
+            (migration.apply)(&tx, table)?;
+        }
+        tx.execute(&format!("DELETE FROM {SCHEMA_VERSION_TABLE}"), [])?;
+        tx.execute(
+            &format!("INSERT INTO {SCHEMA_VERSION_TABLE} (version) VALUES (?1)"),
+            params![migration.version],
+        )?;
+        tx.commit()?;
+        info!(
+            "Applied schema migration {} ({}) to {} bucket tables",
+            migration.version,
+            migration.description,
+            tables.len()
+        );
+        version = migration.version;
+    }
+    Ok(version)
+}
+
+/// Bucket tables to migrate: tables starting with `table_prefix` that hold object data
+fn object_tables(conn: &Connection, table_prefix: &str) -> rusqlite::Result<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT m.name FROM sqlite_master m
+         WHERE m.type = 'table' AND substr(m.name, 1, ?1) = ?2
+           AND EXISTS (SELECT 1 FROM pragma_table_info(m.name) WHERE name = 'data')
+         ORDER BY m.name",
+    )?;
+    stmt.query_map(params![table_prefix.len() as i64, table_prefix], |row| {
+        row.get(0)
+    })?
+    .collect()
+}
+
 /// Create indexes for a bucket table to improve query performance
 pub fn create_bucket_indexes(conn: &Connection, table_name: &str) -> rusqlite::Result<()> {
     // Create an index on the key column for faster lookups
@@ -41,15 +226,18 @@ pub fn create_bucket_indexes(conn: &Connection, table_name: &str) -> rusqlite::R
     );
     conn.execute(&index_sql, [])?;
 
+    // Look up objects by ETag, the most recent first (GET /{bucket}?etag=)
+    let index_sql = format!(
+        "CREATE INDEX IF NOT EXISTS idx_{}_md5 ON {} (md5, last_modified)",
+        table_name, table_name
+    );
+    conn.execute(&index_sql, [])?;
+
     Ok(())
 }
 
 /// Optimize the database by running VACUUM and ANALYZE
-pub fn optimize_database(pool: &Pool<SqliteConnectionManager>) -> rusqlite::Result<()> {
-    let conn = pool
-        .get()
-        .map_err(|_e| rusqlite::Error::QueryReturnedNoRows)?;
-
+pub fn optimize_database(conn: &Connection) -> rusqlite::Result<()> {
     // Run VACUUM to reclaim unused space
     conn.execute("VACUUM", [])?;
 
@@ -59,19 +247,94 @@ pub fn optimize_database(pool: &Pool<SqliteConnectionManager>) -> rusqlite::Resu
     Ok(())
 }
 
+/// Write a consistent copy of the database to `target` with VACUUM INTO.
+/// The copy is made in a read transaction, so writers go on while it runs.
+pub fn backup_database(conn: &Connection, target: &std::path::Path) -> rusqlite::Result<()> {
+    // VACUUM INTO creates the file even on a read-only connection
+    conn.execute("VACUUM INTO ?1", [target.to_string_lossy()])?;
+
+    Ok(())
+}
+
+/// Whether an error means the database is busy or locked by another connection
+pub fn is_busy_error(e: &rusqlite::Error) -> bool {
+    matches!(
+        e.sqlite_error_code(),
+        Some(rusqlite::ErrorCode::DatabaseBusy | rusqlite::ErrorCode::DatabaseLocked)
+    )
+}
+
+/// Run blocking database work from async code without stalling the runtime: the worker
+/// thread hands its other tasks to the remaining workers while `f` runs, so a slow query
+/// or a wait for a pooled connection does not delay unrelated requests.
+/// Runs `f` inline outside a multi-threaded runtime, where that is not possible.
+pub fn run_blocking<T>(f: impl FnOnce() -> T) -> T {
+    match tokio::runtime::Handle::try_current() {
+        Ok(handle) if handle.runtime_flavor() == tokio::runtime::RuntimeFlavor::MultiThread => {
+            tokio::task::block_in_place(f)
+        }
+        _ => f(),
+    }
+}
+
+/// Base backoff between busy retries, doubled on every attempt
+const BUSY_BACKOFF_BASE_MS: u64 = 25;
+/// Cap on the backoff doubling, about 25 seconds
+const BUSY_BACKOFF_MAX_SHIFT: u32 = 10;
+
+/// Run a database operation, retrying with exponential backoff while the database
+/// is busy or locked, at most `max_retries` times. Other errors, and the busy error
+/// once retries are exhausted, are returned to the caller.
+///
+/// The connection is lent to `op` on each attempt rather than captured by it, which
+/// keeps the returned future `Send` (a shared `&Connection` is not). Each attempt runs
+/// through `run_blocking`, off the async executor.
+pub async fn retry_on_busy<T>(
+    max_retries: u32,
+    conn: &mut Connection,
+    mut op: impl FnMut(&mut Connection) -> rusqlite::Result<T>,
+) -> rusqlite::Result<T> {
+    let mut attempt = 0;
+    loop {
+        match run_blocking(|| op(conn)) {
+            Err(e) if is_busy_error(&e) && attempt < max_retries => {
+                attempt += 1;
+                let shift = attempt.min(BUSY_BACKOFF_MAX_SHIFT);
+                let backoff = Duration::from_millis(BUSY_BACKOFF_BASE_MS << shift);
+                warn!("Database busy, retry {attempt} in {backoff:?}: {e}");
+                tokio::time::sleep(backoff).await;
+            }
+            other => return other,
+        }
+    }
+}
+
 /// Schedule periodic database optimization in a background task
-pub fn schedule_optimization(pool: Pool<SqliteConnectionManager>) {
+pub fn schedule_optimization(pool: Pool<SqliteConnectionManager>, interval: Duration) {
     // Clone the pool for the background task
     let pool_clone = pool.clone();
 
     // Spawn a background task to periodically optimize the database
     tokio::spawn(async move {
-        let interval = Duration::from_secs(3600 * 24); // Once per day
         let mut interval = tokio::time::interval(interval);
 
         loop {
             interval.tick().await;
-            if let Err(e) = optimize_database(&pool_clone) {
+            let conn = match run_blocking(|| pool_clone.get()) {
+                Ok(conn) => conn,
+                Err(e) => {
+                    log::warn!(
+                        "Database optimization skipped, no connection available: {}",
+                        e
+                    );
+                    continue;
+                }
+            };
+            if let Err(e) = run_blocking(|| optimize_database(&conn)) {
+                if is_busy_error(&e) {
+                    log::warn!("Database optimization skipped, database is locked: {}", e);
+                    continue;
+                }
                 error!("Database optimization failed: {}", e);
             } else {
                 log::info!("Scheduled database optimization completed successfully");
@@ -79,3 +342,80 @@ pub fn schedule_optimization(pool: Pool<SqliteConnectionManager>) {
         }
     });
 }
+
+/// Number of frames currently in the WAL file of the main database
+pub fn wal_frame_count(conn: &Connection) -> rusqlite::Result<u64> {
+    let db_file: String = conn.query_row(
+        "SELECT file FROM pragma_database_list WHERE name = 'main'",
+        [],
+        |row| row.get(0),
+    )?;
+    let page_size: i64 = conn.query_row("PRAGMA page_size", [], |row| row.get(0))?;
+
+    // WAL layout: 32-byte header followed by frames of (24-byte header + page)
+    let wal_size = std::fs::metadata(format!("{db_file}-wal"))
+        .map(|m| m.len())
+        .unwrap_or(0);
+    Ok(wal_size.saturating_sub(32) / (page_size as u64 + 24))
+}
+
+/// Bytes of the pages of the main database in use, leaving out free pages that later
+/// writes reuse. The WAL is not counted.
+pub fn database_used_bytes(conn: &Connection) -> rusqlite::Result<u64> {
+    conn.query_row(
+        "SELECT (page_count - freelist_count) * page_size
+         FROM pragma_page_count, pragma_freelist_count, pragma_page_size",
+        [],
+        |row| row.get::<_, i64>(0),
+    )
+    .map(|bytes| bytes as u64)
+}
+
+/// Run `PRAGMA wal_checkpoint(TRUNCATE)`, returning (busy, log frames, checkpointed frames)
+pub fn checkpoint_wal(conn: &Connection) -> rusqlite::Result<(i64, i64, i64)> {
+    conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |row| {
+        Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+    })
+}
+
+/// Schedule periodic WAL checkpoints in a background task.
+/// A checkpoint is only attempted once the WAL holds at least `threshold_pages` frames.
+pub fn schedule_wal_checkpoint(
+    pool: Pool<SqliteConnectionManager>,
+    interval: Duration,
+    threshold_pages: u64,
+) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(interval);
+
+        loop {
+            interval.tick().await;
+            let conn = match run_blocking(|| pool.get()) {
+                Ok(conn) => conn,
+                Err(e) => {
+                    error!("WAL checkpoint skipped, no database connection: {}", e);
+                    continue;
+                }
+            };
+
+            match wal_frame_count(&conn) {
+                Ok(frames) if frames < threshold_pages => continue,
+                Ok(_) => {}
+                Err(e) => {
+                    error!("Failed to read WAL size: {}", e);
+                    continue;
+                }
+            }
+
+            match run_blocking(|| checkpoint_wal(&conn)) {
+                Ok((busy, log_frames, checkpointed)) => log::info!(
+                    "WAL checkpoint completed: busy={}, log={}, checkpointed={}",
+                    busy,
+                    log_frames,
+                    checkpointed
+                ),
+                Err(e) => error!("WAL checkpoint failed: {}", e),
+            }
+        }
+    });
+}
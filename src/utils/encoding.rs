@@ -0,0 +1,95 @@
+use axum::http::HeaderMap;
+use flate2::{Compression, write::GzEncoder};
+use std::io::Write;
+
+/// Decode a percent-encoded string.
+/// Returns None on a malformed escape sequence or if the result is not valid UTF-8.
+pub fn percent_decode(s: &str) -> Option<String> {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = s.get(i + 1..i + 3)?;
+            if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return None;
+            }
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+/// Percent-encode a path, leaving unreserved characters and `/` as they are
+pub fn percent_encode_path(s: &str) -> String {
+    encode(s, true)
+}
+
+/// Percent-encode a string, leaving only unreserved characters as they are
+pub fn percent_encode(s: &str) -> String {
+    encode(s, false)
+}
+
+fn encode(s: &str, keep_slash: bool) -> String {
+    let mut encoded = String::with_capacity(s.len());
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric()
+            || matches!(b, b'-' | b'.' | b'_' | b'~')
+            || (keep_slash && b == b'/')
+        {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{b:02X}"));
+        }
+    }
+    encoded
+}
+
+/// Escape text for inclusion in HTML content or attribute values
+pub fn html_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Response extension of bodies whose content coding the handler chose itself,
+/// which the compression layer leaves as they are
+#[derive(Debug, Clone, Copy)]
+pub struct HandlerEncoded;
+
+/// Whether `Accept-Encoding` allows gzip, listed by name or through `*`,
+/// without `q=0`
+pub fn accepts_gzip(headers: &HeaderMap) -> bool {
+    let Some(value) = headers.get("Accept-Encoding").and_then(|v| v.to_str().ok()) else {
+        return false;
+    };
+    value.split(',').any(|coding| {
+        let mut params = coding.split(';');
+        let name = params.next().unwrap_or_default().trim();
+        let quality = params
+            .filter_map(|p| p.trim().strip_prefix("q="))
+            .find_map(|q| q.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+        (name.eq_ignore_ascii_case("gzip") || name == "*") && quality > 0.0
+    })
+}
+
+/// Compress data with gzip
+pub fn gzip(data: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    encoder.finish()
+}
@@ -0,0 +1,46 @@
+use axum::http::{HeaderMap, header};
+
+tokio::task_local! {
+    /// Whether the error responses of the request served by the current task are JSON
+    static JSON_ERRORS: bool;
+}
+
+/// Whether the `Accept` header of a request prefers JSON to XML: `application/json`
+/// with a higher quality than every XML type it lists. Wildcards count for neither,
+/// so XML, the format of S3, stays the default.
+pub fn prefers_json(headers: &HeaderMap) -> bool {
+    let Some(accept) = headers
+        .get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok())
+    else {
+        return false;
+    };
+
+    let mut json_quality: f32 = 0.0;
+    let mut xml_quality: f32 = 0.0;
+    for range in accept.split(',') {
+        let mut parts = range.split(';');
+        let media_type = parts.next().unwrap_or_default().trim().to_ascii_lowercase();
+        let quality = parts
+            .filter_map(|param| param.trim().strip_prefix("q="))
+            .find_map(|q| q.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+        match media_type.as_str() {
+            "application/json" => json_quality = json_quality.max(quality),
+            "application/xml" | "text/xml" => xml_quality = xml_quality.max(quality),
+            _ => {}
+        }
+    }
+    json_quality > 0.0 && json_quality > xml_quality
+}
+
+/// Run a future with its error responses sent as JSON if `json` is set
+pub async fn with_error_format<F: Future>(json: bool, future: F) -> F::Output {
+    JSON_ERRORS.scope(json, future).await
+}
+
+/// Whether error responses are sent as JSON: false outside of a request
+/// (e.g. background tasks and blocking threads)
+pub fn json_errors() -> bool {
+    JSON_ERRORS.try_with(|json| *json).unwrap_or(false)
+}
@@ -0,0 +1,53 @@
+/// Normalize one entity tag from a request header for comparison with a stored md5:
+/// drops the weak validator prefix `W/` and the surrounding quotes.
+fn normalize_etag(etag: &str) -> &str {
+    let etag = etag.trim();
+    let etag = etag.strip_prefix("W/").unwrap_or(etag);
+    etag.trim_matches('"')
+}
+
+/// Check whether an If-Match / If-None-Match style header value matches the md5 of an
+/// existing object. The value may be a comma-separated list of quoted, unquoted or weak
+/// (`W/"..."`) entity tags, compared case-insensitively, or `*`, which matches any object.
+pub fn etag_matches(header_value: &str, md5_hash: &str) -> bool {
+    header_value.split(',').any(|etag| {
+        let etag = etag.trim();
+        etag == "*" || normalize_etag(etag).eq_ignore_ascii_case(md5_hash)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::etag_matches;
+
+    const MD5: &str = "5d41402abc4b2a76b9719d911017c592";
+
+    #[test]
+    fn test_single_etags() {
+        assert!(etag_matches("\"5d41402abc4b2a76b9719d911017c592\"", MD5));
+        assert!(etag_matches("W/\"5d41402abc4b2a76b9719d911017c592\"", MD5));
+        assert!(etag_matches("5d41402abc4b2a76b9719d911017c592", MD5));
+        assert!(etag_matches("\"5D41402ABC4B2A76B9719D911017C592\"", MD5));
+        assert!(!etag_matches("\"7d793037a0760186574b0282f2f435e7\"", MD5));
+        assert!(!etag_matches("", MD5));
+    }
+
+    #[test]
+    fn test_wildcard() {
+        assert!(etag_matches("*", MD5));
+        assert!(etag_matches(" * ", MD5));
+    }
+
+    #[test]
+    fn test_etag_lists() {
+        assert!(etag_matches(
+            "\"7d793037a0760186574b0282f2f435e7\", W/\"5d41402abc4b2a76b9719d911017c592\"",
+            MD5
+        ));
+        assert!(etag_matches("\"7d793037a0760186574b0282f2f435e7\",*", MD5));
+        assert!(!etag_matches(
+            "\"7d793037a0760186574b0282f2f435e7\", W/\"7d793037a0760186574b0282f2f435e7\"",
+            MD5
+        ));
+    }
+}
@@ -0,0 +1,119 @@
+use axum::http::HeaderMap;
+use chrono::{DateTime, Utc};
+use log::{debug, error, info};
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::{Connection, params};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use crate::utils::bucket::sanitize_bucket_name;
+use crate::utils::run_blocking;
+
+/// Expired objects deleted per statement by the reaper, so writers are not blocked for long
+const REAP_BATCH_SIZE: usize = 1000;
+
+/// SQL condition selecting objects that have not expired at the time bound to `?{param}`
+pub fn not_expired(param: usize) -> String {
+    format!("(expires_at IS NULL OR expires_at > ?{param})")
+}
+
+/// Expiry time of an upload, in seconds since the epoch: `now` plus the `ttl` query
+/// parameter in seconds, or the RFC 1123 date of the `x-amz-expires` header.
+/// Returns None without either, and a message for an `InvalidArgument` error if a
+/// value is malformed, both are given, or the expiry is not in the future.
+pub fn requested_expiry(
+    query: &HashMap<String, String>,
+    headers: &HeaderMap,
+    now: DateTime<Utc>,
+) -> Result<Option<i64>, String> {
+    let header = headers.get("x-amz-expires");
+    let expires_at = match (query.get("ttl"), header) {
+        (None, None) => return Ok(None),
+        (Some(_), Some(_)) => {
+            return Err("Specify either the ttl parameter or x-amz-expires, not both".to_string());
+        }
+        (Some(ttl), None) => match ttl.parse::<i64>() {
+            Ok(seconds) if seconds > 0 => now.timestamp().saturating_add(seconds),
+            _ => return Err(format!("ttl must be a positive number of seconds: {ttl}")),
+        },
+        (None, Some(value)) => value
+            .to_str()
+            .ok()
+            .and_then(|v| DateTime::parse_from_rfc2822(v).ok())
+            .ok_or_else(|| "x-amz-expires must be an RFC 1123 date".to_string())?
+            .timestamp(),
+    };
+    if expires_at <= now.timestamp() {
+        return Err("The expiry of the object must be in the future".to_string());
+    }
+    Ok(Some(expires_at))
+}
+
+/// Delete the expired objects of a bucket in batches of `REAP_BATCH_SIZE`,
+/// returning the number removed
+pub fn reap_expired_objects(
+    conn: &Connection,
+    bucket: &str,
+    table_prefix: &str,
+) -> rusqlite::Result<usize> {
+    let table_name = sanitize_bucket_name(bucket, table_prefix).ok_or_else(|| {
+        rusqlite::Error::InvalidParameterName(format!("Invalid bucket name: {bucket}"))
+    })?;
+    let sql = format!(
+        "DELETE FROM {table_name} WHERE key IN (
+             SELECT key FROM {table_name} WHERE expires_at <= ?1 LIMIT ?2
+         ) RETURNING key"
+    );
+    let mut stmt = conn.prepare(&sql)?;
+    let now = Utc::now().timestamp();
+
+    let mut removed = 0;
+    loop {
+        let keys = stmt
+            .query_map(params![now, REAP_BATCH_SIZE as i64], |row| {
+                row.get::<_, String>(0)
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        for key in &keys {
+            debug!("Expired '{key}' in bucket '{bucket}'");
+        }
+        removed += keys.len();
+        if keys.len() < REAP_BATCH_SIZE {
+            return Ok(removed);
+        }
+    }
+}
+
+/// Schedule periodic deletion of expired objects in every bucket in a background task
+pub fn schedule_expiry_reaper(
+    pool: Pool<SqliteConnectionManager>,
+    buckets: Arc<RwLock<HashSet<String>>>,
+    table_prefix: String,
+    interval: Duration,
+) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(interval);
+
+        loop {
+            interval.tick().await;
+            let conn = match run_blocking(|| pool.get()) {
+                Ok(conn) => conn,
+                Err(e) => {
+                    error!("Expiry reaper skipped, no database connection: {}", e);
+                    continue;
+                }
+            };
+
+            let buckets: Vec<String> = buckets.read().unwrap().iter().cloned().collect();
+            for bucket in buckets {
+                match run_blocking(|| reap_expired_objects(&conn, &bucket, &table_prefix)) {
+                    Ok(0) => {}
+                    Ok(removed) => info!("Deleted {removed} expired objects in bucket '{bucket}'"),
+                    Err(e) => error!("Expiry reaper failed in bucket '{bucket}': {}", e),
+                }
+            }
+        }
+    });
+}
@@ -0,0 +1,139 @@
+use axum::http::HeaderMap;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use crate::models::AppConfig;
+
+/// An address range in CIDR notation, `192.168.0.0/16` or `fd00::/8`.
+/// A bare address is a range of that address alone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix_len: u8,
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (address, prefix_len) = match s.trim().split_once('/') {
+            Some((address, prefix_len)) => (address, Some(prefix_len)),
+            None => (s.trim(), None),
+        };
+        let network = IpAddr::from_str(address)
+            .map_err(|_| format!("invalid address in CIDR {s:?}"))?
+            .to_canonical();
+        let max_len = if network.is_ipv4() { 32 } else { 128 };
+        let prefix_len = match prefix_len {
+            Some(len) => match len.parse::<u8>() {
+                Ok(len) if len <= max_len => len,
+                _ => return Err(format!("invalid prefix length in CIDR {s:?}")),
+            },
+            None => max_len,
+        };
+        Ok(Self {
+            network,
+            prefix_len,
+        })
+    }
+}
+
+impl Cidr {
+    /// Whether the range contains an address; IPv4-mapped IPv6 addresses match IPv4 ranges
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - self.prefix_len as u32)
+                    .unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - self.prefix_len as u32)
+                    .unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Client address filtering of the `allow_ips`, `deny_ips`, `write_allow_ips` and
+/// `trusted_proxies` config values
+#[derive(Debug, Clone, Default)]
+pub struct IpFilter {
+    allow: Vec<Cidr>,
+    deny: Vec<Cidr>,
+    write_allow: Vec<Cidr>,
+    trusted_proxies: Vec<Cidr>,
+}
+
+fn parse_cidrs(name: &str, values: &[String]) -> Result<Vec<Cidr>, String> {
+    values
+        .iter()
+        .map(|value| value.parse().map_err(|e| format!("{name}: {e}")))
+        .collect()
+}
+
+fn any_contains(ranges: &[Cidr], ip: IpAddr) -> bool {
+    ranges.iter().any(|range| range.contains(ip))
+}
+
+impl IpFilter {
+    /// Parse the configured ranges, failing on the first invalid one
+    pub fn from_config(config: &AppConfig) -> Result<Self, String> {
+        Ok(Self {
+            allow: parse_cidrs("allow_ips", config.get_allow_ips())?,
+            deny: parse_cidrs("deny_ips", config.get_deny_ips())?,
+            write_allow: parse_cidrs("write_allow_ips", config.get_write_allow_ips())?,
+            trusted_proxies: parse_cidrs("trusted_proxies", config.get_trusted_proxies())?,
+        })
+    }
+
+    /// Whether no address is ever refused
+    pub fn is_empty(&self) -> bool {
+        self.allow.is_empty() && self.deny.is_empty() && self.write_allow.is_empty()
+    }
+
+    /// Address of the client. When the peer is a trusted proxy, `X-Forwarded-For` is
+    /// read from the right, skipping trusted proxies, up to the first other address;
+    /// entries to its left could be forged by the client and are ignored.
+    /// `peer` is None on the unix socket, which only a local reverse proxy is expected
+    /// to connect to, so it is trusted like one. Returns None if the client of a unix
+    /// socket request is not named by `X-Forwarded-For`.
+    pub fn client_ip(&self, peer: Option<IpAddr>, headers: &HeaderMap) -> Option<IpAddr> {
+        let mut client = peer;
+        let forwarded = headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .collect::<Vec<_>>();
+        for entry in forwarded.iter().rev() {
+            if client.is_some_and(|ip| !any_contains(&self.trusted_proxies, ip)) {
+                break;
+            }
+            match entry.trim().parse::<IpAddr>() {
+                Ok(ip) => client = Some(ip),
+                Err(_) => break,
+            }
+        }
+        client
+    }
+
+    /// Check a client address, returning the reason it is refused.
+    /// `deny_ips` wins over `allow_ips`; writes must also match `write_allow_ips`.
+    pub fn check(&self, ip: IpAddr, writes: bool) -> Result<(), &'static str> {
+        if any_contains(&self.deny, ip) {
+            return Err("address in deny_ips");
+        }
+        if !self.allow.is_empty() && !any_contains(&self.allow, ip) {
+            return Err("address not in allow_ips");
+        }
+        if writes && !self.write_allow.is_empty() && !any_contains(&self.write_allow, ip) {
+            return Err("address not in write_allow_ips");
+        }
+        Ok(())
+    }
+}
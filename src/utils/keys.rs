@@ -0,0 +1,48 @@
+/// Match `text` against a glob pattern covering the whole string,
+/// where `*` matches any run of characters and `?` any single character
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` and the text position it currently absorbs up to
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                // Let the last `*` absorb one more character and retry
+                Some((star_p, star_t)) => {
+                    star = Some((star_p, star_t + 1));
+                    p = star_p + 1;
+                    t = star_t + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Describe why an object key is refused, None if it may be stored.
+/// `denylist` holds glob patterns matched against the whole key.
+pub fn key_violation(
+    key: &str,
+    denylist: &[String],
+    reject_control_characters: bool,
+) -> Option<String> {
+    if reject_control_characters && key.chars().any(char::is_control) {
+        return Some("Object key contains control characters".to_string());
+    }
+    denylist
+        .iter()
+        .find(|pattern| glob_match(pattern, key))
+        .map(|pattern| format!("Object key matches the disallowed pattern '{pattern}'"))
+}
@@ -0,0 +1,276 @@
+use chrono::Utc;
+use log::{debug, error, info};
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::{Connection, OptionalExtension, params};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::utils::bucket::{prefix_successor, sanitize_bucket_name};
+use crate::utils::object_cache::ObjectCache;
+use crate::utils::run_blocking;
+use crate::utils::xml::child_elements;
+
+/// Table holding the lifecycle configuration document of each bucket.
+/// The leading underscore keeps it out of the `bucket_*` namespace.
+const LIFECYCLE_TABLE: &str = "_bucket_lifecycle";
+
+/// Objects deleted per statement by a lifecycle sweep, so writers are not blocked for long
+const EXPIRATION_BATCH_SIZE: usize = 1000;
+
+/// Reasons a lifecycle configuration document is refused
+#[derive(Debug, PartialEq)]
+pub enum LifecycleError {
+    /// Not a well-formed `LifecycleConfiguration`
+    Malformed,
+    /// A rule uses a feature that is not enforced, named here
+    Unsupported(&'static str),
+}
+
+/// An enabled `Expiration` rule: objects under `prefix` older than `days` are deleted
+#[derive(Debug, PartialEq)]
+pub struct ExpirationRule {
+    pub prefix: String,
+    pub days: u32,
+}
+
+/// Ensures the lifecycle configuration table exists
+pub fn ensure_lifecycle_table(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute(
+        &format!(
+            "CREATE TABLE IF NOT EXISTS {LIFECYCLE_TABLE} (
+                bucket TEXT NOT NULL PRIMARY KEY,
+                configuration TEXT NOT NULL
+            )"
+        ),
+        [],
+    )?;
+    Ok(())
+}
+
+/// Read the stored lifecycle configuration of a bucket
+pub fn get_lifecycle_configuration(
+    conn: &Connection,
+    bucket: &str,
+) -> rusqlite::Result<Option<String>> {
+    conn.query_row(
+        &format!("SELECT configuration FROM {LIFECYCLE_TABLE} WHERE bucket = ?1"),
+        params![bucket],
+        |row| row.get(0),
+    )
+    .optional()
+}
+
+/// Store or replace the lifecycle configuration of a bucket
+pub fn put_lifecycle_configuration(
+    conn: &Connection,
+    bucket: &str,
+    configuration: &str,
+) -> rusqlite::Result<()> {
+    conn.execute(
+        &format!(
+            "INSERT INTO {LIFECYCLE_TABLE} (bucket, configuration) VALUES (?1, ?2)
+             ON CONFLICT(bucket) DO UPDATE SET configuration=excluded.configuration"
+        ),
+        params![bucket, configuration],
+    )?;
+    Ok(())
+}
+
+/// Remove the lifecycle configuration of a bucket
+pub fn delete_lifecycle_configuration(conn: &Connection, bucket: &str) -> rusqlite::Result<()> {
+    conn.execute(
+        &format!("DELETE FROM {LIFECYCLE_TABLE} WHERE bucket = ?1"),
+        params![bucket],
+    )?;
+    Ok(())
+}
+
+/// Extract the enabled `Expiration` rules from a lifecycle configuration document.
+/// Only rules made of an optional `<Prefix>` (directly or in a `<Filter>`) and an
+/// `<Expiration>` after `<Days>` are understood. Any other filter or action, which
+/// would silently not be enforced or widen the rule to the whole prefix, is refused.
+pub fn parse_expiration_rules(xml: &str) -> Result<Vec<ExpirationRule>, LifecycleError> {
+    if !xml.contains("<LifecycleConfiguration") || !xml.contains("</LifecycleConfiguration>") {
+        return Err(LifecycleError::Malformed);
+    }
+
+    let mut rules = Vec::new();
+    for rule in xml.split("<Rule>").skip(1) {
+        let rule = &rule[..rule.find("</Rule>").ok_or(LifecycleError::Malformed)?];
+        let mut status = None;
+        let mut prefix = "";
+        let mut days = None;
+        for (name, content) in child_elements(rule).ok_or(LifecycleError::Malformed)? {
+            match name {
+                "ID" => {}
+                "Status" => status = Some(content),
+                "Prefix" => prefix = content,
+                "Filter" => prefix = filter_prefix(content)?,
+                "Expiration" => days = Some(expiration_days(content)?),
+                "Transition" | "NoncurrentVersionTransition" => {
+                    return Err(LifecycleError::Unsupported("Transition"));
+                }
+                "NoncurrentVersionExpiration" => {
+                    return Err(LifecycleError::Unsupported("NoncurrentVersionExpiration"));
+                }
+                "AbortIncompleteMultipartUpload" => {
+                    return Err(LifecycleError::Unsupported(
+                        "AbortIncompleteMultipartUpload",
+                    ));
+                }
+                _ => return Err(LifecycleError::Unsupported("Other actions")),
+            }
+        }
+        let days = days.ok_or(LifecycleError::Unsupported("Actions other than Expiration"))?;
+        match status.ok_or(LifecycleError::Malformed)? {
+            "Enabled" => rules.push(ExpirationRule {
+                prefix: prefix.to_string(),
+                days,
+            }),
+            "Disabled" => {}
+            _ => return Err(LifecycleError::Malformed),
+        }
+    }
+    Ok(rules)
+}
+
+/// Prefix of a rule `<Filter>`, which may only hold a `<Prefix>`
+fn filter_prefix(filter: &str) -> Result<&str, LifecycleError> {
+    let mut prefix = "";
+    for (name, content) in child_elements(filter).ok_or(LifecycleError::Malformed)? {
+        match name {
+            "Prefix" => prefix = content,
+            "Tag" | "And" => return Err(LifecycleError::Unsupported("Tag filters")),
+            "ObjectSizeGreaterThan" | "ObjectSizeLessThan" => {
+                return Err(LifecycleError::Unsupported("Object size filters"));
+            }
+            _ => return Err(LifecycleError::Unsupported("Other filters")),
+        }
+    }
+    Ok(prefix)
+}
+
+/// Days of an `<Expiration>`, which may only hold a positive `<Days>`
+fn expiration_days(expiration: &str) -> Result<u32, LifecycleError> {
+    let mut days = None;
+    for (name, content) in child_elements(expiration).ok_or(LifecycleError::Malformed)? {
+        match name {
+            "Days" => {
+                days = Some(
+                    content
+                        .parse::<u32>()
+                        .ok()
+                        .filter(|days| *days > 0)
+                        .ok_or(LifecycleError::Malformed)?,
+                )
+            }
+            "Date" => return Err(LifecycleError::Unsupported("Expiration dates")),
+            "ExpiredObjectDeleteMarker" => {
+                return Err(LifecycleError::Unsupported("ExpiredObjectDeleteMarker"));
+            }
+            _ => return Err(LifecycleError::Unsupported("Other expirations")),
+        }
+    }
+    days.ok_or(LifecycleError::Malformed)
+}
+
+/// Delete the objects of a bucket matched by an expiration rule in batches of
+/// `EXPIRATION_BATCH_SIZE`, returning the number removed
+pub fn expire_objects(
+    conn: &Connection,
+    bucket: &str,
+    table_prefix: &str,
+    rule: &ExpirationRule,
+) -> rusqlite::Result<usize> {
+    let table_name = sanitize_bucket_name(bucket, table_prefix).ok_or_else(|| {
+        rusqlite::Error::InvalidParameterName(format!("Invalid bucket name: {bucket}"))
+    })?;
+    let cutoff = Utc::now().timestamp() - i64::from(rule.days) * 86400;
+    let sql = format!(
+        "DELETE FROM {table_name} WHERE key IN (
+             SELECT key FROM {table_name}
+             WHERE key >= ?1 AND (?2 IS NULL OR key < ?2) AND last_modified < ?3
+             LIMIT ?4
+         ) RETURNING key"
+    );
+    let mut stmt = conn.prepare(&sql)?;
+    let upper = prefix_successor(&rule.prefix);
+
+    let mut removed = 0;
+    loop {
+        let keys = stmt
+            .query_map(
+                params![rule.prefix, upper, cutoff, EXPIRATION_BATCH_SIZE as i64],
+                |row| row.get::<_, String>(0),
+            )?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        for key in &keys {
+            debug!("Lifecycle expired '{key}' in bucket '{bucket}'");
+        }
+        removed += keys.len();
+        if keys.len() < EXPIRATION_BATCH_SIZE {
+            return Ok(removed);
+        }
+    }
+}
+
+/// Apply the expiration rules of every bucket with a stored lifecycle configuration
+pub fn sweep_expired_objects(conn: &Connection, table_prefix: &str) -> rusqlite::Result<usize> {
+    let configurations: Vec<(String, String)> = {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT bucket, configuration FROM {LIFECYCLE_TABLE}"
+        ))?;
+        stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<_>>()?
+    };
+
+    let mut expired = 0;
+    for (bucket, configuration) in configurations {
+        for rule in parse_expiration_rules(&configuration).unwrap_or_default() {
+            let removed = expire_objects(conn, &bucket, table_prefix, &rule)?;
+            if removed > 0 {
+                info!(
+                    "Lifecycle expired {removed} objects older than {} days under '{}' in bucket '{bucket}'",
+                    rule.days, rule.prefix
+                );
+            }
+            expired += removed;
+        }
+    }
+    Ok(expired)
+}
+
+/// Schedule periodic lifecycle expiration sweeps in a background task.
+/// The object cache is cleared after a sweep that expired objects.
+pub fn schedule_lifecycle_sweep(
+    pool: Pool<SqliteConnectionManager>,
+    object_cache: Option<Arc<ObjectCache>>,
+    table_prefix: String,
+    interval: Duration,
+) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(interval);
+
+        loop {
+            interval.tick().await;
+            let conn = match run_blocking(|| pool.get()) {
+                Ok(conn) => conn,
+                Err(e) => {
+                    error!("Lifecycle sweep skipped, no database connection: {}", e);
+                    continue;
+                }
+            };
+
+            match run_blocking(|| sweep_expired_objects(&conn, &table_prefix)) {
+                Ok(0) => {}
+                Ok(_) => {
+                    if let Some(cache) = &object_cache {
+                        cache.clear();
+                    }
+                }
+                Err(e) => error!("Lifecycle sweep failed: {}", e),
+            }
+        }
+    });
+}
@@ -1,3 +1,4 @@
+use axum::http::HeaderMap;
 use chrono::Utc;
 use log;
 use std::fs::OpenOptions;
@@ -52,3 +53,24 @@ pub fn initialize_logger<P: AsRef<Path>>(
 
     Ok(())
 }
+
+/// Format request headers for debug logging, hiding the values of credentials,
+/// payload hashes and user metadata
+pub fn redacted_headers(headers: &HeaderMap) -> String {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let name = name.as_str();
+            let sensitive = matches!(
+                name,
+                "authorization" | "x-amz-content-sha256" | "x-amz-security-token"
+            ) || name.starts_with("x-amz-meta-");
+            if sensitive {
+                format!("{name}: <redacted>")
+            } else {
+                format!("{name}: {}", String::from_utf8_lossy(value.as_bytes()))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
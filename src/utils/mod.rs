@@ -1,8 +1,52 @@
+pub mod access_log;
+pub mod acl;
+pub mod archive;
+pub mod audit;
+pub mod backup;
+pub mod blobs;
 pub mod bucket;
+pub mod bucket_meta;
+pub mod checksum;
+pub mod compression;
+pub mod cors;
 pub mod db;
+pub mod encoding;
+pub mod error_format;
+pub mod etag;
+pub mod expiry;
+pub mod ip_filter;
+pub mod keys;
+pub mod lifecycle;
 pub mod logging;
+pub mod multipart;
+pub mod object_cache;
+pub mod policy;
+pub mod range;
+pub mod rate_limit;
+pub mod request_id;
+pub mod sigv4;
+pub mod temp_credentials;
+pub mod xml;
 
 // Re-exports for convenience
-pub use bucket::{ensure_bucket_table, sanitize_bucket_name, validate_bucket, xml_error_response};
-pub use db::{create_bucket_indexes, create_connection_pool, schedule_optimization};
-pub use logging::initialize_logger;
+pub use acl::ensure_acl_table;
+pub use bucket::{
+    database_error_response, ensure_bucket_table, not_implemented_response, pool_error_response,
+    sanitize_bucket_name, validate_bucket, xml_error_response, xml_response,
+};
+pub use bucket_meta::ensure_bucket_meta_table;
+pub use db::{
+    backup_database, create_bucket_indexes, create_read_pool, create_write_pool,
+    database_used_bytes, is_busy_error, optimize_database, retry_on_busy, run_blocking,
+    run_migrations, schedule_optimization, schedule_wal_checkpoint, wal_frame_count,
+};
+pub use encoding::{
+    HandlerEncoded, accepts_gzip, gzip, html_escape, percent_decode, percent_encode,
+    percent_encode_path,
+};
+pub use error_format::{prefers_json, with_error_format};
+pub use etag::etag_matches;
+pub use expiry::schedule_expiry_reaper;
+pub use lifecycle::{ensure_lifecycle_table, schedule_lifecycle_sweep};
+pub use logging::{initialize_logger, redacted_headers};
+pub use request_id::{RequestId, next_request_id, with_request_id};
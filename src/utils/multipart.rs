@@ -0,0 +1,704 @@
+use chrono::Utc;
+use rusqlite::{Connection, MAIN_DB, OptionalExtension, TransactionBehavior, params};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::models::Compression;
+use crate::utils::blobs::{delete_unreferenced_blob, locate_data, store_blob};
+use crate::utils::bucket::{MISSING_MD5_DATA, prefix_successor, stored_or_computed_md5};
+use crate::utils::bucket_meta::{ObjectQuota, object_usage_size};
+use crate::utils::compression::decompress;
+use crate::utils::expiry::not_expired;
+use crate::utils::xml::element_text;
+
+/// Valid part numbers of a multipart upload
+pub const PART_NUMBER_RANGE: std::ops::RangeInclusive<u32> = 1..=10000;
+
+/// Minimum size of every part but the last, as in S3
+pub const MIN_PART_SIZE: i64 = 5 * 1024 * 1024;
+
+/// Distinguishes upload IDs generated within the same clock tick
+static UPLOAD_ID_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// A pending multipart upload, as recorded by CreateMultipartUpload
+pub struct MultipartUpload<'a> {
+    pub upload_id: &'a str,
+    pub bucket: &'a str,
+    pub key: &'a str,
+    pub content_type: Option<&'a str>,
+    pub metadata: &'a str,
+    pub storage_class: &'a str,
+}
+
+/// A stored part of a pending upload
+pub struct PartInfo {
+    pub part_number: u32,
+    pub md5_hash: String,
+    pub size: u64,
+    pub last_modified: i64,
+}
+
+/// A pending upload as reported by ListMultipartUploads
+pub struct UploadInfo {
+    pub key: String,
+    pub upload_id: String,
+    pub initiated: i64,
+    pub storage_class: String,
+}
+
+/// One page of pending uploads of a bucket
+pub struct UploadListing {
+    pub uploads: Vec<UploadInfo>,
+    pub common_prefixes: Vec<String>,
+    pub is_truncated: bool,
+}
+
+/// A part to be created from (a byte range of) an existing object
+pub struct PartCopy<'a> {
+    pub upload_id: &'a str,
+    pub bucket: &'a str,
+    pub key: &'a str,
+    pub part_number: u32,
+    pub source_table: &'a str,
+    pub source_key: &'a str,
+    /// Inclusive byte range of the source, None for the whole object
+    pub range: Option<(u64, u64)>,
+}
+
+/// Outcome of UploadPartCopy
+pub enum PartCopyOutcome {
+    Copied {
+        md5_hash: String,
+        last_modified: i64,
+    },
+    NoSuchUpload,
+    SourceMissing,
+    PreconditionFailed,
+    /// The range does not fit the source object of this size
+    InvalidRange(u64),
+}
+
+/// Outcome of CompleteMultipartUpload
+pub enum CompleteOutcome {
+    Completed {
+        etag: String,
+    },
+    NoSuchUpload,
+    InvalidPart(u32),
+    InvalidPartOrder,
+    EntityTooSmall(u32),
+    /// The object exists in a write-once bucket; the upload is kept
+    Immutable,
+    /// The object would take the bucket over its maximum number of objects or bytes;
+    /// the upload is kept
+    QuotaExceeded,
+}
+
+/// The body of a completed upload assembled in this process, to store in its stored form
+/// instead of concatenating the parts in SQLite
+pub struct AssembledObject {
+    /// Data as stored, compressed if `compression` is set
+    pub data: Vec<u8>,
+    /// Compression of the data and the size of the object before it, None if stored as sent
+    pub compression: Option<(Compression, u64)>,
+    /// Hex SHA-256 of the data if it is stored as a blob shared by identical objects
+    pub blob_hash: Option<String>,
+}
+
+impl AssembledObject {
+    /// Size of the object before compression
+    fn original_size(&self) -> u64 {
+        self.compression
+            .map_or(self.data.len() as u64, |(_, size)| size)
+    }
+
+    /// Bytes the object counts towards the usage of its bucket, see `USAGE_SIZE`
+    fn usage_size(&self) -> u64 {
+        match self.blob_hash {
+            Some(_) => self.original_size(),
+            None => self.data.len() as u64,
+        }
+    }
+}
+
+/// Ensures the tables tracking pending multipart uploads exist.
+/// They are shared by all buckets, so they are created alongside each bucket table.
+pub fn ensure_multipart_tables(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS multipart_uploads (
+            upload_id TEXT NOT NULL PRIMARY KEY,
+            bucket TEXT NOT NULL,
+            key TEXT NOT NULL,
+            initiated INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+            content_type TEXT,
+            metadata TEXT NOT NULL DEFAULT '{}',
+            storage_class TEXT NOT NULL DEFAULT 'STANDARD'
+        );
+        CREATE INDEX IF NOT EXISTS idx_multipart_uploads_bucket_key
+            ON multipart_uploads (bucket, key);
+        CREATE TABLE IF NOT EXISTS multipart_parts (
+            upload_id TEXT NOT NULL,
+            part_number INTEGER NOT NULL,
+            data BLOB NOT NULL,
+            md5 TEXT(32) NOT NULL,
+            last_modified INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+            PRIMARY KEY (upload_id, part_number)
+        );",
+    )
+}
+
+/// Generate a unique, opaque UploadId
+pub fn generate_upload_id(bucket: &str, key: &str) -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    let counter = UPLOAD_ID_COUNTER.fetch_add(1, Ordering::Relaxed);
+    let seed = format!(
+        "{bucket}\0{key}\0{nanos}\0{counter}\0{}",
+        std::process::id()
+    );
+    hex::encode(md5::compute(seed.as_bytes()).0)
+}
+
+/// Record a new pending multipart upload
+pub fn insert_multipart_upload(
+    conn: &Connection,
+    upload: &MultipartUpload,
+) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO multipart_uploads
+         (upload_id, bucket, key, content_type, metadata, storage_class)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            upload.upload_id,
+            upload.bucket,
+            upload.key,
+            upload.content_type,
+            upload.metadata,
+            upload.storage_class
+        ],
+    )?;
+    Ok(())
+}
+
+/// Store a part of a pending upload inside an IMMEDIATE transaction, replacing any
+/// previous data for the same part number.
+/// Returns false if no upload with this id exists for the bucket and key.
+pub fn put_part(
+    conn: &mut Connection,
+    upload_id: &str,
+    bucket: &str,
+    key: &str,
+    part_number: u32,
+    data: &[u8],
+    md5_hash: &str,
+) -> rusqlite::Result<bool> {
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    if !upload_exists(&tx, upload_id, bucket, key)? {
+        return Ok(false);
+    }
+    store_part(&tx, upload_id, part_number, data, md5_hash)?;
+    tx.commit()?;
+    Ok(true)
+}
+
+/// Insert or replace the data of a part
+fn store_part(
+    conn: &Connection,
+    upload_id: &str,
+    part_number: u32,
+    data: &[u8],
+    md5_hash: &str,
+) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO multipart_parts (upload_id, part_number, data, md5) VALUES (?1, ?2, ?3, ?4)
+         ON CONFLICT(upload_id, part_number) DO UPDATE SET data=excluded.data,
+         md5=excluded.md5, last_modified=strftime('%s', 'now')",
+        params![upload_id, part_number, data, md5_hash],
+    )?;
+    Ok(())
+}
+
+/// Store a byte range of an existing object as a part inside an IMMEDIATE transaction.
+/// Only the requested range is read from the source, using incremental blob I/O.
+/// `preconditions_hold` is given the source md5 and last_modified.
+pub fn copy_part(
+    conn: &mut Connection,
+    copy: &PartCopy,
+    preconditions_hold: impl Fn(&str, i64) -> bool,
+) -> rusqlite::Result<PartCopyOutcome> {
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    if !upload_exists(&tx, copy.upload_id, copy.bucket, copy.key)? {
+        return Ok(PartCopyOutcome::NoSuchUpload);
+    }
+
+    let sql = format!(
+        "SELECT rowid, length(data), md5, last_modified, {MISSING_MD5_DATA}, compression,
+         original_size, blob_hash FROM {} WHERE key = ?1 AND {}",
+        copy.source_table,
+        not_expired(2)
+    );
+    let source = tx
+        .query_row(
+            &sql,
+            params![copy.source_key, Utc::now().timestamp()],
+            |row| {
+                let md5_hash = stored_or_computed_md5(row.get(2)?, row.get(4)?);
+                let size: i64 = row.get(1)?;
+                let compression = Compression::from_columns(row.get(5)?, row.get(6)?)?;
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    size as u64,
+                    md5_hash,
+                    row.get(3)?,
+                    compression,
+                    row.get::<_, Option<String>>(7)?,
+                ))
+            },
+        )
+        .optional()?;
+    let Some((rowid, stored_size, source_md5, source_modified, compression, blob_hash)) = source
+    else {
+        return Ok(PartCopyOutcome::SourceMissing);
+    };
+    if !preconditions_hold(&source_md5, source_modified) {
+        return Ok(PartCopyOutcome::PreconditionFailed);
+    }
+    let (data_table, rowid, stored_size) = locate_data(
+        &tx,
+        copy.source_table,
+        rowid,
+        stored_size,
+        blob_hash.as_deref(),
+    )?;
+
+    let size = compression.map_or(stored_size, |(_, original_size)| original_size);
+    let (start, end) = match copy.range {
+        None => (0, size),
+        Some((first, last)) if first <= last && last < size => (first, last + 1),
+        Some(_) => return Ok(PartCopyOutcome::InvalidRange(size)),
+    };
+    let data = {
+        let blob = tx.blob_open(MAIN_DB, data_table, "data", rowid, true)?;
+        match compression {
+            // A compressed source is decompressed whole before the range is cut out
+            Some((compression, original_size)) => {
+                let mut stored = vec![0u8; stored_size as usize];
+                blob.read_at_exact(&mut stored, 0)?;
+                let mut data = decompress(compression, &stored, original_size)?;
+                data.truncate(end as usize);
+                data.drain(..start as usize);
+                data
+            }
+            None => {
+                let mut data = vec![0u8; (end - start) as usize];
+                blob.read_at_exact(&mut data, start as usize)?;
+                data
+            }
+        }
+    };
+
+    let md5_hash = hex::encode(md5::compute(&data).0);
+    store_part(&tx, copy.upload_id, copy.part_number, &data, &md5_hash)?;
+    let last_modified: i64 = tx.query_row(
+        "SELECT last_modified FROM multipart_parts WHERE upload_id = ?1 AND part_number = ?2",
+        params![copy.upload_id, copy.part_number],
+        |row| row.get(0),
+    )?;
+    tx.commit()?;
+
+    Ok(PartCopyOutcome::Copied {
+        md5_hash,
+        last_modified,
+    })
+}
+
+/// Whether a pending upload with this id exists for the bucket and key
+pub fn upload_exists(
+    conn: &Connection,
+    upload_id: &str,
+    bucket: &str,
+    key: &str,
+) -> rusqlite::Result<bool> {
+    conn.query_row(
+        "SELECT COUNT(*) > 0 FROM multipart_uploads
+         WHERE upload_id = ?1 AND bucket = ?2 AND key = ?3",
+        params![upload_id, bucket, key],
+        |row| row.get(0),
+    )
+}
+
+/// Delete a pending upload and all its parts inside an IMMEDIATE transaction.
+/// Returns false if no upload with this id exists for the bucket and key, which is also
+/// what a concurrent Complete or Abort that committed first leaves behind.
+pub fn abort_upload(
+    conn: &mut Connection,
+    upload_id: &str,
+    bucket: &str,
+    key: &str,
+) -> rusqlite::Result<bool> {
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    let deleted = tx.execute(
+        "DELETE FROM multipart_uploads WHERE upload_id = ?1 AND bucket = ?2 AND key = ?3",
+        params![upload_id, bucket, key],
+    )?;
+    if deleted == 0 {
+        return Ok(false);
+    }
+    tx.execute(
+        "DELETE FROM multipart_parts WHERE upload_id = ?1",
+        params![upload_id],
+    )?;
+    tx.commit()?;
+    Ok(true)
+}
+
+/// List up to `max_parts` parts of a pending upload with a part number above `marker`,
+/// in part number order. Returns None if no upload with this id exists for the bucket
+/// and key, otherwise the upload's storage class, the parts and whether more remain.
+pub fn list_parts(
+    conn: &Connection,
+    upload_id: &str,
+    bucket: &str,
+    key: &str,
+    marker: u32,
+    max_parts: u32,
+) -> rusqlite::Result<Option<(String, Vec<PartInfo>, bool)>> {
+    let storage_class: Option<String> = conn
+        .query_row(
+            "SELECT storage_class FROM multipart_uploads
+             WHERE upload_id = ?1 AND bucket = ?2 AND key = ?3",
+            params![upload_id, bucket, key],
+            |row| row.get(0),
+        )
+        .optional()?;
+    let Some(storage_class) = storage_class else {
+        return Ok(None);
+    };
+
+    let mut stmt = conn.prepare(
+        "SELECT part_number, md5, length(data), last_modified FROM multipart_parts
+         WHERE upload_id = ?1 AND part_number > ?2 ORDER BY part_number LIMIT ?3",
+    )?;
+    let mut parts = stmt
+        .query_map(params![upload_id, marker, max_parts + 1], |row| {
+            Ok(PartInfo {
+                part_number: row.get(0)?,
+                md5_hash: row.get(1)?,
+                size: row.get::<_, i64>(2)?.try_into().unwrap_or(0),
+                last_modified: row.get(3)?,
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let is_truncated = parts.len() > max_parts as usize;
+    parts.truncate(max_parts as usize);
+    Ok(Some((storage_class, parts, is_truncated)))
+}
+
+/// List the pending uploads of a bucket under `prefix`, ordered by key and upload id.
+///
+/// Listing starts after `key_marker`; with an `upload_id_marker`, uploads of the
+/// marker key with a greater upload id are included as well. With a delimiter, keys
+/// containing it after the prefix are rolled up into common prefixes, and a
+/// `key_marker` that is itself a common prefix skips every key sharing it.
+/// At most `max_uploads` entries (uploads and common prefixes together) are returned.
+pub fn list_uploads(
+    conn: &Connection,
+    bucket: &str,
+    prefix: &str,
+    delimiter: Option<char>,
+    key_marker: Option<&str>,
+    upload_id_marker: Option<&str>,
+    max_uploads: usize,
+) -> rusqlite::Result<UploadListing> {
+    let mut stmt = conn.prepare(
+        "SELECT key, upload_id, initiated, storage_class FROM multipart_uploads
+         WHERE bucket = ?1 AND key >= ?2 AND (?3 IS NULL OR key < ?3)
+         AND (?4 IS NULL OR key > ?4 OR (key = ?4 AND ?5 IS NOT NULL AND upload_id > ?5))
+         ORDER BY key, upload_id",
+    )?;
+    let upper_bound = prefix_successor(prefix);
+    let mut rows = stmt.query(params![
+        bucket,
+        prefix,
+        upper_bound,
+        key_marker,
+        upload_id_marker
+    ])?;
+
+    let mut listing = UploadListing {
+        uploads: Vec::new(),
+        common_prefixes: Vec::new(),
+        is_truncated: false,
+    };
+    while let Some(row) = rows.next()? {
+        let key: String = row.get(0)?;
+        let common_prefix = delimiter.and_then(|delimiter| {
+            key[prefix.len()..]
+                .find(delimiter)
+                .map(|pos| key[..prefix.len() + pos + delimiter.len_utf8()].to_string())
+        });
+        if let Some(common_prefix) = &common_prefix
+            && (listing.common_prefixes.last() == Some(common_prefix)
+                || key_marker == Some(common_prefix.as_str()))
+        {
+            continue;
+        }
+
+        if listing.uploads.len() + listing.common_prefixes.len() == max_uploads {
+            listing.is_truncated = true;
+            break;
+        }
+        match common_prefix {
+            Some(common_prefix) => listing.common_prefixes.push(common_prefix),
+            None => listing.uploads.push(UploadInfo {
+                key,
+                upload_id: row.get(1)?,
+                initiated: row.get(2)?,
+                storage_class: row.get(3)?,
+            }),
+        }
+    }
+    Ok(listing)
+}
+
+/// Parse a `<CompleteMultipartUpload>` manifest into (part number, ETag) pairs.
+/// ETags are returned without quotes. Returns None if the document is malformed.
+pub fn parse_complete_manifest(xml: &str) -> Option<Vec<(u32, String)>> {
+    if !xml.contains("<CompleteMultipartUpload") {
+        return None;
+    }
+
+    let mut parts = Vec::new();
+    for part in xml.split("<Part>").skip(1) {
+        let part = &part[..part.find("</Part>")?];
+        let part_number = element_text(part, "PartNumber")?.parse::<u32>().ok()?;
+        let etag = element_text(part, "ETag")?
+            .replace("&quot;", "")
+            .replace('"', "")
+            .to_ascii_lowercase();
+        parts.push((part_number, etag));
+    }
+    if parts.is_empty() {
+        return None;
+    }
+    Some(parts)
+}
+
+/// S3 multipart ETag: the md5 of the concatenated binary part md5s, followed by the part count.
+/// It is stored in the `md5` column in place of the md5 of the data, so every reader of
+/// that column (HEAD, GET, listings, copies, conditions) returns the same ETag.
+pub fn multipart_etag(part_md5s: &[&str]) -> String {
+    let mut digests = Vec::with_capacity(part_md5s.len() * 16);
+    for md5_hash in part_md5s {
+        digests.extend(hex::decode(md5_hash).unwrap_or_default());
+    }
+    format!(
+        "{}-{}",
+        hex::encode(md5::compute(&digests).0),
+        part_md5s.len()
+    )
+}
+
+/// Concatenate the parts listed in the manifest of an upload, or None if one of them is
+/// missing or does not match its ETag, or the object would be larger than `max_size`.
+/// Parts are only read, so the result is checked by `complete_upload` like the manifest.
+pub fn assemble_parts(
+    conn: &Connection,
+    upload_id: &str,
+    manifest: &[(u32, String)],
+    max_size: u64,
+) -> rusqlite::Result<Option<Vec<u8>>> {
+    let size: Option<i64> = conn.query_row(
+        &format!(
+            "SELECT SUM(length(data)) FROM multipart_parts
+             WHERE upload_id = ?1 AND part_number IN ({})",
+            manifest
+                .iter()
+                .map(|(n, _)| n.to_string())
+                .collect::<Vec<_>>()
+                .join(",")
+        ),
+        params![upload_id],
+        |row| row.get(0),
+    )?;
+    if size.is_none_or(|size| size as u64 > max_size) {
+        return Ok(None);
+    }
+
+    let mut body = Vec::with_capacity(size.unwrap_or(0) as usize);
+    let mut stmt = conn.prepare(
+        "SELECT md5, data FROM multipart_parts WHERE upload_id = ?1 AND part_number = ?2",
+    )?;
+    for (part_number, etag) in manifest {
+        let part = stmt
+            .query_row(params![upload_id, part_number], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, Vec<u8>>(1)?))
+            })
+            .optional()?;
+        match part {
+            Some((md5_hash, data)) if md5_hash == *etag => body.extend_from_slice(&data),
+            _ => return Ok(None),
+        }
+    }
+    Ok(Some(body))
+}
+
+/// Assemble a pending upload into an object row of `table_name` inside an IMMEDIATE
+/// transaction, then drop the upload and its parts.
+/// The listed parts must exist with matching ETags, be in ascending order and, except
+/// for the last, be at least `MIN_PART_SIZE` bytes. Unless the body was `assembled`
+/// from the same parts beforehand, the part blobs are concatenated by SQLite, so they
+/// are never loaded into this process.
+/// In a `write_once` bucket an existing object is kept and the upload left pending,
+/// as it is when the object does not fit the quota of the bucket.
+#[allow(clippy::too_many_arguments)]
+pub fn complete_upload(
+    conn: &mut Connection,
+    upload_id: &str,
+    bucket: &str,
+    key: &str,
+    table_name: &str,
+    manifest: &[(u32, String)],
+    assembled: Option<&AssembledObject>,
+    write_once: bool,
+    quota: Option<&ObjectQuota>,
+) -> rusqlite::Result<CompleteOutcome> {
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+
+    let storage_class: Option<String> = tx
+        .query_row(
+            "SELECT storage_class FROM multipart_uploads
+             WHERE upload_id = ?1 AND bucket = ?2 AND key = ?3",
+            params![upload_id, bucket, key],
+            |row| row.get(0),
+        )
+        .optional()?;
+    let Some(storage_class) = storage_class else {
+        return Ok(CompleteOutcome::NoSuchUpload);
+    };
+
+    // part_number -> (md5, size) of the stored parts
+    let stored: HashMap<u32, (String, i64)> = {
+        let mut stmt = tx.prepare(
+            "SELECT part_number, md5, length(data) FROM multipart_parts WHERE upload_id = ?1",
+        )?;
+        stmt.query_map(params![upload_id], |row| {
+            Ok((row.get(0)?, (row.get(1)?, row.get(2)?)))
+        })?
+        .collect::<rusqlite::Result<_>>()?
+    };
+
+    let mut part_md5s = Vec::with_capacity(manifest.len());
+    let mut object_size = 0;
+    for (i, (part_number, etag)) in manifest.iter().enumerate() {
+        if i > 0 && *part_number <= manifest[i - 1].0 {
+            return Ok(CompleteOutcome::InvalidPartOrder);
+        }
+        let Some((md5_hash, size)) = stored.get(part_number) else {
+            return Ok(CompleteOutcome::InvalidPart(*part_number));
+        };
+        if md5_hash != etag {
+            return Ok(CompleteOutcome::InvalidPart(*part_number));
+        }
+        if i + 1 < manifest.len() && *size < MIN_PART_SIZE {
+            return Ok(CompleteOutcome::EntityTooSmall(*part_number));
+        }
+        part_md5s.push(md5_hash.as_str());
+        object_size += *size as u64;
+    }
+    let etag = multipart_etag(&part_md5s);
+
+    // The parts matched the manifest when assembled, so the body is theirs
+    let usage_size = assembled.map_or(object_size, AssembledObject::usage_size);
+    if let Some(quota) = quota
+        && !quota.allows(&tx, usage_size, object_usage_size(&tx, table_name, key)?)?
+    {
+        return Ok(CompleteOutcome::QuotaExceeded);
+    }
+
+    // Parts uploaded but left out of the manifest are discarded
+    let listed: Vec<String> = manifest.iter().map(|(n, _)| n.to_string()).collect();
+    tx.execute(
+        &format!(
+            "DELETE FROM multipart_parts WHERE upload_id = ?1 AND part_number NOT IN ({})",
+            listed.join(",")
+        ),
+        params![upload_id],
+    )?;
+
+    let on_conflict = if write_once {
+        "DO NOTHING"
+    } else {
+        "DO UPDATE SET data=excluded.data, md5=excluded.md5,
+         storage_class=excluded.storage_class, checksum_algorithm=NULL, checksum=NULL,
+         acl=NULL, content_encoding=NULL, content_sha256=NULL, expires_at=NULL,
+         compression=excluded.compression, original_size=excluded.original_size,
+         blob_hash=excluded.blob_hash"
+    };
+    let written = match assembled {
+        Some(object) => {
+            // A deduplicated object keeps no data in its row but its size, as in uploads
+            let (data, original_size) = match &object.blob_hash {
+                Some(hash) => {
+                    store_blob(&tx, hash, &object.data)?;
+                    (&[][..], Some(object.original_size()))
+                }
+                None => (&object.data[..], object.compression.map(|(_, size)| size)),
+            };
+            let sql = format!(
+                "INSERT INTO {table_name}
+                 (key, data, md5, storage_class, compression, original_size, blob_hash)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+                 ON CONFLICT(key) {on_conflict}",
+            );
+            let written = tx.execute(
+                &sql,
+                params![
+                    key,
+                    data,
+                    etag,
+                    storage_class,
+                    object
+                        .compression
+                        .map(|(compression, _)| compression.name()),
+                    original_size.map(|size| size as i64),
+                    object.blob_hash,
+                ],
+            )?;
+            if written == 0
+                && let Some(hash) = &object.blob_hash
+            {
+                delete_unreferenced_blob(&tx, hash)?;
+            }
+            written
+        }
+        None => {
+            let sql = format!(
+                "INSERT INTO {table_name} (key, data, md5, storage_class)
+                 SELECT ?1, CAST(group_concat(data, '' ORDER BY part_number) AS BLOB), ?2, ?3
+                 FROM multipart_parts WHERE upload_id = ?4
+                 ON CONFLICT(key) {on_conflict}",
+            );
+            tx.execute(&sql, params![key, etag, storage_class, upload_id])?
+        }
+    };
+    if written == 0 {
+        return Ok(CompleteOutcome::Immutable);
+    }
+
+    tx.execute(
+        "DELETE FROM multipart_parts WHERE upload_id = ?1",
+        params![upload_id],
+    )?;
+    tx.execute(
+        "DELETE FROM multipart_uploads WHERE upload_id = ?1",
+        params![upload_id],
+    )?;
+    tx.commit()?;
+
+    Ok(CompleteOutcome::Completed { etag })
+}
@@ -0,0 +1,180 @@
+use axum::body::Bytes;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::utils::checksum::ObjectChecksum;
+
+/// An object held in memory, with what GET and HEAD report about it
+#[derive(Debug)]
+pub struct CachedObject {
+    pub data: Bytes,
+    pub md5_hash: String,
+    pub last_modified: i64,
+    pub storage_class: String,
+    pub checksum: Option<ObjectChecksum>,
+    pub content_encoding: Option<String>,
+    /// Seconds since the epoch after which the object is gone, None if it never expires
+    pub expires_at: Option<i64>,
+}
+
+/// Hit and miss counts and the current size of the cache
+#[derive(Debug, Clone, Copy)]
+pub struct ObjectCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+    pub bytes: usize,
+}
+
+/// Least recently used cache of small objects, keyed by bucket and key.
+/// Writes invalidate the entries of the objects they change. A read that missed only
+/// fills the cache if no invalidation happened since it started, so an object read
+/// before a concurrent write commits is never cached after that write.
+pub struct ObjectCache {
+    max_bytes: usize,
+    max_object_size: usize,
+    inner: Mutex<CacheEntries>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+#[derive(Default)]
+struct CacheEntries {
+    objects: HashMap<(String, String), (Arc<CachedObject>, u64)>,
+    /// Keys by last use, the least recently used first
+    recency: BTreeMap<u64, (String, String)>,
+    last_use: u64,
+    bytes: usize,
+    /// Number of invalidations so far
+    generation: u64,
+}
+
+impl CacheEntries {
+    fn remove(&mut self, id: &(String, String)) {
+        if let Some((object, used)) = self.objects.remove(id) {
+            self.recency.remove(&used);
+            self.bytes -= object.data.len();
+        }
+    }
+}
+
+impl ObjectCache {
+    pub fn new(max_bytes: usize, max_object_size: usize) -> Self {
+        Self {
+            max_bytes,
+            max_object_size: max_object_size.min(max_bytes),
+            inner: Mutex::new(CacheEntries::default()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// The cached object, None if it is not cached or has expired
+    pub fn get(&self, bucket: &str, key: &str, now: i64) -> Option<Arc<CachedObject>> {
+        let mut inner = self.inner.lock().unwrap();
+        let id = (bucket.to_string(), key.to_string());
+        let found = match inner.objects.get(&id) {
+            Some((object, _)) if object.expires_at.is_some_and(|t| t <= now) => {
+                inner.remove(&id);
+                None
+            }
+            Some((object, used)) => Some((object.clone(), *used)),
+            None => None,
+        };
+        match found {
+            Some((object, used)) => {
+                inner.last_use += 1;
+                let last_use = inner.last_use;
+                inner.recency.remove(&used);
+                inner.recency.insert(last_use, id.clone());
+                if let Some(entry) = inner.objects.get_mut(&id) {
+                    entry.1 = last_use;
+                }
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                Some(object)
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    /// Current generation, to pass to `insert` for an object read after this call
+    pub fn generation(&self) -> u64 {
+        self.inner.lock().unwrap().generation
+    }
+
+    /// Whether an object of this size may be cached
+    pub fn admits(&self, size: usize) -> bool {
+        size <= self.max_object_size
+    }
+
+    /// Cache an object read from the database, unless it is too large or an
+    /// invalidation happened since `generation` was taken
+    pub fn insert(&self, bucket: &str, key: &str, object: CachedObject, generation: u64) {
+        if !self.admits(object.data.len()) {
+            return;
+        }
+        let mut inner = self.inner.lock().unwrap();
+        if inner.generation != generation {
+            return;
+        }
+        let id = (bucket.to_string(), key.to_string());
+        inner.remove(&id);
+        while inner.bytes + object.data.len() > self.max_bytes {
+            let Some((_, oldest)) = inner.recency.pop_first() else {
+                break;
+            };
+            inner.remove(&oldest);
+        }
+        inner.last_use += 1;
+        let last_use = inner.last_use;
+        inner.bytes += object.data.len();
+        inner.recency.insert(last_use, id.clone());
+        inner.objects.insert(id, (Arc::new(object), last_use));
+    }
+
+    /// Forget an object, called once a write that changes it is committed
+    pub fn invalidate(&self, bucket: &str, key: &str) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.generation += 1;
+        inner.remove(&(bucket.to_string(), key.to_string()));
+    }
+
+    /// Forget every object of a bucket
+    pub fn invalidate_bucket(&self, bucket: &str) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.generation += 1;
+        let ids: Vec<(String, String)> = inner
+            .objects
+            .keys()
+            .filter(|(b, _)| b == bucket)
+            .cloned()
+            .collect();
+        for id in ids {
+            inner.remove(&id);
+        }
+    }
+
+    /// Forget every object
+    pub fn clear(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        let generation = inner.generation + 1;
+        *inner = CacheEntries {
+            generation,
+            ..CacheEntries::default()
+        };
+    }
+
+    pub fn stats(&self) -> ObjectCacheStats {
+        let inner = self.inner.lock().unwrap();
+        ObjectCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: inner.objects.len(),
+            bytes: inner.bytes,
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use serde_json::Value;
+
+use crate::utils::keys::glob_match;
+
+/// Actions a policy statement may grant. Anonymous read is the only supported use.
+const POLICY_ACTIONS: &[&str] = &["s3:GetObject"];
+
+/// The supported subset of a bucket policy: statements granting `s3:GetObject`
+/// to everyone (`"Principal": "*"`) on objects of the bucket
+#[derive(Debug, Clone, PartialEq)]
+pub struct BucketPolicy {
+    /// Object key patterns readable without credentials, `*` matching any run of characters
+    pub public_read_keys: Vec<String>,
+}
+
+impl BucketPolicy {
+    /// Whether the policy makes any object of the bucket public
+    pub fn is_public(&self) -> bool {
+        !self.public_read_keys.is_empty()
+    }
+
+    /// Whether the policy lets anyone read the object `key`
+    pub fn allows_public_read(&self, key: &str) -> bool {
+        self.public_read_keys
+            .iter()
+            .any(|pattern| glob_match(pattern, key))
+    }
+}
+
+/// A JSON value that is either a single string or an array of strings
+fn string_or_array(value: &Value) -> Option<Vec<&str>> {
+    match value {
+        Value::String(s) => Some(vec![s.as_str()]),
+        Value::Array(values) if !values.is_empty() => values.iter().map(Value::as_str).collect(),
+        _ => None,
+    }
+}
+
+/// Parse a bucket policy document of `bucket`, accepting only statements that can
+/// be enforced. Returns the reason the policy is refused as the error.
+pub fn parse_bucket_policy(json: &str, bucket: &str) -> Result<BucketPolicy, String> {
+    let document: Value =
+        serde_json::from_str(json).map_err(|e| format!("Policies must be valid JSON: {e}"))?;
+    let statements = match document.get("Statement") {
+        Some(Value::Array(statements)) if !statements.is_empty() => statements.clone(),
+        Some(statement @ Value::Object(_)) => vec![statement.clone()],
+        _ => return Err("Missing required field Statement".to_string()),
+    };
+
+    let object_arn = format!("arn:aws:s3:::{bucket}/");
+    let mut public_read_keys = Vec::new();
+    for statement in &statements {
+        let Some(statement) = statement.as_object() else {
+            return Err("Statements must be JSON objects".to_string());
+        };
+        if let Some(field) = statement.keys().find(|k| {
+            !matches!(
+                k.as_str(),
+                "Sid" | "Effect" | "Principal" | "Action" | "Resource"
+            )
+        }) {
+            return Err(format!("Unsupported policy field: {field}"));
+        }
+        if statement.get("Effect").and_then(Value::as_str) != Some("Allow") {
+            return Err("Only statements with \"Effect\": \"Allow\" are supported".to_string());
+        }
+
+        let principal_is_everyone = match statement.get("Principal") {
+            Some(Value::String(p)) => p == "*",
+            Some(Value::Object(p)) => {
+                p.len() == 1 && p.get("AWS").and_then(string_or_array) == Some(vec!["*"])
+            }
+            _ => false,
+        };
+        if !principal_is_everyone {
+            return Err("Only the \"*\" principal is supported".to_string());
+        }
+
+        let actions = statement
+            .get("Action")
+            .and_then(string_or_array)
+            .ok_or("Missing required field Action")?;
+        if let Some(action) = actions.iter().find(|a| !POLICY_ACTIONS.contains(a)) {
+            return Err(format!("Unsupported action: {action}"));
+        }
+
+        let resources = statement
+            .get("Resource")
+            .and_then(string_or_array)
+            .ok_or("Missing required field Resource")?;
+        for resource in resources {
+            match resource.strip_prefix(&object_arn) {
+                Some(pattern) if !pattern.is_empty() => public_read_keys.push(pattern.to_string()),
+                _ => return Err(format!("Policy has invalid resource: {resource}")),
+            }
+        }
+    }
+    Ok(BucketPolicy { public_read_keys })
+}
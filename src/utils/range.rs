@@ -0,0 +1,84 @@
+/// A `Range` request header resolved against the size of an object
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteRange {
+    /// No usable range: the whole object is returned with 200
+    Full,
+    /// Inclusive byte positions, both within the object
+    Partial { start: u64, end: u64 },
+    /// No byte of the object is selected, answered with 416
+    Unsatisfiable,
+}
+
+impl ByteRange {
+    /// Number of bytes sent for this range of an object of `size` bytes
+    pub fn len(&self, size: u64) -> u64 {
+        match *self {
+            ByteRange::Full => size,
+            ByteRange::Partial { start, end } => end - start + 1,
+            ByteRange::Unsatisfiable => 0,
+        }
+    }
+
+    /// Offset of the first byte sent
+    pub fn start(&self) -> u64 {
+        match *self {
+            ByteRange::Partial { start, .. } => start,
+            _ => 0,
+        }
+    }
+
+    /// `Content-Range` header of the response: `bytes start-end/size` for a partial
+    /// response, `bytes */size` for an unsatisfiable one
+    pub fn content_range(&self, size: u64) -> Option<String> {
+        match *self {
+            ByteRange::Full => None,
+            ByteRange::Partial { start, end } => Some(format!("bytes {start}-{end}/{size}")),
+            ByteRange::Unsatisfiable => Some(format!("bytes */{size}")),
+        }
+    }
+}
+
+/// Resolve a `Range` header against an object of `size` bytes. Supports the forms
+/// `bytes=start-end` (end clamped to the last byte), `bytes=start-` and `bytes=-suffix`
+/// (the last `suffix` bytes). As in S3, multiple ranges and malformed values are
+/// ignored and the whole object is returned.
+pub fn resolve_range(value: &str, size: u64) -> ByteRange {
+    let Some(spec) = value.trim().strip_prefix("bytes=") else {
+        return ByteRange::Full;
+    };
+    if spec.contains(',') {
+        return ByteRange::Full;
+    }
+    let Some((first, last)) = spec.split_once('-') else {
+        return ByteRange::Full;
+    };
+
+    match (first.trim(), last.trim()) {
+        ("", suffix) => match suffix.parse::<u64>() {
+            Ok(0) => ByteRange::Unsatisfiable,
+            Ok(_) if size == 0 => ByteRange::Unsatisfiable,
+            Ok(suffix) => ByteRange::Partial {
+                start: size.saturating_sub(suffix),
+                end: size - 1,
+            },
+            Err(_) => ByteRange::Full,
+        },
+        (start, "") => match start.parse::<u64>() {
+            Ok(start) if start >= size => ByteRange::Unsatisfiable,
+            Ok(start) => ByteRange::Partial {
+                start,
+                end: size - 1,
+            },
+            Err(_) => ByteRange::Full,
+        },
+        (start, end) => match (start.parse::<u64>(), end.parse::<u64>()) {
+            (Ok(start), Ok(end)) if start > end => ByteRange::Full,
+            (Ok(start), Ok(_)) if start >= size => ByteRange::Unsatisfiable,
+            (Ok(start), Ok(end)) => ByteRange::Partial {
+                start,
+                end: end.min(size - 1),
+            },
+            _ => ByteRange::Full,
+        },
+    }
+}
@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::models::{AppConfig, RateLimit};
+
+/// Clients tracked before idle ones, whose buckets have refilled, are forgotten
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+/// A token bucket refilled at `rate` tokens per second up to `capacity`.
+/// The balance may go negative when more is charged than it holds.
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    capacity: f64,
+    rate: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: f64, capacity: f64, now: Instant) -> Self {
+        Self {
+            tokens: capacity,
+            capacity,
+            rate,
+            updated: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.updated = now;
+    }
+
+    /// Time until the balance reaches `needed` tokens
+    fn wait_for(&self, needed: f64) -> Duration {
+        Duration::from_secs_f64(((needed - self.tokens) / self.rate).max(0.0))
+    }
+
+    fn is_full(&self) -> bool {
+        self.tokens >= self.capacity
+    }
+}
+
+/// The request and byte buckets of one client in one scope
+#[derive(Debug)]
+struct ClientBuckets {
+    requests: Option<TokenBucket>,
+    bytes: Option<TokenBucket>,
+}
+
+impl ClientBuckets {
+    fn new(limit: &RateLimit, now: Instant) -> Self {
+        Self {
+            requests: limit
+                .requests_per_second
+                .map(|rate| TokenBucket::new(rate, limit.burst.unwrap_or(rate).max(1.0), now)),
+            bytes: limit
+                .bytes_per_second
+                .map(|rate| TokenBucket::new(rate, limit.bytes_burst.unwrap_or(rate), now)),
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        self.requests.iter_mut().for_each(|b| b.refill(now));
+        self.bytes.iter_mut().for_each(|b| b.refill(now));
+    }
+
+    fn is_full(&self) -> bool {
+        self.requests
+            .iter()
+            .chain(&self.bytes)
+            .all(TokenBucket::is_full)
+    }
+}
+
+/// Token bucket rate limiting per client, with the `[rate_limit]` limits or the
+/// `[bucket_rate_limits.<bucket>]` ones of the bucket requested.
+/// Requests cost one token; bytes are charged as they are known, the request body
+/// up front and the response body afterwards, and new requests wait while the
+/// byte balance is negative.
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    global: Option<RateLimit>,
+    per_bucket: HashMap<String, RateLimit>,
+    /// Buckets by client and scope, the bucket name or "" for the global limit
+    clients: Mutex<HashMap<(String, String), ClientBuckets>>,
+}
+
+impl RateLimiter {
+    /// Read the configured limits, failing on rates or bursts that are not positive
+    pub fn from_config(config: &AppConfig) -> Result<Self, String> {
+        let global = config.get_rate_limit().cloned();
+        if let Some(limit) = &global {
+            limit.validate().map_err(|e| format!("rate_limit: {e}"))?;
+        }
+        let mut per_bucket = HashMap::new();
+        for (bucket, limit) in config.get_bucket_rate_limits() {
+            limit
+                .validate()
+                .map_err(|e| format!("bucket_rate_limits.{bucket}: {e}"))?;
+            per_bucket.insert(bucket.clone(), limit.clone());
+        }
+        Ok(Self {
+            global,
+            per_bucket,
+            clients: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.global.is_some() || !self.per_bucket.is_empty()
+    }
+
+    /// The limit applying to a bucket and the scope its buckets are kept under
+    fn limit_for<'a>(&'a self, bucket: &'a str) -> Option<(&'a str, &'a RateLimit)> {
+        match self.per_bucket.get(bucket) {
+            Some(limit) => Some((bucket, limit)),
+            None => self.global.as_ref().map(|limit| ("", limit)),
+        }
+    }
+
+    /// Take a request and its `request_bytes` from the client's buckets, or return
+    /// how long to wait before retrying without taking anything
+    pub fn acquire(&self, client: &str, bucket: &str, request_bytes: u64) -> Result<(), Duration> {
+        let Some((scope, limit)) = self.limit_for(bucket) else {
+            return Ok(());
+        };
+        let now = Instant::now();
+        let mut clients = self.clients.lock().unwrap();
+        if clients.len() >= MAX_TRACKED_CLIENTS {
+            clients.retain(|_, buckets| {
+                buckets.refill(now);
+                !buckets.is_full()
+            });
+        }
+        let buckets = clients
+            .entry((client.to_string(), scope.to_string()))
+            .or_insert_with(|| ClientBuckets::new(limit, now));
+        buckets.refill(now);
+
+        let wait = [
+            buckets.requests.as_ref().map(|b| b.wait_for(1.0)),
+            // Any positive byte balance admits a request, whatever its size
+            buckets
+                .bytes
+                .as_ref()
+                .map(|b| b.wait_for(f64::MIN_POSITIVE)),
+        ]
+        .into_iter()
+        .flatten()
+        .max()
+        .unwrap_or_default();
+        if !wait.is_zero() {
+            return Err(wait);
+        }
+        if let Some(requests) = &mut buckets.requests {
+            requests.tokens -= 1.0;
+        }
+        if let Some(bytes) = &mut buckets.bytes {
+            bytes.tokens -= request_bytes as f64;
+        }
+        Ok(())
+    }
+
+    /// Charge the bytes of a response to the client's byte bucket
+    pub fn charge_bytes(&self, client: &str, bucket: &str, bytes: u64) {
+        let Some((scope, _)) = self.limit_for(bucket) else {
+            return;
+        };
+        let mut clients = self.clients.lock().unwrap();
+        if let Some(bucket) = clients
+            .get_mut(&(client.to_string(), scope.to_string()))
+            .and_then(|buckets| buckets.bytes.as_mut())
+        {
+            bucket.tokens -= bytes as f64;
+        }
+    }
+}
@@ -0,0 +1,39 @@
+use std::sync::LazyLock;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+tokio::task_local! {
+    /// Id of the request being served by the current task
+    static REQUEST_ID: String;
+}
+
+/// Monotonic request counter
+static REQUEST_COUNTER: AtomicU64 = AtomicU64::new(0);
+/// Start time of this process, so ids are not reused across restarts
+static INSTANCE_PREFIX: LazyLock<u32> = LazyLock::new(|| {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as u32)
+        .unwrap_or_default()
+});
+
+/// Id attached to a request, available to handlers through the request extensions
+#[derive(Clone, Debug)]
+pub struct RequestId(pub String);
+
+/// Generate a unique request id: the process start time followed by a counter, in hex
+pub fn next_request_id() -> String {
+    let counter = REQUEST_COUNTER.fetch_add(1, Ordering::Relaxed);
+    format!("{:08X}{:08X}", *INSTANCE_PREFIX, counter)
+}
+
+/// Run a future with `request_id` as the current request id
+pub async fn with_request_id<F: Future>(request_id: String, future: F) -> F::Output {
+    REQUEST_ID.scope(request_id, future).await
+}
+
+/// Id of the request being served, or None outside of a request
+/// (e.g. background tasks and blocking threads)
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(|id| id.clone()).ok()
+}
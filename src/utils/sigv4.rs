@@ -0,0 +1,256 @@
+use axum::http::HeaderMap;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+use crate::utils::{percent_decode, percent_encode, percent_encode_path};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Algorithm name of AWS Signature Version 4
+pub const ALGORITHM: &str = "AWS4-HMAC-SHA256";
+/// Query parameter holding the signature of a presigned URL, left out of the canonical query
+pub const SIGNATURE_PARAM: &str = "X-Amz-Signature";
+/// Longest validity of a presigned URL: seven days
+const MAX_PRESIGNED_EXPIRES: u64 = 7 * 24 * 3600;
+
+/// The parts of an `Authorization: AWS4-HMAC-SHA256 Credential=..., SignedHeaders=...,
+/// Signature=...` header
+#[derive(Debug, Clone)]
+pub struct SignatureV4 {
+    pub access_key_id: String,
+    /// Credential scope after the access key: `date/region/service/aws4_request`
+    pub scope: String,
+    pub date: String,
+    pub region: String,
+    pub service: String,
+    /// Lowercase names of the signed headers, in the order they were signed
+    pub signed_headers: Vec<String>,
+    pub signature: String,
+}
+
+/// Parse the value of a SigV4 `Authorization` header.
+/// Returns a message for an `AuthorizationHeaderMalformed` error if it is incomplete.
+pub fn parse_authorization(value: &str) -> Result<SignatureV4, String> {
+    let fields = value
+        .strip_prefix(ALGORITHM)
+        .ok_or_else(|| format!("Unsupported authorization algorithm, expected {ALGORITHM}"))?;
+
+    let (mut credential, mut signed_headers, mut signature) = (None, None, None);
+    for field in fields.split(',') {
+        match field.trim().split_once('=') {
+            Some(("Credential", v)) => credential = Some(v),
+            Some(("SignedHeaders", v)) => signed_headers = Some(v),
+            Some(("Signature", v)) => signature = Some(v),
+            _ => {}
+        }
+    }
+    let (Some(credential), Some(signed_headers), Some(signature)) =
+        (credential, signed_headers, signature)
+    else {
+        return Err(
+            "The authorization header requires Credential, SignedHeaders and Signature".to_string(),
+        );
+    };
+    parse_signature(credential, signed_headers, signature)
+}
+
+/// Parse the query parameters of a presigned URL: `X-Amz-Algorithm`, `X-Amz-Credential`,
+/// `X-Amz-Date`, `X-Amz-Expires`, `X-Amz-SignedHeaders` and `X-Amz-Signature`.
+/// Returns a message for an `AuthorizationQueryParametersError` if one is missing or invalid.
+pub fn parse_presigned_query(query: &HashMap<String, String>) -> Result<PresignedQuery, String> {
+    let param = |name: &str| {
+        query
+            .get(name)
+            .map(String::as_str)
+            .ok_or_else(|| format!("Query-string authentication requires the {name} parameter"))
+    };
+    if param("X-Amz-Algorithm")? != ALGORITHM {
+        return Err(format!("X-Amz-Algorithm only supports \"{ALGORITHM}\""));
+    }
+    let timestamp = param("X-Amz-Date")?;
+    let signed_at = NaiveDateTime::parse_from_str(timestamp, "%Y%m%dT%H%M%SZ")
+        .map_err(|_| "X-Amz-Date must be in the ISO8601 Long Format \"yyyyMMdd'T'HHmmss'Z'\"")?;
+    let expires = match param("X-Amz-Expires")?.parse::<u64>() {
+        Ok(expires) if expires <= MAX_PRESIGNED_EXPIRES => expires,
+        Ok(_) => {
+            return Err(format!(
+                "X-Amz-Expires must be less than a week (in seconds) that is; {MAX_PRESIGNED_EXPIRES}"
+            ));
+        }
+        Err(_) => return Err("X-Amz-Expires should be a number".to_string()),
+    };
+    let signature = parse_signature(
+        param("X-Amz-Credential")?,
+        param("X-Amz-SignedHeaders")?,
+        param(SIGNATURE_PARAM)?,
+    )?;
+
+    Ok(PresignedQuery {
+        signature,
+        timestamp: timestamp.to_string(),
+        expires_at: signed_at.and_utc() + chrono::Duration::seconds(expires as i64),
+    })
+}
+
+/// Split a credential into access key and scope and check the scope
+fn parse_signature(
+    credential: &str,
+    signed_headers: &str,
+    signature: &str,
+) -> Result<SignatureV4, String> {
+    let (access_key_id, scope) = credential
+        .split_once('/')
+        .ok_or_else(|| format!("Malformed credential: {credential}"))?;
+    let [date, region, service, terminator] = scope.split('/').collect::<Vec<_>>()[..] else {
+        return Err(format!("Malformed credential scope: {scope}"));
+    };
+    if terminator != "aws4_request" {
+        return Err(format!(
+            "The credential scope must end with aws4_request: {scope}"
+        ));
+    }
+    if service != "s3" {
+        return Err(format!(
+            "The credential scope is for service {service}, expected s3"
+        ));
+    }
+
+    Ok(SignatureV4 {
+        access_key_id: access_key_id.to_string(),
+        scope: scope.to_string(),
+        date: date.to_string(),
+        region: region.to_string(),
+        service: service.to_string(),
+        signed_headers: signed_headers
+            .split(';')
+            .map(|h| h.trim().to_ascii_lowercase())
+            .collect(),
+        signature: signature.trim().to_string(),
+    })
+}
+
+/// The signature of a presigned URL and the end of its validity
+#[derive(Debug, Clone)]
+pub struct PresignedQuery {
+    pub signature: SignatureV4,
+    /// `X-Amz-Date`, in ISO 8601 basic format
+    pub timestamp: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Parse a timestamp in ISO 8601 basic format (`20130524T000000Z`)
+pub fn parse_timestamp(timestamp: &str) -> Option<DateTime<Utc>> {
+    NaiveDateTime::parse_from_str(timestamp, "%Y%m%dT%H%M%SZ")
+        .ok()
+        .map(|t| t.and_utc())
+}
+
+/// Timestamp of a signed request in ISO 8601 basic format (`20130524T000000Z`),
+/// from `x-amz-date` or else the RFC 1123 `Date` header
+pub fn request_timestamp(headers: &HeaderMap) -> Option<String> {
+    if let Some(value) = headers.get("x-amz-date").and_then(|v| v.to_str().ok()) {
+        return parse_timestamp(value).map(|_| value.to_string());
+    }
+    let date = headers.get("Date").and_then(|v| v.to_str().ok())?;
+    DateTime::parse_from_rfc2822(date)
+        .ok()
+        .map(|t| t.with_timezone(&Utc).format("%Y%m%dT%H%M%SZ").to_string())
+}
+
+/// Build the canonical request of SigV4 from the request as received.
+/// The path and query are decoded and encoded again the way S3 clients sign them;
+/// the signature of a presigned URL is not part of it.
+pub fn canonical_request(
+    method: &str,
+    path: &str,
+    query: Option<&str>,
+    headers: &HeaderMap,
+    signed_headers: &[String],
+    payload_hash: &str,
+) -> String {
+    let path = percent_decode(path).unwrap_or_else(|| path.to_string());
+    let canonical_uri = percent_encode_path(&path);
+
+    let mut params: Vec<(String, String)> = query
+        .unwrap_or_default()
+        .split('&')
+        .filter(|p| !p.is_empty())
+        .map(|p| {
+            let (name, value) = p.split_once('=').unwrap_or((p, ""));
+            let encode =
+                |s: &str| percent_encode(&percent_decode(s).unwrap_or_else(|| s.to_string()));
+            (encode(name), encode(value))
+        })
+        .filter(|(name, _)| name != SIGNATURE_PARAM)
+        .collect();
+    params.sort();
+    let canonical_query = params
+        .iter()
+        .map(|(name, value)| format!("{name}={value}"))
+        .collect::<Vec<_>>()
+        .join("&");
+
+    let canonical_headers: String = signed_headers
+        .iter()
+        .map(|name| {
+            let value = headers
+                .get_all(name.as_str())
+                .iter()
+                .map(|v| {
+                    String::from_utf8_lossy(v.as_bytes())
+                        .split_whitespace()
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                })
+                .collect::<Vec<_>>()
+                .join(",");
+            format!("{name}:{value}\n")
+        })
+        .collect();
+
+    format!(
+        "{method}\n{canonical_uri}\n{canonical_query}\n{canonical_headers}\n{}\n{payload_hash}",
+        signed_headers.join(";")
+    )
+}
+
+/// The string signed by the client: algorithm, timestamp, scope and canonical request hash
+pub fn string_to_sign(timestamp: &str, scope: &str, canonical_request: &str) -> String {
+    format!(
+        "{ALGORITHM}\n{timestamp}\n{scope}\n{}",
+        hex::encode(Sha256::digest(canonical_request.as_bytes()))
+    )
+}
+
+fn hmac_sha256(key: &[u8], data: &str) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data.as_bytes());
+    mac
+}
+
+/// Check a signature against the one derived from the secret key, in constant time
+pub fn signature_matches(
+    secret_access_key: &str,
+    signature: &SignatureV4,
+    string_to_sign: &str,
+) -> bool {
+    let Ok(provided) = hex::decode(&signature.signature) else {
+        return false;
+    };
+    let key = hmac_sha256(
+        format!("AWS4{secret_access_key}").as_bytes(),
+        &signature.date,
+    )
+    .finalize()
+    .into_bytes();
+    let key = hmac_sha256(&key, &signature.region).finalize().into_bytes();
+    let key = hmac_sha256(&key, &signature.service)
+        .finalize()
+        .into_bytes();
+    let key = hmac_sha256(&key, "aws4_request").finalize().into_bytes();
+    hmac_sha256(&key, string_to_sign)
+        .verify_slice(&provided)
+        .is_ok()
+}
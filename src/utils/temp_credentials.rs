@@ -0,0 +1,162 @@
+use chrono::{DateTime, Utc};
+use log::{error, info};
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::{Connection, OptionalExtension, params};
+use std::time::Duration;
+
+use crate::models::{BucketPermission, Secret};
+use crate::utils::run_blocking;
+
+/// Table of the temporary credentials minted with POST /?temp-credentials.
+/// The leading underscore keeps it out of the bucket table namespace.
+const TEMP_CREDENTIALS_TABLE: &str = "_temp_credentials";
+
+/// Short-lived credentials limited to the keys under a prefix of one bucket.
+/// Requests signed with them must carry the session token in
+/// `x-amz-security-token` (or `X-Amz-Security-Token` when presigned).
+#[derive(Debug, Clone)]
+pub struct TempCredentials {
+    pub access_key_id: String,
+    pub secret: Secret,
+    pub session_token: Secret,
+    pub bucket: String,
+    pub prefix: String,
+    pub permission: BucketPermission,
+    pub expires_at: DateTime<Utc>,
+    /// Access key that minted the credentials
+    pub issued_by: Option<String>,
+}
+
+impl TempCredentials {
+    /// Whether an object key is within the scope of the credentials
+    pub fn covers(&self, bucket: &str, key: &str) -> bool {
+        bucket == self.bucket && key.starts_with(&self.prefix)
+    }
+}
+
+/// Ensures the temporary credentials table exists
+pub fn ensure_temp_credentials_table(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(&format!(
+        "CREATE TABLE IF NOT EXISTS {TEMP_CREDENTIALS_TABLE} (
+            access_key_id TEXT NOT NULL PRIMARY KEY,
+            secret_access_key TEXT NOT NULL,
+            session_token TEXT NOT NULL,
+            bucket TEXT NOT NULL,
+            prefix TEXT NOT NULL,
+            writes INTEGER NOT NULL,
+            expires_at INTEGER NOT NULL,
+            issued_by TEXT
+        );
+        CREATE INDEX IF NOT EXISTS idx_{TEMP_CREDENTIALS_TABLE}_expires_at
+            ON {TEMP_CREDENTIALS_TABLE} (expires_at);"
+    ))
+}
+
+/// Store new temporary credentials. The key, secret and token come from SQLite's
+/// `randomblob`, which draws on the operating system's entropy.
+pub fn mint_temp_credentials(
+    conn: &Connection,
+    bucket: &str,
+    prefix: &str,
+    permission: BucketPermission,
+    expires_at: DateTime<Utc>,
+    issued_by: Option<&str>,
+) -> rusqlite::Result<TempCredentials> {
+    let (access_key_id, secret, session_token): (String, String, String) = conn.query_row(
+        "SELECT 'ASIA' || upper(hex(randomblob(8))), lower(hex(randomblob(20))),
+                lower(hex(randomblob(32)))",
+        [],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    )?;
+    conn.execute(
+        &format!(
+            "INSERT INTO {TEMP_CREDENTIALS_TABLE}
+             (access_key_id, secret_access_key, session_token, bucket, prefix, writes, expires_at, issued_by)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)"
+        ),
+        params![
+            access_key_id,
+            secret,
+            session_token,
+            bucket,
+            prefix,
+            permission == BucketPermission::ReadWrite,
+            expires_at.timestamp(),
+            issued_by,
+        ],
+    )?;
+    Ok(TempCredentials {
+        access_key_id,
+        secret: Secret::from(secret),
+        session_token: Secret::from(session_token),
+        bucket: bucket.to_string(),
+        prefix: prefix.to_string(),
+        permission,
+        expires_at,
+        issued_by: issued_by.map(str::to_string),
+    })
+}
+
+/// The temporary credentials of an access key, expired or not; None if unknown
+pub fn lookup_temp_credentials(
+    conn: &Connection,
+    access_key_id: &str,
+) -> rusqlite::Result<Option<TempCredentials>> {
+    conn.query_row(
+        &format!(
+            "SELECT secret_access_key, session_token, bucket, prefix, writes, expires_at, issued_by
+             FROM {TEMP_CREDENTIALS_TABLE} WHERE access_key_id = ?1"
+        ),
+        params![access_key_id],
+        |row| {
+            Ok(TempCredentials {
+                access_key_id: access_key_id.to_string(),
+                secret: Secret::from(row.get::<_, String>(0)?),
+                session_token: Secret::from(row.get::<_, String>(1)?),
+                bucket: row.get(2)?,
+                prefix: row.get(3)?,
+                permission: if row.get(4)? {
+                    BucketPermission::ReadWrite
+                } else {
+                    BucketPermission::ReadOnly
+                },
+                expires_at: DateTime::from_timestamp(row.get(5)?, 0).unwrap_or_default(),
+                issued_by: row.get(6)?,
+            })
+        },
+    )
+    .optional()
+}
+
+/// Delete the temporary credentials expired by now, returning how many were deleted
+pub fn delete_expired_temp_credentials(conn: &Connection) -> rusqlite::Result<usize> {
+    conn.execute(
+        &format!("DELETE FROM {TEMP_CREDENTIALS_TABLE} WHERE expires_at <= ?1"),
+        params![Utc::now().timestamp()],
+    )
+}
+
+/// Schedule periodic deletion of expired temporary credentials in a background task
+pub fn schedule_temp_credentials_cleanup(pool: Pool<SqliteConnectionManager>, interval: Duration) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(interval);
+
+        loop {
+            interval.tick().await;
+            let conn = match run_blocking(|| pool.get()) {
+                Ok(conn) => conn,
+                Err(e) => {
+                    error!("Temporary credentials cleanup skipped, no database connection: {e}");
+                    continue;
+                }
+            };
+
+            match run_blocking(|| delete_expired_temp_credentials(&conn)) {
+                Ok(0) => {}
+                Ok(deleted) => info!("Deleted {deleted} expired temporary credentials"),
+                Err(e) => error!("Temporary credentials cleanup failed: {e}"),
+            }
+        }
+    });
+}
@@ -0,0 +1,51 @@
+/// Text content of the first `<tag>...</tag>` element in `xml`.
+/// This is enough for the small, flat request documents S3 clients send.
+pub fn element_text<'a>(xml: &'a str, tag: &str) -> Option<&'a str> {
+    let open = format!("<{tag}>");
+    let close = format!("</{tag}>");
+    let start = xml.find(&open)? + open.len();
+    let end = start + xml[start..].find(&close)?;
+    Some(xml[start..end].trim())
+}
+
+/// Text content of every `<tag>...</tag>` element in `xml`, in document order
+pub fn element_texts<'a>(xml: &'a str, tag: &str) -> Vec<&'a str> {
+    let open = format!("<{tag}>");
+    let close = format!("</{tag}>");
+    let mut texts = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find(&open) {
+        let start = start + open.len();
+        let Some(len) = rest[start..].find(&close) else {
+            break;
+        };
+        texts.push(rest[start..start + len].trim());
+        rest = &rest[start + len + close.len()..];
+    }
+    texts
+}
+
+/// Name and content of each top-level element of `xml`, in document order.
+/// Self-closing elements have an empty content. None if an element is not closed.
+pub fn child_elements(xml: &str) -> Option<Vec<(&str, &str)>> {
+    let mut children = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find('<') {
+        let tag_end = start + rest[start..].find('>')?;
+        let tag = &rest[start + 1..tag_end];
+        if let Some(name) = tag.strip_suffix('/') {
+            children.push((name.trim(), ""));
+            rest = &rest[tag_end + 1..];
+            continue;
+        }
+        let name = tag.split_whitespace().next()?;
+        if name.starts_with(['/', '?', '!']) {
+            return None;
+        }
+        let close = format!("</{name}>");
+        let content_end = tag_end + 1 + rest[tag_end + 1..].find(&close)?;
+        children.push((name, rest[tag_end + 1..content_end].trim()));
+        rest = &rest[content_end + close.len()..];
+    }
+    Some(children)
+}
@@ -1,3 +1,5 @@
+#![allow(dead_code)]
+
 pub fn read_config() -> (String, String) {
     use std::fs;
     let config_content =
@@ -16,3 +18,240 @@ pub fn read_config() -> (String, String) {
         .to_string();
     (endpoint, bucket)
 }
+
+/// Access key of the first `[[credentials]]` entry in tests/config.toml
+pub const ACCESS_KEY_ID: &str = "minioadmin";
+pub const SECRET_ACCESS_KEY: &str = "minioadmin";
+
+/// Sign a raw HTTP/1.1 request with AWS Signature Version 4, adding `x-amz-date`,
+/// `x-amz-content-sha256: UNSIGNED-PAYLOAD` and `Authorization` after the request line.
+/// Requests that already carry an `Authorization` header are returned unchanged.
+pub fn sign_request(request: &str) -> String {
+    sign_request_as(request, ACCESS_KEY_ID, SECRET_ACCESS_KEY)
+}
+
+/// Sign a raw request like `sign_request`, with another access key
+pub fn sign_request_as(request: &str, access_key_id: &str, secret_access_key: &str) -> String {
+    sign_request_with(
+        request,
+        access_key_id,
+        secret_access_key,
+        "UNSIGNED-PAYLOAD",
+        chrono::Utc::now(),
+    )
+}
+
+/// Sign a raw request like `sign_request`, as if at `signed_at`
+pub fn sign_request_at(request: &str, signed_at: chrono::DateTime<chrono::Utc>) -> String {
+    sign_request_with(
+        request,
+        ACCESS_KEY_ID,
+        SECRET_ACCESS_KEY,
+        "UNSIGNED-PAYLOAD",
+        signed_at,
+    )
+}
+
+/// Sign a raw request like `sign_request`, declaring `payload_hash` in x-amz-content-sha256
+pub fn sign_request_with_payload_hash(request: &str, payload_hash: &str) -> String {
+    sign_request_with(
+        request,
+        ACCESS_KEY_ID,
+        SECRET_ACCESS_KEY,
+        payload_hash,
+        chrono::Utc::now(),
+    )
+}
+
+fn sign_request_with(
+    request: &str,
+    access_key_id: &str,
+    secret_access_key: &str,
+    payload_hash: &str,
+    signed_at: chrono::DateTime<chrono::Utc>,
+) -> String {
+    use hmac::{Hmac, Mac};
+    use sha2::{Digest, Sha256};
+
+    let (head, _) = request.split_once("\r\n\r\n").unwrap_or((request, ""));
+    let mut lines = head.split("\r\n");
+    let request_line = lines.next().expect("missing request line");
+    let headers: Vec<(String, &str)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim()))
+        .collect();
+    if headers.iter().any(|(name, _)| name == "authorization") {
+        return request.to_string();
+    }
+    let host = headers
+        .iter()
+        .find(|(name, _)| name == "host")
+        .map(|(_, value)| *value)
+        .expect("missing Host header");
+
+    let mut parts = request_line.split(' ');
+    let (method, target) = (parts.next().unwrap(), parts.next().unwrap());
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let mut params: Vec<(String, String)> = query
+        .split('&')
+        .filter(|p| !p.is_empty())
+        .map(|p| {
+            let (name, value) = p.split_once('=').unwrap_or((p, ""));
+            (
+                uri_encode(&uri_decode(name), false),
+                uri_encode(&uri_decode(value), false),
+            )
+        })
+        .collect();
+    params.sort();
+    let canonical_query = params
+        .iter()
+        .map(|(name, value)| format!("{name}={value}"))
+        .collect::<Vec<_>>()
+        .join("&");
+
+    let timestamp = signed_at.format("%Y%m%dT%H%M%SZ").to_string();
+    let scope = format!("{}/us-east-1/s3/aws4_request", &timestamp[..8]);
+    let canonical_request = format!(
+        "{method}\n{}\n{canonical_query}\nhost:{host}\nx-amz-content-sha256:{payload_hash}\n\
+         x-amz-date:{timestamp}\n\nhost;x-amz-content-sha256;x-amz-date\n{payload_hash}",
+        uri_encode(&uri_decode(path), true)
+    );
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{timestamp}\n{scope}\n{}",
+        hex::encode(Sha256::digest(canonical_request.as_bytes()))
+    );
+
+    let hmac = |key: &[u8], data: &str| {
+        let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+        mac.update(data.as_bytes());
+        mac.finalize().into_bytes().to_vec()
+    };
+    let key = hmac(
+        format!("AWS4{secret_access_key}").as_bytes(),
+        &timestamp[..8],
+    );
+    let key = hmac(&key, "us-east-1");
+    let key = hmac(&key, "s3");
+    let key = hmac(&key, "aws4_request");
+    let signature = hex::encode(hmac(&key, &string_to_sign));
+
+    let (request_line, rest) = request.split_once("\r\n").unwrap();
+    format!(
+        "{request_line}\r\nx-amz-date: {timestamp}\r\nx-amz-content-sha256: {payload_hash}\r\n\
+         Authorization: AWS4-HMAC-SHA256 Credential={access_key_id}/{scope}, \
+         SignedHeaders=host;x-amz-content-sha256;x-amz-date, Signature={signature}\r\n{rest}"
+    )
+}
+
+/// Decode percent escapes, keeping the string as it is if one is malformed, as the server does
+fn uri_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            match s.get(i + 1..i + 3) {
+                Some(hex) if hex.bytes().all(|b| b.is_ascii_hexdigit()) => {
+                    decoded.push(u8::from_str_radix(hex, 16).unwrap());
+                    i += 3;
+                }
+                _ => return s.to_string(),
+            }
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).unwrap_or_else(|_| s.to_string())
+}
+
+fn uri_encode(s: &str, keep_slash: bool) -> String {
+    s.bytes()
+        .map(|b| {
+            if b.is_ascii_alphanumeric()
+                || matches!(b, b'-' | b'.' | b'_' | b'~')
+                || (keep_slash && b == b'/')
+            {
+                (b as char).to_string()
+            } else {
+                format!("%{b:02X}")
+            }
+        })
+        .collect()
+}
+
+/// A server started from the test binary with tests/config.toml and some settings
+/// overridden, on its own port, database and log under target/test-servers/<name>.
+/// The process is killed when the value is dropped.
+pub struct TestServer {
+    pub addr: String,
+    child: std::process::Child,
+}
+
+impl TestServer {
+    pub fn spawn(name: &str, overrides: &[(&str, toml::Value)]) -> Self {
+        use std::fs;
+        use std::time::{Duration, Instant};
+
+        let dir = std::path::Path::new("target/test-servers").join(name);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).expect("failed to create the server directory");
+
+        // A free port, released for the server to bind
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .expect("failed to find a free port")
+            .port();
+        let path = |file: &str| toml::Value::String(dir.join(file).display().to_string());
+
+        let mut config: toml::Table =
+            toml::from_str(&fs::read_to_string("tests/config.toml").unwrap()).unwrap();
+        config.insert("port".into(), toml::Value::Integer(port.into()));
+        config.insert("database_path".into(), path("database.sqlite"));
+        config.insert("log_path".into(), path("log.txt"));
+        config.insert("backup_dir".into(), path("backups"));
+        for (key, value) in overrides {
+            config.insert(key.to_string(), value.clone());
+        }
+        let config_path = dir.join("config.toml");
+        fs::write(&config_path, toml::to_string(&config).unwrap())
+            .expect("failed to write the server config");
+
+        let child = std::process::Command::new(env!("CARGO_BIN_EXE_s3insqlite"))
+            .arg(&config_path)
+            .spawn()
+            .expect("failed to start the server");
+        let server = TestServer {
+            addr: format!("127.0.0.1:{port}"),
+            child,
+        };
+
+        // A server on a unix socket no longer listens on the port
+        let unix_socket = config
+            .get("unix_socket_path")
+            .and_then(|path| path.as_str())
+            .map(str::to_string);
+        let listening = || match &unix_socket {
+            #[cfg(unix)]
+            Some(path) => std::os::unix::net::UnixStream::connect(path).is_ok(),
+            _ => std::net::TcpStream::connect(&server.addr).is_ok(),
+        };
+        let started = Instant::now();
+        while !listening() {
+            assert!(
+                started.elapsed() < Duration::from_secs(30),
+                "server {name} did not start"
+            );
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        server
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
@@ -0,0 +1,3485 @@
+mod common;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// Send a raw request head, signed with the test credentials, and return the first
+/// chunk of the response
+async fn send_head(request: &str) -> String {
+    send_head_to(&server_addr(), request).await
+}
+
+/// Send a raw request head as is and return the first chunk of the response
+async fn send_unsigned(request: &str) -> String {
+    send_unsigned_to(&server_addr(), request).await
+}
+
+/// Address of the server configured in tests/config.toml
+fn server_addr() -> String {
+    let (endpoint, _) = common::read_config();
+    endpoint.trim_start_matches("http://").to_string()
+}
+
+/// Send a signed raw request head to the server at `addr`
+async fn send_head_to(addr: &str, request: &str) -> String {
+    send_unsigned_to(
+        addr,
+        &common::sign_request(&request.replace("{addr}", addr)),
+    )
+    .await
+}
+
+/// Send a raw request head as is to the server at `addr`
+async fn send_unsigned_to(addr: &str, request: &str) -> String {
+    let mut stream = TcpStream::connect(addr).await.expect("failed to connect");
+    stream
+        .write_all(request.replace("{addr}", addr).as_bytes())
+        .await
+        .expect("failed to send request");
+    let mut buf = vec![0u8; 64 * 1024];
+    let n = stream
+        .read(&mut buf)
+        .await
+        .expect("failed to read response");
+    String::from_utf8_lossy(&buf[..n]).to_string()
+}
+
+#[tokio::test]
+async fn test_user_metadata_size_limit() {
+    // x-amz-meta-* names (without the prefix) and values count towards the default
+    // limit of 2048 bytes
+    let put = async |value_len: usize| {
+        send_head(&format!(
+            "PUT /test/metadata-limit HTTP/1.1\r\nHost: {{addr}}\r\nx-amz-meta-big: {}\r\nContent-Length: 4\r\nConnection: close\r\n\r\ndata",
+            "v".repeat(value_len)
+        ))
+        .await
+    };
+
+    let response = put(2048 - "big".len()).await;
+    assert!(
+        response.starts_with("HTTP/1.1 200"),
+        "unexpected response: {response}"
+    );
+
+    let response = put(2048 - "big".len() + 1).await;
+    assert!(
+        response.starts_with("HTTP/1.1 400") && response.contains("<Code>MetadataTooLarge</Code>"),
+        "unexpected response: {response}"
+    );
+
+    let response = send_head(
+        "DELETE /test/metadata-limit HTTP/1.1\r\nHost: {addr}\r\nConnection: close\r\n\r\n",
+    )
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 204"),
+        "unexpected response: {response}"
+    );
+}
+
+#[tokio::test]
+async fn test_multipart_documents_escape_keys() {
+    // Key `xml&<key>` under the prefix `escape&/`
+    let (_, bucket) = common::read_config();
+    let path = format!("/{bucket}/escape%26/xml%26%3Ckey%3E");
+    let escaped = "escape&amp;/xml&amp;&lt;key&gt;";
+
+    let response = send_head(&format!(
+        "POST {path}?uploads HTTP/1.1\r\nHost: {{addr}}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+    ))
+    .await;
+    assert!(
+        response.contains(&format!("<Key>{escaped}</Key>")),
+        "unexpected response: {response}"
+    );
+    let upload_id = response
+        .split_once("<UploadId>")
+        .and_then(|(_, rest)| rest.split_once("</UploadId>"))
+        .map(|(id, _)| id.to_string())
+        .unwrap_or_else(|| panic!("no UploadId in response: {response}"));
+
+    let response = send_head(&format!(
+        "PUT {path}?partNumber=1&uploadId={upload_id} HTTP/1.1\r\nHost: {{addr}}\r\nContent-Length: 4\r\nConnection: close\r\n\r\npart"
+    ))
+    .await;
+    let etag = response
+        .lines()
+        .find_map(|line| line.strip_prefix("etag: "))
+        .unwrap_or_else(|| panic!("no ETag in response: {response}"))
+        .to_string();
+
+    let response = send_head(&format!(
+        "GET {path}?uploadId={upload_id} HTTP/1.1\r\nHost: {{addr}}\r\nConnection: close\r\n\r\n"
+    ))
+    .await;
+    assert!(
+        response.contains(&format!("<Key>{escaped}</Key>")),
+        "unexpected response: {response}"
+    );
+
+    let response = send_head(&format!(
+        "GET /{bucket}?uploads&prefix=escape%26/&delimiter=%3C HTTP/1.1\r\nHost: {{addr}}\r\nConnection: close\r\n\r\n"
+    ))
+    .await;
+    assert!(
+        response.contains("<Prefix>escape&amp;/</Prefix>")
+            && response.contains("<Delimiter>&lt;</Delimiter>")
+            && response.contains("<Prefix>escape&amp;/xml&amp;&lt;</Prefix>"),
+        "unexpected response: {response}"
+    );
+
+    let manifest = format!(
+        "<CompleteMultipartUpload><Part><PartNumber>1</PartNumber><ETag>{etag}</ETag></Part></CompleteMultipartUpload>"
+    );
+    let response = send_head(&format!(
+        "POST {path}?uploadId={upload_id} HTTP/1.1\r\nHost: {{addr}}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{manifest}",
+        manifest.len()
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 200")
+            && response.contains(&format!("<Location>{path}</Location>"))
+            && response.contains(&format!("<Key>{escaped}</Key>")),
+        "unexpected response: {response}"
+    );
+
+    let response = send_head(&format!(
+        "DELETE {path} HTTP/1.1\r\nHost: {{addr}}\r\nConnection: close\r\n\r\n"
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 204"),
+        "unexpected response: {response}"
+    );
+}
+
+#[tokio::test]
+async fn test_expect_continue_rejects_oversized_upload() {
+    let (_, bucket) = common::read_config();
+
+    // The body is never sent: the server must answer without asking for it
+    let response = send_head(&format!(
+        "PUT /{bucket}/expect/too-large HTTP/1.1\r\nHost: {{addr}}\r\n\
+         Content-Length: 1099511627776\r\nExpect: 100-continue\r\n\r\n"
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 400"),
+        "unexpected response: {response}"
+    );
+    assert!(!response.contains("100 Continue"));
+    assert!(response.contains("EntityTooLarge"));
+}
+
+#[tokio::test]
+async fn test_expect_continue_accepts_small_upload() {
+    let (_, bucket) = common::read_config();
+
+    let response = send_head(&format!(
+        "PUT /{bucket}/expect/small HTTP/1.1\r\nHost: {{addr}}\r\n\
+         Content-Length: 5\r\nExpect: 100-continue\r\n\r\n"
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 100 Continue"),
+        "unexpected response: {response}"
+    );
+}
+
+#[tokio::test]
+async fn test_list_parts_unknown_upload() {
+    let (_, bucket) = common::read_config();
+
+    let response = send_head(&format!(
+        "GET /{bucket}/multipart/missing?uploadId=does-not-exist HTTP/1.1\r\n\
+         Host: {{addr}}\r\nConnection: close\r\n\r\n"
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 404"),
+        "unexpected response: {response}"
+    );
+    assert!(response.contains("NoSuchUpload"));
+}
+
+#[tokio::test]
+async fn test_admin_bucket_tables() {
+    let (_, bucket) = common::read_config();
+
+    let response =
+        send_head("GET /?tables HTTP/1.1\r\nHost: {addr}\r\nConnection: close\r\n\r\n").await;
+    assert!(
+        response.starts_with("HTTP/1.1 200"),
+        "unexpected response: {response}"
+    );
+    let table = format!("bucket_{}", bucket.replace('-', "_"));
+    assert!(
+        response.contains(&format!(
+            r#"{{"table":"{table}","bucket":"{bucket}","orphan":false}}"#
+        )),
+        "configured bucket not reported: {response}"
+    );
+}
+
+#[tokio::test]
+async fn test_list_multipart_uploads() {
+    let (_, bucket) = common::read_config();
+
+    let response = send_head(&format!(
+        "POST /{bucket}/listuploads/object?uploads HTTP/1.1\r\nHost: {{addr}}\r\n\
+         Content-Length: 0\r\nConnection: close\r\n\r\n"
+    ))
+    .await;
+    let upload_id = response
+        .split_once("<UploadId>")
+        .and_then(|(_, rest)| rest.split_once("</UploadId>"))
+        .map(|(id, _)| id.to_string())
+        .unwrap_or_else(|| panic!("no UploadId in response: {response}"));
+
+    let response = send_head(&format!(
+        "GET /{bucket}?uploads&prefix=listuploads/ HTTP/1.1\r\nHost: {{addr}}\r\n\
+         Connection: close\r\n\r\n"
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 200"),
+        "unexpected response: {response}"
+    );
+    assert!(response.contains(&format!(
+        "<Key>listuploads/object</Key><UploadId>{upload_id}</UploadId>"
+    )));
+
+    let response = send_head(&format!(
+        "DELETE /{bucket}/listuploads/object?uploadId={upload_id} HTTP/1.1\r\n\
+         Host: {{addr}}\r\nConnection: close\r\n\r\n"
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 204"),
+        "unexpected response: {response}"
+    );
+}
+
+#[tokio::test]
+async fn test_upload_part_copy_range() {
+    let (_, bucket) = common::read_config();
+
+    let response = send_head(&format!(
+        "PUT /{bucket}/partcopy/source HTTP/1.1\r\nHost: {{addr}}\r\n\
+         Content-Length: 10\r\nConnection: close\r\n\r\n0123456789"
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 200"),
+        "unexpected response: {response}"
+    );
+
+    let response = send_head(&format!(
+        "POST /{bucket}/partcopy/target?uploads HTTP/1.1\r\nHost: {{addr}}\r\n\
+         Content-Length: 0\r\nConnection: close\r\n\r\n"
+    ))
+    .await;
+    let upload_id = response
+        .split_once("<UploadId>")
+        .and_then(|(_, rest)| rest.split_once("</UploadId>"))
+        .map(|(id, _)| id.to_string())
+        .unwrap_or_else(|| panic!("no UploadId in response: {response}"));
+
+    let response = send_head(&format!(
+        "PUT /{bucket}/partcopy/target?partNumber=1&uploadId={upload_id} HTTP/1.1\r\n\
+         Host: {{addr}}\r\nx-amz-copy-source: /{bucket}/partcopy/source\r\n\
+         x-amz-copy-source-range: bytes=2-5\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+    ))
+    .await;
+    let expected_etag = hex::encode(md5::compute(b"2345").0);
+    assert!(
+        response.contains("<CopyPartResult>")
+            && response.contains(&format!("<ETag>\"{expected_etag}\"</ETag>")),
+        "unexpected response: {response}"
+    );
+
+    // A range past the end of the source is rejected
+    let response = send_head(&format!(
+        "PUT /{bucket}/partcopy/target?partNumber=2&uploadId={upload_id} HTTP/1.1\r\n\
+         Host: {{addr}}\r\nx-amz-copy-source: /{bucket}/partcopy/source\r\n\
+         x-amz-copy-source-range: bytes=5-10\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 400") && response.contains("InvalidArgument"),
+        "unexpected response: {response}"
+    );
+
+    send_head(&format!(
+        "DELETE /{bucket}/partcopy/target?uploadId={upload_id} HTTP/1.1\r\n\
+         Host: {{addr}}\r\nConnection: close\r\n\r\n"
+    ))
+    .await;
+    send_head(&format!(
+        "DELETE /{bucket}/partcopy/source HTTP/1.1\r\nHost: {{addr}}\r\nConnection: close\r\n\r\n"
+    ))
+    .await;
+}
+
+#[tokio::test]
+async fn test_create_bucket() {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let bucket = format!("created-{nanos}");
+
+    let request = format!(
+        "PUT /{bucket} HTTP/1.1\r\nHost: {{addr}}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+    );
+    let response = send_head(&request).await;
+    assert!(
+        response.starts_with("HTTP/1.1 200"),
+        "unexpected response: {response}"
+    );
+    assert!(
+        response
+            .to_lowercase()
+            .contains(&format!("location: /{bucket}"))
+    );
+
+    // The new bucket can be listed right away
+    let response = send_head(&format!(
+        "GET /{bucket}?list-type=2 HTTP/1.1\r\nHost: {{addr}}\r\nConnection: close\r\n\r\n"
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 200"),
+        "unexpected response: {response}"
+    );
+
+    let response = send_head(&request).await;
+    assert!(
+        response.starts_with("HTTP/1.1 409") && response.contains("BucketAlreadyOwnedByYou"),
+        "unexpected response: {response}"
+    );
+
+    let response = send_head(
+        "PUT /Invalid_Name HTTP/1.1\r\nHost: {addr}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+    )
+    .await;
+    assert!(
+        response.contains("InvalidBucketName"),
+        "unexpected response: {response}"
+    );
+}
+
+#[tokio::test]
+async fn test_conditional_get_etag_forms() {
+    let (_, bucket) = common::read_config();
+    let etag = hex::encode(md5::compute(b"conditional").0);
+
+    let response = send_head(&format!(
+        "PUT /{bucket}/conditional/object HTTP/1.1\r\nHost: {{addr}}\r\n\
+         Content-Length: 11\r\nConnection: close\r\n\r\nconditional"
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 200"),
+        "unexpected response: {response}"
+    );
+
+    let get = |header: String| {
+        let bucket = bucket.clone();
+        async move {
+            send_head(&format!(
+                "GET /{bucket}/conditional/object HTTP/1.1\r\nHost: {{addr}}\r\n\
+                 {header}\r\nConnection: close\r\n\r\n"
+            ))
+            .await
+        }
+    };
+
+    // Every form of a matching entity tag satisfies If-Match and fails If-None-Match
+    let forms = [
+        format!("\"{etag}\""),
+        etag.clone(),
+        format!("W/\"{etag}\""),
+        format!("\"{}\"", etag.to_uppercase()),
+        format!("\"other\", \"{etag}\""),
+        "*".to_string(),
+    ];
+    for form in &forms {
+        let response = get(format!("If-Match: {form}")).await;
+        assert!(
+            response.starts_with("HTTP/1.1 200"),
+            "If-Match {form}: {response}"
+        );
+        let response = get(format!("If-None-Match: {form}")).await;
+        assert!(
+            response.starts_with("HTTP/1.1 304"),
+            "If-None-Match {form}: {response}"
+        );
+    }
+
+    let response = get("If-Match: \"0123456789abcdef0123456789abcdef\"".to_string()).await;
+    assert!(
+        response.starts_with("HTTP/1.1 412"),
+        "unexpected response: {response}"
+    );
+    let response = get("If-None-Match: W/\"0123456789abcdef0123456789abcdef\"".to_string()).await;
+    assert!(
+        response.starts_with("HTTP/1.1 200"),
+        "unexpected response: {response}"
+    );
+
+    send_head(&format!(
+        "DELETE /{bucket}/conditional/object HTTP/1.1\r\nHost: {{addr}}\r\nConnection: close\r\n\r\n"
+    ))
+    .await;
+}
+
+#[tokio::test]
+async fn test_delete_bucket_requires_empty() {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let bucket = format!("deleted-{nanos}");
+    let request = |method: &str, path: &str, body: &str| {
+        format!(
+            "{method} /{bucket}{path} HTTP/1.1\r\nHost: {{addr}}\r\nContent-Length: {}\r\n\
+             Connection: close\r\n\r\n{body}",
+            body.len()
+        )
+    };
+
+    let response = send_head(&request("PUT", "", "")).await;
+    assert!(
+        response.starts_with("HTTP/1.1 200"),
+        "unexpected response: {response}"
+    );
+    let response = send_head(&request("PUT", "/object", "data")).await;
+    assert!(
+        response.starts_with("HTTP/1.1 200"),
+        "unexpected response: {response}"
+    );
+
+    let response = send_head(&request("DELETE", "", "")).await;
+    assert!(
+        response.starts_with("HTTP/1.1 409") && response.contains("BucketNotEmpty"),
+        "unexpected response: {response}"
+    );
+
+    send_head(&request("DELETE", "/object", "")).await;
+    let response = send_head(&request("DELETE", "", "")).await;
+    assert!(
+        response.starts_with("HTTP/1.1 204"),
+        "unexpected response: {response}"
+    );
+
+    // The bucket is gone
+    let response = send_head(&request("GET", "", "")).await;
+    assert!(
+        !response.starts_with("HTTP/1.1 200"),
+        "unexpected response: {response}"
+    );
+}
+
+#[tokio::test]
+async fn test_checksum_headers() {
+    use base64::{Engine, prelude::BASE64_STANDARD};
+    use sha2::{Digest, Sha256};
+
+    let (_, bucket) = common::read_config();
+    let sha256 = BASE64_STANDARD.encode(Sha256::digest(b"checksummed"));
+    let crc32 = BASE64_STANDARD.encode(crc32fast::hash(b"checksummed").to_be_bytes());
+
+    let put = |headers: String| {
+        let bucket = bucket.clone();
+        async move {
+            send_head(&format!(
+                "PUT /{bucket}/checksum/object HTTP/1.1\r\nHost: {{addr}}\r\n{headers}\
+                 Content-Length: 11\r\nConnection: close\r\n\r\nchecksummed"
+            ))
+            .await
+            .to_lowercase()
+        }
+    };
+    let head = || {
+        let bucket = bucket.clone();
+        async move {
+            send_head(&format!(
+                "HEAD /{bucket}/checksum/object HTTP/1.1\r\nHost: {{addr}}\r\nConnection: close\r\n\r\n"
+            ))
+            .await
+            .to_lowercase()
+        }
+    };
+
+    // A supplied checksum is validated, stored and echoed back
+    let response = put(format!("x-amz-checksum-sha256: {sha256}\r\n")).await;
+    assert!(
+        response.starts_with("http/1.1 200"),
+        "unexpected response: {response}"
+    );
+    let expected = format!("x-amz-checksum-sha256: {}", sha256.to_lowercase());
+    assert!(response.contains(&expected), "missing checksum: {response}");
+    let response = head().await;
+    assert!(response.contains(&expected), "missing checksum: {response}");
+
+    // A wrong checksum is rejected without replacing the object
+    let response = put(format!("x-amz-checksum-crc32: {sha256}\r\n")).await;
+    assert!(
+        response.starts_with("http/1.1 400") && response.contains("<code>baddigest</code>"),
+        "unexpected response: {response}"
+    );
+    let response = head().await;
+    assert!(response.contains(&expected), "missing checksum: {response}");
+
+    // x-amz-checksum-algorithm alone has the server compute the checksum
+    let response = put("x-amz-checksum-algorithm: CRC32\r\n".to_string()).await;
+    assert!(
+        response.starts_with("http/1.1 200"),
+        "unexpected response: {response}"
+    );
+    let response = head().await;
+    let expected = format!("x-amz-checksum-crc32: {}", crc32.to_lowercase());
+    assert!(response.contains(&expected), "missing checksum: {response}");
+    assert!(
+        !response.contains("x-amz-checksum-sha256"),
+        "stale checksum: {response}"
+    );
+
+    send_head(&format!(
+        "DELETE /{bucket}/checksum/object HTTP/1.1\r\nHost: {{addr}}\r\nConnection: close\r\n\r\n"
+    ))
+    .await;
+}
+
+#[tokio::test]
+async fn test_disallowed_keys_rejected() {
+    let (_, bucket) = common::read_config();
+
+    for request in [
+        format!("PUT /{bucket}/denied/../escape HTTP/1.1\r\nContent-Length: 1\r\n"),
+        format!("PUT /{bucket}/denied/ctl%01key HTTP/1.1\r\nContent-Length: 1\r\n"),
+        format!("POST /{bucket}/denied/../escape?uploads HTTP/1.1\r\nContent-Length: 0\r\n"),
+    ] {
+        let response = send_head(&format!(
+            "{request}Host: {{addr}}\r\nConnection: close\r\n\r\nx"
+        ))
+        .await;
+        assert!(
+            response.starts_with("HTTP/1.1 400")
+                && response.contains("<Code>InvalidArgument</Code>"),
+            "unexpected response to {request}: {response}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_bucket_location_and_region() {
+    let (_, bucket) = common::read_config();
+
+    let response = send_head(&format!(
+        "GET /{bucket}?location HTTP/1.1\r\nHost: {{addr}}\r\nConnection: close\r\n\r\n"
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 200")
+            && response.contains(
+                r#"<LocationConstraint xmlns="http://s3.amazonaws.com/doc/2006-03-01/"></LocationConstraint>"#
+            ),
+        "unexpected response: {response}"
+    );
+
+    let response = send_head(&format!(
+        "HEAD /{bucket} HTTP/1.1\r\nHost: {{addr}}\r\nConnection: close\r\n\r\n"
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 200")
+            && response
+                .to_lowercase()
+                .contains("x-amz-bucket-region: us-east-1"),
+        "unexpected response: {response}"
+    );
+}
+
+#[tokio::test]
+async fn test_canned_acl_round_trip() {
+    let (_, bucket) = common::read_config();
+    let all_users = "<URI>http://acs.amazonaws.com/groups/global/AllUsers</URI>";
+
+    let response = send_head(&format!(
+        "PUT /{bucket}/acl/object HTTP/1.1\r\nHost: {{addr}}\r\n\
+         Content-Length: 3\r\nConnection: close\r\n\r\nacl"
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 200"),
+        "unexpected response: {response}"
+    );
+
+    let get_acl = |path: String| async move {
+        send_head(&format!(
+            "GET {path}?acl HTTP/1.1\r\nHost: {{addr}}\r\nConnection: close\r\n\r\n"
+        ))
+        .await
+    };
+    let put_acl = |path: String, acl: &'static str| async move {
+        send_head(&format!(
+            "PUT {path}?acl HTTP/1.1\r\nHost: {{addr}}\r\nx-amz-acl: {acl}\r\n\
+             Content-Length: 0\r\nConnection: close\r\n\r\n"
+        ))
+        .await
+    };
+
+    for path in [format!("/{bucket}"), format!("/{bucket}/acl/object")] {
+        // New buckets and objects are private
+        let response = get_acl(path.clone()).await;
+        assert!(
+            response.starts_with("HTTP/1.1 200")
+                && response.contains("<Permission>FULL_CONTROL</Permission>")
+                && !response.contains(all_users),
+            "unexpected response: {response}"
+        );
+
+        let response = put_acl(path.clone(), "public-read").await;
+        assert!(
+            response.starts_with("HTTP/1.1 200"),
+            "unexpected response: {response}"
+        );
+        let response = get_acl(path.clone()).await;
+        assert!(
+            response.contains(all_users) && response.contains("<Permission>READ</Permission>"),
+            "unexpected response: {response}"
+        );
+
+        let response = put_acl(path.clone(), "world-writable").await;
+        assert!(
+            response.starts_with("HTTP/1.1 400"),
+            "unexpected response: {response}"
+        );
+
+        let response = put_acl(path.clone(), "private").await;
+        assert!(
+            response.starts_with("HTTP/1.1 200"),
+            "unexpected response: {response}"
+        );
+    }
+
+    send_head(&format!(
+        "DELETE /{bucket}/acl/object HTTP/1.1\r\nHost: {{addr}}\r\nConnection: close\r\n\r\n"
+    ))
+    .await;
+}
+
+#[tokio::test]
+async fn test_wrong_method_not_allowed() {
+    let (_, bucket) = common::read_config();
+
+    let response = send_head(&format!(
+        "PATCH /{bucket}/some/object HTTP/1.1\r\nHost: {{addr}}\r\nConnection: close\r\n\r\n"
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 405") && response.contains("<Code>MethodNotAllowed</Code>"),
+        "unexpected response: {response}"
+    );
+    let allow = response
+        .lines()
+        .find_map(|line| {
+            line.to_lowercase()
+                .strip_prefix("allow: ")
+                .map(str::to_string)
+        })
+        .unwrap_or_else(|| panic!("missing Allow header: {response}"));
+    for method in ["get", "head", "put", "delete", "post"] {
+        assert!(
+            allow.contains(method),
+            "{method} missing from Allow: {allow}"
+        );
+    }
+
+    let response =
+        send_head("DELETE / HTTP/1.1\r\nHost: {addr}\r\nConnection: close\r\n\r\n").await;
+    assert!(
+        response.starts_with("HTTP/1.1 405"),
+        "unexpected response: {response}"
+    );
+}
+
+#[tokio::test]
+async fn test_dry_run_upload_stores_nothing() {
+    let (_, bucket) = common::read_config();
+    let etag = hex::encode(md5::compute(b"dry run").0);
+
+    for (path, header) in [
+        ("dry/run/header", "x-amz-dry-run: true\r\n"),
+        ("dry/run/query?dry-run", ""),
+    ] {
+        let response = send_head(&format!(
+            "PUT /{bucket}/{path} HTTP/1.1\r\nHost: {{addr}}\r\n{header}\
+             Content-Length: 7\r\nConnection: close\r\n\r\ndry run"
+        ))
+        .await
+        .to_lowercase();
+        assert!(
+            response.starts_with("http/1.1 200")
+                && response.contains("x-amz-dry-run: true")
+                && response.contains(&format!("etag: \"{etag}\"")),
+            "unexpected response: {response}"
+        );
+
+        let key = path.split('?').next().unwrap();
+        let response = send_head(&format!(
+            "HEAD /{bucket}/{key} HTTP/1.1\r\nHost: {{addr}}\r\nConnection: close\r\n\r\n"
+        ))
+        .await;
+        assert!(
+            response.starts_with("HTTP/1.1 404"),
+            "dry run stored an object: {response}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_put_bucket_versioning() {
+    let (_, bucket) = common::read_config();
+
+    let put = |status: &'static str| {
+        let bucket = bucket.clone();
+        async move {
+            let body = format!(
+                "<VersioningConfiguration><Status>{status}</Status></VersioningConfiguration>"
+            );
+            send_head(&format!(
+                "PUT /{bucket}?versioning HTTP/1.1\r\nHost: {{addr}}\r\n\
+                 Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            ))
+            .await
+        }
+    };
+    let get = || {
+        let bucket = bucket.clone();
+        async move {
+            send_head(&format!(
+                "GET /{bucket}?versioning HTTP/1.1\r\nHost: {{addr}}\r\nConnection: close\r\n\r\n"
+            ))
+            .await
+        }
+    };
+
+    for status in ["Enabled", "Suspended"] {
+        let response = put(status).await;
+        assert!(
+            response.starts_with("HTTP/1.1 200"),
+            "unexpected response: {response}"
+        );
+        let response = get().await;
+        assert!(
+            response.contains(&format!("<Status>{status}</Status>")),
+            "unexpected response: {response}"
+        );
+    }
+
+    let response = put("Sometimes").await;
+    assert!(
+        response.starts_with("HTTP/1.1 400")
+            && response.contains("<Code>IllegalVersioningConfigurationException</Code>"),
+        "unexpected response: {response}"
+    );
+    let response = get().await;
+    assert!(
+        response.contains("<Status>Suspended</Status>"),
+        "unexpected response: {response}"
+    );
+}
+
+#[tokio::test]
+async fn test_lifecycle_rejects_unsupported_rules() {
+    let (_, bucket) = common::read_config();
+
+    let put = |rule: &'static str| {
+        let bucket = bucket.clone();
+        async move {
+            let body = format!(
+                "<LifecycleConfiguration><Rule><ID>r</ID><Status>Enabled</Status>{rule}</Rule></LifecycleConfiguration>"
+            );
+            send_head(&format!(
+                "PUT /{bucket}?lifecycle HTTP/1.1\r\nHost: {{addr}}\r\n\
+                 Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            ))
+            .await
+        }
+    };
+
+    for rule in [
+        "<Filter><Prefix>old/</Prefix></Filter><Transition><Days>30</Days><StorageClass>GLACIER</StorageClass></Transition>",
+        "<Filter><Tag><Key>k</Key><Value>v</Value></Tag></Filter><Expiration><Days>30</Days></Expiration>",
+        // Dropping size filters would expire every object under the prefix
+        "<Filter><ObjectSizeGreaterThan>1024</ObjectSizeGreaterThan></Filter><Expiration><Days>30</Days></Expiration>",
+        "<Filter><And><Prefix>old/</Prefix><ObjectSizeLessThan>10</ObjectSizeLessThan></And></Filter><Expiration><Days>30</Days></Expiration>",
+        "<Filter><Prefix></Prefix></Filter><Expiration><Date>2030-01-01T00:00:00Z</Date></Expiration>",
+        "<Prefix>old/</Prefix><Expiration><ExpiredObjectDeleteMarker>true</ExpiredObjectDeleteMarker></Expiration>",
+        "<Prefix>old/</Prefix><AbortIncompleteMultipartUpload><DaysAfterInitiation>7</DaysAfterInitiation></AbortIncompleteMultipartUpload>",
+        "<Prefix>old/</Prefix><NoncurrentVersionExpiration><NoncurrentDays>7</NoncurrentDays></NoncurrentVersionExpiration>",
+    ] {
+        let response = put(rule).await;
+        assert!(
+            response.starts_with("HTTP/1.1 501"),
+            "unexpected response for {rule}: {response}"
+        );
+    }
+
+    let response = send_head(&format!(
+        "GET /{bucket}?lifecycle HTTP/1.1\r\nHost: {{addr}}\r\nConnection: close\r\n\r\n"
+    ))
+    .await;
+    assert!(
+        response.contains("NoSuchLifecycleConfiguration"),
+        "unsupported rules were stored: {response}"
+    );
+
+    // A prefix, directly or in a filter, with an expiration after days is accepted
+    for rule in [
+        "<Prefix>lifecycle-none/</Prefix><Expiration><Days>36500</Days></Expiration>",
+        "<Filter><Prefix>lifecycle-none/</Prefix></Filter><Expiration><Days>36500</Days></Expiration>",
+    ] {
+        let response = put(rule).await;
+        assert!(
+            response.starts_with("HTTP/1.1 200"),
+            "unexpected response for {rule}: {response}"
+        );
+    }
+    let response = send_head(&format!(
+        "DELETE /{bucket}?lifecycle HTTP/1.1\r\nHost: {{addr}}\r\nConnection: close\r\n\r\n"
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 204"),
+        "unexpected response: {response}"
+    );
+}
+
+#[tokio::test]
+async fn test_list_buckets_owner_region_and_creation_date() {
+    let (_, bucket) = common::read_config();
+
+    // Limited to the configured bucket, as other tests create buckets of their own
+    let response = send_head(&format!(
+        "GET /?prefix={bucket} HTTP/1.1\r\nHost: {{addr}}\r\nConnection: close\r\n\r\n"
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 200")
+            && response.contains("<Owner><ID>s3insqlite</ID>")
+            && response.contains(&format!("<Name>{bucket}</Name>"))
+            && response.contains("<BucketRegion>us-east-1</BucketRegion>")
+            && response.contains("<CreationDate>"),
+        "unexpected response: {response}"
+    );
+
+    let response = send_head(
+        "GET /?bucket-region=eu-west-1 HTTP/1.1\r\nHost: {addr}\r\nConnection: close\r\n\r\n",
+    )
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 200") && !response.contains("<Bucket>"),
+        "unexpected response: {response}"
+    );
+}
+
+#[tokio::test]
+async fn test_cors_configuration_and_preflight() {
+    let (_, bucket) = common::read_config();
+
+    let body = "<CORSConfiguration><CORSRule>\
+        <AllowedOrigin>https://*.example.com</AllowedOrigin>\
+        <AllowedMethod>GET</AllowedMethod><AllowedMethod>PUT</AllowedMethod>\
+        <AllowedHeader>*</AllowedHeader><ExposeHeader>ETag</ExposeHeader>\
+        <MaxAgeSeconds>600</MaxAgeSeconds>\
+        </CORSRule></CORSConfiguration>";
+    let response = send_head(&format!(
+        "PUT /{bucket}?cors HTTP/1.1\r\nHost: {{addr}}\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 200"),
+        "unexpected response: {response}"
+    );
+
+    let preflight = |origin: &'static str| {
+        let bucket = bucket.clone();
+        async move {
+            send_head(&format!(
+                "OPTIONS /{bucket}/cors/object HTTP/1.1\r\nHost: {{addr}}\r\n\
+                 Origin: {origin}\r\nAccess-Control-Request-Method: PUT\r\n\
+                 Access-Control-Request-Headers: content-type\r\nConnection: close\r\n\r\n"
+            ))
+            .await
+        }
+    };
+    let response = preflight("https://app.example.com").await;
+    assert!(
+        response.starts_with("HTTP/1.1 200")
+            && response.contains("access-control-allow-origin: https://app.example.com")
+            && response.contains("access-control-max-age: 600"),
+        "unexpected response: {response}"
+    );
+    let response = preflight("https://evil.test").await;
+    assert!(
+        response.starts_with("HTTP/1.1 403") && response.contains("<Code>AccessForbidden</Code>"),
+        "unexpected response: {response}"
+    );
+
+    let response = send_head(&format!(
+        "GET /{bucket}?location HTTP/1.1\r\nHost: {{addr}}\r\n\
+         Origin: https://app.example.com\r\nConnection: close\r\n\r\n"
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 200")
+            && response.contains("access-control-allow-origin: https://app.example.com")
+            && response.contains("access-control-expose-headers: ETag"),
+        "unexpected response: {response}"
+    );
+
+    let response = send_head(&format!(
+        "DELETE /{bucket}?cors HTTP/1.1\r\nHost: {{addr}}\r\nConnection: close\r\n\r\n"
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 204"),
+        "unexpected response: {response}"
+    );
+    let response = preflight("https://app.example.com").await;
+    assert!(
+        response.starts_with("HTTP/1.1 403"),
+        "unexpected response: {response}"
+    );
+}
+
+#[tokio::test]
+async fn test_bucket_policy_public_read() {
+    let (_, bucket) = common::read_config();
+
+    let request = |method: &str, body: &str| {
+        format!(
+            "{method} /{bucket}?policy HTTP/1.1\r\nHost: {{addr}}\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )
+    };
+
+    let unsupported = format!(
+        r#"{{"Version":"2012-10-17","Statement":[{{"Effect":"Allow","Principal":"*","Action":"s3:PutObject","Resource":"arn:aws:s3:::{bucket}/*"}}]}}"#
+    );
+    let response = send_head(&request("PUT", &unsupported)).await;
+    assert!(
+        response.starts_with("HTTP/1.1 400") && response.contains("<Code>MalformedPolicy</Code>"),
+        "unexpected response: {response}"
+    );
+
+    let public_read = format!(
+        r#"{{"Version":"2012-10-17","Statement":[{{"Sid":"PublicRead","Effect":"Allow","Principal":"*","Action":"s3:GetObject","Resource":"arn:aws:s3:::{bucket}/*"}}]}}"#
+    );
+    let response = send_head(&request("PUT", &public_read)).await;
+    assert!(
+        response.starts_with("HTTP/1.1 204"),
+        "unexpected response: {response}"
+    );
+    let response = send_head(&request("GET", "")).await;
+    assert!(
+        response.starts_with("HTTP/1.1 200") && response.ends_with(&public_read),
+        "unexpected response: {response}"
+    );
+    let response = send_head(&format!(
+        "GET /{bucket}?policyStatus HTTP/1.1\r\nHost: {{addr}}\r\nConnection: close\r\n\r\n"
+    ))
+    .await;
+    assert!(
+        response.contains("<IsPublic>true</IsPublic>"),
+        "unexpected response: {response}"
+    );
+
+    let response = send_head(&request("DELETE", "")).await;
+    assert!(
+        response.starts_with("HTTP/1.1 204"),
+        "unexpected response: {response}"
+    );
+    let response = send_head(&request("GET", "")).await;
+    assert!(
+        response.starts_with("HTTP/1.1 404")
+            && response.contains("<Code>NoSuchBucketPolicy</Code>"),
+        "unexpected response: {response}"
+    );
+}
+
+#[tokio::test]
+async fn test_bucket_logging_delivers_access_logs() {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let source = format!("logsource-{nanos}");
+    let target = format!("logtarget-{nanos}");
+    for bucket in [&source, &target] {
+        let response = send_head(&format!(
+            "PUT /{bucket} HTTP/1.1\r\nHost: {{addr}}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+        ))
+        .await;
+        assert!(
+            response.starts_with("HTTP/1.1 200"),
+            "unexpected response: {response}"
+        );
+    }
+
+    let get_logging =
+        format!("GET /{source}?logging HTTP/1.1\r\nHost: {{addr}}\r\nConnection: close\r\n\r\n");
+    let response = send_head(&get_logging).await;
+    assert!(
+        response.starts_with("HTTP/1.1 200") && !response.contains("<LoggingEnabled>"),
+        "unexpected response: {response}"
+    );
+
+    let body = format!(
+        "<BucketLoggingStatus><LoggingEnabled><TargetBucket>{target}</TargetBucket>\
+         <TargetPrefix>logs/</TargetPrefix></LoggingEnabled></BucketLoggingStatus>"
+    );
+    let response = send_head(&format!(
+        "PUT /{source}?logging HTTP/1.1\r\nHost: {{addr}}\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 200"),
+        "unexpected response: {response}"
+    );
+    let response = send_head(&get_logging).await;
+    assert!(
+        response.contains(&format!("<TargetBucket>{target}</TargetBucket>")),
+        "unexpected response: {response}"
+    );
+
+    let response = send_head(&format!(
+        "PUT /{source}/logged HTTP/1.1\r\nHost: {{addr}}\r\n\
+         Content-Length: 6\r\nConnection: close\r\n\r\nlogged"
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 200"),
+        "unexpected response: {response}"
+    );
+
+    // tests/config.toml delivers access logs every second. A small max-keys keeps the
+    // listing from being streamed in chunks after the head
+    tokio::time::sleep(std::time::Duration::from_secs(3)).await;
+    let response = send_head(&format!(
+        "GET /{target}?list-type=2&prefix=logs/&max-keys=10 HTTP/1.1\r\nHost: {{addr}}\r\nConnection: close\r\n\r\n"
+    ))
+    .await;
+    let key = response
+        .split("<Key>")
+        .nth(1)
+        .and_then(|rest| rest.split("</Key>").next())
+        .unwrap_or_else(|| panic!("no log object delivered: {response}"))
+        .to_string();
+    let response = send_head(&format!(
+        "GET /{target}/{key} HTTP/1.1\r\nHost: {{addr}}\r\nConnection: close\r\n\r\n"
+    ))
+    .await;
+    assert!(
+        response.contains(&format!(" {source} ")) && response.contains("REST.PUT.OBJECT logged"),
+        "unexpected response: {response}"
+    );
+}
+
+#[tokio::test]
+async fn test_compression_skips_object_bodies() {
+    let (_, bucket) = common::read_config();
+    let body = "compressible ".repeat(20);
+
+    let response = send_head(&format!(
+        "PUT /{bucket}/compression/object HTTP/1.1\r\nHost: {{addr}}\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 200"),
+        "unexpected response: {response}"
+    );
+
+    let response = send_head(&format!(
+        "GET /{bucket}?list-type=2&prefix=compression/ HTTP/1.1\r\nHost: {{addr}}\r\n\
+         Accept-Encoding: gzip\r\nConnection: close\r\n\r\n"
+    ))
+    .await
+    .to_lowercase();
+    assert!(
+        response.starts_with("http/1.1 200")
+            && response.contains("content-encoding: gzip")
+            && !response.contains("content-length:"),
+        "unexpected response: {response}"
+    );
+
+    let response = send_head(&format!(
+        "GET /{bucket}/compression/object HTTP/1.1\r\nHost: {{addr}}\r\n\
+         Accept-Encoding: gzip\r\nConnection: close\r\n\r\n"
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 200")
+            && !response.to_lowercase().contains("content-encoding")
+            && response
+                .to_lowercase()
+                .contains(&format!("content-length: {}", body.len()))
+            && response.ends_with(&body),
+        "unexpected response: {response}"
+    );
+}
+
+#[tokio::test]
+async fn test_bucket_usage_counters() {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let bucket = format!("usage-{nanos}");
+    let response = send_head(&format!(
+        "PUT /{bucket} HTTP/1.1\r\nHost: {{addr}}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 200"),
+        "unexpected response: {response}"
+    );
+
+    let put = |key: &'static str, body: &'static str| {
+        let bucket = bucket.clone();
+        async move {
+            send_head(&format!(
+                "PUT /{bucket}/{key} HTTP/1.1\r\nHost: {{addr}}\r\n\
+                 Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            ))
+            .await
+        }
+    };
+    put("a", "abc").await;
+    put("b", "defgh").await;
+    // Overwriting replaces the size of the object, deleting removes it
+    put("a", "x").await;
+    put("c", "gone").await;
+    send_head(&format!(
+        "DELETE /{bucket}/c HTTP/1.1\r\nHost: {{addr}}\r\nConnection: close\r\n\r\n"
+    ))
+    .await;
+
+    let response = send_head(&format!(
+        "HEAD /{bucket} HTTP/1.1\r\nHost: {{addr}}\r\nConnection: close\r\n\r\n"
+    ))
+    .await
+    .to_lowercase();
+    assert!(
+        response.contains("x-s3insqlite-object-count: 2")
+            && response.contains("x-s3insqlite-bytes-used: 6"),
+        "unexpected response: {response}"
+    );
+
+    let response = send_head(&format!(
+        "GET /{bucket}?stats HTTP/1.1\r\nHost: {{addr}}\r\nConnection: close\r\n\r\n"
+    ))
+    .await;
+    assert!(
+        response.contains(r#""object_count":2"#) && response.contains(r#""size_bytes":6"#),
+        "unexpected response: {response}"
+    );
+}
+
+#[tokio::test]
+async fn test_range_requests() {
+    let (_, bucket) = common::read_config();
+
+    for (key, body) in [("digits", "0123456789"), ("empty", "")] {
+        let response = send_head(&format!(
+            "PUT /{bucket}/range/{key} HTTP/1.1\r\nHost: {{addr}}\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        ))
+        .await;
+        assert!(
+            response.starts_with("HTTP/1.1 200"),
+            "unexpected response: {response}"
+        );
+    }
+
+    let get = |key: &'static str, range: &'static str| {
+        let bucket = bucket.clone();
+        async move {
+            send_head(&format!(
+                "GET /{bucket}/range/{key} HTTP/1.1\r\nHost: {{addr}}\r\n\
+                 Range: {range}\r\nConnection: close\r\n\r\n"
+            ))
+            .await
+        }
+    };
+
+    // (range, expected body, expected Content-Range)
+    let partial = [
+        ("bytes=2-5", "2345", "bytes 2-5/10"),
+        ("bytes=7-", "789", "bytes 7-9/10"),
+        ("bytes=-3", "789", "bytes 7-9/10"),
+        ("bytes=-20", "0123456789", "bytes 0-9/10"),
+        ("bytes=5-100", "56789", "bytes 5-9/10"),
+        ("bytes=0-0", "0", "bytes 0-0/10"),
+    ];
+    for (range, body, content_range) in partial {
+        let response = get("digits", range).await;
+        let lower = response.to_lowercase();
+        assert!(
+            response.starts_with("HTTP/1.1 206")
+                && lower.contains(&format!("content-range: {content_range}"))
+                && lower.contains(&format!("content-length: {}", body.len()))
+                && response.ends_with(&format!("\r\n\r\n{body}")),
+            "unexpected response to {range}: {response}"
+        );
+    }
+
+    // Malformed and multiple ranges are ignored
+    for range in ["bytes=5-2", "bytes=0-1,3-4", "items=0-1"] {
+        let response = get("digits", range).await;
+        assert!(
+            response.starts_with("HTTP/1.1 200") && response.ends_with("0123456789"),
+            "unexpected response to {range}: {response}"
+        );
+    }
+
+    for (key, range, size) in [
+        ("digits", "bytes=10-", 10),
+        ("digits", "bytes=-0", 10),
+        ("empty", "bytes=0-", 0),
+        ("empty", "bytes=-5", 0),
+    ] {
+        let response = get(key, range).await;
+        assert!(
+            response.starts_with("HTTP/1.1 416")
+                && response.contains("<Code>InvalidRange</Code>")
+                && response
+                    .to_lowercase()
+                    .contains(&format!("content-range: bytes */{size}")),
+            "unexpected response to {range}: {response}"
+        );
+    }
+}
+
+/// A ustar archive of regular files
+fn tar_archive(files: &[(&str, &str)]) -> String {
+    let mut archive = String::new();
+    for (path, data) in files {
+        let mut header = [0u8; 512];
+        header[..path.len()].copy_from_slice(path.as_bytes());
+        header[100..107].copy_from_slice(b"0000644");
+        header[108..115].copy_from_slice(b"0000000");
+        header[116..123].copy_from_slice(b"0000000");
+        header[124..135].copy_from_slice(format!("{:011o}", data.len()).as_bytes());
+        header[136..147].copy_from_slice(b"00000000000");
+        header[148..156].copy_from_slice(b"        ");
+        header[156] = b'0';
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[263..265].copy_from_slice(b"00");
+        let checksum: u32 = header.iter().map(|&b| b as u32).sum();
+        header[148..155].copy_from_slice(format!("{checksum:06o}\0").as_bytes());
+        archive.push_str(std::str::from_utf8(&header).unwrap());
+        archive.push_str(data);
+        archive.push_str(&"\0".repeat((512 - data.len() % 512) % 512));
+    }
+    archive.push_str(&"\0".repeat(1024));
+    archive
+}
+
+#[tokio::test]
+async fn test_import_archive() {
+    let (_, bucket) = common::read_config();
+
+    let archive = tar_archive(&[
+        ("import/a.txt", "first"),
+        ("import/bad..key", "skipped"),
+        ("import/nested/b.txt", "second"),
+    ]);
+    let response = send_head(&format!(
+        "POST /{bucket}?import HTTP/1.1\r\nHost: {{addr}}\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n{archive}",
+        archive.len()
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 200")
+            && response.contains(r#""imported":2"#)
+            && response.contains(r#""failed":1"#)
+            && response.contains(r#"{"key":"import/bad..key","imported":false,"error":"#),
+        "unexpected response: {response}"
+    );
+
+    let response = send_head(&format!(
+        "GET /{bucket}/import/nested/b.txt HTTP/1.1\r\nHost: {{addr}}\r\nConnection: close\r\n\r\n"
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 200") && response.ends_with("\r\n\r\nsecond"),
+        "unexpected response: {response}"
+    );
+
+    let response = send_head(&format!(
+        "POST /{bucket}?import HTTP/1.1\r\nHost: {{addr}}\r\n\
+         Content-Length: 9\r\nConnection: close\r\n\r\nnot a tar"
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 400") && response.contains("<Code>MalformedArchive</Code>"),
+        "unexpected response: {response}"
+    );
+
+    // Archives decompressing to more than max_import_size are refused as a whole
+    let server = common::TestServer::spawn(
+        "import-budget",
+        &[("max_import_size", toml::Value::Integer(10))],
+    );
+    let addr = server.addr.as_str();
+    let archive = tar_archive(&[("budget/a.txt", "first"), ("budget/b.txt", "second")]);
+    let response = send_head_to(
+        addr,
+        &format!(
+            "POST /{bucket}?import HTTP/1.1\r\nHost: {{addr}}\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n{archive}",
+            archive.len()
+        ),
+    )
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 400") && response.contains("<Code>EntityTooLarge</Code>"),
+        "unexpected response: {response}"
+    );
+    let response = send_head_to(
+        addr,
+        &format!(
+            "HEAD /{bucket}/budget/a.txt HTTP/1.1\r\nHost: {{addr}}\r\nConnection: close\r\n\r\n"
+        ),
+    )
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 404"),
+        "unexpected response: {response}"
+    );
+}
+
+#[tokio::test]
+async fn test_exhausted_connection_pool_asks_to_slow_down() {
+    let (_, bucket) = common::read_config();
+    let server = common::TestServer::spawn(
+        "pool-timeout",
+        &[
+            ("db_pool_max_size", toml::Value::Integer(1)),
+            ("db_pool_min_idle", toml::Value::Integer(1)),
+            ("db_pool_timeout_seconds", toml::Value::Integer(1)),
+        ],
+    );
+    let addr = server.addr.as_str();
+
+    // Enough long keys for a listing far larger than what the server buffers, socket
+    // send buffers of up to 4 MB included
+    let keys: Vec<String> = (0..24000).map(|i| format!("pool/{i:0>95}")).collect();
+    let files: Vec<(&str, &str)> = keys.iter().map(|key| (key.as_str(), "")).collect();
+    let archive = tar_archive(&files);
+    let response = send_head_to(
+        addr,
+        &format!(
+            "POST /{bucket}?import HTTP/1.1\r\nHost: {{addr}}\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n{archive}",
+            archive.len()
+        ),
+    )
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 200"),
+        "unexpected response: {response}"
+    );
+
+    // A streamed listing holds the only read connection while its client does not read
+    let socket = tokio::net::TcpSocket::new_v4().unwrap();
+    socket.set_recv_buffer_size(4096).unwrap();
+    let mut stalled = socket.connect(addr.parse().unwrap()).await.unwrap();
+    stalled
+        .write_all(
+            common::sign_request(&format!(
+                "GET /{bucket}?list-type=2&max-keys=100000 HTTP/1.1\r\nHost: {addr}\r\n\r\n"
+            ))
+            .as_bytes(),
+        )
+        .await
+        .unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+
+    // Reads and backups both wait for a read connection
+    for request in [
+        format!(
+            "GET /{bucket}/pool/missing HTTP/1.1\r\nHost: {{addr}}\r\nConnection: close\r\n\r\n"
+        ),
+        "POST /?backup&path=pool.sqlite HTTP/1.1\r\nHost: {addr}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+            .to_string(),
+    ] {
+        let response = send_head_to(addr, &request).await;
+        assert!(
+            response.starts_with("HTTP/1.1 503")
+                && response.contains("<Code>SlowDown</Code>")
+                && response.to_lowercase().contains("retry-after: 1\r\n"),
+            "unexpected response to {request}: {response}"
+        );
+    }
+    drop(stalled);
+}
+
+#[tokio::test]
+async fn test_rename_object() {
+    let (_, bucket) = common::read_config();
+
+    for (key, body) in [("rename/source", "moved"), ("rename/taken", "old")] {
+        let response = send_head(&format!(
+            "PUT /{bucket}/{key} HTTP/1.1\r\nHost: {{addr}}\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        ))
+        .await;
+        assert!(
+            response.starts_with("HTTP/1.1 200"),
+            "unexpected response: {response}"
+        );
+    }
+
+    let rename = |key: &'static str, target: &'static str, overwrite: bool| {
+        let bucket = bucket.clone();
+        async move {
+            send_head(&format!(
+                "POST /{bucket}/{key}?rename HTTP/1.1\r\nHost: {{addr}}\r\n\
+                 x-s3insqlite-rename-target: {target}\r\n\
+                 x-s3insqlite-rename-overwrite: {overwrite}\r\n\
+                 Content-Length: 0\r\nConnection: close\r\n\r\n"
+            ))
+            .await
+        }
+    };
+
+    let response = rename("rename/source", "rename/taken", false).await;
+    assert!(
+        response.starts_with("HTTP/1.1 409") && response.contains("<Code>KeyAlreadyExists</Code>"),
+        "unexpected response: {response}"
+    );
+    let response = rename("rename/missing", "rename/other", false).await;
+    assert!(
+        response.starts_with("HTTP/1.1 404") && response.contains("<Code>NoSuchKey</Code>"),
+        "unexpected response: {response}"
+    );
+
+    let response = rename("rename/source", "rename/taken", true).await;
+    assert!(
+        response.starts_with("HTTP/1.1 204"),
+        "unexpected response: {response}"
+    );
+    let response = send_head(&format!(
+        "GET /{bucket}/rename/taken HTTP/1.1\r\nHost: {{addr}}\r\nConnection: close\r\n\r\n"
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 200")
+            && response.contains("\"11dfd868d93bc2b0e4ce0bee5756f8b1\"")
+            && response.ends_with("\r\n\r\nmoved"),
+        "unexpected response: {response}"
+    );
+    let response = send_head(&format!(
+        "HEAD /{bucket}/rename/source HTTP/1.1\r\nHost: {{addr}}\r\nConnection: close\r\n\r\n"
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 404"),
+        "unexpected response: {response}"
+    );
+}
+
+#[tokio::test]
+async fn test_expected_bucket_owner() {
+    let (_, bucket) = common::read_config();
+
+    for (owner, status) in [("s3insqlite", "200"), ("123456789012", "403")] {
+        let response = send_head(&format!(
+            "PUT /{bucket}/owner/object HTTP/1.1\r\nHost: {{addr}}\r\n\
+             x-amz-expected-bucket-owner: {owner}\r\n\
+             Content-Length: 4\r\nConnection: close\r\n\r\ndata"
+        ))
+        .await;
+        assert!(
+            response.starts_with(&format!("HTTP/1.1 {status}")),
+            "unexpected response for owner {owner}: {response}"
+        );
+    }
+
+    let response = send_head(&format!(
+        "HEAD /{bucket} HTTP/1.1\r\nHost: {{addr}}\r\n\
+         x-amz-expected-bucket-owner: 123456789012\r\nConnection: close\r\n\r\n"
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 403"),
+        "unexpected response: {response}"
+    );
+}
+
+#[tokio::test]
+async fn test_signature_v4_rejections() {
+    let (_, bucket) = common::read_config();
+
+    // Dated now, to be within max_clock_skew_seconds
+    let now = chrono::Utc::now();
+    let (amz_date, date) = (
+        now.format("%Y%m%dT%H%M%SZ").to_string(),
+        now.format("%Y%m%d").to_string(),
+    );
+    let signed = |access_key: &str| {
+        format!(
+            "GET /{bucket}/sigv4/object HTTP/1.1\r\nHost: {{addr}}\r\n\
+             x-amz-date: {amz_date}\r\n\
+             x-amz-content-sha256: UNSIGNED-PAYLOAD\r\n\
+             Authorization: AWS4-HMAC-SHA256 Credential={access_key}/{date}/us-east-1/s3/aws4_request, \
+             SignedHeaders=host;x-amz-content-sha256;x-amz-date, Signature={}\r\n\
+             Connection: close\r\n\r\n",
+            "0".repeat(64)
+        )
+    };
+
+    let response = send_head(&signed("unknown")).await;
+    assert!(
+        response.starts_with("HTTP/1.1 403")
+            && response.contains("<Code>InvalidAccessKeyId</Code>"),
+        "unexpected response: {response}"
+    );
+
+    let response = send_head(&signed("minioadmin")).await;
+    assert!(
+        response.starts_with("HTTP/1.1 403")
+            && response.contains("<Code>SignatureDoesNotMatch</Code>")
+            && response.contains(&format!(
+                "<StringToSign>AWS4-HMAC-SHA256\n{amz_date}\n{date}/us-east-1/s3/aws4_request\n"
+            )),
+        "unexpected response: {response}"
+    );
+
+    let response = send_head(&format!(
+        "GET /{bucket}/sigv4/object HTTP/1.1\r\nHost: {{addr}}\r\n\
+         Authorization: AWS4-HMAC-SHA256 Signature=abc\r\nConnection: close\r\n\r\n"
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 400")
+            && response.contains("<Code>AuthorizationHeaderMalformed</Code>"),
+        "unexpected response: {response}"
+    );
+
+    // Every configured access key may sign, each with its own secret
+    let (endpoint, _) = common::read_config();
+    let addr = endpoint.trim_start_matches("http://");
+    let request = format!(
+        "GET /{bucket}?list-type=2&max-keys=1 HTTP/1.1\r\nHost: {addr}\r\nConnection: close\r\n\r\n"
+    );
+    let response = send_unsigned(&common::sign_request_as(
+        &request,
+        "second-key",
+        "second-secret",
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 200"),
+        "unexpected response: {response}"
+    );
+    let response = send_unsigned(&common::sign_request_as(
+        &request,
+        "second-key",
+        "minioadmin",
+    ))
+    .await;
+    assert!(
+        response.contains("<Code>SignatureDoesNotMatch</Code>"),
+        "unexpected response: {response}"
+    );
+}
+
+#[tokio::test]
+async fn test_content_encoding_passthrough_and_decoding() {
+    use flate2::{Compression, write::GzEncoder};
+    use std::io::Write;
+
+    let (endpoint, bucket) = common::read_config();
+    let addr = endpoint.trim_start_matches("http://");
+    let exchange = |request: Vec<u8>| async move {
+        let mut stream = TcpStream::connect(addr).await.expect("failed to connect");
+        stream
+            .write_all(&request)
+            .await
+            .expect("failed to send request");
+        let mut response = Vec::new();
+        stream
+            .read_to_end(&mut response)
+            .await
+            .expect("failed to read response");
+        response
+    };
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(b"decompressed content").unwrap();
+    let gzipped = encoder.finish().unwrap();
+
+    let mut request = common::sign_request(&format!(
+        "PUT /{bucket}/encoding/object HTTP/1.1\r\nHost: {addr}\r\n\
+         Content-Encoding: gzip\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        gzipped.len()
+    ))
+    .into_bytes();
+    request.extend_from_slice(&gzipped);
+    let response = exchange(request).await;
+    assert!(response.starts_with(b"HTTP/1.1 200"));
+
+    // Passthrough: the stored bytes with their Content-Encoding
+    let response = exchange(
+        common::sign_request(&format!(
+            "GET /{bucket}/encoding/object HTTP/1.1\r\nHost: {addr}\r\nConnection: close\r\n\r\n"
+        ))
+        .into_bytes(),
+    )
+    .await;
+    let head = String::from_utf8_lossy(&response).to_lowercase();
+    assert!(
+        head.starts_with("http/1.1 200") && head.contains("content-encoding: gzip"),
+        "unexpected response: {head}"
+    );
+    assert!(response.ends_with(&gzipped));
+
+    // Decoded for clients accepting only the identity encoding
+    let response = exchange(
+        common::sign_request(&format!(
+            "GET /{bucket}/encoding/object HTTP/1.1\r\nHost: {addr}\r\n\
+             Accept-Encoding: identity\r\nConnection: close\r\n\r\n"
+        ))
+        .into_bytes(),
+    )
+    .await;
+    let response = String::from_utf8_lossy(&response);
+    assert!(
+        response.starts_with("HTTP/1.1 200")
+            && !response.to_lowercase().contains("content-encoding")
+            && response.ends_with("\r\n\r\ndecompressed content"),
+        "unexpected response: {response}"
+    );
+}
+
+#[tokio::test]
+async fn test_anonymous_access() {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let private = format!("private-{nanos}");
+    let response = send_head(&format!(
+        "PUT /{private} HTTP/1.1\r\nHost: {{addr}}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 200"),
+        "unexpected response: {response}"
+    );
+
+    let put = |bucket: &str, key: &str| {
+        format!(
+            "PUT /{bucket}/{key} HTTP/1.1\r\nHost: {{addr}}\r\n\
+             Content-Length: 4\r\nConnection: close\r\n\r\ndata"
+        )
+    };
+    let get = |bucket: &str, key: &str| {
+        format!("GET /{bucket}/{key} HTTP/1.1\r\nHost: {{addr}}\r\nConnection: close\r\n\r\n")
+    };
+    for (bucket, key) in [
+        ("public", "anonymous/object"),
+        (private.as_str(), "anonymous/object"),
+        (private.as_str(), "shared/object"),
+    ] {
+        let response = send_head(&put(bucket, key)).await;
+        assert!(
+            response.starts_with("HTTP/1.1 200"),
+            "unexpected response: {response}"
+        );
+    }
+
+    // Reads of public_read buckets need no signature, writes do
+    let response = send_unsigned(&get("public", "anonymous/object")).await;
+    assert!(
+        response.starts_with("HTTP/1.1 200") && response.ends_with("\r\n\r\ndata"),
+        "unexpected response: {response}"
+    );
+    let response = send_unsigned(
+        "GET /public?list-type=2&prefix=anonymous/ HTTP/1.1\r\nHost: {addr}\r\nConnection: close\r\n\r\n",
+    )
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 200"),
+        "unexpected response: {response}"
+    );
+    for request in [
+        put("public", "anonymous/object"),
+        get(&private, "anonymous/object"),
+        get(&private, "shared/object"),
+    ] {
+        let response = send_unsigned(&request).await;
+        assert!(
+            response.starts_with("HTTP/1.1 403") && response.contains("<Code>AccessDenied</Code>"),
+            "unexpected response to {request}: {response}"
+        );
+    }
+
+    // A bucket policy makes the objects it covers public
+    let policy = format!(
+        r#"{{"Statement":[{{"Effect":"Allow","Principal":"*","Action":"s3:GetObject","Resource":"arn:aws:s3:::{private}/shared/*"}}]}}"#
+    );
+    let response = send_head(&format!(
+        "PUT /{private}?policy HTTP/1.1\r\nHost: {{addr}}\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n{policy}",
+        policy.len()
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 204"),
+        "unexpected response: {response}"
+    );
+    let response = send_unsigned(&get(&private, "shared/object")).await;
+    assert!(
+        response.starts_with("HTTP/1.1 200") && response.ends_with("\r\n\r\ndata"),
+        "unexpected response: {response}"
+    );
+    let response = send_unsigned(&get(&private, "anonymous/object")).await;
+    assert!(
+        response.starts_with("HTTP/1.1 403"),
+        "unexpected response: {response}"
+    );
+}
+
+#[tokio::test]
+async fn test_json_listing() {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let prefix = format!("json-{nanos}/");
+    let response = send_head(&format!(
+        "PUT /test/{prefix}a.txt HTTP/1.1\r\nHost: {{addr}}\r\nContent-Length: 3\r\nConnection: close\r\n\r\nabc"
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 200"),
+        "unexpected response: {response}"
+    );
+
+    for request in [
+        format!("GET /test?list-type=2&prefix={prefix}&format=json HTTP/1.1\r\n"),
+        format!("GET /test?prefix={prefix} HTTP/1.1\r\nAccept: application/json\r\n"),
+    ] {
+        let response = send_head(&format!(
+            "{request}Host: {{addr}}\r\nConnection: close\r\n\r\n"
+        ))
+        .await;
+        assert!(
+            response
+                .to_lowercase()
+                .contains("content-type: application/json"),
+            "unexpected response: {response}"
+        );
+        assert!(
+            response.contains(&format!(r#""key":"{prefix}a.txt","size":3"#)),
+            "unexpected response: {response}"
+        );
+    }
+
+    // XML stays the default; a small max-keys keeps the listing from being streamed
+    let response = send_head(&format!(
+        "GET /test?list-type=2&prefix={prefix}&max-keys=10 HTTP/1.1\r\nHost: {{addr}}\r\nConnection: close\r\n\r\n"
+    ))
+    .await;
+    assert!(
+        response.contains(&format!("<Key>{prefix}a.txt</Key>")),
+        "unexpected response: {response}"
+    );
+}
+
+#[tokio::test]
+async fn test_delete_object_version() {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let key = format!("versioned-{nanos}.txt");
+    let response = send_head(&format!(
+        "PUT /test/{key} HTTP/1.1\r\nHost: {{addr}}\r\nContent-Length: 3\r\nConnection: close\r\n\r\nabc"
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 200"),
+        "unexpected response: {response}"
+    );
+
+    let response = send_head(&format!(
+        "DELETE /test/{key}?versionId=3HL4kqtJlcpXroDTDmjVBH40Nrjfkd HTTP/1.1\r\nHost: {{addr}}\r\nConnection: close\r\n\r\n"
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 404") && response.contains("<Code>NoSuchVersion</Code>"),
+        "unexpected response: {response}"
+    );
+
+    let response = send_head(&format!(
+        "DELETE /test/{key}?versionId=null HTTP/1.1\r\nHost: {{addr}}\r\nConnection: close\r\n\r\n"
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 204")
+            && response.to_lowercase().contains("x-amz-version-id: null"),
+        "unexpected response: {response}"
+    );
+    let response = send_head(&format!(
+        "HEAD /test/{key} HTTP/1.1\r\nHost: {{addr}}\r\nConnection: close\r\n\r\n"
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 404"),
+        "unexpected response: {response}"
+    );
+}
+
+#[tokio::test]
+async fn test_object_expiry() {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let key = format!("expiring-{nanos}.txt");
+
+    let response = send_head(&format!(
+        "PUT /test/{key}?ttl=0 HTTP/1.1\r\nHost: {{addr}}\r\nContent-Length: 3\r\nConnection: close\r\n\r\nabc"
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 400") && response.contains("<Code>InvalidArgument</Code>"),
+        "unexpected response: {response}"
+    );
+    let response = send_head(&format!(
+        "PUT /test/{key} HTTP/1.1\r\nHost: {{addr}}\r\nx-amz-expires: Thu, 01 Jan 1970 00:00:00 GMT\r\n\
+         Content-Length: 3\r\nConnection: close\r\n\r\nabc"
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 400"),
+        "unexpected response: {response}"
+    );
+
+    // Expiry is kept in whole seconds, so a ttl of 2 leaves at least a second to read
+    for key in [key.clone(), format!("{key}.target")] {
+        let response = send_head(&format!(
+            "PUT /test/{key}?ttl=2 HTTP/1.1\r\nHost: {{addr}}\r\nContent-Length: 3\r\nConnection: close\r\n\r\nabc"
+        ))
+        .await;
+        assert!(
+            response.starts_with("HTTP/1.1 200"),
+            "unexpected response: {response}"
+        );
+    }
+    let response = send_head(&format!(
+        "GET /test/{key} HTTP/1.1\r\nHost: {{addr}}\r\nConnection: close\r\n\r\n"
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 200") && response.ends_with("abc"),
+        "unexpected response: {response}"
+    );
+
+    tokio::time::sleep(std::time::Duration::from_secs(3)).await;
+    for method in ["GET", "HEAD"] {
+        let response = send_head(&format!(
+            "{method} /test/{key} HTTP/1.1\r\nHost: {{addr}}\r\nConnection: close\r\n\r\n"
+        ))
+        .await;
+        assert!(
+            response.starts_with("HTTP/1.1 404"),
+            "unexpected response: {response}"
+        );
+    }
+
+    // An expired object can neither be renamed nor block a rename onto its key
+    let rename = |key: &str, target: String| {
+        let request = format!(
+            "POST /test/{key}?rename HTTP/1.1\r\nHost: {{addr}}\r\n\
+             x-s3insqlite-rename-target: {target}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+        );
+        async move { send_head(&request).await }
+    };
+    let response = rename(&key, format!("{key}.moved")).await;
+    assert!(
+        response.starts_with("HTTP/1.1 404") && response.contains("<Code>NoSuchKey</Code>"),
+        "unexpected response: {response}"
+    );
+
+    // An expired object no longer exists for conditional writes
+    let response = send_head(&format!(
+        "PUT /test/{key} HTTP/1.1\r\nHost: {{addr}}\r\nIf-None-Match: *\r\n\
+         Content-Length: 3\r\nConnection: close\r\n\r\nnew"
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 200"),
+        "unexpected response: {response}"
+    );
+
+    let response = rename(&key, format!("{key}.target")).await;
+    assert!(
+        response.starts_with("HTTP/1.1 204"),
+        "unexpected response: {response}"
+    );
+    let response = send_head(&format!(
+        "GET /test/{key}.target HTTP/1.1\r\nHost: {{addr}}\r\nConnection: close\r\n\r\n"
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 200") && response.ends_with("new"),
+        "unexpected response: {response}"
+    );
+}
+
+#[tokio::test]
+async fn test_access_key_bucket_permissions() {
+    let (endpoint, _) = common::read_config();
+    let addr = endpoint.trim_start_matches("http://");
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let other = format!("perm-{nanos}");
+    let response = send_head(&format!(
+        "PUT /{other} HTTP/1.1\r\nHost: {{addr}}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 200"),
+        "unexpected response: {response}"
+    );
+
+    // limited-key has read-write access to "test" and read-only access to "public"
+    let limited = |request: String| async move {
+        send_unsigned(&common::sign_request_as(
+            &request,
+            "limited-key",
+            "limited-secret",
+        ))
+        .await
+    };
+    let put = |path: &str| {
+        format!(
+            "PUT {path} HTTP/1.1\r\nHost: {addr}\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok"
+        )
+    };
+    let get =
+        |path: &str| format!("GET {path} HTTP/1.1\r\nHost: {addr}\r\nConnection: close\r\n\r\n");
+
+    let response = limited(put(&format!("/test/perm-{nanos}.txt"))).await;
+    assert!(
+        response.starts_with("HTTP/1.1 200"),
+        "unexpected response: {response}"
+    );
+    let response = limited(get("/public?list-type=2&max-keys=1")).await;
+    assert!(
+        response.starts_with("HTTP/1.1 200"),
+        "unexpected response: {response}"
+    );
+    for request in [
+        put(&format!("/public/perm-{nanos}.txt")),
+        get(&format!("/{other}?list-type=2")),
+        put(&format!("/{other}/perm.txt")),
+        get("/?tables"),
+    ] {
+        let response = limited(request).await;
+        assert!(
+            response.starts_with("HTTP/1.1 403") && response.contains("<Code>AccessDenied</Code>"),
+            "unexpected response: {response}"
+        );
+    }
+
+    // Copying needs access to the source bucket
+    let response = limited(format!(
+        "PUT /test/perm-{nanos}-copy HTTP/1.1\r\nHost: {addr}\r\nx-amz-copy-source: /{other}/perm.txt\r\n\
+         Content-Length: 0\r\nConnection: close\r\n\r\n"
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 403"),
+        "unexpected response: {response}"
+    );
+
+    let mut stream = TcpStream::connect(addr).await.expect("failed to connect");
+    stream
+        .write_all(common::sign_request_as(&get("/"), "limited-key", "limited-secret").as_bytes())
+        .await
+        .expect("failed to send request");
+    let mut response = Vec::new();
+    stream
+        .read_to_end(&mut response)
+        .await
+        .expect("failed to read response");
+    let response = String::from_utf8_lossy(&response);
+    assert!(
+        response.contains("<Name>test</Name>")
+            && response.contains("<Name>public</Name>")
+            && !response.contains(&other),
+        "unexpected response: {response}"
+    );
+}
+
+#[tokio::test]
+async fn test_write_once_bucket() {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let key = format!("record-{nanos}.txt");
+    let put = |body: &'static str| {
+        let key = key.clone();
+        async move {
+            send_head(&format!(
+                "PUT /worm/{key} HTTP/1.1\r\nHost: {{addr}}\r\n\
+                 Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            ))
+            .await
+        }
+    };
+
+    let response = put("first").await;
+    assert!(
+        response.starts_with("HTTP/1.1 200"),
+        "unexpected response: {response}"
+    );
+    let response = put("second").await;
+    assert!(
+        response.starts_with("HTTP/1.1 403") && response.contains("<Code>AccessDenied</Code>"),
+        "unexpected response: {response}"
+    );
+    // A dry run answers as the upload would
+    let response = send_head(&format!(
+        "PUT /worm/{key} HTTP/1.1\r\nHost: {{addr}}\r\nx-amz-dry-run: true\r\n\
+         Content-Length: 6\r\nConnection: close\r\n\r\nsecond"
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 403") && response.contains("<Code>AccessDenied</Code>"),
+        "unexpected response: {response}"
+    );
+    let response = send_head(&format!(
+        "PUT /worm/{key} HTTP/1.1\r\nHost: {{addr}}\r\nx-amz-copy-source: /worm/{key}\r\n\
+         Content-Length: 0\r\nConnection: close\r\n\r\n"
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 403"),
+        "unexpected response: {response}"
+    );
+    let response = send_head(&format!(
+        "DELETE /worm/{key} HTTP/1.1\r\nHost: {{addr}}\r\nConnection: close\r\n\r\n"
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 403"),
+        "unexpected response: {response}"
+    );
+
+    let response = send_head(&format!(
+        "GET /worm/{key} HTTP/1.1\r\nHost: {{addr}}\r\nConnection: close\r\n\r\n"
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 200") && response.ends_with("first"),
+        "unexpected response: {response}"
+    );
+}
+
+#[tokio::test]
+async fn test_zero_byte_objects() {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    const EMPTY_ETAG: &str = "\"d41d8cd98f00b204e9800998ecf8427e\"";
+
+    for key in [format!("empty-{nanos}"), format!("marker-{nanos}/")] {
+        let response = send_head(&format!(
+            "PUT /test/{key} HTTP/1.1\r\nHost: {{addr}}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+        ))
+        .await;
+        assert!(
+            response.starts_with("HTTP/1.1 200")
+                && response
+                    .to_lowercase()
+                    .contains(&format!("etag: {EMPTY_ETAG}")),
+            "unexpected response: {response}"
+        );
+
+        for method in ["GET", "HEAD"] {
+            let response = send_head(&format!(
+                "{method} /test/{key} HTTP/1.1\r\nHost: {{addr}}\r\nConnection: close\r\n\r\n"
+            ))
+            .await;
+            let lower = response.to_lowercase();
+            assert!(
+                response.starts_with("HTTP/1.1 200")
+                    && lower.contains("content-length: 0\r\n")
+                    && lower.contains(&format!("etag: {EMPTY_ETAG}"))
+                    && response.ends_with("\r\n\r\n"),
+                "unexpected {method} response: {response}"
+            );
+        }
+    }
+
+    // A small max-keys keeps the listing from being streamed
+    let response = send_head(&format!(
+        "GET /test?list-type=2&prefix=marker-{nanos}/&max-keys=10 HTTP/1.1\r\nHost: {{addr}}\r\nConnection: close\r\n\r\n"
+    ))
+    .await;
+    assert!(
+        response.contains(&format!("<Key>marker-{nanos}/</Key>"))
+            && response.contains("<Size>0</Size>"),
+        "unexpected response: {response}"
+    );
+}
+
+#[tokio::test]
+async fn test_content_sha256_verification() {
+    let (endpoint, bucket) = common::read_config();
+    let addr = endpoint.trim_start_matches("http://");
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let put = |key: &str| {
+        format!(
+            "PUT /{bucket}/sha256-{nanos}-{key} HTTP/1.1\r\nHost: {addr}\r\n\
+             Content-Length: 5\r\nConnection: close\r\n\r\nhello"
+        )
+    };
+    // SHA-256 of "hello"
+    const HELLO_SHA256: &str = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+    let response = send_unsigned(&common::sign_request_with_payload_hash(
+        &put("match"),
+        HELLO_SHA256,
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 200"),
+        "unexpected response: {response}"
+    );
+
+    let response = send_unsigned(&common::sign_request_with_payload_hash(
+        &put("mismatch"),
+        &"0".repeat(64),
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 400")
+            && response.contains("<Code>XAmzContentSHA256Mismatch</Code>"),
+        "unexpected response: {response}"
+    );
+    let response = send_head(&format!(
+        "HEAD /{bucket}/sha256-{nanos}-mismatch HTTP/1.1\r\nHost: {{addr}}\r\nConnection: close\r\n\r\n"
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 404"),
+        "rejected upload was stored: {response}"
+    );
+
+    let response = send_unsigned(&common::sign_request_with_payload_hash(
+        &put("malformed"),
+        "not-a-hash",
+    ))
+    .await;
+    assert!(
+        response.contains("<Code>InvalidArgument</Code>"),
+        "unexpected response: {response}"
+    );
+}
+
+#[tokio::test]
+async fn test_unimplemented_bucket_subresources() {
+    let (_, bucket) = common::read_config();
+    for subresource in ["encryption", "replication", "website", "object-lock"] {
+        let response = send_head(&format!(
+            "GET /{bucket}?{subresource} HTTP/1.1\r\nHost: {{addr}}\r\nConnection: close\r\n\r\n"
+        ))
+        .await;
+        assert!(
+            response.starts_with("HTTP/1.1 501")
+                && response.contains("<Code>NotImplemented</Code>")
+                && !response.contains("<ListBucketResult"),
+            "unexpected response to ?{subresource}: {response}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_ip_filter() {
+    let (_, bucket) = common::read_config();
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let request = |method: &str, forwarded_for: &str| {
+        let (path, body) = match method {
+            "PUT" => (format!("/{bucket}/ip-filter-{nanos}"), "data"),
+            _ => (format!("/{bucket}?list-type=2&max-keys=1"), ""),
+        };
+        format!(
+            "{method} {path} HTTP/1.1\r\nHost: {{addr}}\r\nX-Forwarded-For: {forwarded_for}\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )
+    };
+
+    // The test client is a trusted proxy, so X-Forwarded-For names the client
+    for (method, forwarded_for, allowed) in [
+        ("GET", "198.51.100.7", true),
+        ("PUT", "198.51.100.7", false), // not in write_allow_ips
+        ("PUT", "192.0.2.5", true),
+        ("GET", "203.0.113.9", false), // in deny_ips
+        ("GET", "10.1.2.3", false),    // not in allow_ips
+        ("PUT", "2001:db8:1::5", true),
+        ("GET", "2001:db8:2::5", true),
+        ("PUT", "2001:db8:2::5", false),    // not in write_allow_ips
+        ("GET", "2001:db8:dead::1", false), // deny_ips wins over allow_ips
+        ("GET", "::ffff:203.0.113.9", false), // IPv4-mapped addresses match IPv4 ranges
+        // Trusted proxies are skipped from the right up to the first other address
+        ("GET", "198.51.100.7, 127.0.0.1", true),
+        ("GET", "203.0.113.9, 127.0.0.1", false),
+        // Entries left of an untrusted address may be forged and are ignored
+        ("GET", "203.0.113.9, 198.51.100.7", true),
+        ("GET", "198.51.100.7, 203.0.113.9", false),
+    ] {
+        let response = send_head(&request(method, forwarded_for)).await;
+        if allowed {
+            assert!(
+                response.starts_with("HTTP/1.1 200"),
+                "{method} from {forwarded_for} refused: {response}"
+            );
+        } else {
+            assert!(
+                response.starts_with("HTTP/1.1 403")
+                    && response.contains("<Code>AccessDenied</Code>"),
+                "{method} from {forwarded_for} allowed: {response}"
+            );
+        }
+    }
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_ip_filter_on_unix_socket() {
+    let (_, bucket) = common::read_config();
+    let socket = "target/test-servers/ip-filter-unix/s3.sock";
+    let _server = common::TestServer::spawn(
+        "ip-filter-unix",
+        &[("unix_socket_path", toml::Value::String(socket.into()))],
+    );
+    let get = async |forwarded_for: Option<&str>| {
+        let forwarded_for = forwarded_for
+            .map(|ip| format!("X-Forwarded-For: {ip}\r\n"))
+            .unwrap_or_default();
+        let request = common::sign_request(&format!(
+            "GET /{bucket}?list-type=2&max-keys=1 HTTP/1.1\r\nHost: localhost\r\n\
+             {forwarded_for}Connection: close\r\n\r\n"
+        ));
+        let mut stream = tokio::net::UnixStream::connect(socket)
+            .await
+            .expect("failed to connect");
+        stream
+            .write_all(request.as_bytes())
+            .await
+            .expect("failed to send request");
+        let mut buf = vec![0u8; 64 * 1024];
+        let n = stream
+            .read(&mut buf)
+            .await
+            .expect("failed to read response");
+        String::from_utf8_lossy(&buf[..n]).to_string()
+    };
+
+    // The peer of the unix socket is a local proxy, so X-Forwarded-For names the client
+    for (forwarded_for, allowed) in [
+        (Some("198.51.100.7"), true),
+        (Some("198.51.100.7, 127.0.0.1"), true),
+        (Some("203.0.113.9"), false), // in deny_ips
+        (Some("10.1.2.3"), false),    // not in allow_ips
+        (Some("not an address"), false),
+        (None, false),
+    ] {
+        let response = get(forwarded_for).await;
+        if allowed {
+            assert!(
+                response.starts_with("HTTP/1.1 200"),
+                "GET from {forwarded_for:?} refused: {response}"
+            );
+        } else {
+            assert!(
+                response.starts_with("HTTP/1.1 403")
+                    && response.contains("<Code>AccessDenied</Code>"),
+                "GET from {forwarded_for:?} allowed: {response}"
+            );
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_get_object_by_etag() {
+    let (_, bucket) = common::read_config();
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let body = format!("content addressed {nanos}");
+    let etag = hex::encode(md5::compute(body.as_bytes()).0);
+
+    for key in [
+        format!("by-etag-{nanos}/older"),
+        format!("by-etag-{nanos}/newer"),
+    ] {
+        let response = send_head(&format!(
+            "PUT /{bucket}/{key} HTTP/1.1\r\nHost: {{addr}}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        ))
+        .await;
+        assert!(
+            response.starts_with("HTTP/1.1 200"),
+            "unexpected response: {response}"
+        );
+        // last_modified has a resolution of one second
+        tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+    }
+
+    let response = send_head(&format!(
+        "GET /{bucket}?etag=%22{etag}%22 HTTP/1.1\r\nHost: {{addr}}\r\nConnection: close\r\n\r\n"
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 200")
+            && response
+                .to_lowercase()
+                .contains(&format!("x-amz-object-key: by-etag-{nanos}/newer\r\n"))
+            && response.contains(&format!("\"{etag}\"")),
+        "unexpected response: {response}"
+    );
+
+    let response = send_head(&format!(
+        "GET /{bucket}?etag={} HTTP/1.1\r\nHost: {{addr}}\r\nConnection: close\r\n\r\n",
+        "0".repeat(32)
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 404") && response.contains("<Code>NoSuchKey</Code>"),
+        "unexpected response: {response}"
+    );
+}
+
+#[tokio::test]
+async fn test_bucket_rate_limit() {
+    // The throttled bucket allows a burst of 2 requests, then one every 2 seconds
+    let mut statuses = Vec::new();
+    for _ in 0..4 {
+        let response = send_head(
+            "GET /throttled?list-type=2&max-keys=1 HTTP/1.1\r\nHost: {addr}\r\nConnection: close\r\n\r\n",
+        )
+        .await;
+        if response.starts_with("HTTP/1.1 503") {
+            assert!(
+                response.contains("<Code>SlowDown</Code>")
+                    && response.to_lowercase().contains("retry-after: "),
+                "unexpected response: {response}"
+            );
+        }
+        statuses.push(response.get(9..12).unwrap_or_default().to_string());
+    }
+    assert_eq!(statuses[0], "200");
+    assert!(
+        statuses.iter().any(|s| s == "503"),
+        "no request was limited: {statuses:?}"
+    );
+
+    // Other buckets are not limited
+    for _ in 0..4 {
+        let response = send_head(
+            "GET /test?list-type=2&max-keys=1 HTTP/1.1\r\nHost: {addr}\r\nConnection: close\r\n\r\n",
+        )
+        .await;
+        assert!(
+            response.starts_with("HTTP/1.1 200"),
+            "unexpected response: {response}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_request_time_too_skewed() {
+    let (endpoint, bucket) = common::read_config();
+    let addr = endpoint.trim_start_matches("http://");
+    let request = format!(
+        "GET /{bucket}?list-type=2&max-keys=1 HTTP/1.1\r\nHost: {addr}\r\nConnection: close\r\n\r\n"
+    );
+    let now = chrono::Utc::now();
+
+    // Within the default window of 15 minutes, either way
+    for offset in [-5, 5] {
+        let signed_at = now + chrono::Duration::minutes(offset);
+        let response = send_unsigned(&common::sign_request_at(&request, signed_at)).await;
+        assert!(
+            response.starts_with("HTTP/1.1 200"),
+            "request signed {offset} minutes off refused: {response}"
+        );
+    }
+
+    for offset in [-20, 20] {
+        let signed_at = now + chrono::Duration::minutes(offset);
+        let response = send_unsigned(&common::sign_request_at(&request, signed_at)).await;
+        assert!(
+            response.starts_with("HTTP/1.1 403")
+                && response.contains("<Code>RequestTimeTooSkewed</Code>")
+                && response.contains(&format!(
+                    "<RequestTime>{}</RequestTime>",
+                    signed_at.format("%Y%m%dT%H%M%SZ")
+                ))
+                && response.contains("<ServerTime>")
+                && response
+                    .contains("<MaxAllowedSkewMilliseconds>900000</MaxAllowedSkewMilliseconds>"),
+            "request signed {offset} minutes off accepted: {response}"
+        );
+    }
+}
+
+/// Start a multipart upload of `path` with a single part on the server at `addr`,
+/// returning the upload ID and the manifest completing it
+async fn start_single_part_upload(addr: &str, path: &str, part: &str) -> (String, String) {
+    let response = send_head_to(
+        addr,
+        &format!(
+            "POST {path}?uploads HTTP/1.1\r\nHost: {{addr}}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+        ),
+    )
+    .await;
+    let upload_id = response
+        .split_once("<UploadId>")
+        .and_then(|(_, rest)| rest.split_once("</UploadId>"))
+        .map(|(id, _)| id.to_string())
+        .unwrap_or_else(|| panic!("no UploadId in response: {response}"));
+
+    let response = send_head_to(
+        addr,
+        &format!(
+            "PUT {path}?partNumber=1&uploadId={upload_id} HTTP/1.1\r\nHost: {{addr}}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{part}",
+            part.len()
+        ),
+    )
+    .await;
+    let etag = response
+        .lines()
+        .find_map(|line| line.strip_prefix("etag: "))
+        .unwrap_or_else(|| panic!("no ETag in response: {response}"));
+    let manifest = format!(
+        "<CompleteMultipartUpload><Part><PartNumber>1</PartNumber><ETag>{etag}</ETag></Part></CompleteMultipartUpload>"
+    );
+    (upload_id, manifest)
+}
+
+#[tokio::test]
+async fn test_bucket_quota_objects_of_copies_multipart_uploads_and_imports() {
+    // A bucket of its own server, holding at most 2 objects
+    let server = common::TestServer::spawn(
+        "quota-objects",
+        &[
+            ("buckets", toml::Value::Array(vec!["quota".into()])),
+            ("max_objects_per_bucket", toml::Value::Integer(2)),
+        ],
+    );
+    let addr = server.addr.as_str();
+    let quota_exceeded = |response: &str| {
+        response.starts_with("HTTP/1.1 403") && response.contains("<Code>QuotaExceeded</Code>")
+    };
+
+    // Fill the bucket by count
+    let archive = tar_archive(&[("a", "1"), ("b", "1")]);
+    let import = |archive: &str| {
+        format!(
+            "POST /quota?import HTTP/1.1\r\nHost: {{addr}}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{archive}",
+            archive.len()
+        )
+    };
+    let response = send_head_to(addr, &import(&archive)).await;
+    assert!(
+        response.starts_with("HTTP/1.1 200") && response.contains(r#""imported":2"#),
+        "unexpected response: {response}"
+    );
+    // Nothing of an import adding a key is written, overwrites are allowed
+    let response = send_head_to(addr, &import(&tar_archive(&[("a", "2"), ("c", "1")]))).await;
+    assert!(quota_exceeded(&response), "unexpected response: {response}");
+    let response = send_head_to(addr, &import(&tar_archive(&[("a", "2")]))).await;
+    assert!(
+        response.starts_with("HTTP/1.1 200"),
+        "unexpected response: {response}"
+    );
+
+    let copy = |key: &str| {
+        format!(
+            "PUT /quota/{key} HTTP/1.1\r\nHost: {{addr}}\r\nx-amz-copy-source: /quota/a\r\n\
+             Content-Length: 0\r\nConnection: close\r\n\r\n"
+        )
+    };
+    let response = send_head_to(addr, &copy("c")).await;
+    assert!(quota_exceeded(&response), "unexpected response: {response}");
+    let response = send_head_to(addr, &copy("b")).await;
+    assert!(
+        response.starts_with("HTTP/1.1 200"),
+        "unexpected response: {response}"
+    );
+
+    let complete = |key: &str, upload_id: &str, manifest: &str| {
+        format!(
+            "POST /quota/{key}?uploadId={upload_id} HTTP/1.1\r\nHost: {{addr}}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{manifest}",
+            manifest.len()
+        )
+    };
+    let (upload_id, manifest) = start_single_part_upload(addr, "/quota/c", "1").await;
+    let response = send_head_to(addr, &complete("c", &upload_id, &manifest)).await;
+    assert!(quota_exceeded(&response), "unexpected response: {response}");
+    let (upload_id, manifest) = start_single_part_upload(addr, "/quota/b", "1").await;
+    let response = send_head_to(addr, &complete("b", &upload_id, &manifest)).await;
+    assert!(
+        response.starts_with("HTTP/1.1 200"),
+        "unexpected response: {response}"
+    );
+}
+
+#[tokio::test]
+async fn test_audit_log() {
+    let (_, bucket) = common::read_config();
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let key = format!("audit-{nanos}");
+
+    let response = send_head(&format!(
+        "PUT /{bucket}/{key} HTTP/1.1\r\nHost: {{addr}}\r\nContent-Length: 7\r\nConnection: close\r\n\r\naudited"
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 200"),
+        "unexpected response: {response}"
+    );
+    let response = send_unsigned(&format!(
+        "PUT /{bucket}/{key} HTTP/1.1\r\nHost: {{addr}}\r\nContent-Length: 7\r\nConnection: close\r\n\r\nforged!"
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 403"),
+        "unexpected response: {response}"
+    );
+
+    // Both writes are recorded, the signed one with its access key and size
+    let response = send_head(&format!(
+        "GET /?audit&bucket={bucket}&key={key} HTTP/1.1\r\nHost: {{addr}}\r\nConnection: close\r\n\r\n"
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 200"),
+        "unexpected response: {response}"
+    );
+    let body = response.split("\r\n\r\n").nth(1).unwrap_or_default();
+    let records: serde_json::Value = serde_json::from_str(body).expect("invalid audit records");
+    let records = records.as_array().expect("audit records are not an array");
+    assert_eq!(records.len(), 2, "unexpected records: {body}");
+    assert_eq!(records[0]["access_key"], "minioadmin");
+    assert_eq!(records[0]["operation"], "REST.PUT.OBJECT");
+    assert_eq!(records[0]["status"], 200);
+    assert_eq!(records[0]["bytes"], 7);
+    assert_eq!(records[1]["access_key"], "anonymous");
+    assert_eq!(records[1]["status"], 403);
+
+    let response = send_head(
+        "GET /?audit&since=yesterday HTTP/1.1\r\nHost: {addr}\r\nConnection: close\r\n\r\n",
+    )
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 400") && response.contains("<Code>InvalidArgument</Code>"),
+        "unexpected response: {response}"
+    );
+}
+
+#[tokio::test]
+async fn test_bucket_quota() {
+    // The quota bucket holds at most 2 objects and 10 bytes
+    let put = |key: &str, body: &str, extra: &str| {
+        format!(
+            "PUT /quota/{key} HTTP/1.1\r\nHost: {{addr}}\r\n{extra}Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )
+    };
+    for key in ["a", "b", "c"] {
+        let response = send_head(&format!(
+            "DELETE /quota/{key} HTTP/1.1\r\nHost: {{addr}}\r\nConnection: close\r\n\r\n"
+        ))
+        .await;
+        assert!(
+            response.starts_with("HTTP/1.1 204"),
+            "unexpected response: {response}"
+        );
+    }
+
+    for (key, body, status) in [
+        ("a", "1234", "200"),
+        ("b", "1234", "200"),
+        // A third object is over max_objects
+        ("c", "1", "403"),
+        // Overwrites only count their growth: 8 bytes become 10, then 11
+        ("a", "123456", "200"),
+        ("a", "1234567", "403"),
+        ("b", "1", "200"),
+        ("a", "1234567", "200"),
+    ] {
+        // A dry run, which writes nothing, answers as the upload does
+        for extra in ["x-amz-dry-run: true\r\n", ""] {
+            let response = send_head(&put(key, body, extra)).await;
+            assert!(
+                response.starts_with(&format!("HTTP/1.1 {status}")),
+                "unexpected response to {extra}{key}={body}: {response}"
+            );
+            if status == "403" {
+                assert!(
+                    response.contains("<Code>QuotaExceeded</Code>"),
+                    "unexpected response: {response}"
+                );
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_bucket_quota_bytes_of_copies_and_multipart_uploads() {
+    // A bucket of its own server, holding at most 2 objects and 10 bytes
+    let server = common::TestServer::spawn("quota-bytes", &[]);
+    let addr = server.addr.as_str();
+    let put = |key: &str, body: &str| {
+        format!(
+            "PUT /quota/{key} HTTP/1.1\r\nHost: {{addr}}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )
+    };
+    let copy = |source: &str, key: &str| {
+        format!(
+            "PUT /quota/{key} HTTP/1.1\r\nHost: {{addr}}\r\nx-amz-copy-source: /quota/{source}\r\n\
+             Content-Length: 0\r\nConnection: close\r\n\r\n"
+        )
+    };
+    let quota_exceeded = |response: &str| {
+        response.starts_with("HTTP/1.1 403") && response.contains("<Code>QuotaExceeded</Code>")
+    };
+
+    let response = send_head_to(addr, &put("a", "12345678")).await;
+    assert!(
+        response.starts_with("HTTP/1.1 200"),
+        "unexpected response: {response}"
+    );
+    // 8 more bytes are over max_bytes
+    let response = send_head_to(addr, &copy("a", "b")).await;
+    assert!(quota_exceeded(&response), "unexpected response: {response}");
+
+    let (upload_id, manifest) = start_single_part_upload(addr, "/quota/c", "123456").await;
+    let complete = format!(
+        "POST /quota/c?uploadId={upload_id} HTTP/1.1\r\nHost: {{addr}}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{manifest}",
+        manifest.len()
+    );
+    let response = send_head_to(addr, &complete).await;
+    assert!(quota_exceeded(&response), "unexpected response: {response}");
+
+    // The refused upload is kept, and fits once the bucket shrank
+    let response = send_head_to(addr, &put("a", "1234")).await;
+    assert!(
+        response.starts_with("HTTP/1.1 200"),
+        "unexpected response: {response}"
+    );
+    let response = send_head_to(addr, &complete).await;
+    assert!(
+        response.starts_with("HTTP/1.1 200"),
+        "unexpected response: {response}"
+    );
+    let response = send_head_to(addr, &copy("c", "a")).await;
+    assert!(quota_exceeded(&response), "unexpected response: {response}");
+    let response = send_head_to(addr, &copy("a", "c")).await;
+    assert!(
+        response.starts_with("HTTP/1.1 200"),
+        "unexpected response: {response}"
+    );
+}
+
+#[tokio::test]
+async fn test_temp_credentials() {
+    let (endpoint, bucket) = common::read_config();
+    let addr = endpoint.trim_start_matches("http://");
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let prefix = format!("notebook-{nanos}/");
+
+    let mint = |permission: &str| {
+        format!(
+            "POST /?temp-credentials&bucket={bucket}&prefix={prefix}&permission={permission}&duration-seconds=900 HTTP/1.1\r\nHost: {{addr}}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+        )
+    };
+    let response = send_head(&mint("rw")).await;
+    assert!(
+        response.starts_with("HTTP/1.1 200"),
+        "unexpected response: {response}"
+    );
+    let body = response.split("\r\n\r\n").nth(1).unwrap_or_default();
+    let credentials: serde_json::Value =
+        serde_json::from_str(body).expect("invalid temporary credentials");
+    let id = credentials["access_key_id"].as_str().unwrap().to_string();
+    let secret = credentials["secret_access_key"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let token = credentials["session_token"].as_str().unwrap().to_string();
+    assert_eq!(credentials["prefix"], prefix.as_str());
+
+    let send_temp = |request: String, id: String, secret: String, token: String| async move {
+        let request = request.replace("{addr}", addr).replacen(
+            "\r\n",
+            &format!("\r\nx-amz-security-token: {token}\r\n"),
+            1,
+        );
+        send_unsigned(&common::sign_request_as(&request, &id, &secret)).await
+    };
+    let put = |key: String| {
+        format!(
+            "PUT /{bucket}/{key} HTTP/1.1\r\nHost: {{addr}}\r\nContent-Length: 4\r\nConnection: close\r\n\r\ndata"
+        )
+    };
+
+    for (request, token, expected) in [
+        (put(format!("{prefix}a")), token.clone(), "HTTP/1.1 200"),
+        (
+            format!(
+                "GET /{bucket}/{prefix}a HTTP/1.1\r\nHost: {{addr}}\r\nConnection: close\r\n\r\n"
+            ),
+            token.clone(),
+            "HTTP/1.1 200",
+        ),
+        (
+            format!(
+                "GET /{bucket}?list-type=2&prefix={prefix} HTTP/1.1\r\nHost: {{addr}}\r\nConnection: close\r\n\r\n"
+            ),
+            token.clone(),
+            "HTTP/1.1 200",
+        ),
+        // Outside the prefix, or listing more than it
+        (put(format!("other-{nanos}")), token.clone(), "HTTP/1.1 403"),
+        (
+            format!(
+                "GET /{bucket}?list-type=2 HTTP/1.1\r\nHost: {{addr}}\r\nConnection: close\r\n\r\n"
+            ),
+            token.clone(),
+            "HTTP/1.1 403",
+        ),
+        (put(format!("{prefix}b")), "0".repeat(64), "HTTP/1.1 400"),
+    ] {
+        let response = send_temp(request.clone(), id.clone(), secret.clone(), token).await;
+        assert!(
+            response.starts_with(expected),
+            "unexpected response to {request}: {response}"
+        );
+        if expected.ends_with("403") {
+            assert!(
+                response.contains("limited to"),
+                "unexpected response: {response}"
+            );
+        } else if expected.ends_with("400") {
+            assert!(
+                response.contains("<Code>InvalidToken</Code>"),
+                "unexpected response: {response}"
+            );
+        }
+    }
+
+    // Read-only credentials may not write, even under their prefix
+    let response = send_head(&mint("ro")).await;
+    let body = response.split("\r\n\r\n").nth(1).unwrap_or_default();
+    let credentials: serde_json::Value =
+        serde_json::from_str(body).expect("invalid temporary credentials");
+    let response = send_temp(
+        put(format!("{prefix}c")),
+        credentials["access_key_id"].as_str().unwrap().to_string(),
+        credentials["secret_access_key"]
+            .as_str()
+            .unwrap()
+            .to_string(),
+        credentials["session_token"].as_str().unwrap().to_string(),
+    )
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 403") && response.contains("read-only"),
+        "unexpected response: {response}"
+    );
+
+    // A rename may not move an object out of the prefix, its target decoded first
+    let rename = |target: String| {
+        format!(
+            "POST /{bucket}/{prefix}a?rename HTTP/1.1\r\nHost: {{addr}}\r\n\
+             x-s3insqlite-rename-target: {target}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+        )
+    };
+    let response = send_temp(
+        rename(format!("other%2D{nanos}")),
+        id.clone(),
+        secret.clone(),
+        token.clone(),
+    )
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 403") && response.contains("rename target is outside"),
+        "unexpected response: {response}"
+    );
+    let response = send_temp(rename(format!("{prefix}renamed")), id, secret, token).await;
+    assert!(
+        response.starts_with("HTTP/1.1 204"),
+        "unexpected response: {response}"
+    );
+}
+
+#[tokio::test]
+async fn test_credentials_reload() {
+    const CREDENTIALS_FILE: &str = "tests/credentials.toml";
+    let (endpoint, bucket) = common::read_config();
+    let addr = endpoint.trim_start_matches("http://");
+    let original = std::fs::read_to_string(CREDENTIALS_FILE).expect("missing credentials file");
+    let list = format!(
+        "GET /{bucket}?list-type=2&max-keys=1 HTTP/1.1\r\nHost: {addr}\r\nConnection: close\r\n\r\n"
+    );
+    let reload = || async {
+        send_head(
+            "POST /?reload-credentials HTTP/1.1\r\nHost: {addr}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        )
+        .await
+    };
+
+    let response = send_unsigned(&common::sign_request_as(&list, "file-key", "file-secret")).await;
+    assert!(
+        response.starts_with("HTTP/1.1 200"),
+        "unexpected response: {response}"
+    );
+
+    // Added keys work as soon as the file is reloaded
+    std::fs::write(
+        CREDENTIALS_FILE,
+        format!(
+            "{original}\n[[credentials]]\naccess_key_id = \"rotated-key\"\nsecret_access_key = \"rotated-secret\"\n"
+        ),
+    )
+    .unwrap();
+    let response = reload().await;
+    assert!(
+        response.starts_with("HTTP/1.1 200") && response.contains(r#""status":"ok""#),
+        "unexpected response: {response}"
+    );
+    let response = send_unsigned(&common::sign_request_as(
+        &list,
+        "rotated-key",
+        "rotated-secret",
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 200"),
+        "unexpected response: {response}"
+    );
+
+    // A file that cannot be parsed keeps the previous keys
+    std::fs::write(CREDENTIALS_FILE, "[[credentials]\n").unwrap();
+    let response = reload().await;
+    assert!(
+        response.starts_with("HTTP/1.1 500"),
+        "unexpected response: {response}"
+    );
+    let response = send_unsigned(&common::sign_request_as(
+        &list,
+        "rotated-key",
+        "rotated-secret",
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 200"),
+        "unexpected response: {response}"
+    );
+
+    // Removed keys stop working
+    std::fs::write(CREDENTIALS_FILE, &original).unwrap();
+    let response = reload().await;
+    assert!(
+        response.starts_with("HTTP/1.1 200"),
+        "unexpected response: {response}"
+    );
+    let response = send_unsigned(&common::sign_request_as(
+        &list,
+        "rotated-key",
+        "rotated-secret",
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 403") && response.contains("InvalidAccessKeyId"),
+        "unexpected response: {response}"
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_head_latency_during_slow_database_work() {
+    let response = send_head(
+        "PUT /test/latency/probe HTTP/1.1\r\nHost: {addr}\r\nContent-Length: 5\r\nConnection: close\r\n\r\nprobe",
+    )
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 200"),
+        "unexpected response: {response}"
+    );
+
+    // Large uploads and optimize runs keep database connections busy meanwhile
+    let body = "x".repeat(8 * 1024 * 1024);
+    let slow = tokio::spawn(async move {
+        for i in 0..4 {
+            send_head(&format!(
+                "PUT /test/latency/large-{i} HTTP/1.1\r\nHost: {{addr}}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            ))
+            .await;
+            send_head("POST /?optimize HTTP/1.1\r\nHost: {addr}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+                .await;
+        }
+    });
+
+    let heads = (0..200).map(|_| {
+        tokio::spawn(async {
+            let start = std::time::Instant::now();
+            let response = send_head(
+                "HEAD /test/latency/probe HTTP/1.1\r\nHost: {addr}\r\nConnection: close\r\n\r\n",
+            )
+            .await;
+            assert!(
+                response.starts_with("HTTP/1.1 200"),
+                "unexpected response: {response}"
+            );
+            start.elapsed()
+        })
+    });
+    let mut latencies = Vec::new();
+    for head in heads.collect::<Vec<_>>() {
+        latencies.push(head.await.unwrap());
+    }
+    slow.await.unwrap();
+
+    latencies.sort();
+    let p99 = latencies[latencies.len() * 99 / 100 - 1];
+    assert!(
+        p99 < std::time::Duration::from_secs(2),
+        "p99 HEAD latency {p99:?} during slow database work"
+    );
+}
+
+#[tokio::test]
+async fn test_listing_gzip_above_threshold() {
+    for i in 0..40 {
+        let response = send_head(&format!(
+            "PUT /test/gzip-listing/object-with-a-reasonably-long-name-{i:03} HTTP/1.1\r\nHost: {{addr}}\r\nContent-Length: 1\r\nConnection: close\r\n\r\nx"
+        ))
+        .await;
+        assert!(
+            response.starts_with("HTTP/1.1 200"),
+            "unexpected response: {response}"
+        );
+    }
+    let list = |max_keys: usize, accept: &str| {
+        format!(
+            "GET /test?list-type=2&prefix=gzip-listing/&max-keys={max_keys} HTTP/1.1\r\nHost: {{addr}}\r\nAccept-Encoding: {accept}\r\nConnection: close\r\n\r\n"
+        )
+    };
+
+    // A listing over list_gzip_threshold_bytes is compressed, with its Content-Length
+    let response = send_head(&list(40, "gzip, deflate")).await.to_lowercase();
+    assert!(
+        response.starts_with("http/1.1 200"),
+        "unexpected response: {response}"
+    );
+    assert!(
+        response.contains("content-encoding: gzip"),
+        "unexpected response: {response}"
+    );
+    assert!(
+        response.contains("content-length:"),
+        "unexpected response: {response}"
+    );
+
+    // Small listings and clients refusing gzip get plain XML
+    for request in [list(2, "gzip"), list(40, "gzip;q=0, identity")] {
+        let response = send_head(&request).await.to_lowercase();
+        assert!(
+            response.starts_with("http/1.1 200"),
+            "unexpected response: {response}"
+        );
+        assert!(
+            !response.contains("content-encoding"),
+            "unexpected response: {response}"
+        );
+        assert!(
+            response.contains("<listbucketresult"),
+            "unexpected response: {response}"
+        );
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_concurrent_writes_through_single_writer() {
+    // Writers queue for the single writer connection instead of failing busy
+    let puts = (0..32).map(|i| {
+        tokio::spawn(async move {
+            send_head(&format!(
+                "PUT /test/concurrent-writes/{i} HTTP/1.1\r\nHost: {{addr}}\r\nContent-Length: 5\r\nConnection: close\r\n\r\nvalue"
+            ))
+            .await
+        })
+    });
+    for put in puts.collect::<Vec<_>>() {
+        let response = put.await.unwrap();
+        assert!(
+            response.starts_with("HTTP/1.1 200"),
+            "unexpected response: {response}"
+        );
+    }
+
+    for i in 0..32 {
+        let response = send_head(&format!(
+            "HEAD /test/concurrent-writes/{i} HTTP/1.1\r\nHost: {{addr}}\r\nConnection: close\r\n\r\n"
+        ))
+        .await;
+        assert!(
+            response.starts_with("HTTP/1.1 200"),
+            "unexpected response: {response}"
+        );
+    }
+
+    let response =
+        send_head("GET /?metrics HTTP/1.1\r\nHost: {addr}\r\nConnection: close\r\n\r\n").await;
+    assert!(
+        response.contains("\"db_writer_busy\":"),
+        "unexpected response: {response}"
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_concurrent_conditional_creates() {
+    let response =
+        send_head("DELETE /test/create-once HTTP/1.1\r\nHost: {addr}\r\nConnection: close\r\n\r\n")
+            .await;
+    assert!(
+        response.starts_with("HTTP/1.1 204"),
+        "unexpected response: {response}"
+    );
+
+    let puts = (0..16).map(|i| {
+        tokio::spawn(async move {
+            send_head(&format!(
+                "PUT /test/create-once HTTP/1.1\r\nHost: {{addr}}\r\nIf-None-Match: *\r\nContent-Length: 2\r\nConnection: close\r\n\r\n{:02}",
+                i
+            ))
+            .await
+        })
+    });
+    let mut created = 0;
+    for put in puts.collect::<Vec<_>>() {
+        let response = put.await.unwrap();
+        if response.starts_with("HTTP/1.1 200") {
+            created += 1;
+        } else {
+            assert!(
+                response.starts_with("HTTP/1.1 412"),
+                "unexpected response: {response}"
+            );
+        }
+    }
+    assert_eq!(created, 1);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_write_batching() {
+    let server = common::TestServer::spawn(
+        "write-batching",
+        &[("write_batching", toml::Value::Boolean(true))],
+    );
+    let addr = server.addr.as_str();
+
+    // Concurrent uploads share batches and are each acknowledged once committed
+    let puts = (0..16).map(|i| {
+        let addr = addr.to_string();
+        tokio::spawn(async move {
+            send_head_to(
+                &addr,
+                &format!(
+                    "PUT /test/batched/{i} HTTP/1.1\r\nHost: {{addr}}\r\nContent-Length: 2\r\nConnection: close\r\n\r\n{i:02}"
+                ),
+            )
+            .await
+        })
+    });
+    for put in puts.collect::<Vec<_>>() {
+        let response = put.await.unwrap();
+        assert!(
+            response.starts_with("HTTP/1.1 200"),
+            "unexpected response: {response}"
+        );
+    }
+    for i in 0..16 {
+        let response = send_head_to(
+            addr,
+            &format!(
+                "GET /test/batched/{i} HTTP/1.1\r\nHost: {{addr}}\r\nConnection: close\r\n\r\n"
+            ),
+        )
+        .await;
+        assert!(
+            response.starts_with("HTTP/1.1 200") && response.ends_with(&format!("\r\n\r\n{i:02}")),
+            "unexpected response: {response}"
+        );
+    }
+
+    // Uploads committed in the same batch still see each other's writes: one of the
+    // conditional creates and two uploads into the quota bucket (2 objects) succeed,
+    // the others are rolled back alone
+    let puts = (0..16).map(|i| {
+        let addr = addr.to_string();
+        tokio::spawn(async move {
+            let create = format!(
+                "PUT /test/create-once HTTP/1.1\r\nHost: {{addr}}\r\nIf-None-Match: *\r\nContent-Length: 2\r\nConnection: close\r\n\r\n{i:02}"
+            );
+            let quota = format!(
+                "PUT /quota/{i} HTTP/1.1\r\nHost: {{addr}}\r\nContent-Length: 1\r\nConnection: close\r\n\r\n1"
+            );
+            tokio::join!(send_head_to(&addr, &create), send_head_to(&addr, &quota))
+        })
+    });
+    let (mut created, mut stored) = (0, 0);
+    for put in puts.collect::<Vec<_>>() {
+        let (create, quota) = put.await.unwrap();
+        if create.starts_with("HTTP/1.1 200") {
+            created += 1;
+        } else {
+            assert!(
+                create.starts_with("HTTP/1.1 412"),
+                "unexpected response: {create}"
+            );
+        }
+        if quota.starts_with("HTTP/1.1 200") {
+            stored += 1;
+        } else {
+            assert!(
+                quota.contains("<Code>QuotaExceeded</Code>"),
+                "unexpected response: {quota}"
+            );
+        }
+    }
+    assert_eq!((created, stored), (1, 2));
+}
+
+#[tokio::test]
+async fn test_object_cache_serves_and_invalidates() {
+    let get = || {
+        send_head("GET /test/cached-object HTTP/1.1\r\nHost: {addr}\r\nConnection: close\r\n\r\n")
+    };
+    let response = send_head(
+        "PUT /test/cached-object HTTP/1.1\r\nHost: {addr}\r\nContent-Length: 5\r\nConnection: close\r\n\r\nfirst",
+    )
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 200"),
+        "unexpected response: {response}"
+    );
+
+    // The first read fills the cache, the second is served from it
+    for _ in 0..2 {
+        let response = get().await;
+        assert!(
+            response.starts_with("HTTP/1.1 200") && response.ends_with("\r\n\r\nfirst"),
+            "unexpected response: {response}"
+        );
+    }
+    let etag = get()
+        .await
+        .lines()
+        .find_map(|line| line.strip_prefix("etag: ").map(str::to_string))
+        .expect("missing ETag");
+
+    let response = send_head(&format!(
+        "GET /test/cached-object HTTP/1.1\r\nHost: {{addr}}\r\nIf-None-Match: {etag}\r\nConnection: close\r\n\r\n"
+    ))
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 304"),
+        "unexpected response: {response}"
+    );
+
+    // An overwrite is seen at once, whole or by range
+    let response = send_head(
+        "PUT /test/cached-object HTTP/1.1\r\nHost: {addr}\r\nContent-Length: 6\r\nConnection: close\r\n\r\nsecond",
+    )
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 200"),
+        "unexpected response: {response}"
+    );
+    let response = get().await;
+    assert!(
+        response.starts_with("HTTP/1.1 200") && response.ends_with("\r\n\r\nsecond"),
+        "unexpected response: {response}"
+    );
+    let response = send_head(
+        "GET /test/cached-object HTTP/1.1\r\nHost: {addr}\r\nRange: bytes=0-2\r\nConnection: close\r\n\r\n",
+    )
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 206") && response.ends_with("\r\n\r\nsec"),
+        "unexpected response: {response}"
+    );
+
+    let response = send_head(
+        "DELETE /test/cached-object HTTP/1.1\r\nHost: {addr}\r\nConnection: close\r\n\r\n",
+    )
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 204"),
+        "unexpected response: {response}"
+    );
+    let response = get().await;
+    assert!(
+        response.starts_with("HTTP/1.1 404"),
+        "unexpected response: {response}"
+    );
+
+    let response =
+        send_head("GET /?metrics HTTP/1.1\r\nHost: {addr}\r\nConnection: close\r\n\r\n").await;
+    let hits: u64 = response
+        .split("\"object_cache_hits\":")
+        .nth(1)
+        .and_then(|rest| rest.split([',', '}']).next())
+        .and_then(|hits| hits.parse().ok())
+        .expect("missing object_cache_hits");
+    assert!(hits >= 2, "unexpected response: {response}");
+}
+
+#[tokio::test]
+async fn test_backup_into_backup_dir() {
+    let name = format!(
+        "backup-{}.sqlite",
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos()
+    );
+    let backup = |path: String| async move {
+        send_head(&format!(
+            "POST /?backup&path={path} HTTP/1.1\r\nHost: {{addr}}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+        ))
+        .await
+    };
+
+    let response = backup(name.clone()).await;
+    assert!(
+        response.starts_with("HTTP/1.1 200") && response.contains("\"size_bytes\":"),
+        "unexpected response: {response}"
+    );
+    assert!(
+        !response.contains("\"size_bytes\":0,"),
+        "unexpected response: {response}"
+    );
+
+    // An existing backup is never overwritten
+    let response = backup(name).await;
+    assert!(
+        response.starts_with("HTTP/1.1 409"),
+        "unexpected response: {response}"
+    );
+
+    for path in [
+        "../escaped.sqlite",
+        "%2Ftmp%2Fescaped.sqlite",
+        "missing-dir/backup.sqlite",
+    ] {
+        let response = backup(path.to_string()).await;
+        assert!(
+            response.starts_with("HTTP/1.1 400"),
+            "unexpected response for {path}: {response}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_multipart_upload_compressed() {
+    // A bucket of its own server, so its usage counts this object only
+    let server = common::TestServer::spawn("multipart-compression", &[]);
+    let addr = server.addr.as_str();
+    let body = "0123456789".repeat(800);
+
+    let (upload_id, manifest) = start_single_part_upload(addr, "/compressed/parts", &body).await;
+    let response = send_head_to(
+        addr,
+        &format!(
+            "POST /compressed/parts?uploadId={upload_id} HTTP/1.1\r\nHost: {{addr}}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{manifest}",
+            manifest.len()
+        ),
+    )
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 200"),
+        "unexpected response: {response}"
+    );
+
+    // Stored compressed, read back as uploaded
+    let response = send_head_to(
+        addr,
+        "HEAD /compressed HTTP/1.1\r\nHost: {addr}\r\nConnection: close\r\n\r\n",
+    )
+    .await
+    .to_lowercase();
+    let bytes_used: usize = response
+        .lines()
+        .find_map(|line| line.strip_prefix("x-s3insqlite-bytes-used: "))
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or_else(|| panic!("no usage in response: {response}"));
+    assert!(bytes_used < body.len() / 2, "stored {bytes_used} bytes");
+
+    let response = send_head_to(
+        addr,
+        "GET /compressed/parts HTTP/1.1\r\nHost: {addr}\r\nRange: bytes=4005-4014\r\nConnection: close\r\n\r\n",
+    )
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 206")
+            && response
+                .to_lowercase()
+                .contains(&format!("content-range: bytes 4005-4014/{}", body.len()))
+            && response.ends_with("\r\n\r\n5678901234"),
+        "unexpected response: {response}"
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_snapshot_while_writing() {
+    // Uploads keep being served while the snapshot copies the database
+    let puts = (0..16).map(|i| {
+        tokio::spawn(async move {
+            send_head(&format!(
+                "PUT /test/snapshot/{i} HTTP/1.1\r\nHost: {{addr}}\r\nContent-Length: 8\r\nConnection: close\r\n\r\nsnapshot"
+            ))
+            .await
+        })
+    });
+    let response = send_head(
+        "POST /?snapshot HTTP/1.1\r\nHost: {addr}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+    )
+    .await;
+    assert!(
+        (response.starts_with("HTTP/1.1 200")
+            && response.contains("\"pages\":")
+            && response.contains("snapshot-"))
+            || response.starts_with("HTTP/1.1 409"),
+        "unexpected response: {response}"
+    );
+    for put in puts.collect::<Vec<_>>() {
+        let response = put.await.unwrap();
+        assert!(
+            response.starts_with("HTTP/1.1 200"),
+            "unexpected response: {response}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_error_format_follows_accept() {
+    let get = async |accept: &str| {
+        send_head(&format!(
+            "GET /test/no-such-error-object HTTP/1.1\r\nHost: {{addr}}\r\n{accept}Connection: close\r\n\r\n"
+        ))
+        .await
+    };
+
+    // JSON for clients preferring it
+    for accept in [
+        "Accept: application/json\r\n",
+        "Accept: application/json, text/plain, */*\r\n",
+        "Accept: application/xml;q=0.5, application/json\r\n",
+    ] {
+        let response = get(accept).await;
+        assert!(
+            response.starts_with("HTTP/1.1 404")
+                && response.contains("content-type: application/json")
+                && response.contains(r#""Code":"NoSuchKey""#)
+                && response.contains(r#""RequestId":""#),
+            "unexpected response to {accept:?}: {response}"
+        );
+    }
+
+    // XML otherwise, as S3 sends it
+    for accept in [
+        "",
+        "Accept: */*\r\n",
+        "Accept: application/json;q=0.5, application/xml\r\n",
+        "Accept: application/json;q=0\r\n",
+    ] {
+        let response = get(accept).await;
+        assert!(
+            response.starts_with("HTTP/1.1 404")
+                && response.contains("content-type: application/xml")
+                && response.contains("<Code>NoSuchKey</Code>"),
+            "unexpected response to {accept:?}: {response}"
+        );
+    }
+
+    // Messages are escaped for the format they are sent in only: key `"quoted"&<key>`
+    let get_quoted = async |accept: &str| {
+        send_head(&format!(
+            "GET /test/%22quoted%22%26%3Ckey%3E HTTP/1.1\r\nHost: {{addr}}\r\n{accept}Connection: close\r\n\r\n"
+        ))
+        .await
+    };
+    let response = get_quoted("Accept: application/json\r\n").await;
+    assert!(
+        response.contains(r#"does not exist: \"quoted\"&<key>""#),
+        "unexpected response: {response}"
+    );
+    let response = get_quoted("").await;
+    assert!(
+        response.contains("does not exist: &quot;quoted&quot;&amp;&lt;key&gt;</Message>"),
+        "unexpected response: {response}"
+    );
+}
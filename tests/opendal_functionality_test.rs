@@ -57,3 +57,317 @@ async fn test_connection() {
         .expect("failed to delete object");
     println!("Deleted object: {object_key}");
 }
+
+/// Send a request to a presigned URL over a plain TCP connection and return the response
+async fn fetch_presigned(presigned: &opendal::raw::PresignedRequest, body: &str) -> String {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let uri = presigned.uri();
+    let addr = uri
+        .authority()
+        .expect("presigned URL without host")
+        .as_str();
+    let mut request = format!(
+        "{} {} HTTP/1.1\r\nHost: {addr}\r\n",
+        presigned.method(),
+        uri.path_and_query().expect("presigned URL without path")
+    );
+    for (name, value) in presigned.header() {
+        request.push_str(&format!("{name}: {}\r\n", value.to_str().unwrap()));
+    }
+    request.push_str(&format!(
+        "Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    ));
+
+    let mut stream = tokio::net::TcpStream::connect(addr)
+        .await
+        .expect("failed to connect");
+    stream
+        .write_all(request.as_bytes())
+        .await
+        .expect("failed to send request");
+    let mut response = Vec::new();
+    stream
+        .read_to_end(&mut response)
+        .await
+        .expect("failed to read response");
+    String::from_utf8_lossy(&response).to_string()
+}
+
+#[tokio::test]
+async fn test_presigned_urls() {
+    use std::time::Duration;
+
+    let (endpoint, bucket) = common::read_config();
+    let builder = services::S3::default()
+        .endpoint(&endpoint)
+        .bucket(&bucket)
+        .access_key_id("minioadmin")
+        .secret_access_key("minioadmin")
+        .region("auto");
+    let op = Operator::new(builder)
+        .expect("failed to create S3 backend")
+        .finish();
+
+    let key = "presigned/object.txt";
+    let write = op
+        .presign_write(key, Duration::from_secs(300))
+        .await
+        .expect("failed to presign write");
+    let response = fetch_presigned(&write, "shared content").await;
+    assert!(
+        response.starts_with("HTTP/1.1 200"),
+        "unexpected response: {response}"
+    );
+
+    let read = op
+        .presign_read(key, Duration::from_secs(300))
+        .await
+        .expect("failed to presign read");
+    let response = fetch_presigned(&read, "").await;
+    assert!(
+        response.starts_with("HTTP/1.1 200") && response.ends_with("\r\n\r\nshared content"),
+        "unexpected response: {response}"
+    );
+
+    // The signature covers the path: it does not open other objects
+    let other = opendal::raw::PresignedRequest::new(
+        read.method().clone(),
+        read.uri()
+            .to_string()
+            .replace("object.txt", "other.txt")
+            .parse()
+            .unwrap(),
+        read.header().clone(),
+    );
+    let response = fetch_presigned(&other, "").await;
+    assert!(
+        response.starts_with("HTTP/1.1 403") && response.contains("SignatureDoesNotMatch"),
+        "unexpected response: {response}"
+    );
+
+    let expiring = op
+        .presign_read(key, Duration::from_secs(1))
+        .await
+        .expect("failed to presign read");
+    tokio::time::sleep(Duration::from_secs(2)).await;
+    let response = fetch_presigned(&expiring, "").await;
+    assert!(
+        response.starts_with("HTTP/1.1 403") && response.contains("Request has expired"),
+        "unexpected response: {response}"
+    );
+}
+
+#[tokio::test]
+async fn test_compressed_bucket_round_trip() {
+    let (endpoint, _) = common::read_config();
+    let builder = services::S3::default()
+        .endpoint(&endpoint)
+        .bucket("compressed")
+        .access_key_id("minioadmin")
+        .secret_access_key("minioadmin")
+        .region("auto");
+    let op = Operator::new(builder)
+        .expect("failed to create S3 backend")
+        .finish();
+
+    // Compressible JSON is stored compressed, random bytes are kept as sent
+    let json: Vec<u8> = (0..4000)
+        .map(|i| format!(r#"{{"t":{i},"v":[0.5,1.25,2.0]}}"#))
+        .collect::<String>()
+        .into_bytes();
+    let mut rng = rand::rng();
+    let random: Vec<u8> = (0..4096).map(|_| rng.random::<u8>()).collect();
+
+    for (key, data) in [
+        ("compressed/data.json", &json),
+        ("compressed/random.bin", &random),
+    ] {
+        op.write(key, data.clone())
+            .await
+            .expect("failed to upload object");
+
+        let read = op.read(key).await.expect("failed to read object").to_vec();
+        assert!(read == *data, "{key} differs from the uploaded data");
+        let read = op
+            .read_with(key)
+            .range(1000..3000)
+            .await
+            .expect("failed to read range")
+            .to_vec();
+        assert!(read == data[1000..3000], "range of {key} differs");
+
+        // Sizes and ETags describe the object as uploaded
+        let meta = op.stat(key).await.expect("failed to stat object");
+        assert_eq!(meta.content_length(), data.len() as u64);
+        let etag = hex::encode(md5::compute(data).0);
+        assert_eq!(meta.etag(), Some(format!("\"{etag}\"").as_str()));
+    }
+
+    let entries = op
+        .list("compressed/")
+        .await
+        .expect("failed to list objects");
+    let entry = entries
+        .iter()
+        .find(|entry| entry.path() == "compressed/data.json")
+        .expect("compressed object not listed");
+    assert_eq!(entry.metadata().content_length(), json.len() as u64);
+
+    // Copies keep the object compressed and readable
+    op.copy("compressed/data.json", "compressed/copy.json")
+        .await
+        .expect("failed to copy object");
+    let read = op
+        .read("compressed/copy.json")
+        .await
+        .expect("failed to read copy")
+        .to_vec();
+    assert!(read == json, "copy differs from the uploaded data");
+
+    for key in [
+        "compressed/data.json",
+        "compressed/random.bin",
+        "compressed/copy.json",
+    ] {
+        op.delete(key).await.expect("failed to delete object");
+    }
+}
+
+/// A numeric field of `GET /?metrics`
+async fn metric(name: &str) -> u64 {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let (endpoint, _) = common::read_config();
+    let addr = endpoint.trim_start_matches("http://");
+    let request = common::sign_request(&format!(
+        "GET /?metrics HTTP/1.1\r\nHost: {addr}\r\nConnection: close\r\n\r\n"
+    ));
+    let mut stream = tokio::net::TcpStream::connect(addr)
+        .await
+        .expect("failed to connect");
+    stream
+        .write_all(request.as_bytes())
+        .await
+        .expect("failed to send request");
+    let mut response = String::new();
+    stream
+        .read_to_string(&mut response)
+        .await
+        .expect("failed to read response");
+    response
+        .split(&format!("\"{name}\":"))
+        .nth(1)
+        .and_then(|rest| rest.split([',', '}']).next())
+        .and_then(|value| value.parse().ok())
+        .unwrap_or_else(|| panic!("missing {name} in {response}"))
+}
+
+#[tokio::test]
+async fn test_dedup_bucket_stores_identical_objects_once() {
+    let (endpoint, _) = common::read_config();
+    let builder = services::S3::default()
+        .endpoint(&endpoint)
+        .bucket("dedup")
+        .access_key_id("minioadmin")
+        .secret_access_key("minioadmin")
+        .region("auto");
+    let op = Operator::new(builder)
+        .expect("failed to create S3 backend")
+        .finish();
+
+    const SIZE: usize = 10 * 1024 * 1024;
+    const COPIES: usize = 100;
+    let mut rng = rand::rng();
+    let data: Vec<u8> = (0..SIZE).map(|_| rng.random::<u8>()).collect();
+    let blobs_before = metric("shared_blobs").await;
+    let used_before = metric("db_used_bytes").await;
+
+    for i in 0..COPIES {
+        op.write(&format!("dedup/{i}.bin"), data.clone())
+            .await
+            .expect("failed to upload object");
+    }
+
+    // So does the object assembled from a multipart upload of the same data
+    let mut writer = op
+        .writer_with("dedup/multipart.bin")
+        .chunk(SIZE / 2)
+        .await
+        .expect("failed to create multipart writer");
+    writer
+        .write(data.clone())
+        .await
+        .expect("failed to write parts");
+    writer.close().await.expect("failed to complete upload");
+
+    // One blob of 10 MB holds the 1 GB of objects
+    assert_eq!(metric("shared_blobs").await, blobs_before + 1);
+    let growth = metric("db_used_bytes").await.saturating_sub(used_before);
+    assert!(
+        growth < 2 * SIZE as u64,
+        "database grew by {growth} bytes for {COPIES} identical objects of {SIZE} bytes"
+    );
+
+    // Every object reads back whole and by range, with its own size and ETag
+    let read = op
+        .read("dedup/42.bin")
+        .await
+        .expect("failed to read object")
+        .to_vec();
+    assert!(read == data, "object differs from the uploaded data");
+    let read = op
+        .read_with("dedup/7.bin")
+        .range(1000..3000)
+        .await
+        .expect("failed to read range")
+        .to_vec();
+    assert!(read == data[1000..3000], "range differs");
+    let read = op
+        .read("dedup/multipart.bin")
+        .await
+        .expect("failed to read multipart object")
+        .to_vec();
+    assert!(
+        read == data,
+        "multipart object differs from the uploaded data"
+    );
+    let meta = op.stat("dedup/0.bin").await.expect("failed to stat object");
+    assert_eq!(meta.content_length(), SIZE as u64);
+    let etag = hex::encode(md5::compute(&data).0);
+    assert_eq!(meta.etag(), Some(format!("\"{etag}\"").as_str()));
+
+    // Overwrites and copies share blobs too, and the blob outlives all but its last object
+    op.write("dedup/0.bin", b"overwritten".to_vec())
+        .await
+        .expect("failed to overwrite object");
+    op.copy("dedup/1.bin", "dedup/copy.bin")
+        .await
+        .expect("failed to copy object");
+    assert_eq!(metric("shared_blobs").await, blobs_before + 2);
+    for i in 1..COPIES {
+        op.delete(&format!("dedup/{i}.bin"))
+            .await
+            .expect("failed to delete object");
+    }
+    let read = op
+        .read("dedup/copy.bin")
+        .await
+        .expect("failed to read copy")
+        .to_vec();
+    assert!(read == data, "copy differs from the uploaded data");
+
+    // Deleting the last reference frees the blob
+    op.delete("dedup/copy.bin")
+        .await
+        .expect("failed to delete object");
+    op.delete("dedup/0.bin")
+        .await
+        .expect("failed to delete object");
+    assert_eq!(metric("shared_blobs").await, blobs_before + 1);
+    op.delete("dedup/multipart.bin")
+        .await
+        .expect("failed to delete object");
+    assert_eq!(metric("shared_blobs").await, blobs_before);
+}
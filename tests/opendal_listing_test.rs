@@ -0,0 +1,296 @@
+mod common;
+use opendal::Operator;
+use opendal::services;
+
+fn operator() -> Operator {
+    let (endpoint, bucket) = common::read_config();
+    let builder = services::S3::default()
+        .endpoint(&endpoint)
+        .bucket(&bucket)
+        .access_key_id("minioadmin")
+        .secret_access_key("minioadmin")
+        .region("us-east-1");
+
+    Operator::new(builder)
+        .expect("failed to create S3 backend")
+        .finish()
+}
+
+async fn list_keys(op: &Operator, prefix: &str) -> Vec<String> {
+    op.list_with(prefix)
+        .recursive(true)
+        .await
+        .unwrap_or_else(|e| panic!("failed to list {prefix}: {e}"))
+        .into_iter()
+        .map(|entry| entry.path().to_string())
+        .filter(|path| !path.ends_with('/'))
+        .collect()
+}
+
+#[tokio::test]
+async fn test_list_prefix_wildcards_are_literal() {
+    let op = operator();
+
+    // Keys that a LIKE-based prefix match would confuse with each other
+    let keys = [
+        "wildcard/v1_0/a",
+        "wildcard/v1a0/b",
+        "wildcard/v190/c",
+        "wildcard/p%/d",
+        "wildcard/pq/e",
+        "wildcard/back\\/f",
+        "wildcard/backx/g",
+    ];
+    for key in keys {
+        op.write(key, key.as_bytes().to_vec())
+            .await
+            .unwrap_or_else(|e| panic!("failed to upload {key}: {e}"));
+    }
+
+    assert_eq!(
+        list_keys(&op, "wildcard/v1_0/").await,
+        vec!["wildcard/v1_0/a"]
+    );
+    assert_eq!(list_keys(&op, "wildcard/p%/").await, vec!["wildcard/p%/d"]);
+    assert_eq!(
+        list_keys(&op, "wildcard/back\\/").await,
+        vec!["wildcard/back\\/f"]
+    );
+
+    for key in keys {
+        op.delete(key)
+            .await
+            .unwrap_or_else(|e| panic!("failed to delete {key}: {e}"));
+    }
+}
+
+#[tokio::test]
+async fn test_list_start_after() {
+    let op = operator();
+
+    let keys = [
+        "startafter/a",
+        "startafter/b",
+        "startafter/c",
+        "startafter/d",
+    ];
+    for key in keys {
+        op.write(key, key.as_bytes().to_vec())
+            .await
+            .unwrap_or_else(|e| panic!("failed to upload {key}: {e}"));
+    }
+
+    let listed: Vec<String> = op
+        .list_with("startafter/")
+        .start_after("startafter/b")
+        .await
+        .expect("failed to list with start-after")
+        .into_iter()
+        .map(|entry| entry.path().to_string())
+        .filter(|path| !path.ends_with('/'))
+        .collect();
+    assert_eq!(listed, vec!["startafter/c", "startafter/d"]);
+
+    for key in keys {
+        op.delete(key)
+            .await
+            .unwrap_or_else(|e| panic!("failed to delete {key}: {e}"));
+    }
+}
+
+#[tokio::test]
+async fn test_list_start_after_excludes_marker() {
+    let op = operator();
+
+    let keys = ["startafter-abc/a", "startafter-abc/b", "startafter-abc/c"];
+    for key in keys {
+        op.write(key, key.as_bytes().to_vec())
+            .await
+            .unwrap_or_else(|e| panic!("failed to upload {key}: {e}"));
+    }
+
+    // Without a delimiter
+    let listed: Vec<String> = op
+        .list_with("startafter-abc/")
+        .recursive(true)
+        .start_after("startafter-abc/a")
+        .await
+        .expect("failed to list with start-after")
+        .into_iter()
+        .map(|entry| entry.path().to_string())
+        .filter(|path| !path.ends_with('/'))
+        .collect();
+    assert_eq!(listed, vec!["startafter-abc/b", "startafter-abc/c"]);
+
+    // With a delimiter
+    let listed: Vec<String> = op
+        .list_with("startafter-abc/")
+        .start_after("startafter-abc/a")
+        .await
+        .expect("failed to list with start-after")
+        .into_iter()
+        .map(|entry| entry.path().to_string())
+        .filter(|path| !path.ends_with('/'))
+        .collect();
+    assert_eq!(listed, vec!["startafter-abc/b", "startafter-abc/c"]);
+
+    for key in keys {
+        op.delete(key)
+            .await
+            .unwrap_or_else(|e| panic!("failed to delete {key}: {e}"));
+    }
+}
+
+#[tokio::test]
+async fn test_list_pages_with_max_keys() {
+    let op = operator();
+
+    let keys = [
+        "maxkeys/a",
+        "maxkeys/b",
+        "maxkeys/c/1",
+        "maxkeys/c/2",
+        "maxkeys/d",
+        "maxkeys/e/1",
+    ];
+    for key in keys {
+        op.write(key, key.as_bytes().to_vec())
+            .await
+            .unwrap_or_else(|e| panic!("failed to upload {key}: {e}"));
+    }
+
+    // Without a delimiter, pages of two keys must add up to the full listing
+    let listed: Vec<String> = op
+        .list_with("maxkeys/")
+        .recursive(true)
+        .limit(2)
+        .await
+        .expect("failed to list with max-keys")
+        .into_iter()
+        .map(|entry| entry.path().to_string())
+        .filter(|path| !path.ends_with('/'))
+        .collect();
+    assert_eq!(listed, keys);
+
+    // With a delimiter, a page boundary on a common prefix must not repeat it
+    let listed: Vec<String> = op
+        .list_with("maxkeys/")
+        .limit(2)
+        .await
+        .expect("failed to list with max-keys")
+        .into_iter()
+        .map(|entry| entry.path().to_string())
+        .filter(|path| *path != "maxkeys/")
+        .collect();
+    assert_eq!(
+        listed,
+        vec![
+            "maxkeys/a",
+            "maxkeys/b",
+            "maxkeys/c/",
+            "maxkeys/d",
+            "maxkeys/e/"
+        ]
+    );
+
+    for key in keys {
+        op.delete(key)
+            .await
+            .unwrap_or_else(|e| panic!("failed to delete {key}: {e}"));
+    }
+}
+
+#[tokio::test]
+async fn benchmark_delimiter_listing() {
+    const ARRAY_COUNT: usize = 20;
+    const CHUNK_COUNT: usize = 50;
+    let op = operator();
+
+    for array in 0..ARRAY_COUNT {
+        for chunk in 0..CHUNK_COUNT {
+            let key = format!("manyprefix/array{array:03}/c/{chunk}");
+            op.write(&key, vec![0u8; 16])
+                .await
+                .unwrap_or_else(|e| panic!("failed to upload {key}: {e}"));
+        }
+    }
+
+    let start = std::time::Instant::now();
+    let entries = op
+        .list("manyprefix/")
+        .await
+        .expect("failed to list with delimiter");
+    let elapsed = start.elapsed();
+
+    let prefixes: Vec<&str> = entries
+        .iter()
+        .map(|entry| entry.path())
+        .filter(|path| *path != "manyprefix/")
+        .collect();
+    assert_eq!(prefixes.len(), ARRAY_COUNT);
+    assert!(prefixes.iter().all(|path| path.ends_with('/')));
+    println!(
+        "Delimiter listing of {} keys under {} prefixes took {:.2} ms",
+        ARRAY_COUNT * CHUNK_COUNT,
+        ARRAY_COUNT,
+        elapsed.as_secs_f64() * 1000.0
+    );
+
+    for array in 0..ARRAY_COUNT {
+        for chunk in 0..CHUNK_COUNT {
+            let key = format!("manyprefix/array{array:03}/c/{chunk}");
+            op.delete(&key)
+                .await
+                .unwrap_or_else(|e| panic!("failed to delete {key}: {e}"));
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_streamed_listing_client_disconnect() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    const OBJECT_COUNT: usize = 2000;
+    let (endpoint, bucket) = common::read_config();
+    let op = operator();
+
+    for i in 0..OBJECT_COUNT {
+        let key = format!("streamed/{i:05}");
+        op.write(&key, vec![0u8; 16])
+            .await
+            .unwrap_or_else(|e| panic!("failed to upload {key}: {e}"));
+    }
+
+    // Start a streamed listing and hang up after the first bytes arrive
+    for _ in 0..10 {
+        let addr = endpoint.trim_start_matches("http://");
+        let mut stream = tokio::net::TcpStream::connect(addr)
+            .await
+            .expect("failed to connect");
+        let request = common::sign_request(&format!(
+            "GET /{bucket}?list-type=2&prefix=streamed/ HTTP/1.1\r\nHost: {addr}\r\n\r\n"
+        ));
+        stream
+            .write_all(request.as_bytes())
+            .await
+            .expect("failed to send request");
+        let mut buf = [0u8; 256];
+        let n = stream
+            .read(&mut buf)
+            .await
+            .expect("failed to read response");
+        assert!(String::from_utf8_lossy(&buf[..n]).starts_with("HTTP/1.1 200"));
+        drop(stream);
+    }
+
+    // The server must still answer complete listings afterwards
+    let listed = list_keys(&op, "streamed/").await;
+    assert_eq!(listed.len(), OBJECT_COUNT);
+
+    for i in 0..OBJECT_COUNT {
+        let key = format!("streamed/{i:05}");
+        op.delete(&key)
+            .await
+            .unwrap_or_else(|e| panic!("failed to delete {key}: {e}"));
+    }
+}
@@ -0,0 +1,98 @@
+mod common;
+use opendal::Operator;
+use opendal::services;
+
+fn operator() -> Operator {
+    let (endpoint, bucket) = common::read_config();
+    let builder = services::S3::default()
+        .endpoint(&endpoint)
+        .bucket(&bucket)
+        .access_key_id("minioadmin")
+        .secret_access_key("minioadmin")
+        .region("us-east-1");
+
+    Operator::new(builder)
+        .expect("failed to create S3 backend")
+        .finish()
+}
+
+#[tokio::test]
+async fn test_multipart_upload_roundtrip() {
+    const PART_SIZE: usize = 5 * 1024 * 1024;
+    let op = operator();
+    let key = "multipart/object.bin";
+
+    // Two full parts and a short last part
+    let data: Vec<u8> = (0..PART_SIZE * 2 + 1234).map(|i| (i % 251) as u8).collect();
+
+    let mut writer = op
+        .writer_with(key)
+        .chunk(PART_SIZE)
+        .await
+        .expect("failed to create multipart writer");
+    writer
+        .write(data.clone())
+        .await
+        .expect("failed to write parts");
+    writer.close().await.expect("failed to complete upload");
+
+    let read = op.read(key).await.expect("failed to read object").to_vec();
+    assert_eq!(read.len(), data.len());
+    assert!(read == data, "assembled object differs from uploaded data");
+
+    let meta = op.stat(key).await.expect("failed to stat object");
+    assert!(
+        meta.etag()
+            .is_some_and(|etag| etag.trim_matches('"').ends_with("-3")),
+        "unexpected multipart ETag: {:?}",
+        meta.etag()
+    );
+    let etag = meta.etag().unwrap().to_string();
+
+    // Listings and conditional reads use the same composite ETag
+    let entries = op.list("multipart/").await.expect("failed to list objects");
+    let entry = entries
+        .iter()
+        .find(|entry| entry.path() == key)
+        .expect("multipart object not listed");
+    assert_eq!(entry.metadata().etag(), Some(etag.as_str()));
+    let read = op
+        .read_with(key)
+        .if_match(&etag)
+        .await
+        .expect("If-Match with the composite ETag failed")
+        .to_vec();
+    assert_eq!(read.len(), data.len());
+    let err = op
+        .read_with(key)
+        .if_none_match(&format!("W/{etag}"))
+        .await
+        .expect_err("If-None-Match with the weak composite ETag must not return the object");
+    assert_eq!(err.kind(), opendal::ErrorKind::ConditionNotMatch);
+
+    op.delete(key).await.expect("failed to delete object");
+}
+
+#[tokio::test]
+async fn test_multipart_upload_abort() {
+    const PART_SIZE: usize = 5 * 1024 * 1024;
+    let op = operator();
+    let key = "multipart/aborted.bin";
+
+    let mut writer = op
+        .writer_with(key)
+        .chunk(PART_SIZE)
+        .await
+        .expect("failed to create multipart writer");
+    writer
+        .write(vec![7u8; PART_SIZE * 2])
+        .await
+        .expect("failed to write parts");
+    writer.abort().await.expect("failed to abort upload");
+
+    let err = op
+        .stat(key)
+        .await
+        .expect_err("aborted upload must not create an object");
+    assert_eq!(err.kind(), opendal::ErrorKind::NotFound);
+}
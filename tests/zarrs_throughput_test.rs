@@ -16,6 +16,10 @@ const SECRET_ACCESS_KEY: &str = "minioadmin";
 const REGION: &str = "us-east-1";
 const ARRAY_SHAPE: (usize, usize) = (128, 512); // 128x128 f32 array = 256KB per array
 const ARRAY_COUNT: usize = 1000;
+// 1024x1024 f32 arrays in 64x64 chunks: 256 chunks of 16KB per array, written concurrently
+const CHUNKED_ARRAY_SHAPE: (usize, usize) = (1024, 1024);
+const CHUNK_SHAPE: (usize, usize) = (64, 64);
+const CHUNKED_ARRAY_COUNT: usize = 8;
 
 fn random_array(shape: (usize, usize)) -> Array2<f32> {
     let mut rng = rand::rng();
@@ -116,6 +120,83 @@ async fn benchmark_read(
     elapsed
 }
 
+/// Write `count` arrays whose chunks are uploaded concurrently, returning the chunks per second
+async fn benchmark_chunked_write(
+    store: &AsyncReadableWritableListableStorage,
+    shape: (usize, usize),
+    chunk_shape: (usize, usize),
+    count: usize,
+) -> f64 {
+    let start = Instant::now();
+    for i in 0..count {
+        let array_path = format!("/chunked_{i:05}");
+        let data = random_array(shape);
+
+        let array = ArrayBuilder::new(
+            data.shape().iter().map(|x| *x as u64).collect::<Vec<_>>(),
+            [chunk_shape.0 as u64, chunk_shape.1 as u64],
+            data_type::float32(),
+            FillValue::from(ZARR_NAN_F32),
+        )
+        .build(store.clone(), &array_path)
+        .expect("failed to create array");
+
+        array
+            .async_store_metadata()
+            .await
+            .expect("Failed to store metadata");
+        array
+            .async_store_array_subset(
+                &ArraySubset::new_with_shape(vec![shape.0 as u64, shape.1 as u64]),
+                data.as_slice().unwrap(),
+            )
+            .await
+            .expect("Failed to write data to Zarr array");
+    }
+    let chunks = count * (shape.0 / chunk_shape.0) * (shape.1 / chunk_shape.1);
+    chunks as f64 / start.elapsed().as_secs_f64()
+}
+
+fn server_store(addr: &str) -> AsyncReadableWritableListableStorage {
+    let builder = services::S3::default()
+        .endpoint(&format!("http://{addr}"))
+        .bucket("test")
+        .access_key_id(ACCESS_KEY_ID)
+        .secret_access_key(SECRET_ACCESS_KEY)
+        .region(REGION);
+    let operator = Operator::new(builder)
+        .expect("failed to create S3 backend")
+        .finish();
+    Arc::new(AsyncOpendalStore::new(operator))
+}
+
+/// Compare the write throughput of many small chunks with and without `write_batching`,
+/// each on a server of its own
+#[tokio::test(flavor = "multi_thread")]
+async fn zarrs_write_batching_benchmark() {
+    let mut chunks_per_second = Vec::new();
+    for write_batching in [false, true] {
+        let server = common::TestServer::spawn(
+            &format!("zarrs-batching-{write_batching}"),
+            &[("write_batching", toml::Value::Boolean(write_batching))],
+        );
+        let store = server_store(&server.addr);
+        let rate = benchmark_chunked_write(
+            &store,
+            CHUNKED_ARRAY_SHAPE,
+            CHUNK_SHAPE,
+            CHUNKED_ARRAY_COUNT,
+        )
+        .await;
+        println!("Chunk writes with write_batching = {write_batching}: {rate:.2} chunks/s");
+        chunks_per_second.push(rate);
+    }
+    println!(
+        "Write batching speedup: {:.2}x",
+        chunks_per_second[1] / chunks_per_second[0]
+    );
+}
+
 #[tokio::test]
 async fn zarrs_throughput_benchmark() {
     // Set up opendal S3 backend